@@ -1,8 +1,8 @@
 import path from "node:path";
 import {
 	batchGenerateClipEmbeddings,
-	discoverPhotos as discoverPhotosRust,
-	processPhotosWithCallback,
+	discoverPhotosAsync,
+	processPhotosStreaming,
 } from "@photobrain/image-processing";
 import {
 	type BatchEmbeddingJobData,
@@ -35,10 +35,35 @@ const scanWorker = new Worker<ScanJobData>(
 		const { directory, thumbnailsDir } = job.data;
 		console.log(`📂 Starting parallel scan of ${directory}`);
 
-		// Fast parallel discovery in Rust
-		const discovery = discoverPhotosRust(directory);
-		const { filePaths, relativePaths, totalCount } = discovery;
-		console.log(`Found ${totalCount} photos (parallel discovery)`);
+		// Fast parallel discovery in Rust, off the main thread so progress can be reported
+		const discovery = await discoverPhotosAsync(
+			directory,
+			undefined,
+			(_err, progress) => {
+				void job
+					.updateProgress({
+						phase: "discovery",
+						directoriesScanned: progress.directoriesScanned,
+						filesSeen: progress.filesSeen,
+						found: progress.supportedFiles,
+					})
+					.catch(() => {});
+			},
+		);
+		const { filePaths, relativePaths, stats, groups } = discovery;
+		// Only the primary of each group (RAW+JPEG pair, Live Photo, burst) is processed,
+		// the other files are stacked under it
+		const stackedCount = groups.reduce(
+			(count, group) => count + group.members.length - 1,
+			0,
+		);
+		const totalCount = discovery.totalCount - stackedCount;
+		console.log(
+			`Found ${discovery.totalCount} photos in ${totalCount} stacks (parallel discovery)`,
+		);
+		for (const error of discovery.errors) {
+			console.warn(`⚠️ Skipped ${error.path}: ${error.reason}`);
+		}
 
 		await job.updateProgress({ phase: "discovery", total: totalCount });
 
@@ -47,11 +72,10 @@ const scanWorker = new Worker<ScanJobData>(
 		let completedCount = 0;
 
 		// Create a promise that resolves when all photos are processed
-		const processingComplete = new Promise<void>((resolve) => {
-			// Process all photos in parallel with streaming callback
-			// Rust calls this callback synchronously for each completed photo
-			// The Blocking mode means Rust waits for callback to return
-			processPhotosWithCallback(
+		const processingComplete = new Promise<void>((resolve, reject) => {
+			// Process all photos in parallel in the background
+			// Rust streams each result to this callback as soon as it completes
+			processPhotosStreaming(
 				filePaths,
 				relativePaths,
 				thumbnailsDir,
@@ -108,7 +132,9 @@ const scanWorker = new Worker<ScanJobData>(
 						}
 					})();
 				},
-			);
+				// Reuse the sizes and timestamps read during discovery
+				{ fileStats: stats, groups },
+			).catch(reject);
 
 			// Handle edge case of empty directory
 			if (totalCount === 0) {
@@ -209,7 +235,9 @@ const embeddingWorker = new Worker<BatchEmbeddingJobData>(
 			);
 
 			// Call Rust batch function
-			const embeddings = batchGenerateClipEmbeddings(thumbnailPaths);
+			// The model is returned with the embeddings, it can be switched while a batch runs
+			const { embeddings, model } = batchGenerateClipEmbeddings(thumbnailPaths);
+			const { modelVersion } = model;
 
 			// Save each embedding to database
 			for (let j = 0; j < batch.length; j++) {
@@ -224,7 +252,7 @@ const embeddingWorker = new Worker<BatchEmbeddingJobData>(
 					await db.insert(photoEmbedding).values({
 						photoId,
 						embedding: Buffer.from(new Float32Array(embedding).buffer),
-						modelVersion: "clip-vit-b32",
+						modelVersion,
 						createdAt: new Date(),
 					});
 					await db
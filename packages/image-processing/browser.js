@@ -6,15 +6,126 @@ const notAvailable = (name) => () => {
 	throw new Error(`${name}() is not available in the browser`);
 };
 
+module.exports.CancellationToken = class CancellationToken {
+	constructor() {
+		notAvailable("CancellationToken")();
+	}
+};
+module.exports.analyzeExposure = notAvailable("analyzeExposure");
 module.exports.batchGenerateClipEmbeddings = notAvailable("batchGenerateClipEmbeddings");
+module.exports.batchGenerateClipEmbeddingsAsync = notAvailable("batchGenerateClipEmbeddingsAsync");
+module.exports.batchGenerateClipEmbeddingsF32 = notAvailable("batchGenerateClipEmbeddingsF32");
+module.exports.batchGenerateClipEmbeddingsF32Async = notAvailable("batchGenerateClipEmbeddingsF32Async");
+module.exports.batchGenerateClipEmbeddingsQuantized = notAvailable("batchGenerateClipEmbeddingsQuantized");
+module.exports.batchGenerateClipEmbeddingsQuantizedAsync = notAvailable("batchGenerateClipEmbeddingsQuantizedAsync");
+module.exports.classifyImage = notAvailable("classifyImage");
+module.exports.clipEmbeddingFromBuffer = notAvailable("clipEmbeddingFromBuffer");
+module.exports.clipEmbeddingFromBufferF32 = notAvailable("clipEmbeddingFromBufferF32");
 module.exports.clipTextEmbedding = notAvailable("clipTextEmbedding");
+module.exports.clipTextEmbeddingAsync = notAvailable("clipTextEmbeddingAsync");
+module.exports.clipTextEmbeddingF32 = notAvailable("clipTextEmbeddingF32");
+module.exports.closeProcessingCache = notAvailable("closeProcessingCache");
+module.exports.clusterNearDuplicates = notAvailable("clusterNearDuplicates");
+module.exports.computeAutoEnhance = notAvailable("computeAutoEnhance");
+module.exports.computeContentHash = notAvailable("computeContentHash");
+module.exports.computeHashes = notAvailable("computeHashes");
+module.exports.configureEmbeddingPostprocessing = notAvailable("configureEmbeddingPostprocessing");
+module.exports.configureModels = notAvailable("configureModels");
+module.exports.configureNsfwModel = notAvailable("configureNsfwModel");
+module.exports.configureOcrModels = notAvailable("configureOcrModels");
+module.exports.configurePresenceModel = notAvailable("configurePresenceModel");
+module.exports.configureRuntime = notAvailable("configureRuntime");
+module.exports.configureTaggingModel = notAvailable("configureTaggingModel");
+module.exports.cosineSimilarity = notAvailable("cosineSimilarity");
+module.exports.decryptPrivateMetadata = notAvailable("decryptPrivateMetadata");
+module.exports.deleteThumbnails = notAvailable("deleteThumbnails");
+module.exports.dequantizeEmbedding = notAvailable("dequantizeEmbedding");
+module.exports.detectPresence = notAvailable("detectPresence");
 module.exports.discoverPhotos = notAvailable("discoverPhotos");
+module.exports.discoverPhotosAsync = notAvailable("discoverPhotosAsync");
+module.exports.exportPhoto = notAvailable("exportPhoto");
+module.exports.exportPhotoAsync = notAvailable("exportPhotoAsync");
 module.exports.extractExif = notAvailable("extractExif");
+module.exports.extractExifBatch = notAvailable("extractExifBatch");
+module.exports.extractExifThumbnail = notAvailable("extractExifThumbnail");
+module.exports.extractGainMap = notAvailable("extractGainMap");
+module.exports.extractHeifAuxiliaryImages = notAvailable("extractHeifAuxiliaryImages");
+module.exports.extractMotionPhotoVideo = notAvailable("extractMotionPhotoVideo");
+module.exports.extractPhotoMetadata = notAvailable("extractPhotoMetadata");
+module.exports.extractPhotoMetadataFromBuffer = notAvailable("extractPhotoMetadataFromBuffer");
+module.exports.extractPreviewForSize = notAvailable("extractPreviewForSize");
+module.exports.extractPreviewImage = notAvailable("extractPreviewImage");
+module.exports.extractText = notAvailable("extractText");
+module.exports.extractVideoMetadata = notAvailable("extractVideoMetadata");
+module.exports.findExactDuplicates = notAvailable("findExactDuplicates");
+module.exports.findExactDuplicatesAsync = notAvailable("findExactDuplicatesAsync");
+module.exports.findExposureBrackets = notAvailable("findExposureBrackets");
+module.exports.findNearDuplicates = notAvailable("findNearDuplicates");
+module.exports.findSimilarImages = notAvailable("findSimilarImages");
+module.exports.formatExifValues = notAvailable("formatExifValues");
+module.exports.generateHeifImageThumbnails = notAvailable("generateHeifImageThumbnails");
 module.exports.generatePhash = notAvailable("generatePhash");
+module.exports.generateThumbnailBuffers = notAvailable("generateThumbnailBuffers");
 module.exports.generateThumbnailsFromFile = notAvailable("generateThumbnailsFromFile");
+module.exports.generateTilesFromFile = notAvailable("generateTilesFromFile");
+module.exports.getAvailableFeatures = notAvailable("getAvailableFeatures");
+module.exports.getClipModelStatus = notAvailable("getClipModelStatus");
+module.exports.getEmbeddingModel = notAvailable("getEmbeddingModel");
+module.exports.getExecutionProvider = notAvailable("getExecutionProvider");
 module.exports.getSupportedExtensions = notAvailable("getSupportedExtensions");
+module.exports.hashFiles = notAvailable("hashFiles");
+module.exports.hashFilesAsync = notAvailable("hashFilesAsync");
 module.exports.isSupportedImage = notAvailable("isSupportedImage");
+module.exports.listHeifImages = notAvailable("listHeifImages");
+module.exports.mergeExposures = notAvailable("mergeExposures");
+module.exports.mergeExposuresAsync = notAvailable("mergeExposuresAsync");
+module.exports.needsPreviewExtraction = notAvailable("needsPreviewExtraction");
+module.exports.normalizeOrientation = notAvailable("normalizeOrientation");
+module.exports.nsfwScore = notAvailable("nsfwScore");
+module.exports.openProcessingCache = notAvailable("openProcessingCache");
 module.exports.perceptualHash = notAvailable("perceptualHash");
+module.exports.perceptualHashAsync = notAvailable("perceptualHashAsync");
+module.exports.perceptualHashBatch = notAvailable("perceptualHashBatch");
+module.exports.perceptualHashBatchAsync = notAvailable("perceptualHashBatchAsync");
+module.exports.perceptualHashFromBuffer = notAvailable("perceptualHashFromBuffer");
+module.exports.phashDistance = notAvailable("phashDistance");
+module.exports.pickBestShot = notAvailable("pickBestShot");
+module.exports.preloadClipModels = notAvailable("preloadClipModels");
+module.exports.processLivePhotoVideo = notAvailable("processLivePhotoVideo");
 module.exports.processPhoto = notAvailable("processPhoto");
 module.exports.processPhotosBatch = notAvailable("processPhotosBatch");
+module.exports.processPhotosBatchAsync = notAvailable("processPhotosBatchAsync");
+module.exports.processPhotosStreaming = notAvailable("processPhotosStreaming");
 module.exports.processPhotosWithCallback = notAvailable("processPhotosWithCallback");
+module.exports.pruneThumbnails = notAvailable("pruneThumbnails");
+module.exports.quantizeEmbedding = notAvailable("quantizeEmbedding");
+module.exports.quantizedSimilarity = notAvailable("quantizedSimilarity");
+module.exports.readColorSpace = notAvailable("readColorSpace");
+module.exports.readHeifMetadata = notAvailable("readHeifMetadata");
+module.exports.readXmpSidecar = notAvailable("readXmpSidecar");
+module.exports.renderEdits = notAvailable("renderEdits");
+module.exports.renderEditsAsync = notAvailable("renderEditsAsync");
+module.exports.resumePhotosBatch = notAvailable("resumePhotosBatch");
+module.exports.resumePhotosBatchAsync = notAvailable("resumePhotosBatchAsync");
+module.exports.robustPerceptualHash = notAvailable("robustPerceptualHash");
+module.exports.robustPhashDistance = notAvailable("robustPhashDistance");
+module.exports.runBenchmark = notAvailable("runBenchmark");
+module.exports.runSelfBenchmark = notAvailable("runSelfBenchmark");
+module.exports.scanPhotosMetadata = notAvailable("scanPhotosMetadata");
+module.exports.scanPhotosMetadataAsync = notAvailable("scanPhotosMetadataAsync");
+module.exports.setEmbeddingModel = notAvailable("setEmbeddingModel");
+module.exports.setExecutionProvider = notAvailable("setExecutionProvider");
+module.exports.setLogCallback = notAvailable("setLogCallback");
+module.exports.setMultilingualTextModel = notAvailable("setMultilingualTextModel");
+module.exports.tagPhoto = notAvailable("tagPhoto");
+module.exports.topKSimilar = notAvailable("topKSimilar");
+module.exports.topKSimilarQuantized = notAvailable("topKSimilarQuantized");
+module.exports.unloadClipModels = notAvailable("unloadClipModels");
+module.exports.unlockPrivatePhoto = notAvailable("unlockPrivatePhoto");
+module.exports.unwatchDirectory = notAvailable("unwatchDirectory");
+module.exports.videoClipEmbedding = notAvailable("videoClipEmbedding");
+module.exports.videoClipEmbeddingAsync = notAvailable("videoClipEmbeddingAsync");
+module.exports.videoPhash = notAvailable("videoPhash");
+module.exports.watchDirectory = notAvailable("watchDirectory");
+module.exports.writeExifFields = notAvailable("writeExifFields");
+module.exports.writeXmpSidecar = notAvailable("writeXmpSidecar");
@@ -0,0 +1,183 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{imageops::FilterType, AnimationDecoder, DynamicImage, RgbaImage};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::color::SrgbConverter;
+use crate::thumbnails::{resize_thumbnail, ThumbnailConfig, ThumbnailCrop};
+
+/// Number of frames sampled from the source animation
+const ANIMATED_FRAMES: usize = 8;
+
+/// Frames decoded at most, to bound the work on very long GIFs
+const MAX_DECODED_FRAMES: usize = 600;
+
+/// Browsers play GIF frames of 10ms or less at 100ms
+const DEFAULT_FRAME_MS: u32 = 100;
+
+/// Frames sampled from an animated GIF or WebP, shrunk for the largest animated size
+pub struct Animation {
+	/// Frames with their display duration in milliseconds
+	frames: Vec<(DynamicImage, u32)>,
+}
+
+impl Animation {
+	/// Render a looping animated WebP thumbnail, returning its width, height and encoded bytes
+	pub fn render(
+		&self,
+		config: &ThumbnailConfig,
+		srgb: Option<&SrgbConverter>,
+	) -> Result<(u32, u32, Vec<u8>), String> {
+		// A smart crop could pick a different square for every frame; frames aren't padded
+		let config = ThumbnailConfig {
+			crop: config.crop.map(|_| ThumbnailCrop::Center),
+			letterbox: None,
+			..*config
+		};
+		let frames: Vec<RgbaImage> = self
+			.frames
+			.iter()
+			.map(|(frame, _)| resize_thumbnail(frame, &config, srgb).to_rgba8())
+			.collect();
+		let (width, height) = frames[0].dimensions();
+
+		let mut webp_config = webp::WebPConfig::new()
+			.map_err(|_| "Failed to configure animated thumbnail encoder".to_string())?;
+		webp_config.quality = config.quality.min(100) as f32;
+
+		let mut encoder = webp::AnimEncoder::new(width, height, &webp_config);
+		encoder.set_loop_count(0);
+		let mut timestamp = 0;
+		for (pixels, (_, duration)) in frames.iter().zip(&self.frames) {
+			let frame = webp::AnimFrame::from_rgba(pixels.as_raw(), width, height, timestamp);
+			encoder.add_frame(frame);
+			timestamp += *duration as i32;
+		}
+		let data = encoder
+			.try_encode()
+			.map_err(|e| format!("Failed to encode animated thumbnail: {:?}", e))?;
+		Ok((width, height, data.to_vec()))
+	}
+}
+
+/// Decode an animated GIF or WebP and sample a few evenly spaced frames, shrunk for
+/// thumbnails up to `max_dimension`
+/// None for still images, other formats and files that fail to decode
+pub fn decode_animation(file_path: &str, max_dimension: u32) -> Option<Animation> {
+	let extension = Path::new(file_path).extension()?.to_str()?.to_lowercase();
+	let reader = BufReader::new(File::open(file_path).ok()?);
+	let frames = match extension.as_str() {
+		"gif" => GifDecoder::new(reader).ok()?.into_frames(),
+		"webp" => {
+			let decoder = WebPDecoder::new(reader).ok()?;
+			if !decoder.has_animation() {
+				return None;
+			}
+			decoder.into_frames()
+		}
+		_ => return None,
+	};
+
+	let mut images = Vec::new();
+	let mut durations = Vec::new();
+	// Keep the frames decoded before a broken one
+	for frame in frames.take(MAX_DECODED_FRAMES).map_while(Result::ok) {
+		let (numer, denom) = frame.delay().numer_denom_ms();
+		durations.push(match numer / denom.max(1) {
+			0..=10 => DEFAULT_FRAME_MS,
+			duration => duration,
+		});
+		images.push(shrink_frame(frame.into_buffer(), max_dimension));
+	}
+	if images.len() < 2 {
+		return None;
+	}
+
+	let frames = sample_frames(&durations, ANIMATED_FRAMES)
+		.into_iter()
+		.map(|(index, duration)| (images[index].clone(), duration))
+		.collect();
+	Some(Animation { frames })
+}
+
+/// Shrink a frame so its short side is at most `max_dimension`, leaving room for square crops
+fn shrink_frame(frame: RgbaImage, max_dimension: u32) -> DynamicImage {
+	let (width, height) = frame.dimensions();
+	let short_side = width.min(height);
+	let frame = DynamicImage::ImageRgba8(frame);
+	if short_side <= max_dimension {
+		return frame;
+	}
+
+	let scale = max_dimension as f64 / short_side as f64;
+	frame.resize_exact(
+		((width as f64 * scale).round() as u32).max(1),
+		((height as f64 * scale).round() as u32).max(1),
+		FilterType::Triangle,
+	)
+}
+
+/// Indices of `count` evenly spaced frames, each lasting until the next sampled one
+/// so the animation keeps its overall duration
+fn sample_frames(durations: &[u32], count: usize) -> Vec<(usize, u32)> {
+	let total = durations.len();
+	let count = count.min(total);
+	let starts: Vec<usize> = (0..count).map(|i| i * total / count).collect();
+
+	starts
+		.iter()
+		.enumerate()
+		.map(|(i, &start)| {
+			let end = starts.get(i + 1).copied().unwrap_or(total);
+			(start, durations[start..end].iter().sum())
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgba;
+
+	#[test]
+	fn test_sample_frames_keeps_duration() {
+		let samples = sample_frames(&[100; 20], 8);
+		assert_eq!(samples.len(), 8);
+		assert_eq!(samples[0], (0, 200));
+		assert_eq!(samples[1], (2, 300));
+		assert_eq!(samples.iter().map(|&(_, d)| d).sum::<u32>(), 2000);
+
+		// Short animations keep every frame
+		assert_eq!(
+			sample_frames(&[50, 70, 90], 8),
+			vec![(0, 50), (1, 70), (2, 90)]
+		);
+	}
+
+	#[test]
+	fn test_render_animated_webp() {
+		let frames = (0..3)
+			.map(|i| {
+				let frame = RgbaImage::from_pixel(300, 200, Rgba([i * 80, 0, 0, 255]));
+				(DynamicImage::ImageRgba8(frame), 100)
+			})
+			.collect();
+		let animation = Animation { frames };
+		let config = ThumbnailConfig {
+			max_dimension: 150,
+			quality: 80,
+			crop: None,
+			sharpen: None,
+			progressive: None,
+			animated: Some(true),
+			letterbox: None,
+		};
+
+		let (width, height, data) = animation.render(&config, None).unwrap();
+		assert_eq!((width, height), (150, 100));
+		assert_eq!(&data[..4], b"RIFF");
+		assert!(data.windows(4).any(|chunk| chunk == b"ANIM"));
+	}
+}
@@ -1,23 +1,69 @@
+use aes_gcm::Aes256Gcm;
 use image::ImageReader;
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
 use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+#[cfg(feature = "node")]
+use napi::{Env, Status, Task};
+#[cfg(feature = "node")]
 use napi_derive::napi;
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use crate::exif::{extract_exif_internal, ExifData};
-use crate::heif::{decode_heif, is_heif_by_magic_bytes, is_heif_file};
-use crate::orientation::apply_orientation;
+use crate::animation::decode_animation;
+use crate::cache::{
+	cached_photo, processing_cache_open, store_photo, CachedPhoto, CachedThumbnails,
+};
+use crate::cancellation::{is_cancelled, CancellationToken};
+use crate::clip::LabelScore;
+use crate::color::SrgbConverter;
+use crate::content_hash::{content_hash, content_hash_bytes};
+use crate::dates::{resolve_capture_date, DateSource};
+use crate::decode::{
+	decode_image_with_profile, decode_loaded_image, decode_poster_frame, source_kind,
+	source_kind_from_bytes, SourceKind,
+};
+use crate::discovery::{file_stat, AssetGroup, FileStat};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::{ErrorStage, ProcessingError};
+use crate::exif::{extract_exif_from_bytes, extract_exif_internal, ExifData};
+use crate::hdr::{read_gain_map, tone_map_hdr};
+use crate::heif::{
+	decode_heif_thumbnail, heif_color_profile, heif_dimensions_from_bytes,
+	read_heif_metadata_internal,
+};
+use crate::journal::{read_completed_paths, Journal};
+use crate::live_photo::find_live_photo_video;
+use crate::logging::{log_event, LogLevel};
+use crate::memory::{estimate_footprint_mb, MemoryBudget};
+use crate::nsfw::{has_nsfw_model, score_nsfw_image};
+use crate::ocr::{has_ocr_models, recognize_text};
+use crate::orientation::{apply_orientation, resolve_orientation};
+use crate::panorama::detect_panorama;
 use crate::phash::generate_phash_from_image;
-use crate::preview::{extract_preview, get_raw_format, is_raw_file};
-use crate::thumbnails::generate_all_thumbnails_internal;
-
-/// Standard image extensions (directly decodable by image crate)
-const STANDARD_EXTENSIONS: &[&str] = &[
-	".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp", ".tiff", ".tif",
-];
+use crate::presence::{detect_presence_image, has_presence_model};
+use crate::preview::{
+	extract_raw_preview, get_raw_format, is_motion_photo, is_motion_photo_data,
+	raw_thumbnail_from_bytes,
+};
+use crate::privacy::{encrypt_metadata, is_private_path, parse_key, PrivateMetadata};
+use crate::quality::{sharpness, SHARPNESS_SIZE};
+use crate::runtime::rayon_threads;
+use crate::screenshot::is_screenshot;
+use crate::tagging::{has_tagging_model, tag_image};
+use crate::thumbnails::{
+	generate_all_thumbnails_internal, generate_blurhash, reuse_thumbnails, ThumbnailFormat,
+	ThumbnailPlan, ThumbnailSize,
+};
+use crate::video::{get_video_mime_type, local_creation_date, probe_video, VideoMetadata};
+use crate::xmp::{find_sidecar, read_sidecar_internal, resolve_rating_and_label};
+use crate::Buffer;
 
 /// All supported extensions
 const ALL_EXTENSIONS: &[&str] = &[
@@ -28,46 +74,97 @@ const ALL_EXTENSIONS: &[&str] = &[
 	".3fr", ".iiq", ".rwl",
 	// HEIF
 	".heic", ".heif",
+	// Video
+	".mp4", ".mov", ".m4v", ".3gp", ".avi", ".mkv", ".webm", ".mts",
 ];
 
 /// Check if file is supported
-#[napi]
+#[cfg_attr(feature = "node", napi)]
 pub fn is_supported_image(file_path: String) -> bool {
 	let lower = file_path.to_lowercase();
 	ALL_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
 }
 
 /// Get all supported extensions
-#[napi]
+#[cfg_attr(feature = "node", napi)]
 pub fn get_supported_extensions() -> Vec<String> {
 	ALL_EXTENSIONS.iter().map(|s| s.to_string()).collect()
 }
 
 /// Unified result for any photo type
-#[napi(object)]
+#[cfg_attr(feature = "node", napi(object))]
 pub struct PhotoProcessingResult {
 	pub path: String,
 	pub name: String,
 	pub size: i64,
+	/// Hash of the file's bytes (BLAKE3, hex), for exact duplicates and moved files
+	/// Left out by metadata-only scans
+	pub content_hash: Option<String>,
 	pub created_at: f64,
 	pub modified_at: f64,
 	pub width: Option<u32>,
 	pub height: Option<u32>,
 	pub mime_type: Option<String>,
 	pub phash: Option<String>,
+	/// BlurHash placeholder to show while thumbnails load
+	pub blurhash: Option<String>,
+	/// Variance of the Laplacian at 512px; below ~100 the photo is usually blurry
+	pub sharpness: Option<f64>,
 	pub exif: Option<ExifData>,
 	pub is_raw: bool,
 	pub raw_format: Option<String>,
 	pub raw_status: Option<String>,
 	pub raw_error: Option<String>,
+	pub is_video: bool,
+	pub duration: Option<f64>, // video duration in seconds
+	pub is_private: bool,      // in a private folder: no thumbnails or embeddings
+	/// Container metadata of a video: codec, frame rate, recorded location
+	pub video: Option<VideoMetadata>,
+	/// Best available capture date (ISO 8601), see `date_source`
+	pub resolved_date: Option<String>,
+	pub resolved_date_ms: Option<f64>,
+	pub date_source: Option<DateSource>,
+	pub is_panorama: bool,
+	/// 360° equirectangular image that needs a spherical viewer
+	pub is_photosphere: bool,
+	/// GPano projection, e.g. "equirectangular"
+	pub projection_type: Option<String>,
+	/// Still of an Apple Live Photo, see `live_photo_video_path`
+	pub is_live_photo: bool,
+	/// Relative path of the Live Photo's paired video clip
+	pub live_photo_video_path: Option<String>,
+	/// Relative paths of the other files of the photo's group (see `BatchOptions.groups`),
+	/// stacked under it and not processed themselves
+	pub group_members: Option<Vec<String>>,
+	/// Android Motion Photo with a video clip embedded after the still
+	pub is_motion_photo: bool,
+	/// Looks like a screenshot (screen-sized, no camera EXIF, flat UI colors, screen name)
+	pub is_screenshot: bool,
+	/// Scene/object labels from the tagging model, best first (see `BatchOptions.max_tags`)
+	pub tags: Option<Vec<LabelScore>>,
+	/// Text recognized in the photo, in reading order (see `BatchOptions.extract_text`)
+	pub text: Option<String>,
+	/// Probability (0-1) that the photo is sensitive (see `BatchOptions.score_nsfw`)
+	pub nsfw_score: Option<f64>,
+	/// People and pets seen by the detection model (see `BatchOptions.detect_presence`)
+	pub has_people: Option<bool>,
+	pub people_count: Option<u32>,
+	pub has_pets: Option<bool>,
+	pub pet_count: Option<u32>,
+	pub encrypted_metadata: Option<String>,
 	pub success: bool,
 	pub error: Option<String>,
+	/// Code, stage and file of `error`, e.g. to retry files with an `Io` code later
+	pub error_detail: Option<ProcessingError>,
 }
 
-/// Check if file is a standard image (directly decodable)
-fn is_standard_image(file_path: &str) -> bool {
-	let lower = file_path.to_lowercase();
-	STANDARD_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+impl PhotoProcessingResult {
+	/// Mark the photo as failed
+	fn fail(&mut self, error: ProcessingError) {
+		self.success = false;
+		self.error = Some(error.message.clone());
+		self.error_detail = Some(error);
+	}
 }
 
 /// Get MIME type for a file
@@ -78,6 +175,10 @@ fn get_mime_type(file_path: &str, raw_format: &Option<String>, is_heif: bool) ->
 		return Some(format!("image/x-{}", fmt.to_lowercase()));
 	}
 
+	if let Some(mime) = get_video_mime_type(file_path) {
+		return Some(mime);
+	}
+
 	// Check if it's a HEIF file (by extension or magic bytes)
 	if lower.ends_with(".heic") || lower.ends_with(".heif") || is_heif {
 		return Some("image/heic".to_string());
@@ -87,34 +188,311 @@ fn get_mime_type(file_path: &str, raw_format: &Option<String>, is_heif: bool) ->
 	None // Will be set during decoding
 }
 
+/// MIME type of a standard image from its extension
+fn mime_type_from_extension(file_path: &str) -> String {
+	let lower = file_path.to_lowercase();
+	let mime_type = if lower.ends_with(".jpg") || lower.ends_with(".jpeg") {
+		"image/jpeg"
+	} else if lower.ends_with(".png") {
+		"image/png"
+	} else if lower.ends_with(".webp") {
+		"image/webp"
+	} else if lower.ends_with(".gif") {
+		"image/gif"
+	} else {
+		"image/unknown"
+	};
+	mime_type.to_string()
+}
+
+/// Dimensions of a photo as stored, read without decoding its pixels
+/// Videos aren't read here, their dimensions come from probing
+fn stored_dimensions(file_path: &str, kind: SourceKind) -> Option<(u32, u32)> {
+	let header_dimensions = |data: Vec<u8>| {
+		ImageReader::new(Cursor::new(data))
+			.with_guessed_format()
+			.ok()?
+			.into_dimensions()
+			.ok()
+	};
+	match kind {
+		SourceKind::Standard => ImageReader::open(file_path)
+			.and_then(|reader| reader.with_guessed_format())
+			.ok()?
+			.into_dimensions()
+			.ok(),
+		SourceKind::Heif => read_heif_metadata_internal(file_path)
+			.ok()
+			.map(|metadata| (metadata.width, metadata.height)),
+		SourceKind::RawPreview => header_dimensions(extract_raw_preview(file_path)?),
+		SourceKind::Video => None,
+	}
+}
+
+/// Dimensions of photo contents held in memory, read without decoding the pixels
+fn stored_dimensions_from_bytes(data: &[u8], kind: SourceKind) -> Option<(u32, u32)> {
+	let header_dimensions = |data: &[u8]| {
+		ImageReader::new(Cursor::new(data))
+			.with_guessed_format()
+			.ok()?
+			.into_dimensions()
+			.ok()
+	};
+	match kind {
+		SourceKind::Standard => header_dimensions(data),
+		SourceKind::Heif => heif_dimensions_from_bytes(data),
+		SourceKind::RawPreview => header_dimensions(&raw_thumbnail_from_bytes(data)?),
+		SourceKind::Video => None,
+	}
+}
+
+/// Set the upright dimensions and panorama flags of a result from its stored dimensions
+fn set_stored_dimensions(
+	result: &mut PhotoProcessingResult,
+	(width, height): (u32, u32),
+	orientation: Option<u32>,
+) {
+	let (width, height) = match orientation {
+		// 90° rotations swap width and height
+		Some(5..=8) => (height, width),
+		_ => (width, height),
+	};
+	let panorama = detect_panorama(width, height, result.exif.as_ref());
+	result.width = Some(width);
+	result.height = Some(height);
+	result.is_panorama = panorama.is_panorama;
+	result.is_photosphere = panorama.is_photosphere;
+	result.projection_type = panorama.projection_type;
+}
+
 /// Create error result
-fn error_result(path: &str, name: String, error: String) -> PhotoProcessingResult {
+fn error_result(path: &str, name: String, error: ProcessingError) -> PhotoProcessingResult {
 	PhotoProcessingResult {
 		path: path.to_string(),
 		name,
 		size: 0,
+		content_hash: None,
 		created_at: 0.0,
 		modified_at: 0.0,
 		width: None,
 		height: None,
 		mime_type: None,
 		phash: None,
+		blurhash: None,
+		sharpness: None,
 		exif: None,
 		is_raw: false,
 		raw_format: None,
 		raw_status: None,
 		raw_error: None,
+		is_video: false,
+		duration: None,
+		video: None,
+		resolved_date: None,
+		resolved_date_ms: None,
+		date_source: None,
+		is_panorama: false,
+		is_photosphere: false,
+		projection_type: None,
+		is_live_photo: false,
+		live_photo_video_path: None,
+		group_members: None,
+		is_motion_photo: false,
+		is_screenshot: false,
+		tags: None,
+		text: None,
+		nsfw_score: None,
+		has_people: None,
+		people_count: None,
+		has_pets: None,
+		pet_count: None,
+		is_private: false,
+		encrypted_metadata: None,
 		success: false,
-		error: Some(error),
+		error: Some(error.message.clone()),
+		error_detail: Some(error),
+	}
+}
+
+/// Optional analyses of the decoded photo, on top of thumbnails and hashes
+#[derive(Debug, Clone, Copy, Default)]
+struct AnalysisPlan {
+	/// Tags to keep from the tagging model, 0 to skip tagging
+	max_tags: u32,
+	/// Recognize text with the OCR models
+	extract_text: bool,
+	/// Score sensitive content with the NSFW model
+	score_nsfw: bool,
+	/// Count people and pets with the detection model
+	detect_presence: bool,
+}
+
+impl AnalysisPlan {
+	/// Whether any model runs on the photo
+	fn uses_models(&self) -> bool {
+		self.max_tags > 0 || self.extract_text || self.score_nsfw || self.detect_presence
 	}
 }
 
-/// Process a single photo (any type)
-fn process_photo_internal(
+/// Whether an embedded thumbnail of this size can stand in for the primary image of a HEIC
+/// photo: it renders every thumbnail size, scores the same sharpness, and no model runs
+fn thumbnail_suffices(
+	plan: &ThumbnailPlan,
+	analysis: &AnalysisPlan,
+	width: u32,
+	height: u32,
+) -> bool {
+	plan.all_fit(width, height)
+		&& width.max(height) >= SHARPNESS_SIZE
+		&& !plan.tone_map_hdr
+		&& !analysis.uses_models()
+}
+
+impl CachedPhoto {
+	/// Whether the cached analyses include everything the plan asks for
+	fn covers(&self, analysis: &AnalysisPlan) -> bool {
+		(analysis.max_tags == 0 || (self.tags.is_some() && self.tag_limit >= analysis.max_tags))
+			&& (!analysis.extract_text || self.text.is_some())
+			&& (!analysis.score_nsfw || self.nsfw_score.is_some())
+			&& (!analysis.detect_presence || self.has_people.is_some())
+	}
+}
+
+/// Whether the photo has thumbnails matching the plan, copying those of a moved or
+/// renamed file; always true without a thumbnails directory
+fn reuse_cached_thumbnails(
+	cached: &CachedPhoto,
+	content_hash: &str,
+	relative_path: &str,
+	thumbnails_dir: Option<&str>,
+	plan: &ThumbnailPlan,
+) -> bool {
+	let Some(thumbnails_dir) = thumbnails_dir else {
+		return true;
+	};
+	let Some(previous) = cached.thumbnails.as_ref().filter(|t| t.plan == plan.key()) else {
+		return false;
+	};
+	if !reuse_thumbnails(&previous.relative_path, relative_path, thumbnails_dir, plan) {
+		return false;
+	}
+	// Later moves copy from here, as the old path's thumbnails may be pruned
+	if previous.relative_path != relative_path {
+		let mut cached = cached.clone();
+		cached.thumbnails = Some(CachedThumbnails {
+			relative_path: relative_path.to_string(),
+			plan: previous.plan.clone(),
+		});
+		store_photo(content_hash, &cached);
+	}
+	true
+}
+
+/// Fill a result with cached contents results, keeping the analyses the plan asks for
+fn apply_cached(result: &mut PhotoProcessingResult, cached: &CachedPhoto, analysis: &AnalysisPlan) {
+	let (width, height) = (cached.width, cached.height);
+	if let (Some(width), Some(height)) = (width, height) {
+		let panorama = detect_panorama(width, height, result.exif.as_ref());
+		result.is_panorama = panorama.is_panorama;
+		result.is_photosphere = panorama.is_photosphere;
+		result.projection_type = panorama.projection_type;
+	}
+	result.width = width;
+	result.height = height;
+	result.phash = cached.phash.clone();
+	result.blurhash = cached.blurhash.clone();
+	result.sharpness = cached.sharpness;
+	result.is_screenshot = cached.is_screenshot;
+	result.tags = (analysis.max_tags > 0)
+		.then(|| cached.tags.clone())
+		.flatten()
+		.map(|tags| tags.into_iter().take(analysis.max_tags as usize).collect());
+	result.text = analysis.extract_text.then(|| cached.text.clone()).flatten();
+	result.nsfw_score = analysis.score_nsfw.then_some(cached.nsfw_score).flatten();
+	if analysis.detect_presence {
+		result.has_people = cached.has_people;
+		result.people_count = cached.people_count;
+		result.has_pets = cached.has_pets;
+		result.pet_count = cached.pet_count;
+	}
+}
+
+/// Cache entry for a processed photo, keeping analyses of an earlier entry this run skipped
+fn cache_entry(
+	result: &PhotoProcessingResult,
+	exif: Option<ExifData>,
+	analysis: &AnalysisPlan,
+	thumbnails: Option<CachedThumbnails>,
+	previous: Option<CachedPhoto>,
+) -> CachedPhoto {
+	let previous = previous.unwrap_or_default();
+	let (tags, tag_limit) = match &result.tags {
+		Some(tags) => (Some(tags.clone()), analysis.max_tags),
+		None => (previous.tags, previous.tag_limit),
+	};
+	let presence = match result.has_people {
+		Some(_) => (
+			result.has_people,
+			result.people_count,
+			result.has_pets,
+			result.pet_count,
+		),
+		None => (
+			previous.has_people,
+			previous.people_count,
+			previous.has_pets,
+			previous.pet_count,
+		),
+	};
+	CachedPhoto {
+		exif,
+		video: result.video.clone(),
+		width: result.width,
+		height: result.height,
+		phash: result.phash.clone(),
+		blurhash: result.blurhash.clone(),
+		sharpness: result.sharpness,
+		is_screenshot: result.is_screenshot,
+		tags,
+		tag_limit,
+		text: result.text.clone().or(previous.text),
+		nsfw_score: result.nsfw_score.or(previous.nsfw_score),
+		has_people: presence.0,
+		people_count: presence.1,
+		has_pets: presence.2,
+		pet_count: presence.3,
+		thumbnails,
+	}
+}
+
+/// What to do with a photo besides reading its metadata
+#[derive(Clone, Copy)]
+struct PhotoPlan<'a> {
+	/// Thumbnails are skipped without a directory
+	thumbnails_dir: Option<&'a str>,
+	thumbnails: &'a ThumbnailPlan,
+	/// Skip decoding, so no thumbnails or hashes, and read the dimensions from the header
+	metadata_only: bool,
+	analysis: AnalysisPlan,
+	/// Read and write the processing cache; off for private files, whose metadata must not
+	/// be kept in plaintext
+	use_cache: bool,
+}
+
+/// Process a single photo (any type) as the plan says
+fn process_with_plan(
 	file_path: &str,
 	relative_path: &str,
-	thumbnails_dir: &str,
+	stat: Option<FileStat>,
+	plan: &PhotoPlan,
 ) -> PhotoProcessingResult {
+	let PhotoPlan {
+		thumbnails_dir,
+		thumbnails,
+		metadata_only,
+		ref analysis,
+		use_cache,
+	} = *plan;
 	let path = Path::new(file_path);
 	let name = path
 		.file_name()
@@ -122,188 +500,1182 @@ fn process_photo_internal(
 		.to_string_lossy()
 		.to_string();
 
-	// Get file metadata
-	let metadata = match fs::metadata(file_path) {
-		Ok(m) => m,
-		Err(e) => return error_result(relative_path, name, format!("Failed to read file: {}", e)),
+	// Get file metadata, unless discovery already read it
+	let read_error = |e: io::Error| {
+		ProcessingError::io(&e, "Failed to read file")
+			.at(ErrorStage::Read)
+			.for_file(file_path)
+	};
+	let stat = match stat.map_or_else(|| fs::metadata(file_path).map(|m| file_stat(&m)), Ok) {
+		Ok(stat) => stat,
+		Err(e) => return error_result(relative_path, name, read_error(e)),
 	};
+	let FileStat {
+		size,
+		created_at,
+		modified_at,
+		..
+	} = stat;
 
-	let size = metadata.len() as i64;
-	let created_at = metadata
-		.created()
-		.ok()
-		.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-		.map(|d| d.as_millis() as f64)
-		.unwrap_or(0.0);
-	let modified_at = metadata
-		.modified()
-		.ok()
-		.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-		.map(|d| d.as_millis() as f64)
-		.unwrap_or(0.0);
+	// Pick the decoder - HEIF is detected by extension or magic bytes (handles mislabeled iOS files)
+	let kind = source_kind(file_path);
+	let is_heif = kind == Some(SourceKind::Heif);
+
+	let is_video = kind == Some(SourceKind::Video);
+
+	// JPEG, HEIC and other stills are read once, then hashed, checked for a Motion Photo and
+	// decoded from memory; RAW files and videos are hashed in chunks and decoded from disk
+	// Metadata-only scans don't read the whole file to hash it (nor use the cache keyed by
+	// the hash); processing the file later adds it
+	let in_memory = kind.is_some_and(SourceKind::decodes_from_memory);
+	let data = match (!metadata_only && in_memory).then(|| fs::read(file_path)) {
+		Some(Ok(data)) => Some(data),
+		Some(Err(e)) => return error_result(relative_path, name, read_error(e)),
+		None => None,
+	};
+	let content_hash = match (&data, metadata_only) {
+		(Some(data), _) => Some(content_hash_bytes(data)),
+		(None, false) => match content_hash(file_path) {
+			Ok(hash) => Some(hash),
+			Err(e) => return error_result(relative_path, name, read_error(e)),
+		},
+		(None, true) => None,
+	};
+	// Contents imported before (moved, renamed or copied files) reuse what was read from them
+	let cache_key = content_hash
+		.as_deref()
+		.filter(|_| use_cache && processing_cache_open());
+	let cached = cache_key.and_then(cached_photo);
 
 	// Determine if this is a RAW file
 	let raw_format = get_raw_format(file_path);
 	let is_raw = raw_format.is_some();
 
-	// Check for HEIF files - by extension or magic bytes (handles mislabeled iOS files)
-	let is_heif = is_heif_file(file_path) || is_heif_by_magic_bytes(file_path);
-
 	// Extract EXIF (works for all formats via exiftool)
-	let exif = extract_exif_internal(file_path);
-	let orientation = exif.as_ref().and_then(|e| e.orientation);
-
-	// Decode image based on file type
-	// Check magic bytes first to handle mislabeled HEIC files (e.g., iOS saving HEIC as .JPEG)
-	let decode_result = if is_heif {
-		// HEIC/HEIF: decode using libheif
-		decode_heif(file_path)
-	} else if is_raw_file(file_path) {
-		// RAW: extract embedded preview
-		match extract_preview(file_path) {
-			Some(preview_bytes) => {
-				ImageReader::new(Cursor::new(preview_bytes))
-					.with_guessed_format()
-					.map_err(|e| e.to_string())
-					.and_then(|reader| reader.decode().map_err(|e| e.to_string()))
-			}
-			None => Err("No embedded preview found".to_string()),
+	let mut exif = match &cached {
+		Some(cached) => cached.exif.clone(),
+		None => extract_exif_internal(file_path),
+	};
+	let embedded_exif = cache_key.and_then(|_| exif.clone());
+
+	// Sidecar edits (rating, keywords, fixed dates and GPS) take precedence over embedded values
+	let sidecar = find_sidecar(file_path).and_then(|p| read_sidecar_internal(&p));
+	if let Some(sidecar) = &sidecar {
+		sidecar.merge_into(exif.get_or_insert_with(ExifData::default));
+	}
+	if let Some(exif) = exif.as_mut() {
+		resolve_rating_and_label(exif, sidecar.as_ref());
+	}
+
+	// Videos: read container metadata, and fall back to its creation date in local time
+	let video_probe = match &cached {
+		Some(cached) => cached.video.clone(),
+		None => is_video.then(|| probe_video(file_path)).flatten(),
+	};
+	let duration = video_probe.as_ref().and_then(|p| p.duration);
+	if let Some(creation_date) = video_probe.as_ref().and_then(local_creation_date) {
+		let exif = exif.get_or_insert_with(ExifData::default);
+		if exif.date_taken.is_none() {
+			exif.set_date_taken(creation_date);
 		}
-	} else if is_standard_image(file_path) {
-		// Standard image: decode directly
-		ImageReader::open(file_path)
-			.map_err(|e| e.to_string())
-			.and_then(|reader| reader.decode().map_err(|e| e.to_string()))
-	} else {
-		Err("Unsupported file type".to_string())
+	}
+	if let Some(probe) = video_probe.as_ref().filter(|p| p.gps_latitude.is_some()) {
+		let exif = exif.get_or_insert_with(ExifData::default);
+		if exif.gps_latitude.is_none() {
+			exif.gps_latitude = probe.gps_latitude;
+			exif.gps_longitude = probe.gps_longitude;
+			exif.gps_altitude = exif.gps_altitude.or(probe.gps_altitude);
+		}
+	}
+
+	// Live Photo stills are paired with the clip sharing their content identifier
+	let live_photo_video_path = (!is_video)
+		.then(|| {
+			let content_identifier = exif.as_ref().and_then(|e| e.content_identifier.as_deref());
+			find_live_photo_video(file_path, relative_path, content_identifier)
+		})
+		.flatten();
+
+	// Android Motion Photos carry their clip inside the still
+	let is_motion_photo = !is_video
+		&& !is_raw
+		&& data
+			.as_deref()
+			.map_or_else(|| is_motion_photo(file_path), is_motion_photo_data);
+
+	// Best available capture date, down to filename patterns and the file's mtime
+	let resolved_date = resolve_capture_date(exif.as_ref(), &name, modified_at);
+
+	let mime_type = get_mime_type(file_path, &raw_format, is_heif);
+	let mut result = PhotoProcessingResult {
+		path: relative_path.to_string(),
+		name,
+		size,
+		content_hash: content_hash.clone(),
+		created_at,
+		modified_at,
+		// Videos still get their probed resolution when no frame can be extracted
+		width: video_probe.as_ref().and_then(|p| p.width),
+		height: video_probe.as_ref().and_then(|p| p.height),
+		mime_type: mime_type.clone(),
+		phash: None,
+		blurhash: None,
+		sharpness: None,
+		exif,
+		is_raw,
+		raw_format,
+		raw_status: None,
+		raw_error: None,
+		is_video,
+		duration,
+		video: video_probe,
+		resolved_date: resolved_date.as_ref().map(|d| d.date.clone()),
+		resolved_date_ms: resolved_date.as_ref().map(|d| d.date_ms),
+		date_source: resolved_date.as_ref().map(|d| d.source),
+		is_panorama: false,
+		is_photosphere: false,
+		projection_type: None,
+		is_live_photo: live_photo_video_path.is_some(),
+		live_photo_video_path,
+		group_members: None,
+		is_motion_photo,
+		is_screenshot: false,
+		tags: None,
+		text: None,
+		nsfw_score: None,
+		has_people: None,
+		people_count: None,
+		has_pets: None,
+		pet_count: None,
+		is_private: false,
+		encrypted_metadata: None,
+		success: true,
+		error: None,
+		error_detail: None,
 	};
+	// Standard images get their MIME type from the extension once they're known to decode
+	let mime_type = mime_type.or_else(|| Some(mime_type_from_extension(file_path)));
+
+	// Metadata only: read the dimensions from the header instead of decoding the pixels
+	if metadata_only {
+		let Some(kind) = kind else {
+			result.fail(ProcessingError::unsupported(file_path).at(ErrorStage::Metadata));
+			return result;
+		};
+		if let Some(dimensions) = stored_dimensions(file_path, kind) {
+			let orientation = resolve_orientation(
+				file_path,
+				kind,
+				result.exif.as_ref().and_then(|e| e.orientation),
+			);
+			set_stored_dimensions(&mut result, dimensions, orientation);
+		}
+		result.mime_type = mime_type;
+		return result;
+	}
+
+	// Skip decoding when the cache has everything asked for, thumbnails included
+	if let Some(cached) = cached.as_ref().filter(|cached| cached.covers(analysis))
+		&& let Some(cache_key) = cache_key
+		&& reuse_cached_thumbnails(cached, cache_key, relative_path, thumbnails_dir, thumbnails)
+	{
+		apply_cached(&mut result, cached, analysis);
+		result.mime_type = mime_type;
+		result.raw_status = is_raw.then(|| "converted".to_string());
+		return result;
+	}
+
+	// HEIC thumbnails render small sizes from the embedded thumbnail, which is only decoded
+	// when some size fits it; the primary image (48MP on recent iPhones) isn't decoded at all
+	// when the thumbnail suffices
+	let embedded = (is_heif && thumbnails_dir.is_some())
+		.then(|| {
+			decode_heif_thumbnail(file_path, |width, height| {
+				thumbnails.any_fits(width, height)
+			})
+		})
+		.flatten();
+	let (embedded, thumbnail_only) = match embedded {
+		Some(thumbnail)
+			if thumbnail_suffices(thumbnails, analysis, thumbnail.width(), thumbnail.height()) =>
+		{
+			(None, Some(thumbnail))
+		}
+		embedded => (embedded, None),
+	};
+	let primary_dimensions = thumbnail_only
+		.as_ref()
+		.and_then(|_| data.as_deref().and_then(heif_dimensions_from_bytes));
+
+	// Decode image based on file type (videos decode their poster frame)
+	let decode_result = match (kind, thumbnail_only) {
+		(_, Some(thumbnail)) => Ok((thumbnail, heif_color_profile(file_path))),
+		(Some(SourceKind::Video), None) => {
+			decode_poster_frame(file_path, duration).map(|img| (img, None))
+		}
+		(Some(kind), None) => match &data {
+			Some(data) => decode_loaded_image(file_path, data, kind),
+			None => decode_image_with_profile(file_path, kind),
+		},
+		(None, None) => Err(ProcessingError::unsupported(file_path)),
+	};
+	// Only the decoded pixels are needed from here on
+	drop(data);
 
 	// Process the decoded image
 	match decode_result {
-		Ok(img) => {
-			// Apply EXIF orientation
+		Ok((img, profile)) => {
+			let exif = result.exif.as_ref();
+			// Apply EXIF orientation (HEIF is already upright after decoding)
+			let orientation = kind.and_then(|kind| {
+				resolve_orientation(file_path, kind, exif.and_then(|e| e.orientation))
+			});
 			let img = apply_orientation(img, orientation);
-			let width = img.width();
-			let height = img.height();
-
-			// Generate phash
-			let phash = Some(generate_phash_from_image(&img));
+			let (width, height) = primary_dimensions.unwrap_or((img.width(), img.height()));
+			let panorama = detect_panorama(width, height, exif);
+			let srgb = profile
+				.filter(|_| thumbnails.color_manage)
+				.and_then(|profile| SrgbConverter::new(&profile));
 
 			// Generate thumbnails
-			if let Err(e) = generate_all_thumbnails_internal(&img, relative_path, thumbnails_dir) {
-				eprintln!("Warning: Failed to generate thumbnails: {}", e);
+			let mut thumbnails_generated = false;
+			if let Some(thumbnails_dir) = thumbnails_dir {
+				let animation = thumbnails
+					.animated_dimension()
+					.and_then(|max_dimension| decode_animation(file_path, max_dimension));
+				// HDR photos get thumbnails of their tone-mapped render
+				let hdr = kind
+					.filter(|_| thumbnails.tone_map_hdr)
+					.and_then(|kind| read_gain_map(file_path, kind))
+					.map(|gain_map| tone_map_hdr(&img, &gain_map.oriented(orientation)));
+				// The embedded thumbnail isn't tone mapped
+				let embedded = embedded.as_ref().filter(|_| hdr.is_none());
+				match generate_all_thumbnails_internal(
+					hdr.as_ref().unwrap_or(&img),
+					embedded,
+					relative_path,
+					thumbnails_dir,
+					thumbnails,
+					srgb.as_ref(),
+					animation.as_ref(),
+				) {
+					Ok(()) => thumbnails_generated = true,
+					Err(e) => {
+						let message = format!("Failed to generate thumbnails: {}", e);
+						log_event(LogLevel::Warn, "thumbnails", Some(file_path), message);
+					}
+				}
 			}
 
 			// Note: CLIP embeddings are generated in a batch job after scan completes
 			// This makes the initial scan ~3x faster
 
-			// Determine MIME type
-			let mime_type = get_mime_type(file_path, &raw_format, is_heif).or_else(|| {
-				let lower = file_path.to_lowercase();
-				if lower.ends_with(".jpg") || lower.ends_with(".jpeg") {
-					Some("image/jpeg".to_string())
-				} else if lower.ends_with(".png") {
-					Some("image/png".to_string())
-				} else if lower.ends_with(".webp") {
-					Some("image/webp".to_string())
-				} else if lower.ends_with(".gif") {
-					Some("image/gif".to_string())
-				} else {
-					Some("image/unknown".to_string())
-				}
-			});
+			// Hashes and analysis see wide-gamut photos in sRGB, like their thumbnails
+			let img = match &srgb {
+				Some(converter) => converter.convert(img),
+				None => img,
+			};
+
+			result.width = Some(width);
+			result.height = Some(height);
+			result.mime_type = mime_type;
+			result.phash = Some(generate_phash_from_image(&img));
+			result.blurhash = generate_blurhash(&img);
+			result.sharpness = Some(sharpness(&img));
+			result.tags = (analysis.max_tags > 0)
+				.then(|| match tag_image(&img, analysis.max_tags) {
+					Ok(tags) => Some(tags),
+					Err(e) => {
+						let message = format!("Failed to tag photo: {}", e);
+						log_event(LogLevel::Warn, "batch", Some(file_path), message);
+						None
+					}
+				})
+				.flatten();
+			result.text = analysis
+				.extract_text
+				.then(|| match recognize_text(&img) {
+					Ok(text) => Some(text.text),
+					Err(e) => {
+						let message = format!("Failed to extract text: {}", e);
+						log_event(LogLevel::Warn, "batch", Some(file_path), message);
+						None
+					}
+				})
+				.flatten();
+			result.is_screenshot =
+				!is_video && is_screenshot(&img, &result.name, exif, result.text.as_deref());
+			result.nsfw_score = analysis
+				.score_nsfw
+				.then(|| match score_nsfw_image(&img) {
+					Ok(score) => Some(score),
+					Err(e) => {
+						let message = format!("Failed to score NSFW content: {}", e);
+						log_event(LogLevel::Warn, "batch", Some(file_path), message);
+						None
+					}
+				})
+				.flatten();
+			let presence = analysis
+				.detect_presence
+				.then(|| match detect_presence_image(&img) {
+					Ok(counts) => Some(counts),
+					Err(e) => {
+						let message = format!("Failed to detect people and pets: {}", e);
+						log_event(LogLevel::Warn, "batch", Some(file_path), message);
+						None
+					}
+				})
+				.flatten();
+			result.has_people = presence.as_ref().map(|p| p.has_people);
+			result.people_count = presence.as_ref().map(|p| p.people_count);
+			result.has_pets = presence.as_ref().map(|p| p.has_pets);
+			result.pet_count = presence.as_ref().map(|p| p.pet_count);
+			result.raw_status = is_raw.then(|| "converted".to_string());
+			result.is_panorama = panorama.is_panorama;
+			result.is_photosphere = panorama.is_photosphere;
+			result.projection_type = panorama.projection_type;
 
-			PhotoProcessingResult {
-				path: relative_path.to_string(),
-				name,
-				size,
-				created_at,
-				modified_at,
-				width: Some(width),
-				height: Some(height),
-				mime_type,
-				phash,
-				exif,
-				is_raw,
-				raw_format,
-				raw_status: if is_raw {
-					Some("converted".to_string())
-				} else {
-					None
-				},
-				raw_error: None,
-				success: true,
-				error: None,
+			if let Some(cache_key) = cache_key {
+				let cached_thumbnails = match thumbnails_dir {
+					Some(_) => thumbnails_generated.then(|| CachedThumbnails {
+						relative_path: relative_path.to_string(),
+						plan: thumbnails.key(),
+					}),
+					None => cached.as_ref().and_then(|cached| cached.thumbnails.clone()),
+				};
+				let entry =
+					cache_entry(&result, embedded_exif, analysis, cached_thumbnails, cached);
+				store_photo(cache_key, &entry);
 			}
+			result
 		}
 		Err(e) => {
-			let mime_type = get_mime_type(file_path, &raw_format, is_heif);
-
-			PhotoProcessingResult {
-				path: relative_path.to_string(),
-				name,
-				size,
-				created_at,
-				modified_at,
-				width: None,
-				height: None,
-				mime_type,
-				phash: None,
-				exif,
-				is_raw,
-				raw_format,
-				raw_status: if is_raw {
-					Some("failed".to_string())
-				} else {
-					None
-				},
-				raw_error: if is_raw { Some(e.clone()) } else { None },
-				success: false,
-				error: Some(e),
-			}
+			result.raw_status = is_raw.then(|| "failed".to_string());
+			result.raw_error = is_raw.then(|| e.message.clone());
+			result.fail(e.at(ErrorStage::Decode).for_file(file_path));
+			result
 		}
 	}
 }
 
+/// Default number of files processed at once when no memory budget is given
+pub(crate) const DEFAULT_MAX_CONCURRENT: usize = 4;
+
+/// Options for the batch processing functions
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct BatchOptions {
+	/// Cap on the estimated memory of files processed at once (in MB)
+	/// When set, small JPEGs run on every core while large RAW files are throttled
+	pub memory_budget_mb: Option<u32>,
+	/// Append each finished file to this ndjson journal so an interrupted import can resume
+	pub journal_path: Option<String>,
+	/// Relative folders whose files get no thumbnails and only encrypted metadata
+	pub private_folders: Option<Vec<String>>,
+	/// 32-byte key for the metadata of private files; without it none is kept
+	pub private_metadata_key: Option<Buffer>,
+	/// Thumbnail format (default from `configure_runtime`, else WebP); AVIF applies to the
+	/// tiny and small sizes
+	pub thumbnail_format: Option<ThumbnailFormat>,
+	/// Thumbnail sizes to generate instead of tiny/small/medium/large
+	pub thumbnail_sizes: Option<Vec<ThumbnailSize>>,
+	/// Convert wide-gamut photos (Display P3, Adobe RGB) to sRGB for thumbnails, hashes and
+	/// analysis (default true)
+	pub color_manage_thumbnails: Option<bool>,
+	/// Tone map the gain map of HDR photos into their thumbnails (default false)
+	pub tone_map_hdr_thumbnails: Option<bool>,
+	/// Size and timestamps of each file from discovery, in the order of the file paths,
+	/// so they aren't read again
+	pub file_stats: Option<Vec<FileStat>>,
+	/// Groups from discovery (RAW+JPEG pairs, Live Photos, bursts): only the primary of each
+	/// is processed, its result listing the rest in `groupMembers`
+	pub groups: Option<Vec<AssetGroup>>,
+	/// Only read metadata: skip decoding, thumbnails and hashes (default false)
+	pub metadata_only: Option<bool>,
+	/// Tag each photo with up to this many scene/object labels from the model set with
+	/// `configure_tagging_model` (default 0: no tagging)
+	pub max_tags: Option<u32>,
+	/// Recognize text in each photo (screenshots, receipts, whiteboards) with the models set
+	/// with `configure_ocr_models` (default false)
+	pub extract_text: Option<bool>,
+	/// Score each photo for sensitive content with the model set with
+	/// `configure_nsfw_model`, so shared and kid-safe views can filter it (default false)
+	pub score_nsfw: Option<bool>,
+	/// Count the people and pets in each photo with the model set with
+	/// `configure_presence_model`, for smart albums like "photos of pets" (default false)
+	pub detect_presence: Option<bool>,
+}
+
+/// Build the rayon pool for a batch, with the threads set by `configure_runtime` if any
+/// With a memory budget the pool uses every core and the budget limits in-flight work
+fn build_pool(options: &BatchOptions) -> rayon::ThreadPool {
+	let max_concurrent = if let Some(threads) = rayon_threads() {
+		threads
+	} else if options.memory_budget_mb.is_some() {
+		num_cpus::get()
+	} else {
+		std::cmp::min(num_cpus::get(), DEFAULT_MAX_CONCURRENT)
+	};
+
+	rayon::ThreadPoolBuilder::new()
+		.num_threads(max_concurrent)
+		.build()
+		.unwrap_or_else(|_| rayon::ThreadPoolBuilder::new().build().unwrap())
+}
+
+/// Shared state for the files of one batch
+struct BatchContext {
+	thumbnails_dir: String,
+	budget: Option<MemoryBudget>,
+	journal: Option<Journal>,
+	private_folders: Vec<String>,
+	private_cipher: Option<Aes256Gcm>,
+	thumbnails: ThumbnailPlan,
+	file_stats: Vec<FileStat>,
+	/// Other members of each group, by the index of its primary
+	group_members: HashMap<usize, Vec<usize>>,
+	/// Files stacked under a primary, which aren't processed
+	stacked: HashSet<usize>,
+	metadata_only: bool,
+	analysis: AnalysisPlan,
+	cancellation: Option<CancellationToken>,
+}
+
+impl BatchContext {
+	fn new(
+		thumbnails_dir: String,
+		options: &BatchOptions,
+		cancellation: Option<&CancellationToken>,
+	) -> Result<Self, ProcessingError> {
+		let journal = options
+			.journal_path
+			.as_deref()
+			.map(Journal::open)
+			.transpose()?;
+		let private_cipher = options
+			.private_metadata_key
+			.as_deref()
+			.map(parse_key)
+			.transpose()?;
+		let mut thumbnails =
+			ThumbnailPlan::new(options.thumbnail_sizes.clone(), options.thumbnail_format)?;
+		thumbnails.color_manage = options.color_manage_thumbnails.unwrap_or(true);
+		thumbnails.tone_map_hdr = options.tone_map_hdr_thumbnails.unwrap_or(false);
+		let analysis = AnalysisPlan {
+			max_tags: options.max_tags.unwrap_or(0),
+			extract_text: options.extract_text.unwrap_or(false),
+			score_nsfw: options.score_nsfw.unwrap_or(false),
+			detect_presence: options.detect_presence.unwrap_or(false),
+		};
+		if analysis.max_tags > 0 && !has_tagging_model() {
+			return Err(ProcessingError::invalid(
+				"Tagging needs a model, set one with configure_tagging_model",
+			));
+		}
+		if analysis.extract_text && !has_ocr_models() {
+			return Err(ProcessingError::invalid(
+				"Text extraction needs OCR models, set them with configure_ocr_models",
+			));
+		}
+		if analysis.score_nsfw && !has_nsfw_model() {
+			return Err(ProcessingError::invalid(
+				"NSFW scoring needs a model, set one with configure_nsfw_model",
+			));
+		}
+		if analysis.detect_presence && !has_presence_model() {
+			return Err(ProcessingError::invalid(
+				"Person/pet detection needs a model, set one with configure_presence_model",
+			));
+		}
+
+		let mut group_members = HashMap::new();
+		let mut stacked = HashSet::new();
+		for group in options.groups.iter().flatten() {
+			let primary = group.primary as usize;
+			let others: Vec<usize> = group
+				.members
+				.iter()
+				.map(|&member| member as usize)
+				.filter(|&member| member != primary)
+				.collect();
+			stacked.extend(others.iter().copied());
+			group_members.insert(primary, others);
+		}
+
+		Ok(Self {
+			thumbnails_dir,
+			budget: options
+				.memory_budget_mb
+				.map(|mb| MemoryBudget::new(mb as u64)),
+			journal,
+			private_folders: options.private_folders.clone().unwrap_or_default(),
+			private_cipher,
+			thumbnails,
+			file_stats: options.file_stats.clone().unwrap_or_default(),
+			group_members,
+			stacked,
+			metadata_only: options.metadata_only.unwrap_or(false),
+			analysis,
+			cancellation: cancellation.cloned(),
+		})
+	}
+
+	/// Whether the batch was cancelled, so files not started yet are skipped
+	fn is_cancelled(&self) -> bool {
+		is_cancelled(self.cancellation.as_ref())
+	}
+
+	/// Process the file at `index` of a batch unless it's stacked under its group's primary,
+	/// listing the other files of its group in the result
+	fn process_at(
+		&self,
+		index: usize,
+		file_paths: &[String],
+		relative_paths: &[String],
+	) -> Option<PhotoProcessingResult> {
+		if self.stacked.contains(&index) {
+			return None;
+		}
+		let relative_path = |i: usize| relative_paths.get(i).map(|s| s.as_str()).unwrap_or("");
+		let mut result = self.process(index, &file_paths[index], relative_path(index));
+		result.group_members = self.group_members.get(&index).map(|members| {
+			members
+				.iter()
+				.map(|&member| relative_path(member).to_string())
+				.collect()
+		});
+		Some(result)
+	}
+
+	/// Process the photo at `index` of a batch, reserving its estimated footprint from the
+	/// budget first and journaling it once finished
+	fn process(&self, index: usize, file_path: &str, relative_path: &str) -> PhotoProcessingResult {
+		let stat = self.file_stats.get(index).copied();
+		let _reservation = self.budget.as_ref().map(|b| {
+			let cost_mb = estimate_footprint_mb(file_path, source_kind(file_path));
+			b.acquire(cost_mb)
+		});
+
+		let result = if is_private_path(relative_path, &self.private_folders) {
+			let plan = PhotoPlan {
+				thumbnails_dir: None,
+				thumbnails: &self.thumbnails,
+				metadata_only: self.metadata_only,
+				// Private files are only analyzed once unlocked
+				analysis: AnalysisPlan::default(),
+				use_cache: false,
+			};
+			let result = process_with_plan(file_path, relative_path, stat, &plan);
+			seal_private_result(result, self.private_cipher.as_ref())
+		} else {
+			let plan = PhotoPlan {
+				thumbnails_dir: Some(&self.thumbnails_dir),
+				thumbnails: &self.thumbnails,
+				metadata_only: self.metadata_only,
+				analysis: self.analysis,
+				use_cache: true,
+			};
+			process_with_plan(file_path, relative_path, stat, &plan)
+		};
+
+		if let Some(journal) = &self.journal {
+			journal.record(file_path, relative_path, result.success);
+		}
+		result
+	}
+}
+
+/// Strip the metadata of a private file, keeping it only in encrypted form
+fn seal_private_result(
+	mut result: PhotoProcessingResult,
+	cipher: Option<&Aes256Gcm>,
+) -> PhotoProcessingResult {
+	let metadata = PrivateMetadata {
+		width: result.width.take(),
+		height: result.height.take(),
+		mime_type: result.mime_type.take(),
+		phash: result.phash.take(),
+		exif: result.exif.take(),
+		duration: result.duration.take(),
+		video: result.video.take(),
+	};
+	// The resolved date usually comes from EXIF, which is sealed above
+	result.resolved_date = None;
+	result.resolved_date_ms = None;
+	result.date_source = None;
+	// So is what the pixels give away about the photo
+	result.blurhash = None;
+	result.sharpness = None;
+	result.is_screenshot = false;
+
+	result.is_private = true;
+	result.encrypted_metadata =
+		cipher.and_then(|cipher| match encrypt_metadata(&metadata, cipher) {
+			Ok(encrypted) => Some(encrypted),
+			Err(e) => {
+				log_event(
+					LogLevel::Error,
+					"batch",
+					Some(&result.path),
+					format!("Failed to encrypt private metadata: {}", e),
+				);
+				None
+			}
+		});
+	result
+}
+
+/// Process every file of a batch on its pool, collecting the results
+fn process_all(
+	context: &BatchContext,
+	pool: &rayon::ThreadPool,
+	file_paths: &[String],
+	relative_paths: &[String],
+) -> Vec<PhotoProcessingResult> {
+	pool.install(|| {
+		(0..file_paths.len())
+			.into_par_iter()
+			.filter(|_| !context.is_cancelled())
+			.filter_map(|i| context.process_at(i, file_paths, relative_paths))
+			.collect()
+	})
+}
+
+/// Process every file of a batch on its pool, handing each result to `on_result` as soon as
+/// it is ready; returns the number of files processed
+fn process_each(
+	context: &BatchContext,
+	pool: &rayon::ThreadPool,
+	file_paths: &[String],
+	relative_paths: &[String],
+	on_result: impl Fn(PhotoProcessingResult) + Sync,
+) -> u32 {
+	let count = AtomicU32::new(0);
+	pool.install(|| {
+		(0..file_paths.len())
+			.into_par_iter()
+			.filter(|_| !context.is_cancelled())
+			.filter_map(|i| context.process_at(i, file_paths, relative_paths))
+			.for_each(|result| {
+				count.fetch_add(1, Ordering::Relaxed);
+				on_result(result);
+			});
+	});
+	count.into_inner()
+}
+
+/// Process a batch of photos in parallel, see `process_photos_batch`
+pub fn process_photos_batch_internal(
+	file_paths: &[String],
+	relative_paths: &[String],
+	thumbnails_dir: &str,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> Result<Vec<PhotoProcessingResult>, ProcessingError> {
+	let options = options.unwrap_or_default();
+	let context = BatchContext::new(thumbnails_dir.to_string(), &options, cancellation)?;
+	let pool = build_pool(&options);
+
+	Ok(process_all(&context, &pool, file_paths, relative_paths))
+}
+
 /// Process a batch of photos in parallel
+/// Blocks the JS thread until done, see `process_photos_batch_async` to cancel a batch
+#[cfg(feature = "node")]
 #[napi]
 pub fn process_photos_batch(
 	file_paths: Vec<String>,
 	relative_paths: Vec<String>,
 	thumbnails_dir: String,
-) -> Vec<PhotoProcessingResult> {
-	let max_concurrent = std::cmp::min(num_cpus::get(), 4);
+	options: Option<BatchOptions>,
+) -> napi::Result<Vec<PhotoProcessingResult>, ErrorCode> {
+	process_photos_batch_internal(&file_paths, &relative_paths, &thumbnails_dir, options, None)
+		.map_err(napi::Error::from)
+}
 
-	let pool = rayon::ThreadPoolBuilder::new()
-		.num_threads(max_concurrent)
-		.build()
-		.unwrap_or_else(|_| rayon::ThreadPoolBuilder::new().build().unwrap());
+/// Processes a batch off the JS thread, resolving with every result at once
+#[cfg(feature = "node")]
+pub struct ProcessBatchTask {
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	context: BatchContext,
+	pool: rayon::ThreadPool,
+}
 
-	pool.install(|| {
-		file_paths
-			.par_iter()
-			.enumerate()
-			.map(|(i, path)| {
-				let rel_path = relative_paths.get(i).map(|s| s.as_str()).unwrap_or("");
-				process_photo_internal(path, rel_path, &thumbnails_dir)
+#[cfg(feature = "node")]
+impl Task for ProcessBatchTask {
+	type Output = Vec<PhotoProcessingResult>;
+	type JsValue = Vec<PhotoProcessingResult>;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		Ok(process_all(
+			&self.context,
+			&self.pool,
+			&self.file_paths,
+			&self.relative_paths,
+		))
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+}
+
+/// Process a batch like `process_photos_batch`, off the JS thread so an Electron app stays
+/// responsive during imports; options are validated before the promise is returned
+/// Once `cancellation` is cancelled, only the results of files already started are returned
+#[cfg(feature = "node")]
+#[napi]
+pub fn process_photos_batch_async(
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	thumbnails_dir: String,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> napi::Result<AsyncTask<ProcessBatchTask>, ErrorCode> {
+	let options = options.unwrap_or_default();
+	let context = BatchContext::new(thumbnails_dir, &options, cancellation)?;
+
+	Ok(AsyncTask::new(ProcessBatchTask {
+		file_paths,
+		relative_paths,
+		context,
+		pool: build_pool(&options),
+	}))
+}
+
+/// File names as relative paths, and options of a metadata-only batch without a journal
+#[cfg(feature = "node")]
+fn metadata_scan(
+	file_paths: &[String],
+	relative_paths: Option<Vec<String>>,
+	options: Option<BatchOptions>,
+) -> (Vec<String>, BatchOptions) {
+	let relative_paths =
+		relative_paths.unwrap_or_else(|| file_paths.iter().map(|path| file_name(path)).collect());
+	let options = BatchOptions {
+		metadata_only: Some(true),
+		journal_path: None,
+		..options.unwrap_or_default()
+	};
+	(relative_paths, options)
+}
+
+/// Read the metadata of photos in parallel, like `extract_photo_metadata`: file stats, EXIF,
+/// dates and dimensions from the headers, without decoding, hashes or thumbnails
+/// Quickly fills the library list; processing the files later adds the rest
+/// `relative_paths` default to the file names; journaling is skipped
+#[cfg(feature = "node")]
+#[napi]
+pub fn scan_photos_metadata(
+	file_paths: Vec<String>,
+	relative_paths: Option<Vec<String>>,
+	options: Option<BatchOptions>,
+) -> napi::Result<Vec<PhotoProcessingResult>, ErrorCode> {
+	let (relative_paths, options) = metadata_scan(&file_paths, relative_paths, options);
+	process_photos_batch(file_paths, relative_paths, String::new(), Some(options))
+}
+
+/// Same as `scan_photos_metadata`, off the JS thread
+/// Once `cancellation` is cancelled, only the results of files already started are returned
+#[cfg(feature = "node")]
+#[napi]
+pub fn scan_photos_metadata_async(
+	file_paths: Vec<String>,
+	relative_paths: Option<Vec<String>>,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> napi::Result<AsyncTask<ProcessBatchTask>, ErrorCode> {
+	let (relative_paths, options) = metadata_scan(&file_paths, relative_paths, options);
+	process_photos_batch_async(
+		file_paths,
+		relative_paths,
+		String::new(),
+		Some(options),
+		cancellation,
+	)
+}
+
+/// Check that the stats and groups of a batch's options refer to its files
+fn validate_batch_indices(
+	file_count: usize,
+	options: &BatchOptions,
+) -> Result<(), ProcessingError> {
+	let stat_count = options.file_stats.as_ref().map_or(file_count, Vec::len);
+	if stat_count != file_count {
+		return Err(ProcessingError::invalid(format!(
+			"{} file stats given for {} files",
+			stat_count, file_count
+		)));
+	}
+	let indices = options
+		.groups
+		.iter()
+		.flatten()
+		.flat_map(|group| std::iter::once(group.primary).chain(group.members.iter().copied()));
+	for index in indices {
+		if index as usize >= file_count {
+			return Err(ProcessingError::invalid(format!(
+				"Group index {} out of range for {} files",
+				index, file_count
+			)));
+		}
+	}
+	Ok(())
+}
+
+/// Files of a batch its journal doesn't mark as done, with their relative paths, and the
+/// options for them: journaling to `journal_path`, with stats and groups kept aligned
+fn resume_plan(
+	file_paths: &[String],
+	relative_paths: &[String],
+	journal_path: &str,
+	options: Option<BatchOptions>,
+) -> Result<(Vec<String>, Vec<String>, BatchOptions), ProcessingError> {
+	let mut options = options.unwrap_or_default();
+	validate_batch_indices(file_paths.len(), &options)?;
+	let completed = read_completed_paths(journal_path);
+
+	// The files stacked under a primary are done along with it
+	let is_done = |i: usize| completed.contains(&file_paths[i]);
+	let done_with_primary: HashSet<usize> = options
+		.groups
+		.iter()
+		.flatten()
+		.filter(|group| is_done(group.primary as usize))
+		.flat_map(|group| group.members.iter().map(|&member| member as usize))
+		.collect();
+	let remaining: Vec<usize> = (0..file_paths.len())
+		.filter(|&i| !is_done(i) && !done_with_primary.contains(&i))
+		.collect();
+	let relative_paths: Vec<String> = remaining
+		.iter()
+		.map(|&i| relative_paths.get(i).cloned().unwrap_or_default())
+		.collect();
+	// Keep the stats aligned with the remaining files
+	let file_stats = options
+		.file_stats
+		.take()
+		.map(|stats| remaining.iter().map(|&i| stats[i]).collect());
+	// And the groups with the remaining files, renumbered; those of a done primary are done
+	let position: HashMap<usize, u32> = remaining
+		.iter()
+		.enumerate()
+		.map(|(new, &old)| (old, new as u32))
+		.collect();
+	let groups = options.groups.take().map(|groups| {
+		groups
+			.into_iter()
+			.filter_map(|group| {
+				Some(AssetGroup {
+					primary: *position.get(&(group.primary as usize))?,
+					members: group
+						.members
+						.iter()
+						.filter_map(|&member| position.get(&(member as usize)).copied())
+						.collect(),
+					kind: group.kind,
+				})
 			})
 			.collect()
-	})
+	});
+	let file_paths: Vec<String> = remaining.iter().map(|&i| file_paths[i].clone()).collect();
+
+	let options = BatchOptions {
+		journal_path: Some(journal_path.to_string()),
+		file_stats,
+		groups,
+		..options
+	};
+	Ok((file_paths, relative_paths, options))
+}
+
+/// Resume an interrupted batch from its journal, see `resume_photos_batch`
+pub fn resume_photos_batch_internal(
+	file_paths: &[String],
+	relative_paths: &[String],
+	thumbnails_dir: &str,
+	journal_path: &str,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> Result<Vec<PhotoProcessingResult>, ProcessingError> {
+	let (file_paths, relative_paths, options) =
+		resume_plan(file_paths, relative_paths, journal_path, options)?;
+	process_photos_batch_internal(
+		&file_paths,
+		&relative_paths,
+		thumbnails_dir,
+		Some(options),
+		cancellation,
+	)
+}
+
+/// Resume an interrupted batch from its journal
+/// Files the journal marks as successfully processed are skipped; new results are appended to it
+#[cfg(feature = "node")]
+#[napi]
+pub fn resume_photos_batch(
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	thumbnails_dir: String,
+	journal_path: String,
+	options: Option<BatchOptions>,
+) -> napi::Result<Vec<PhotoProcessingResult>, ErrorCode> {
+	resume_photos_batch_internal(
+		&file_paths,
+		&relative_paths,
+		&thumbnails_dir,
+		&journal_path,
+		options,
+		None,
+	)
+	.map_err(napi::Error::from)
+}
+
+/// Same as `resume_photos_batch`, off the JS thread
+/// Once `cancellation` is cancelled, only the results of files already started are returned
+#[cfg(feature = "node")]
+#[napi]
+pub fn resume_photos_batch_async(
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	thumbnails_dir: String,
+	journal_path: String,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> napi::Result<AsyncTask<ProcessBatchTask>, ErrorCode> {
+	let (file_paths, relative_paths, options) =
+		resume_plan(&file_paths, &relative_paths, &journal_path, options)?;
+	process_photos_batch_async(
+		file_paths,
+		relative_paths,
+		thumbnails_dir,
+		Some(options),
+		cancellation,
+	)
+}
+
+/// Process a single photo with its thumbnails, see `process_photo`
+pub fn process_photo_internal(
+	file_path: &str,
+	relative_path: &str,
+	thumbnails_dir: &str,
+	thumbnail_format: Option<ThumbnailFormat>,
+	thumbnail_sizes: Option<Vec<ThumbnailSize>>,
+) -> Result<PhotoProcessingResult, ProcessingError> {
+	let thumbnails = ThumbnailPlan::new(thumbnail_sizes, thumbnail_format)?;
+	let plan = PhotoPlan {
+		thumbnails_dir: Some(thumbnails_dir),
+		thumbnails: &thumbnails,
+		metadata_only: false,
+		analysis: AnalysisPlan::default(),
+		use_cache: true,
+	};
+	Ok(process_with_plan(file_path, relative_path, None, &plan))
 }
 
 /// Process a single photo
+#[cfg(feature = "node")]
 #[napi]
 pub fn process_photo(
 	file_path: String,
 	relative_path: String,
 	thumbnails_dir: String,
+	thumbnail_format: Option<ThumbnailFormat>,
+	thumbnail_sizes: Option<Vec<ThumbnailSize>>,
+) -> napi::Result<PhotoProcessingResult, ErrorCode> {
+	process_photo_internal(
+		&file_path,
+		&relative_path,
+		&thumbnails_dir,
+		thumbnail_format,
+		thumbnail_sizes,
+	)
+	.map_err(napi::Error::from)
+}
+
+fn file_name(file_path: &str) -> String {
+	Path::new(file_path)
+		.file_name()
+		.unwrap_or_default()
+		.to_string_lossy()
+		.to_string()
+}
+
+/// Read the metadata of a photo (EXIF, dates, dimensions, Live/Motion Photo pairing) the
+/// way processing does, without decoding it or generating thumbnails
+/// `relative_path` names the result and locates a Live Photo's clip (default: the file name)
+#[cfg_attr(feature = "node", napi)]
+pub fn extract_photo_metadata(
+	file_path: String,
+	relative_path: Option<String>,
+) -> PhotoProcessingResult {
+	let relative_path = relative_path.unwrap_or_else(|| file_name(&file_path));
+	let thumbnails = ThumbnailPlan::default();
+	let plan = PhotoPlan {
+		thumbnails_dir: None,
+		thumbnails: &thumbnails,
+		metadata_only: true,
+		analysis: AnalysisPlan::default(),
+		use_cache: true,
+	};
+	process_with_plan(&file_path, &relative_path, None, &plan)
+}
+
+/// Read the metadata of a photo held in memory, e.g. one fetched from a cloud provider,
+/// like `extract_photo_metadata` does for a file, without writing it to a temp file
+/// `virtual_path` names the result and gives its extension; as nothing is on disk there are
+/// no file dates, sidecars or Live Photo pairing, and videos aren't probed
+#[cfg_attr(feature = "node", napi)]
+pub fn extract_photo_metadata_from_buffer(
+	data: Buffer,
+	virtual_path: String,
 ) -> PhotoProcessingResult {
-	process_photo_internal(&file_path, &relative_path, &thumbnails_dir)
+	let data: &[u8] = &data;
+	let name = file_name(&virtual_path);
+	let raw_format = get_raw_format(&virtual_path);
+	let is_raw = raw_format.is_some();
+	let kind = source_kind_from_bytes(&virtual_path, data);
+	let is_heif = kind == Some(SourceKind::Heif);
+	let is_video = kind == Some(SourceKind::Video);
+
+	let exif = extract_exif_from_bytes(data);
+	let is_motion_photo = !is_video && !is_raw && is_motion_photo_data(data);
+	let resolved_date = resolve_capture_date(exif.as_ref(), &name, 0.0);
+	let mime_type = get_mime_type(&virtual_path, &raw_format, is_heif)
+		.unwrap_or_else(|| mime_type_from_extension(&virtual_path));
+
+	let mut result = PhotoProcessingResult {
+		path: virtual_path.clone(),
+		name,
+		size: data.len() as i64,
+		content_hash: Some(content_hash_bytes(data)),
+		created_at: 0.0,
+		modified_at: 0.0,
+		width: None,
+		height: None,
+		mime_type: Some(mime_type),
+		phash: None,
+		blurhash: None,
+		sharpness: None,
+		exif,
+		is_raw,
+		raw_format,
+		raw_status: None,
+		raw_error: None,
+		is_video,
+		duration: None,
+		video: None,
+		resolved_date: resolved_date.as_ref().map(|d| d.date.clone()),
+		resolved_date_ms: resolved_date.as_ref().map(|d| d.date_ms),
+		date_source: resolved_date.as_ref().map(|d| d.source),
+		is_panorama: false,
+		is_photosphere: false,
+		projection_type: None,
+		is_live_photo: false,
+		live_photo_video_path: None,
+		group_members: None,
+		is_motion_photo,
+		is_screenshot: false,
+		tags: None,
+		text: None,
+		nsfw_score: None,
+		has_people: None,
+		people_count: None,
+		has_pets: None,
+		pet_count: None,
+		is_private: false,
+		encrypted_metadata: None,
+		success: true,
+		error: None,
+		error_detail: None,
+	};
+
+	let Some(kind) = kind else {
+		result.fail(ProcessingError::unsupported(&virtual_path).at(ErrorStage::Metadata));
+		return result;
+	};
+	if let Some(dimensions) = stored_dimensions_from_bytes(data, kind) {
+		// libheif output is already upright
+		let orientation = (kind != SourceKind::Heif)
+			.then(|| result.exif.as_ref().and_then(|e| e.orientation))
+			.flatten();
+		set_stored_dimensions(&mut result, dimensions, orientation);
+	}
+	result
+}
+
+/// Fully process a file from a private folder on demand, see `unlock_private_photo`
+pub fn unlock_private_photo_internal(
+	file_path: &str,
+	relative_path: &str,
+	thumbnails_dir: &str,
+	thumbnail_format: Option<ThumbnailFormat>,
+	thumbnail_sizes: Option<Vec<ThumbnailSize>>,
+) -> Result<PhotoProcessingResult, ProcessingError> {
+	let thumbnails = ThumbnailPlan::new(thumbnail_sizes, thumbnail_format)?;
+	let plan = PhotoPlan {
+		thumbnails_dir: Some(thumbnails_dir),
+		thumbnails: &thumbnails,
+		metadata_only: false,
+		analysis: AnalysisPlan::default(),
+		// Still private: nothing of it is cached
+		use_cache: false,
+	};
+	Ok(process_with_plan(file_path, relative_path, None, &plan))
+}
+
+/// Fully process a file from a private folder on demand (after the user unlocks it)
+/// Generates its thumbnails and returns its metadata unencrypted
+#[cfg(feature = "node")]
+#[napi]
+pub fn unlock_private_photo(
+	file_path: String,
+	relative_path: String,
+	thumbnails_dir: String,
+	thumbnail_format: Option<ThumbnailFormat>,
+	thumbnail_sizes: Option<Vec<ThumbnailSize>>,
+) -> napi::Result<PhotoProcessingResult, ErrorCode> {
+	unlock_private_photo_internal(
+		&file_path,
+		&relative_path,
+		&thumbnails_dir,
+		thumbnail_format,
+		thumbnail_sizes,
+	)
+	.map_err(napi::Error::from)
+}
+
+/// Process photos in parallel, calling `on_photo_processed` with each result as it
+/// completes, see `process_photos_with_callback`
+/// Returns the number of photos processed, fewer than given once `cancellation` is cancelled
+pub fn process_photos_with_callback_internal(
+	file_paths: &[String],
+	relative_paths: &[String],
+	thumbnails_dir: &str,
+	on_photo_processed: impl Fn(PhotoProcessingResult) + Sync,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> Result<u32, ProcessingError> {
+	let options = options.unwrap_or_default();
+	let context = BatchContext::new(thumbnails_dir.to_string(), &options, cancellation)?;
+	let pool = build_pool(&options);
+
+	Ok(process_each(
+		&context,
+		&pool,
+		file_paths,
+		relative_paths,
+		on_photo_processed,
+	))
 }
 
 /// Process photos in parallel with callback for each completed photo.
 /// Uses rayon for CPU-bound parallel processing.
 /// Callback is called with Blocking mode - this allows Rust to wait for JS to process.
+/// Returns the number of photos processed; see `process_photos_streaming` to cancel a batch
+#[cfg(feature = "node")]
 #[napi]
 pub fn process_photos_with_callback(
 	file_paths: Vec<String>,
@@ -311,32 +1683,188 @@ pub fn process_photos_with_callback(
 	thumbnails_dir: String,
 	#[napi(ts_arg_type = "(result: PhotoProcessingResult) => void")]
 	on_photo_processed: ThreadsafeFunction<PhotoProcessingResult>,
-) -> u32 {
-	let callback = Arc::new(on_photo_processed);
-	let max_concurrent = std::cmp::min(num_cpus::get(), 4);
+	options: Option<BatchOptions>,
+) -> napi::Result<u32, ErrorCode> {
+	process_photos_with_callback_internal(
+		&file_paths,
+		&relative_paths,
+		&thumbnails_dir,
+		|result| {
+			// Blocking mode waits for JS to process before continuing
+			// This provides natural backpressure
+			on_photo_processed.call(Ok(result), ThreadsafeFunctionCallMode::Blocking);
+		},
+		options,
+		None,
+	)
+	.map_err(napi::Error::from)
+}
 
-	let pool = rayon::ThreadPoolBuilder::new()
-		.num_threads(max_concurrent)
-		.build()
-		.unwrap_or_else(|_| rayon::ThreadPoolBuilder::new().build().unwrap());
+/// Results queued for JS before streaming workers wait for the callback to catch up
+#[cfg(feature = "node")]
+const STREAM_QUEUE_SIZE: usize = 64;
 
-	let count = file_paths.len() as u32;
+/// Callback receiving each result of a streaming batch, with a bounded queue for backpressure
+#[cfg(feature = "node")]
+type StreamCallback = ThreadsafeFunction<
+	PhotoProcessingResult,
+	(),
+	PhotoProcessingResult,
+	Status,
+	true,
+	false,
+	STREAM_QUEUE_SIZE,
+>;
 
-	pool.install(|| {
-		file_paths
-			.par_iter()
-			.enumerate()
-			.for_each(|(i, file_path)| {
-				let rel_path = relative_paths.get(i).map(|s| s.as_str()).unwrap_or("");
+/// Counts returned once a streaming batch has finished
+#[cfg_attr(feature = "node", napi(object))]
+pub struct BatchSummary {
+	/// Files processed, fewer than given when the batch was cancelled
+	pub total: u32,
+	pub succeeded: u32,
+	pub failed: u32,
+	pub cancelled: bool,
+}
+
+/// Processes a batch off the JS thread, handing each result to JS as soon as it is ready
+#[cfg(feature = "node")]
+pub struct StreamingBatchTask {
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	context: BatchContext,
+	pool: rayon::ThreadPool,
+	callback: StreamCallback,
+}
 
-				// Process the photo
-				let result = process_photo_internal(file_path, rel_path, &thumbnails_dir);
+#[cfg(feature = "node")]
+impl Task for StreamingBatchTask {
+	type Output = BatchSummary;
+	type JsValue = BatchSummary;
 
-				// Call JS callback - Blocking mode waits for JS to process before continuing
-				// This provides natural backpressure
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		let succeeded = AtomicU32::new(0);
+		let callback = &self.callback;
+
+		let total = process_each(
+			&self.context,
+			&self.pool,
+			&self.file_paths,
+			&self.relative_paths,
+			|result| {
+				if result.success {
+					succeeded.fetch_add(1, Ordering::Relaxed);
+				}
+				// Blocks only while the queue is full, so results never pile up in memory
 				callback.call(Ok(result), ThreadsafeFunctionCallMode::Blocking);
-			});
-	});
+			},
+		);
+		let succeeded = succeeded.into_inner();
+		Ok(BatchSummary {
+			total,
+			succeeded,
+			failed: total - succeeded,
+			cancelled: self.context.is_cancelled(),
+		})
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+}
+
+/// Process photos in the background, streaming each result to the callback as it completes
+/// Results are never collected; the returned promise resolves with a summary once all are
+/// done, or once the files in flight finish after `cancellation` is cancelled
+#[cfg(feature = "node")]
+#[napi]
+pub fn process_photos_streaming(
+	file_paths: Vec<String>,
+	relative_paths: Vec<String>,
+	thumbnails_dir: String,
+	#[napi(ts_arg_type = "(err: Error | null, result: PhotoProcessingResult) => void")]
+	on_photo_processed: StreamCallback,
+	options: Option<BatchOptions>,
+	cancellation: Option<&CancellationToken>,
+) -> napi::Result<AsyncTask<StreamingBatchTask>, ErrorCode> {
+	let options = options.unwrap_or_default();
+	let context = BatchContext::new(thumbnails_dir, &options, cancellation)?;
 
-	count
+	Ok(AsyncTask::new(StreamingBatchTask {
+		file_paths,
+		relative_paths,
+		context,
+		pool: build_pool(&options),
+		callback: on_photo_processed,
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::discovery::AssetGroupKind;
+	use crate::error::ErrorCode;
+	use crate::thumbnails::ThumbnailSizes;
+
+	#[test]
+	fn test_heic_thumbnail_skips_primary() {
+		let mut sizes = ThumbnailSizes::default().to_list();
+		sizes.truncate(2);
+		let grid = ThumbnailPlan::new(Some(sizes), None).unwrap();
+		let analysis = AnalysisPlan::default();
+
+		// A 1024px embedded thumbnail renders the grid sizes and scores sharpness like the primary
+		assert!(thumbnail_suffices(&grid, &analysis, 1024, 768));
+		// Too small for sharpness, a larger size, tone mapping or a model needs the primary
+		assert!(!thumbnail_suffices(&grid, &analysis, 480, 360));
+		let every_size = ThumbnailPlan::new(None, None).unwrap();
+		assert!(!thumbnail_suffices(&every_size, &analysis, 1024, 768));
+		let mut tone_mapped = grid.clone();
+		tone_mapped.tone_map_hdr = true;
+		assert!(!thumbnail_suffices(&tone_mapped, &analysis, 1024, 768));
+		let tagged = AnalysisPlan {
+			max_tags: 5,
+			..analysis
+		};
+		assert!(!thumbnail_suffices(&grid, &tagged, 1024, 768));
+	}
+
+	#[test]
+	fn test_resume_plan_validates_indices() {
+		let dir = tempfile::tempdir().unwrap();
+		let journal = dir.path().join("batch.journal");
+		let journal = journal.to_string_lossy();
+		let files = vec!["/photos/a.cr2".to_string(), "/photos/a.jpg".to_string()];
+		let relative = vec!["a.cr2".to_string(), "a.jpg".to_string()];
+		let pair = |primary, members| BatchOptions {
+			groups: Some(vec![AssetGroup {
+				kind: AssetGroupKind::RawJpeg,
+				primary,
+				members,
+			}]),
+			..Default::default()
+		};
+
+		let (remaining, _, options) =
+			resume_plan(&files, &relative, &journal, Some(pair(1, vec![0, 1]))).unwrap();
+		assert_eq!(remaining, files);
+		assert_eq!(options.groups.unwrap()[0].primary, 1);
+
+		let invalid = |options| {
+			resume_plan(&files, &relative, &journal, Some(options))
+				.is_err_and(|e| e.code == ErrorCode::InvalidArgument)
+		};
+		assert!(invalid(pair(2, vec![0, 2])));
+		assert!(invalid(pair(0, vec![0, 7])));
+		let stat = FileStat {
+			size: 1,
+			created_at: 0.0,
+			modified_at: 0.0,
+			changed_at: None,
+		};
+		let short_stats = BatchOptions {
+			file_stats: Some(vec![stat]),
+			..Default::default()
+		};
+		assert!(invalid(short_stats));
+	}
 }
@@ -0,0 +1,499 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, RgbImage};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::clip::embed_decoded_images;
+use crate::decode::{decode_bytes, decode_image, source_kind, SourceKind};
+#[cfg(feature = "node")]
+use crate::error::{reject_with_code, ErrorCode, ProcessingError};
+use crate::phash::generate_phash_from_image;
+use crate::thumbnails::{generate_thumbnail_buffers_internal, ThumbnailPlan};
+
+/// Default synthetic image sizes in megapixels (typical phone and mirrorless cameras)
+const DEFAULT_MEGAPIXELS: &[u32] = &[12, 24];
+
+/// Default number of timed runs per stage
+const DEFAULT_ITERATIONS: u32 = 3;
+
+/// Long edge of the resize stage (largest default thumbnail)
+const RESIZE_TARGET: u32 = 1600;
+
+/// A thread count is recommended once it reaches this share of the best throughput
+const SCALING_EFFICIENCY: f64 = 0.9;
+
+/// Sample files read from `sample_dir` when no limit is given
+const DEFAULT_SAMPLE_FILES: u32 = 20;
+
+/// Options for `run_self_benchmark`
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct SelfBenchmarkOptions {
+	/// Timed runs per stage (default 3)
+	pub iterations: Option<u32>,
+	/// Synthetic image sizes in megapixels (default [12, 24])
+	pub megapixels: Option<Vec<u32>>,
+	/// Also time CLIP image embeddings (loads the model; default false)
+	pub include_embeddings: Option<bool>,
+	/// Highest thread count tried in the scaling test (default: all cores)
+	pub max_threads: Option<u32>,
+	/// Folder of the user's own photos to time instead of synthetic images, to diagnose
+	/// slow imports on real files (RAW previews, HEIC, ...)
+	pub sample_dir: Option<String>,
+	/// Sample files read from `sample_dir`, in name order (default 20)
+	pub max_files: Option<u32>,
+}
+
+/// Timing of one pipeline stage at one image size
+#[cfg_attr(feature = "node", napi(object))]
+pub struct BenchmarkStageResult {
+	pub stage: String, // "decode", "raw_preview", "resize", "hash", "thumbnails" or "embed"
+	pub megapixels: u32,
+	pub width: u32,
+	pub height: u32,
+	pub iterations: u32,
+	pub mean_ms: f64,
+	pub images_per_second: f64,
+	/// Sample file timed, None for synthetic images
+	pub file: Option<String>,
+	/// Why the stage failed on the sample file, e.g. a missing libheif; timings are 0
+	pub error: Option<String>,
+}
+
+/// Full-pipeline throughput at one thread count
+#[cfg_attr(feature = "node", napi(object))]
+pub struct BenchmarkScalingResult {
+	pub threads: u32,
+	pub images_per_second: f64,
+}
+
+/// Machine-readable benchmark report
+#[cfg_attr(feature = "node", napi(object))]
+pub struct SelfBenchmarkReport {
+	pub cpu_count: u32,
+	pub stages: Vec<BenchmarkStageResult>,
+	pub scaling: Vec<BenchmarkScalingResult>,
+	/// Smallest thread count within 90% of the best measured throughput
+	pub recommended_concurrency: u32,
+	/// Resident memory of the process before the run, in MB (Linux only)
+	pub start_rss_mb: Option<f64>,
+	/// Highest resident memory of the process so far, in MB (Linux only)
+	pub peak_rss_mb: Option<f64>,
+	pub total_ms: f64,
+}
+
+/// 4:3 dimensions for a megapixel count
+fn dimensions_for(megapixels: u32) -> (u32, u32) {
+	let width = ((megapixels as f64 * 1_000_000.0 * 4.0 / 3.0).sqrt()) as u32;
+	(width, width * 3 / 4)
+}
+
+/// Build a JPEG with gradients and noise so it compresses like a real photo
+fn synthetic_jpeg(width: u32, height: u32) -> Result<Vec<u8>, String> {
+	let img = RgbImage::from_fn(width, height, |x, y| {
+		let noise = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)) % 48;
+		image::Rgb([
+			((x * 255 / width) as u8).wrapping_add(noise as u8),
+			((y * 255 / height) as u8).wrapping_add(noise as u8),
+			(((x + y) * 127 / (width + height)) as u8).wrapping_add(noise as u8),
+		])
+	});
+
+	let mut data = Vec::new();
+	JpegEncoder::new_with_quality(&mut data, 90)
+		.encode_image(&img)
+		.map_err(|e| format!("Failed to encode synthetic image: {}", e))?;
+	Ok(data)
+}
+
+/// Resize to the largest thumbnail size, as the batch pipeline does
+fn resize_for_thumbnail(img: &DynamicImage) -> DynamicImage {
+	img.resize(RESIZE_TARGET, RESIZE_TARGET, FilterType::Lanczos3)
+}
+
+/// Time `run` over several iterations and return the mean in milliseconds
+fn time_stage<T>(iterations: u32, mut run: impl FnMut() -> T) -> f64 {
+	let start = Instant::now();
+	for _ in 0..iterations {
+		std::hint::black_box(run());
+	}
+	start.elapsed().as_secs_f64() * 1000.0 / iterations as f64
+}
+
+/// Like `time_stage` for a stage that can fail, run once first to check it (which also
+/// keeps model loading out of the timing)
+fn time_fallible<T, E: fmt::Display>(
+	iterations: u32,
+	mut run: impl FnMut() -> Result<T, E>,
+) -> Result<f64, String> {
+	run().map_err(|e| e.to_string())?;
+	Ok(time_stage(iterations, run))
+}
+
+fn stage_result(
+	stage: &str,
+	megapixels: u32,
+	(width, height): (u32, u32),
+	iterations: u32,
+	mean_ms: f64,
+) -> BenchmarkStageResult {
+	BenchmarkStageResult {
+		stage: stage.to_string(),
+		megapixels,
+		width,
+		height,
+		iterations,
+		mean_ms,
+		images_per_second: if mean_ms > 0.0 { 1000.0 / mean_ms } else { 0.0 },
+		file: None,
+		error: None,
+	}
+}
+
+/// A stage that failed on a sample file
+fn failed_stage(stage: &str, file: &str, error: impl fmt::Display) -> BenchmarkStageResult {
+	BenchmarkStageResult {
+		file: Some(file.to_string()),
+		error: Some(error.to_string()),
+		..stage_result(stage, 0, (0, 0), 0, 0.0)
+	}
+}
+
+/// Thread counts tried in the scaling test: powers of two, plus the maximum itself
+fn thread_counts(max_threads: u32) -> Vec<u32> {
+	let mut counts: Vec<u32> = std::iter::successors(Some(1u32), |n| n.checked_mul(2))
+		.take_while(|&n| n < max_threads)
+		.collect();
+	counts.push(max_threads.max(1));
+	counts
+}
+
+/// Pick the smallest thread count whose throughput is close to the best one
+fn recommend_concurrency(scaling: &[BenchmarkScalingResult]) -> u32 {
+	let best = scaling
+		.iter()
+		.map(|s| s.images_per_second)
+		.fold(0.0, f64::max);
+
+	scaling
+		.iter()
+		.find(|s| s.images_per_second >= best * SCALING_EFFICIENCY)
+		.map(|s| s.threads)
+		.unwrap_or(1)
+}
+
+/// Measure decode + resize + hash throughput with a given number of threads
+fn measure_scaling(jpeg: &[u8], threads: u32, iterations: u32) -> Result<f64, String> {
+	let pool = rayon::ThreadPoolBuilder::new()
+		.num_threads(threads as usize)
+		.build()
+		.map_err(|e| e.to_string())?;
+	let jobs = threads * iterations;
+
+	let start = Instant::now();
+	pool.install(|| {
+		(0..jobs).into_par_iter().try_for_each(|_| {
+			let img = decode_bytes(jpeg)?;
+			let thumbnail = resize_for_thumbnail(&img);
+			std::hint::black_box(generate_phash_from_image(&thumbnail));
+			Ok::<(), String>(())
+		})
+	})?;
+	Ok(jobs as f64 / start.elapsed().as_secs_f64())
+}
+
+/// Time each stage on synthetic photos of each size
+fn synthetic_stages(
+	megapixels: &[u32],
+	iterations: u32,
+	include_embeddings: bool,
+) -> Result<Vec<BenchmarkStageResult>, String> {
+	let mut stages = Vec::new();
+	for &mp in megapixels {
+		let dimensions = dimensions_for(mp.max(1));
+		let jpeg = synthetic_jpeg(dimensions.0, dimensions.1)?;
+		let img = decode_bytes(&jpeg)?;
+
+		let decode_ms = time_stage(iterations, || decode_bytes(&jpeg));
+		let resize_ms = time_stage(iterations, || resize_for_thumbnail(&img));
+		let hash_ms = time_stage(iterations, || generate_phash_from_image(&img));
+		for (stage, mean_ms) in [
+			("decode", decode_ms),
+			("resize", resize_ms),
+			("hash", hash_ms),
+		] {
+			stages.push(stage_result(stage, mp, dimensions, iterations, mean_ms));
+		}
+
+		if include_embeddings {
+			let embed_ms = time_fallible(iterations, || embed_decoded_images(vec![img.clone()]))?;
+			stages.push(stage_result("embed", mp, dimensions, iterations, embed_ms));
+		}
+	}
+	Ok(stages)
+}
+
+/// Resident memory of the process from /proc (Linux), in MB
+/// `field` is "VmRSS" for the current value or "VmHWM" for the peak
+fn resident_mb(field: &str) -> Option<f64> {
+	let status = fs::read_to_string("/proc/self/status").ok()?;
+	let kb: f64 = status
+		.lines()
+		.find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?
+		.trim()
+		.trim_end_matches("kB")
+		.trim()
+		.parse()
+		.ok()?;
+	Some(kb / 1024.0)
+}
+
+/// Photos directly in the sample folder, in name order; videos are skipped
+fn sample_files(sample_dir: &str, max_files: usize) -> Result<Vec<(String, SourceKind)>, String> {
+	let mut paths: Vec<PathBuf> = fs::read_dir(sample_dir)
+		.map_err(|e| format!("Failed to read {}: {}", sample_dir, e))?
+		.flatten()
+		.map(|entry| entry.path())
+		.filter(|path| path.is_file())
+		.collect();
+	paths.sort();
+	Ok(paths
+		.into_iter()
+		.filter_map(|path| {
+			let path = path.to_string_lossy().to_string();
+			let kind = source_kind(&path).filter(|kind| *kind != SourceKind::Video)?;
+			Some((path, kind))
+		})
+		.take(max_files)
+		.collect())
+}
+
+/// Time each stage on every sample file, one file at a time so only one decoded image is
+/// held; failures such as a missing libheif are reported per stage instead of failing the run
+fn sample_stages(
+	files: &[(String, SourceKind)],
+	iterations: u32,
+	include_embeddings: bool,
+) -> Vec<BenchmarkStageResult> {
+	let plan = ThumbnailPlan::default();
+	let mut embed = include_embeddings;
+	let mut stages = Vec::new();
+
+	for (path, kind) in files {
+		// RAW files decode their embedded preview, like imports do; nothing is demosaiced
+		let decode_stage = if *kind == SourceKind::RawPreview {
+			"raw_preview"
+		} else {
+			"decode"
+		};
+		let img = match decode_image(path, *kind) {
+			Ok(img) => img,
+			Err(e) => {
+				stages.push(failed_stage(decode_stage, path, e));
+				continue;
+			}
+		};
+		let dimensions = img.dimensions();
+		let mp = (dimensions.0 as f64 * dimensions.1 as f64 / 1_000_000.0).round() as u32;
+		let sample = |stage: &str, mean_ms: Result<f64, String>| match mean_ms {
+			Ok(mean_ms) => BenchmarkStageResult {
+				file: Some(path.clone()),
+				..stage_result(stage, mp, dimensions, iterations, mean_ms)
+			},
+			Err(e) => failed_stage(stage, path, e),
+		};
+
+		stages.push(sample(
+			decode_stage,
+			Ok(time_stage(iterations, || decode_image(path, *kind))),
+		));
+		stages.push(sample(
+			"resize",
+			Ok(time_stage(iterations, || resize_for_thumbnail(&img))),
+		));
+		stages.push(sample(
+			"hash",
+			Ok(time_stage(iterations, || generate_phash_from_image(&img))),
+		));
+		stages.push(sample(
+			"thumbnails",
+			time_fallible(iterations, || {
+				generate_thumbnail_buffers_internal(&img, None, &plan, None, None)
+			}),
+		));
+		if embed {
+			let result = sample(
+				"embed",
+				time_fallible(iterations, || embed_decoded_images(vec![img.clone()])),
+			);
+			// A model that can't load fails once
+			embed = result.error.is_none();
+			stages.push(result);
+		}
+	}
+	stages
+}
+
+/// Benchmark the stages on synthetic or sample photos, see `run_self_benchmark`
+pub fn run_benchmark_internal(
+	options: &SelfBenchmarkOptions,
+) -> Result<SelfBenchmarkReport, String> {
+	let start = Instant::now();
+	let start_rss_mb = resident_mb("VmRSS");
+	let iterations = options.iterations.unwrap_or(DEFAULT_ITERATIONS).max(1);
+	let mut megapixels = options
+		.megapixels
+		.clone()
+		.filter(|m| !m.is_empty())
+		.unwrap_or_else(|| DEFAULT_MEGAPIXELS.to_vec());
+	megapixels.sort_unstable();
+	let include_embeddings = options.include_embeddings.unwrap_or(false);
+	let cpu_count = num_cpus::get() as u32;
+
+	let stages = match &options.sample_dir {
+		Some(sample_dir) => {
+			let max_files = options.max_files.unwrap_or(DEFAULT_SAMPLE_FILES).max(1);
+			let files = sample_files(sample_dir, max_files as usize)?;
+			if files.is_empty() {
+				return Err(format!("No photos found in {}", sample_dir));
+			}
+			sample_stages(&files, iterations, include_embeddings)
+		}
+		None => synthetic_stages(&megapixels, iterations, include_embeddings)?,
+	};
+
+	// Throughput of the full per-photo pipeline at increasing thread counts, on the smallest
+	// synthetic size so runs stay comparable
+	let max_threads = options
+		.max_threads
+		.unwrap_or(cpu_count)
+		.clamp(1, cpu_count.max(1));
+	let (width, height) = dimensions_for(megapixels[0].max(1));
+	let jpeg = synthetic_jpeg(width, height)?;
+	let mut scaling = Vec::new();
+	for threads in thread_counts(max_threads) {
+		scaling.push(BenchmarkScalingResult {
+			threads,
+			images_per_second: measure_scaling(&jpeg, threads, iterations)?,
+		});
+	}
+
+	Ok(SelfBenchmarkReport {
+		cpu_count,
+		stages,
+		recommended_concurrency: recommend_concurrency(&scaling),
+		scaling,
+		start_rss_mb,
+		peak_rss_mb: resident_mb("VmHWM"),
+		total_ms: start.elapsed().as_secs_f64() * 1000.0,
+	})
+}
+
+#[cfg(feature = "node")]
+pub struct SelfBenchmarkTask {
+	options: SelfBenchmarkOptions,
+}
+
+#[cfg(feature = "node")]
+impl Task for SelfBenchmarkTask {
+	type Output = SelfBenchmarkReport;
+	type JsValue = SelfBenchmarkReport;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		run_benchmark_internal(&self.options)
+			.map_err(|e| ProcessingError::from_message(ErrorCode::Internal, e).into())
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+
+	fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+		reject_with_code(env, err)
+	}
+}
+
+/// Measure decode/resize/hash/embed throughput on synthetic images, off the JS thread
+/// With `sample_dir`, the stages (plus RAW previews and thumbnail encoding) run on the user's
+/// own photos instead, to diagnose slow imports
+/// The report can be sent to support or used to pick the default batch concurrency
+#[cfg(feature = "node")]
+#[napi]
+pub fn run_self_benchmark(options: Option<SelfBenchmarkOptions>) -> AsyncTask<SelfBenchmarkTask> {
+	AsyncTask::new(SelfBenchmarkTask {
+		options: options.unwrap_or_default(),
+	})
+}
+
+/// Time each stage (decode, RAW preview, resize, hash, thumbnails and CLIP embeddings) on the
+/// photos in `sample_dir`, with throughput per file and the process's memory
+/// Same as `run_self_benchmark` with `sample_dir` and `include_embeddings` set
+#[cfg(feature = "node")]
+#[napi]
+pub fn run_benchmark(sample_dir: String) -> AsyncTask<SelfBenchmarkTask> {
+	AsyncTask::new(SelfBenchmarkTask {
+		options: SelfBenchmarkOptions {
+			sample_dir: Some(sample_dir),
+			include_embeddings: Some(true),
+			..Default::default()
+		},
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn scaling(results: &[(u32, f64)]) -> Vec<BenchmarkScalingResult> {
+		results
+			.iter()
+			.map(|&(threads, images_per_second)| BenchmarkScalingResult {
+				threads,
+				images_per_second,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_recommends_smallest_near_peak_thread_count() {
+		// Throughput flattens after 4 threads (memory bandwidth bound)
+		let results = scaling(&[(1, 10.0), (2, 19.0), (4, 35.0), (8, 37.0), (12, 36.0)]);
+		assert_eq!(recommend_concurrency(&results), 4);
+		assert_eq!(recommend_concurrency(&[]), 1);
+	}
+
+	#[test]
+	fn test_thread_counts_include_maximum() {
+		assert_eq!(thread_counts(1), vec![1]);
+		assert_eq!(thread_counts(6), vec![1, 2, 4, 6]);
+		assert_eq!(thread_counts(8), vec![1, 2, 4, 8]);
+	}
+
+	#[test]
+	fn test_sample_stages() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::write(dir.path().join("a.jpg"), synthetic_jpeg(400, 300).unwrap()).unwrap();
+		fs::write(dir.path().join("b.jpg"), b"not a photo").unwrap();
+		fs::write(dir.path().join("notes.txt"), b"skipped").unwrap();
+
+		let files = sample_files(&dir.path().to_string_lossy(), 10).unwrap();
+		assert_eq!(files.len(), 2);
+		let stages = sample_stages(&files, 1, false);
+		let names: Vec<&str> = stages.iter().map(|s| s.stage.as_str()).collect();
+		assert_eq!(names, ["decode", "resize", "hash", "thumbnails", "decode"]);
+		assert_eq!((stages[0].width, stages[0].height), (400, 300));
+		assert!(stages[..4].iter().all(|s| s.error.is_none()));
+		// A file that fails to decode is reported instead of failing the run
+		assert!(stages[4].file.as_deref().unwrap().ends_with("b.jpg"));
+		assert!(stages[4].error.is_some());
+	}
+}
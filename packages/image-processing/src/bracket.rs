@@ -0,0 +1,445 @@
+use image::buffer::ConvertBuffer;
+use image::codecs::jpeg::JpegEncoder;
+use image::{
+	imageops, imageops::FilterType, DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb32FImage,
+	RgbImage,
+};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::clip::decode_upright;
+#[cfg(feature = "node")]
+use crate::error::reject_with_code;
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::exif::extract_exif_batch_internal;
+
+/// Per-pixel fusion weights of one shot
+type WeightMap = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Shots further apart than this (ms) belong to different brackets
+const BRACKET_MAX_GAP_MS: f64 = 2000.0;
+
+/// Fewest shots in a bracket (auto exposure bracketing takes 3, 5, 7 or 9)
+const MIN_BRACKET_SHOTS: usize = 3;
+
+/// Spread of the well-exposedness weight around mid-gray, as in Mertens et al.
+const EXPOSEDNESS_SIGMA: f32 = 0.2;
+
+/// Pyramid levels stop before the short side gets below this
+const MIN_PYRAMID_SIDE: u32 = 8;
+
+/// Longest side fusion weights are computed at; the finer pyramid levels take them scaled
+/// up, so a bracket of 45MP shots doesn't need a full-size weight map per shot
+const WEIGHT_MAX_SIDE: u32 = 1024;
+
+/// Keeps weights positive where every shot scores 0 (flat areas), so they average there
+const WEIGHT_EPSILON: f32 = 1e-12;
+
+/// JPEG quality of merged images when none is given
+const DEFAULT_JPEG_QUALITY: u8 = 92;
+
+/// Capture details of a shot, for finding brackets
+#[derive(Debug, Clone, PartialEq)]
+struct BracketShot {
+	index: usize,
+	time_ms: f64,
+	/// Exposure bias in hundredths of a stop, so equal biases compare equal
+	bias: i64,
+	camera: Option<String>,
+}
+
+/// Group shots into brackets: runs taken in quick succession with the same camera, each
+/// at a different exposure bias; a repeated bias starts the next bracket
+/// Returns input positions in capture order
+fn bracket_runs(mut shots: Vec<BracketShot>) -> Vec<Vec<u32>> {
+	shots.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+	let mut brackets = Vec::new();
+	let mut run: Vec<&BracketShot> = Vec::new();
+	for shot in &shots {
+		let continues = run.last().is_some_and(|last| {
+			shot.time_ms - last.time_ms <= BRACKET_MAX_GAP_MS
+				&& shot.camera == last.camera
+				&& run.iter().all(|other| other.bias != shot.bias)
+		});
+		if !continues {
+			if run.len() >= MIN_BRACKET_SHOTS {
+				brackets.push(run.iter().map(|shot| shot.index as u32).collect());
+			}
+			run.clear();
+		}
+		run.push(shot);
+	}
+	if run.len() >= MIN_BRACKET_SHOTS {
+		brackets.push(run.iter().map(|shot| shot.index as u32).collect());
+	}
+	brackets
+}
+
+/// Find exposure brackets among photos from their EXIF, see `find_exposure_brackets`
+pub fn find_exposure_brackets_internal(
+	file_paths: &[String],
+) -> Result<Vec<Vec<u32>>, ProcessingError> {
+	let shots = extract_exif_batch_internal(file_paths, None)?
+		.into_iter()
+		.enumerate()
+		.filter_map(|(index, exif)| {
+			let exif = exif?;
+			Some(BracketShot {
+				index,
+				time_ms: exif.date_taken_ms?,
+				bias: (exif.exposure_compensation? * 100.0).round() as i64,
+				camera: exif.camera_model,
+			})
+		})
+		.collect();
+	Ok(bracket_runs(shots))
+}
+
+/// Find exposure brackets among photos from their EXIF: 3 or more shots taken within
+/// seconds of each other by the same camera at different exposure biases
+/// Returns the positions of each bracket's shots in capture order, for `merge_exposures`
+#[cfg(feature = "node")]
+#[napi]
+pub fn find_exposure_brackets(file_paths: Vec<String>) -> napi::Result<Vec<Vec<u32>>, ErrorCode> {
+	find_exposure_brackets_internal(&file_paths).map_err(napi::Error::from)
+}
+
+/// Mertens weight of each pixel: local contrast x saturation x well-exposedness
+fn weight_map(img: &Rgb32FImage) -> WeightMap {
+	let (width, height) = img.dimensions();
+	let gray: Vec<f32> = img
+		.pixels()
+		.map(|p| 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2])
+		.collect();
+	let luma = |x: i64, y: i64| {
+		let x = x.clamp(0, width as i64 - 1);
+		let y = y.clamp(0, height as i64 - 1);
+		gray[(y * width as i64 + x) as usize]
+	};
+
+	WeightMap::from_fn(width, height, |x, y| {
+		let (x, y) = (x as i64, y as i64);
+		let contrast = (luma(x - 1, y) + luma(x + 1, y) + luma(x, y - 1) + luma(x, y + 1)
+			- 4.0 * luma(x, y))
+		.abs();
+		let p = img.get_pixel(x as u32, y as u32).0;
+		let mean = (p[0] + p[1] + p[2]) / 3.0;
+		let saturation = (p.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / 3.0).sqrt();
+		let exposedness: f32 = p
+			.iter()
+			.map(|v| (-(v - 0.5).powi(2) / (2.0 * EXPOSEDNESS_SIGMA * EXPOSEDNESS_SIGMA)).exp())
+			.product();
+		Luma([contrast * saturation * exposedness + WEIGHT_EPSILON])
+	})
+}
+
+/// Number of pyramid levels for an image, halving until the short side gets small
+fn pyramid_levels(width: u32, height: u32) -> usize {
+	let mut levels = 1;
+	let mut side = width.min(height);
+	while side / 2 >= MIN_PYRAMID_SIDE {
+		side /= 2;
+		levels += 1;
+	}
+	levels
+}
+
+/// Size weights are computed at for shots of a size, see `WEIGHT_MAX_SIDE`
+fn weight_size(width: u32, height: u32) -> (u32, u32) {
+	let longest = width.max(height);
+	if longest <= WEIGHT_MAX_SIDE {
+		return (width, height);
+	}
+	let scale = WEIGHT_MAX_SIDE as f64 / longest as f64;
+	(
+		((width as f64 * scale).round() as u32).max(1),
+		((height as f64 * scale).round() as u32).max(1),
+	)
+}
+
+/// Gaussian pyramid: the image, then successively blurred half-size copies
+fn gaussian_pyramid(img: Rgb32FImage, levels: usize) -> Vec<Rgb32FImage> {
+	let mut pyramid = vec![img];
+	for _ in 1..levels {
+		let last = &pyramid[pyramid.len() - 1];
+		let (width, height) = last.dimensions();
+		let smaller = imageops::resize(
+			last,
+			width.div_ceil(2),
+			height.div_ceil(2),
+			FilterType::Triangle,
+		);
+		pyramid.push(smaller);
+	}
+	pyramid
+}
+
+/// Scale an image up to the size of the pyramid level above
+fn expand(img: &Rgb32FImage, width: u32, height: u32) -> Rgb32FImage {
+	imageops::resize(img, width, height, FilterType::Triangle)
+}
+
+/// Laplacian pyramid: the detail lost between each Gaussian level and the next, then the
+/// smallest level itself; built in place of the Gaussian pyramid
+fn laplacian_pyramid(img: Rgb32FImage, levels: usize) -> Vec<Rgb32FImage> {
+	let mut pyramid = gaussian_pyramid(img, levels);
+	for level in 0..levels - 1 {
+		let (width, height) = pyramid[level].dimensions();
+		let expanded = expand(&pyramid[level + 1], width, height);
+		for (value, coarse) in pyramid[level].iter_mut().zip(expanded.iter()) {
+			*value -= coarse;
+		}
+	}
+	pyramid
+}
+
+/// Exposure fusion (Mertens et al.) of shots of the same size: each level of their
+/// Laplacian pyramids is blended with their normalized weights scaled to the level, then
+/// the pyramid is collapsed
+/// Shots stay 8-bit and only one is expanded to a float pyramid at a time, and the weights
+/// are computed at a reduced size, which keeps large brackets within memory
+fn fuse_exposures(images: &[RgbImage]) -> Rgb32FImage {
+	let (width, height) = images[0].dimensions();
+	let levels = pyramid_levels(width, height);
+
+	let (weight_width, weight_height) = weight_size(width, height);
+	let mut weights: Vec<WeightMap> = images
+		.par_iter()
+		.map(|img| {
+			let small = imageops::resize(img, weight_width, weight_height, FilterType::Triangle);
+			weight_map(&small.convert())
+		})
+		.collect();
+	let mut totals = vec![0.0f32; (weight_width * weight_height) as usize];
+	for weight in &weights {
+		for (total, value) in totals.iter_mut().zip(weight.iter()) {
+			*total += value;
+		}
+	}
+	for weight in &mut weights {
+		for (value, total) in weight.iter_mut().zip(&totals) {
+			*value /= total;
+		}
+	}
+
+	let mut blended: Vec<Rgb32FImage> = Vec::new();
+	for (img, weight) in images.iter().zip(&weights) {
+		let details = laplacian_pyramid(img.convert(), levels);
+		if blended.is_empty() {
+			blended = details
+				.iter()
+				.map(|level| Rgb32FImage::new(level.width(), level.height()))
+				.collect();
+		}
+		for (sum, detail) in blended.iter_mut().zip(&details) {
+			let weight = imageops::resize(weight, sum.width(), sum.height(), FilterType::Triangle);
+			for ((sum, detail), weight) in
+				sum.pixels_mut().zip(detail.pixels()).zip(weight.pixels())
+			{
+				for c in 0..3 {
+					sum[c] += weight[0] * detail[c];
+				}
+			}
+		}
+	}
+
+	let mut result = blended
+		.pop()
+		.unwrap_or_else(|| Rgb32FImage::new(width, height));
+	while let Some(mut level) = blended.pop() {
+		let expanded = expand(&result, level.width(), level.height());
+		for (value, coarse) in level.iter_mut().zip(expanded.iter()) {
+			*value = (*value + coarse).clamp(0.0, 1.0);
+		}
+		result = level;
+	}
+	result
+}
+
+/// Merged bracket written by `merge_exposures`
+#[cfg_attr(feature = "node", napi(object))]
+pub struct MergedExposure {
+	pub output_path: String,
+	pub width: u32,
+	pub height: u32,
+	/// Shots merged
+	pub shots: u32,
+}
+
+/// Write a merged image as JPEG or 16-bit TIFF, from the output path's extension
+fn write_merged(img: Rgb32FImage, output_path: &str, quality: u8) -> Result<(), ProcessingError> {
+	let extension = Path::new(output_path)
+		.extension()
+		.map(|e| e.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+	let img = DynamicImage::ImageRgb32F(img);
+	let message = format!("Failed to write {}", output_path);
+	let encode_error = |e: image::ImageError| {
+		ProcessingError::new(ErrorCode::Internal, format!("{}: {}", message, e))
+	};
+	let written = match extension.as_str() {
+		"jpg" | "jpeg" => {
+			let file = File::create(output_path).map_err(|e| ProcessingError::io(&e, &message))?;
+			JpegEncoder::new_with_quality(BufWriter::new(file), quality.min(100))
+				.encode_image(&img.to_rgb8())
+				.map_err(encode_error)
+		}
+		"tif" | "tiff" => DynamicImage::ImageRgb16(img.to_rgb16())
+			.save_with_format(output_path, ImageFormat::Tiff)
+			.map_err(encode_error),
+		_ => Err(ProcessingError::invalid(
+			"Output must be a .jpg or .tif file",
+		)),
+	};
+	written.map_err(|e| e.at(ErrorStage::Write).for_file(output_path))
+}
+
+/// Merge an exposure bracket into one well-exposed image, see `merge_exposures`
+pub fn merge_exposures_internal(
+	file_paths: &[String],
+	output_path: &str,
+	quality: Option<u8>,
+) -> Result<MergedExposure, ProcessingError> {
+	if file_paths.len() < 2 {
+		return Err(ProcessingError::invalid("A bracket needs at least 2 shots"));
+	}
+	let images = file_paths
+		.par_iter()
+		.map(|file_path| decode_upright(file_path).map(|img| img.into_rgb8()))
+		.collect::<Result<Vec<_>, _>>()?;
+	let (width, height) = images[0].dimensions();
+	if images.iter().any(|img| img.dimensions() != (width, height)) {
+		return Err(ProcessingError::invalid(
+			"Bracketed shots must have the same dimensions",
+		));
+	}
+
+	let merged = fuse_exposures(&images);
+	write_merged(merged, output_path, quality.unwrap_or(DEFAULT_JPEG_QUALITY))?;
+	Ok(MergedExposure {
+		output_path: output_path.to_string(),
+		width,
+		height,
+		shots: images.len() as u32,
+	})
+}
+
+/// Merge an exposure bracket (e.g. from `find_exposure_brackets`) into one well-exposed
+/// image with exposure fusion, written as JPEG (`quality`, default 92) or 16-bit TIFF
+/// depending on `output_path`'s extension; the shots must have the same dimensions
+#[cfg(feature = "node")]
+#[napi]
+pub fn merge_exposures(
+	file_paths: Vec<String>,
+	output_path: String,
+	quality: Option<u8>,
+) -> napi::Result<MergedExposure, ErrorCode> {
+	merge_exposures_internal(&file_paths, &output_path, quality).map_err(napi::Error::from)
+}
+
+#[cfg(feature = "node")]
+pub struct MergeExposuresTask {
+	file_paths: Vec<String>,
+	output_path: String,
+	quality: Option<u8>,
+}
+
+#[cfg(feature = "node")]
+impl Task for MergeExposuresTask {
+	type Output = MergedExposure;
+	type JsValue = MergedExposure;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		merge_exposures_internal(&self.file_paths, &self.output_path, self.quality)
+			.map_err(napi::Error::from)
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+
+	fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+		reject_with_code(env, err)
+	}
+}
+
+/// Same as `merge_exposures`, off the JS thread, which decoding and fusing every shot at
+/// full resolution would otherwise block for seconds
+#[cfg(feature = "node")]
+#[napi]
+pub fn merge_exposures_async(
+	file_paths: Vec<String>,
+	output_path: String,
+	quality: Option<u8>,
+) -> AsyncTask<MergeExposuresTask> {
+	AsyncTask::new(MergeExposuresTask {
+		file_paths,
+		output_path,
+		quality,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::Rgb;
+
+	#[test]
+	fn test_bracket_runs() {
+		let shot = |index: usize, time_ms: f64, bias: i64| BracketShot {
+			index,
+			time_ms,
+			bias,
+			camera: Some("EOS R5".to_string()),
+		};
+		let shots = vec![
+			shot(0, 0.0, 0),
+			shot(1, 300.0, -200),
+			shot(2, 600.0, 200),
+			// Same biases again: the next bracket
+			shot(4, 1200.0, -200),
+			shot(3, 900.0, 0),
+			shot(5, 1500.0, 200),
+			// Too late, and too short
+			shot(6, 9000.0, 0),
+			shot(7, 9300.0, -100),
+		];
+		assert_eq!(bracket_runs(shots), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+	}
+
+	#[test]
+	fn test_fusion_favors_well_exposed_shots() {
+		// Textured mid-tones, so the shot has contrast to weigh
+		let shot = RgbImage::from_fn(64, 48, |x, y| {
+			let v = 0.3 + ((x * 7 + y * 13) % 5) as f32 * 0.08;
+			Rgb([v, v * 0.8, v * 0.6].map(|c| (c * 255.0).round() as u8))
+		});
+		let shot_f32: Rgb32FImage = shot.convert();
+		// Identical shots fuse back into themselves
+		let fused = fuse_exposures(&[shot.clone(), shot.clone()]);
+		assert!(fused
+			.iter()
+			.zip(shot_f32.iter())
+			.all(|(a, b)| (a - b).abs() < 1e-3));
+
+		// A blown-out shot barely shows through a well-exposed one
+		let blown = RgbImage::from_pixel(64, 48, Rgb([255, 255, 255]));
+		let fused = fuse_exposures(&[shot.clone(), blown]);
+		let mean = |img: &Rgb32FImage| img.iter().sum::<f32>() / img.len() as f32;
+		assert!(mean(&fused) - mean(&shot_f32) < 0.1);
+	}
+
+	#[test]
+	fn test_weight_size() {
+		assert_eq!(weight_size(800, 600), (800, 600));
+		assert_eq!(weight_size(8192, 5464), (1024, 683));
+	}
+}
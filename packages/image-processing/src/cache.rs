@@ -0,0 +1,266 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::clip::LabelScore;
+use crate::error::{ErrorCode, ProcessingError};
+use crate::exif::ExifData;
+use crate::logging::{log_event, LogLevel};
+use crate::video::VideoMetadata;
+
+/// What processing read from a file's contents, reused when the same bytes are imported
+/// again (a moved, renamed or copied file)
+/// Names, file dates and sidecars depend on the path and are always read again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct CachedPhoto {
+	/// Embedded metadata, before sidecar edits are merged in
+	pub exif: Option<ExifData>,
+	pub video: Option<VideoMetadata>,
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	pub phash: Option<String>,
+	pub blurhash: Option<String>,
+	pub sharpness: Option<f64>,
+	pub is_screenshot: bool,
+	pub tags: Option<Vec<LabelScore>>,
+	/// `max_tags` the tags were kept with
+	pub tag_limit: u32,
+	pub text: Option<String>,
+	pub nsfw_score: Option<f64>,
+	pub has_people: Option<bool>,
+	pub people_count: Option<u32>,
+	pub has_pets: Option<bool>,
+	pub pet_count: Option<u32>,
+	pub thumbnails: Option<CachedThumbnails>,
+}
+
+/// Thumbnails last generated for the contents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedThumbnails {
+	/// Relative path of the photo they were generated for
+	pub relative_path: String,
+	/// `ThumbnailPlan::key` of the settings they were generated with
+	pub plan: String,
+}
+
+/// How long a write waits for another thread's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Path of the open cache, with the generation it was opened as
+static CACHE: Mutex<Option<(u64, String)>> = Mutex::new(None);
+
+/// Bumped on every open, so threads drop their connections to an earlier cache
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+	/// This thread's connection to the open cache and its generation; each batch thread
+	/// has its own, so WAL lets them read while another writes
+	static CONNECTION: RefCell<Option<(u64, Connection)>> = const { RefCell::new(None) };
+}
+
+fn open(path: &str) -> rusqlite::Result<Connection> {
+	let connection = Connection::open(path)?;
+	// Batches read and write from several threads, each with its own connection
+	connection.pragma_update(None, "journal_mode", "WAL")?;
+	connection.busy_timeout(BUSY_TIMEOUT)?;
+	create_tables(&connection)?;
+	Ok(connection)
+}
+
+fn create_tables(connection: &Connection) -> rusqlite::Result<()> {
+	connection.execute_batch(
+		"CREATE TABLE IF NOT EXISTS photos (
+			content_hash TEXT PRIMARY KEY,
+			data TEXT NOT NULL
+		);
+		CREATE TABLE IF NOT EXISTS embeddings (
+			content_hash TEXT NOT NULL,
+			model TEXT NOT NULL,
+			embedding BLOB NOT NULL,
+			PRIMARY KEY (content_hash, model)
+		);",
+	)
+}
+
+/// Open the processing cache at `path`, see `open_processing_cache`
+pub fn open_processing_cache_internal(path: &str) -> Result<(), ProcessingError> {
+	let connection = open(path).map_err(|e| {
+		let message = format!("Failed to open processing cache {}: {}", path, e);
+		ProcessingError::new(ErrorCode::Io, message)
+	})?;
+	let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+	*CACHE.lock().unwrap_or_else(|e| e.into_inner()) = Some((generation, path.to_string()));
+	CONNECTION.set(Some((generation, connection)));
+	Ok(())
+}
+
+/// Keep processing results in a SQLite database at `path` (created if missing), keyed by
+/// BLAKE3 content hash, so moved, renamed or copied files are imported without decoding them again
+/// Used by file batches (`process_photos_batch` and its variants) and batch embeddings
+#[cfg(feature = "node")]
+#[napi]
+pub fn open_processing_cache(path: String) -> napi::Result<(), ErrorCode> {
+	open_processing_cache_internal(&path).map_err(napi::Error::from)
+}
+
+/// Stop using the processing cache; returns whether one was open
+#[cfg_attr(feature = "node", napi)]
+pub fn close_processing_cache() -> bool {
+	CONNECTION.set(None);
+	CACHE
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.take()
+		.is_some()
+}
+
+/// Whether a processing cache is open
+pub(crate) fn processing_cache_open() -> bool {
+	CACHE.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Run a query on the open cache with this thread's connection, opening it first if needed;
+/// a failure is logged and treated like a miss
+fn with_cache<T>(query: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Option<T> {
+	let Some((generation, path)) = CACHE.lock().unwrap_or_else(|e| e.into_inner()).clone() else {
+		// Closed since this thread last used it
+		CONNECTION.set(None);
+		return None;
+	};
+	let result = CONNECTION.with_borrow_mut(|connection| {
+		let current = connection.take().filter(|(g, _)| *g == generation);
+		let entry = match current {
+			Some(entry) => entry,
+			None => (generation, open(&path)?),
+		};
+		query(&connection.insert(entry).1)
+	});
+	match result {
+		Ok(value) => Some(value),
+		Err(e) => {
+			let message = format!("Processing cache error: {}", e);
+			log_event(LogLevel::Warn, "cache", None, message);
+			None
+		}
+	}
+}
+
+fn read_photo(
+	connection: &Connection,
+	content_hash: &str,
+) -> rusqlite::Result<Option<CachedPhoto>> {
+	let data: Option<String> = connection
+		.query_row(
+			"SELECT data FROM photos WHERE content_hash = ?1",
+			params![content_hash],
+			|row| row.get(0),
+		)
+		.optional()?;
+	// Entries written by another version may not parse; they are replaced once processed
+	Ok(data.and_then(|data| serde_json::from_str(&data).ok()))
+}
+
+fn write_photo(
+	connection: &Connection,
+	content_hash: &str,
+	photo: &CachedPhoto,
+) -> rusqlite::Result<()> {
+	let data = serde_json::to_string(photo)
+		.map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+	connection.execute(
+		"INSERT OR REPLACE INTO photos (content_hash, data) VALUES (?1, ?2)",
+		params![content_hash, data],
+	)?;
+	Ok(())
+}
+
+fn read_embedding(
+	connection: &Connection,
+	content_hash: &str,
+	model: &str,
+) -> rusqlite::Result<Option<Vec<f32>>> {
+	let bytes: Option<Vec<u8>> = connection
+		.query_row(
+			"SELECT embedding FROM embeddings WHERE content_hash = ?1 AND model = ?2",
+			params![content_hash, model],
+			|row| row.get(0),
+		)
+		.optional()?;
+	Ok(bytes.map(|bytes| {
+		bytes
+			.chunks_exact(4)
+			.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+			.collect()
+	}))
+}
+
+fn write_embedding(
+	connection: &Connection,
+	content_hash: &str,
+	model: &str,
+	embedding: &[f32],
+) -> rusqlite::Result<()> {
+	let bytes: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+	connection.execute(
+		"INSERT OR REPLACE INTO embeddings (content_hash, model, embedding) VALUES (?1, ?2, ?3)",
+		params![content_hash, model, bytes],
+	)?;
+	Ok(())
+}
+
+/// Cached results for contents with this hash
+pub(crate) fn cached_photo(content_hash: &str) -> Option<CachedPhoto> {
+	with_cache(|connection| read_photo(connection, content_hash)).flatten()
+}
+
+/// Cache the results for contents with this hash, replacing earlier ones
+pub(crate) fn store_photo(content_hash: &str, photo: &CachedPhoto) {
+	with_cache(|connection| write_photo(connection, content_hash, photo));
+}
+
+/// Cached raw model output for contents with this hash, from the model with this version
+pub(crate) fn cached_embedding(content_hash: &str, model: &str) -> Option<Vec<f32>> {
+	with_cache(|connection| read_embedding(connection, content_hash, model)).flatten()
+}
+
+/// Cache the raw model output for contents with this hash
+pub(crate) fn store_embedding(content_hash: &str, model: &str, embedding: &[f32]) {
+	with_cache(|connection| write_embedding(connection, content_hash, model, embedding));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_round_trip() {
+		let connection = Connection::open_in_memory().unwrap();
+		create_tables(&connection).unwrap();
+		assert!(read_photo(&connection, "abc").unwrap().is_none());
+
+		let photo = CachedPhoto {
+			phash: Some("f0f0".to_string()),
+			thumbnails: Some(CachedThumbnails {
+				relative_path: "2024/IMG_1.jpg".to_string(),
+				plan: "1234".to_string(),
+			}),
+			..Default::default()
+		};
+		write_photo(&connection, "abc", &photo).unwrap();
+		let cached = read_photo(&connection, "abc").unwrap().unwrap();
+		assert_eq!(cached.phash, photo.phash);
+		assert_eq!(cached.thumbnails, photo.thumbnails);
+
+		write_embedding(&connection, "abc", "clip-vit-b32", &[0.5, -1.25]).unwrap();
+		let embedding = read_embedding(&connection, "abc", "clip-vit-b32").unwrap();
+		assert_eq!(embedding, Some(vec![0.5, -1.25]));
+		assert!(read_embedding(&connection, "abc", "resnet50")
+			.unwrap()
+			.is_none());
+	}
+}
@@ -0,0 +1,37 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Lets JavaScript stop a long-running operation, e.g. when the user cancels an import
+/// Operations check it between files: batches return the files finished so far, batch
+/// hashes and embeddings return null for the rest, and discovery fails
+#[cfg_attr(feature = "node", napi)]
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+	cancelled: Arc<AtomicBool>,
+}
+
+#[cfg_attr(feature = "node", napi)]
+impl CancellationToken {
+	#[cfg_attr(feature = "node", napi(constructor))]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Ask every operation given this token to stop; it can't be reset
+	#[cfg_attr(feature = "node", napi)]
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	#[cfg_attr(feature = "node", napi(getter))]
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+}
+
+/// Whether an optional token has been cancelled
+pub(crate) fn is_cancelled(token: Option<&CancellationToken>) -> bool {
+	token.is_some_and(CancellationToken::is_cancelled)
+}
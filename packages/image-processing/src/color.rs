@@ -0,0 +1,253 @@
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use moxcms::{ColorProfile, DataColorSpace, Layout, Transform8BitExecutor, TransformOptions, Xyzd};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::sync::Arc;
+
+use crate::decode::{source_kind, SourceKind};
+use crate::heif::heif_color_profile;
+
+/// Largest primary (colorant) difference at which two profiles count as the same space
+const COLORANT_TOLERANCE: f64 = 0.002;
+
+/// Converts pixels from a photo's embedded color profile to sRGB
+pub struct SrgbConverter {
+	rgb: Arc<Transform8BitExecutor>,
+	rgba: Arc<Transform8BitExecutor>,
+}
+
+impl SrgbConverter {
+	/// None when the profile already is sRGB (or close enough) or isn't an RGB profile
+	pub fn new(profile: &ColorProfile) -> Option<Self> {
+		if profile.color_space != DataColorSpace::Rgb
+			|| same_primaries(profile, &ColorProfile::new_srgb())
+		{
+			return None;
+		}
+
+		let srgb = ColorProfile::new_srgb();
+		let options = TransformOptions::default();
+		Some(Self {
+			rgb: profile
+				.create_transform_8bit(Layout::Rgb, &srgb, Layout::Rgb, options)
+				.ok()?,
+			rgba: profile
+				.create_transform_8bit(Layout::Rgba, &srgb, Layout::Rgba, options)
+				.ok()?,
+		})
+	}
+
+	/// Convert an image to sRGB; the image is returned unchanged if the transform fails
+	pub fn convert(&self, img: DynamicImage) -> DynamicImage {
+		if img.color().has_alpha() {
+			let source = img.to_rgba8();
+			let mut converted = source.clone();
+			match self.rgba.transform(&source, &mut converted) {
+				Ok(()) => DynamicImage::ImageRgba8(converted),
+				Err(_) => img,
+			}
+		} else {
+			let source = img.to_rgb8();
+			let mut converted = source.clone();
+			match self.rgb.transform(&source, &mut converted) {
+				Ok(()) => DynamicImage::ImageRgb8(converted),
+				Err(_) => img,
+			}
+		}
+	}
+}
+
+/// Embedded color profile of a photo: its ICC profile, or for HEIF its NCLX primaries
+/// Embedded RAW previews and video frames are treated as sRGB and have none
+pub fn embedded_profile(file_path: &str, kind: SourceKind) -> Option<ColorProfile> {
+	match kind {
+		SourceKind::Heif => heif_color_profile(file_path),
+		SourceKind::Standard => {
+			let mut decoder = ImageReader::open(file_path)
+				.ok()?
+				.with_guessed_format()
+				.ok()?
+				.into_decoder()
+				.ok()?;
+			let icc = decoder.icc_profile().ok()??;
+			ColorProfile::new_from_slice(&icc).ok()
+		}
+		SourceKind::RawPreview | SourceKind::Video => None,
+	}
+}
+
+/// Build an sRGB converter for a photo with a non-sRGB embedded profile
+/// (e.g. Display P3 HEICs or Adobe RGB JPEGs)
+pub fn srgb_converter(file_path: &str, kind: SourceKind) -> Option<SrgbConverter> {
+	SrgbConverter::new(&embedded_profile(file_path, kind)?)
+}
+
+/// Convert a decoded photo to sRGB from the profile it was decoded with (see
+/// `decode_image_with_profile`), so wide-gamut photos hash, embed and render like they
+/// look; sRGB and untagged photos are returned as is
+pub fn convert_to_srgb(img: DynamicImage, profile: Option<&ColorProfile>) -> DynamicImage {
+	match profile.and_then(SrgbConverter::new) {
+		Some(converter) => converter.convert(img),
+		None => img,
+	}
+}
+
+/// Red, green and blue colorants of a matrix/shaper profile
+fn profile_colorants(profile: &ColorProfile) -> [Xyzd; 3] {
+	[
+		profile.red_colorant,
+		profile.green_colorant,
+		profile.blue_colorant,
+	]
+}
+
+/// Whether colorants are the primaries of a reference profile
+fn same_colorants(colorants: &[Xyzd; 3], reference: &ColorProfile) -> bool {
+	let close = |a: &Xyzd, b: &Xyzd| {
+		(a.x - b.x).abs() < COLORANT_TOLERANCE
+			&& (a.y - b.y).abs() < COLORANT_TOLERANCE
+			&& (a.z - b.z).abs() < COLORANT_TOLERANCE
+	};
+	colorants
+		.iter()
+		.zip(profile_colorants(reference).iter())
+		.all(|(a, b)| close(a, b))
+}
+
+/// Whether two matrix/shaper profiles have the same primaries
+fn same_primaries(profile: &ColorProfile, reference: &ColorProfile) -> bool {
+	profile.is_matrix_shaper() && same_colorants(&profile_colorants(profile), reference)
+}
+
+/// Color space of a photo's embedded profile
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+	Srgb,
+	DisplayP3,
+	AdobeRgb,
+	ProPhotoRgb,
+	Rec2020,
+	Grayscale,
+	Cmyk,
+	/// Another RGB profile, or one that isn't RGB, grayscale or CMYK
+	Other,
+}
+
+impl ColorSpace {
+	/// Display name, as in `ExifData.color_gamut`
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Srgb => "sRGB",
+			Self::DisplayP3 => "Display P3",
+			Self::AdobeRgb => "Adobe RGB",
+			Self::ProPhotoRgb => "ProPhoto RGB",
+			Self::Rec2020 => "Rec. 2020",
+			Self::Grayscale => "Grayscale",
+			Self::Cmyk => "CMYK",
+			Self::Other => "Other",
+		}
+	}
+
+	/// Whether this is a known space larger than sRGB; unrecognized profiles aren't
+	/// assumed to be
+	pub fn is_wide(self) -> bool {
+		matches!(
+			self,
+			Self::DisplayP3 | Self::AdobeRgb | Self::ProPhotoRgb | Self::Rec2020
+		)
+	}
+}
+
+/// Standard profile of HEIF NCLX color primaries (ITU-T H.273) wider than sRGB, which
+/// iPhones tag Display P3 photos with when they embed no ICC profile
+pub(crate) fn nclx_profile(color_primaries: u32) -> Option<ColorProfile> {
+	match color_primaries {
+		9 => Some(ColorProfile::new_bt2020()),
+		12 => Some(ColorProfile::new_display_p3()),
+		_ => None,
+	}
+}
+
+/// Name the color space of a profile from its data color space and, for RGB profiles, the
+/// colorants of its matrix (None for LUT-based profiles)
+/// Shared by `read_color_space` and EXIF extraction, which reads them with exiftool
+pub(crate) fn classify_colorants(
+	color_space: DataColorSpace,
+	colorants: Option<&[Xyzd; 3]>,
+) -> ColorSpace {
+	let references = [
+		(ColorSpace::Srgb, ColorProfile::new_srgb()),
+		(ColorSpace::DisplayP3, ColorProfile::new_display_p3()),
+		(ColorSpace::AdobeRgb, ColorProfile::new_adobe_rgb()),
+		(ColorSpace::ProPhotoRgb, ColorProfile::new_pro_photo_rgb()),
+		(ColorSpace::Rec2020, ColorProfile::new_bt2020()),
+	];
+	match color_space {
+		DataColorSpace::Gray => ColorSpace::Grayscale,
+		DataColorSpace::Cmyk => ColorSpace::Cmyk,
+		DataColorSpace::Rgb => colorants
+			.and_then(|colorants| {
+				references
+					.iter()
+					.find(|(_, reference)| same_colorants(colorants, reference))
+			})
+			.map_or(ColorSpace::Other, |(space, _)| *space),
+		_ => ColorSpace::Other,
+	}
+}
+
+/// Name the color space of a profile from its primaries
+pub(crate) fn classify_profile(profile: &ColorProfile) -> ColorSpace {
+	let colorants = profile
+		.is_matrix_shaper()
+		.then(|| profile_colorants(profile));
+	classify_colorants(profile.color_space, colorants.as_ref())
+}
+
+/// Color space of the profile embedded in a photo (e.g. Display P3 for iPhone HEICs), or
+/// null when it has none and is shown as sRGB
+#[cfg_attr(feature = "node", napi)]
+pub fn read_color_space(file_path: String) -> Option<ColorSpace> {
+	let kind = source_kind(&file_path)?;
+	embedded_profile(&file_path, kind).map(|profile| classify_profile(&profile))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn test_converts_wide_gamut_to_srgb() {
+		assert!(SrgbConverter::new(&ColorProfile::new_srgb()).is_none());
+
+		assert_eq!(
+			classify_profile(&ColorProfile::new_adobe_rgb()),
+			ColorSpace::AdobeRgb
+		);
+		assert_eq!(
+			classify_profile(&ColorProfile::new_pro_photo_rgb()),
+			ColorSpace::ProPhotoRgb
+		);
+		assert_eq!(
+			classify_colorants(DataColorSpace::Gray, None),
+			ColorSpace::Grayscale
+		);
+		assert_eq!(
+			classify_colorants(DataColorSpace::Rgb, None),
+			ColorSpace::Other
+		);
+
+		let converter = SrgbConverter::new(&ColorProfile::new_display_p3()).unwrap();
+		let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])));
+		let converted = converter.convert(red).to_rgb8();
+		// P3 red is outside sRGB, so it clips to full red
+		assert_eq!(converted.get_pixel(0, 0)[0], 255);
+
+		let gray = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([128, 128, 128])));
+		let converted = converter.convert(gray).to_rgb8();
+		let pixel = converted.get_pixel(0, 0);
+		assert!(pixel.0.iter().all(|&c| c.abs_diff(128) <= 1));
+	}
+}
@@ -0,0 +1,64 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::fs::File;
+use std::io::{self, Read};
+
+#[cfg(feature = "node")]
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+
+/// Bytes read at a time while hashing a file
+pub(crate) const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Hash of a file's bytes (BLAKE3, hex), read in chunks so large RAWs and videos aren't
+/// loaded whole
+/// Identical files hash the same whatever their name, dates or stripped EXIF; the same hash
+/// is used for `contentHash` in results, `hash_files` and the processing cache
+pub fn content_hash(file_path: &str) -> io::Result<String> {
+	let mut file = File::open(file_path)?;
+	let mut hasher = blake3::Hasher::new();
+	let mut chunk = vec![0; CHUNK_SIZE];
+	loop {
+		let read = file.read(&mut chunk)?;
+		if read == 0 {
+			break;
+		}
+		hasher.update(&chunk[..read]);
+	}
+	Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hash of contents held in memory, matching `content_hash` of a file with the same bytes
+pub fn content_hash_bytes(data: &[u8]) -> String {
+	blake3::hash(data).to_hex().to_string()
+}
+
+/// Hash a file's content, for exact-duplicate detection and tracking moved or renamed files
+#[cfg(feature = "node")]
+#[napi]
+pub fn compute_content_hash(file_path: String) -> napi::Result<String, ErrorCode> {
+	content_hash(&file_path).map_err(|e| {
+		let message = format!("Failed to read {}", file_path);
+		ProcessingError::io(&e, &message)
+			.at(ErrorStage::Read)
+			.into()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_streamed_hash_matches_bytes() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("a.jpg");
+		// Spans several chunks
+		let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 17).map(|i| i as u8).collect();
+		std::fs::write(&path, &data).unwrap();
+
+		let hash = content_hash(&path.to_string_lossy()).unwrap();
+		assert_eq!(hash, content_hash_bytes(&data));
+		assert_eq!(hash.len(), 64);
+		assert_ne!(hash, content_hash_bytes(&data[1..]));
+	}
+}
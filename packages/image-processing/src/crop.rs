@@ -0,0 +1,140 @@
+use image::{DynamicImage, GenericImageView, GrayImage};
+
+/// Longest edge the image is shrunk to before looking for detail
+const ANALYSIS_SIZE: u32 = 256;
+
+/// How strongly windows near the middle are preferred (0 = no preference)
+const CENTER_BIAS: f64 = 0.25;
+
+/// Square region of an image: left, top and side length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SquareRegion {
+	pub x: u32,
+	pub y: u32,
+	pub side: u32,
+}
+
+/// Largest centered square of an image
+pub fn center_square(width: u32, height: u32) -> SquareRegion {
+	let side = width.min(height);
+	SquareRegion {
+		x: (width - side) / 2,
+		y: (height - side) / 2,
+		side,
+	}
+}
+
+/// Largest square of an image placed over its most detailed region
+/// Detail is measured by gradient energy, with a slight pull towards the center so that
+/// plain backgrounds still give a centered crop
+pub fn smart_square(img: &DynamicImage) -> SquareRegion {
+	let (width, height) = img.dimensions();
+	let centered = center_square(width, height);
+	if width == height || width == 0 || height == 0 {
+		return centered;
+	}
+
+	let small = img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8();
+	let landscape = width > height;
+	let profile = detail_profile(&small, landscape);
+	if profile.iter().all(|&energy| energy == 0.0) {
+		return centered;
+	}
+	let small_short = small.width().min(small.height());
+	let window = (small_short as usize).clamp(1, profile.len());
+	let positions = profile.len() - window;
+	if positions == 0 {
+		return centered;
+	}
+	let offset = best_window(&profile, window);
+
+	// Map the offset back to full resolution
+	let max_offset = width.max(height) - centered.side;
+	let offset = (offset as f64 / positions as f64 * max_offset as f64).round() as u32;
+
+	if landscape {
+		SquareRegion {
+			x: offset,
+			..centered
+		}
+	} else {
+		SquareRegion {
+			y: offset,
+			..centered
+		}
+	}
+}
+
+/// Gradient energy of each column (landscape) or row (portrait)
+fn detail_profile(gray: &GrayImage, by_column: bool) -> Vec<f64> {
+	let (width, height) = gray.dimensions();
+	let mut profile = vec![0.0; if by_column { width } else { height } as usize];
+
+	for y in 1..height.saturating_sub(1) {
+		for x in 1..width.saturating_sub(1) {
+			let at = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+			let energy = (at(x + 1, y) - at(x - 1, y)).abs() + (at(x, y + 1) - at(x, y - 1)).abs();
+			profile[if by_column { x } else { y } as usize] += energy;
+		}
+	}
+	profile
+}
+
+/// Start of the window of `window` entries with the highest (center-weighted) score
+fn best_window(profile: &[f64], window: usize) -> usize {
+	let positions = profile.len().saturating_sub(window);
+	if positions == 0 {
+		return 0;
+	}
+
+	let mut sum: f64 = profile[..window].iter().sum();
+	let center = positions as f64 / 2.0;
+	let weight = |start: usize| 1.0 - CENTER_BIAS * ((start as f64 - center).abs() / center);
+
+	let mut best = (sum * weight(0), 0);
+	for start in 1..=positions {
+		sum += profile[start + window - 1] - profile[start - 1];
+		let score = sum * weight(start);
+		// Prefer the more central window on ties
+		if score > best.0
+			|| (score == best.0 && (start as f64 - center).abs() < (best.1 as f64 - center).abs())
+		{
+			best = (score, start);
+		}
+	}
+	best.1
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, RgbImage};
+
+	#[test]
+	fn test_best_window_follows_detail() {
+		let mut profile = vec![1.0; 10];
+		profile[8] = 50.0;
+		profile[9] = 50.0;
+		assert_eq!(best_window(&profile, 4), 6);
+		// Flat profile stays centered
+		assert_eq!(best_window(&[1.0; 10], 4), 3);
+	}
+
+	#[test]
+	fn test_smart_square_finds_detailed_side() {
+		// Plain landscape image with a checkerboard on its right end
+		let img = RgbImage::from_fn(600, 200, |x, y| {
+			if x >= 400 && (x / 10 + y / 10) % 2 == 0 {
+				image::Rgb([255, 255, 255])
+			} else {
+				image::Rgb([40, 40, 40])
+			}
+		});
+		let region = smart_square(&DynamicImage::ImageRgb8(img));
+		assert_eq!(region.side, 200);
+		assert!(region.x >= 350, "x = {}", region.x);
+
+		let plain = DynamicImage::ImageLuma8(GrayImage::from_pixel(200, 600, Luma([128])));
+		assert_eq!(smart_square(&plain), center_square(200, 600));
+	}
+}
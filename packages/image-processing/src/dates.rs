@@ -0,0 +1,248 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+
+use crate::exif::{date_taken_to_epoch_ms, DateTimeParts, ExifData};
+use crate::timezone::offset_minutes_at_local;
+
+/// Where a photo's resolved capture date came from, from most to least reliable
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSource {
+	DateTimeOriginal,
+	CreateDate,
+	GpsTimestamp,
+	Filename,
+	FileModified,
+}
+
+/// Best available capture date of a photo
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedDate {
+	/// ISO 8601, e.g. "2023-07-14T10:22:01" (UTC with a trailing "Z" for GPS and file dates)
+	pub date: String,
+	/// UTC epoch milliseconds
+	pub date_ms: f64,
+	pub source: DateSource,
+}
+
+/// Years accepted from filename patterns, to avoid matching unrelated digit runs
+const FILENAME_YEARS: std::ops::RangeInclusive<u32> = 1990..=2100;
+
+/// Pick the capture date: DateTimeOriginal -> CreateDate -> GPS timestamp -> filename -> mtime
+pub fn resolve_capture_date(
+	exif: Option<&ExifData>,
+	file_name: &str,
+	modified_at_ms: f64,
+) -> Option<ResolvedDate> {
+	// Local dates get the offset DateTimeOriginal has: the recorded one, else the offset of the
+	// timezone at the GPS position; without either they're read as UTC
+	let recorded_offset = exif.and_then(|e| e.date_taken_offset_minutes);
+	let timezone = exif
+		.and_then(|e| e.timezone.as_deref())
+		.and_then(|name| name.parse::<Tz>().ok());
+	let local = |date: &Option<String>, source| {
+		let date = date.clone()?;
+		let (local_ms, _) = date_taken_to_epoch_ms(&date, None, None)?;
+		let offset = recorded_offset
+			.or_else(|| timezone.and_then(|tz| offset_minutes_at_local(tz, local_ms)))
+			.unwrap_or(0);
+		Some(ResolvedDate {
+			date,
+			date_ms: local_ms - offset as f64 * 60_000.0,
+			source,
+		})
+	};
+	let utc = |date_ms: f64, source| {
+		Some(ResolvedDate {
+			date: epoch_ms_to_iso(date_ms)?,
+			date_ms,
+			source,
+		})
+	};
+
+	if let Some(exif) = exif {
+		if let (Some(date), Some(date_ms)) = (&exif.date_taken, exif.date_taken_ms) {
+			return Some(ResolvedDate {
+				date: date.clone(),
+				date_ms,
+				source: DateSource::DateTimeOriginal,
+			});
+		}
+		if let Some(resolved) = local(&exif.create_date, DateSource::CreateDate) {
+			return Some(resolved);
+		}
+		if let Some(resolved) = exif
+			.gps_date_time_ms
+			.and_then(|date_ms| utc(date_ms, DateSource::GpsTimestamp))
+		{
+			return Some(resolved);
+		}
+	}
+
+	local(&date_from_filename(file_name), DateSource::Filename).or_else(|| {
+		(modified_at_ms > 0.0)
+			.then(|| utc(modified_at_ms, DateSource::FileModified))
+			.flatten()
+	})
+}
+
+/// Find a capture date in common camera and app filename patterns
+/// e.g. "IMG_20230714_102201.jpg", "PXL_20230714_102201123.jpg",
+/// "Screenshot 2023-07-14 at 10.22.01.png", "VID-20230714-WA0001.mp4" (date only)
+pub fn date_from_filename(file_name: &str) -> Option<String> {
+	let bytes = file_name.as_bytes();
+	let digits = |start: usize, len: usize| -> Option<u32> {
+		let slice = bytes.get(start..start + len)?;
+		if !slice.iter().all(u8::is_ascii_digit) {
+			return None;
+		}
+		std::str::from_utf8(slice).ok()?.parse().ok()
+	};
+	let is_digit_at = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+
+	for start in 0..bytes.len() {
+		if start > 0 && is_digit_at(start - 1) {
+			continue;
+		}
+		let Some(year) = digits(start, 4).filter(|y| FILENAME_YEARS.contains(y)) else {
+			continue;
+		};
+
+		// "20230714" or "2023-07-14" / "2023_07_14" / "2023.07.14"
+		let separator = bytes.get(start + 4).copied();
+		let (month, day, end) = match separator {
+			Some(sep @ (b'-' | b'_' | b'.')) if bytes.get(start + 7) == Some(&sep) => {
+				(digits(start + 5, 2), digits(start + 8, 2), start + 10)
+			}
+			_ => (digits(start + 4, 2), digits(start + 6, 2), start + 8),
+		};
+		let (Some(month), Some(day)) = (month, day) else {
+			continue;
+		};
+		if is_digit_at(end) && separator.is_some_and(|s| !s.is_ascii_digit()) {
+			continue;
+		}
+
+		let (hour, minute, second) = time_from_filename(bytes, end).unwrap_or((0, 0, 0));
+		let candidate = format!(
+			"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+			year, month, day, hour, minute, second
+		);
+		if let Some(parts) = DateTimeParts::parse(&candidate) {
+			return Some(parts.to_iso());
+		}
+	}
+	None
+}
+
+/// Parse a time following a filename date: "_102201", "-10-22-01", " at 10.22.01", "102201"
+fn time_from_filename(bytes: &[u8], date_end: usize) -> Option<(u32, u32, u32)> {
+	// Skip a short separator ("_", "-", " ", "T", " at "), if any
+	let start = (date_end..(date_end + 5).min(bytes.len())).find(|&i| bytes[i].is_ascii_digit())?;
+
+	let number = |i: usize| -> Option<u32> {
+		let slice = bytes.get(i..i + 2)?;
+		if !slice.iter().all(u8::is_ascii_digit) {
+			return None;
+		}
+		Some(((slice[0] - b'0') * 10 + (slice[1] - b'0')) as u32)
+	};
+	let separated = matches!(bytes.get(start + 2), Some(b'.' | b'-' | b':' | b'_'));
+	let (hour, minute, second) = if separated {
+		(number(start)?, number(start + 3)?, number(start + 6)?)
+	} else {
+		(number(start)?, number(start + 2)?, number(start + 4)?)
+	};
+	(hour < 24 && minute < 60 && second < 60).then_some((hour, minute, second))
+}
+
+/// Format UTC epoch milliseconds as ISO 8601, e.g. "2023-07-14T10:22:01Z"
+pub fn epoch_ms_to_iso(epoch_ms: f64) -> Option<String> {
+	let date = DateTime::from_timestamp_millis(epoch_ms.floor() as i64)?;
+	Some(date.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_date_from_filename() {
+		let date = |name: &str| date_from_filename(name);
+		assert_eq!(
+			date("IMG_20230714_102201.jpg"),
+			Some("2023-07-14T10:22:01".to_string())
+		);
+		assert_eq!(
+			date("PXL_20230714_102201123.MP.jpg"),
+			Some("2023-07-14T10:22:01".to_string())
+		);
+		assert_eq!(
+			date("Screenshot 2023-07-14 at 10.22.01.png"),
+			Some("2023-07-14T10:22:01".to_string())
+		);
+		assert_eq!(
+			date("VID-20230714-WA0001.mp4"),
+			Some("2023-07-14T00:00:00".to_string())
+		);
+		assert_eq!(date("DSC_0042.NEF"), None);
+		assert_eq!(date("IMG_20231345_000000.jpg"), None);
+	}
+
+	#[test]
+	fn test_resolution_order() {
+		let exif = ExifData {
+			create_date: Some("2023-07-14T10:22:01".to_string()),
+			gps_date_time_ms: Some(1_689_322_921_000.0),
+			..Default::default()
+		};
+		let resolved = resolve_capture_date(Some(&exif), "IMG_20200101_000000.jpg", 1.0).unwrap();
+		assert_eq!(resolved.source, DateSource::CreateDate);
+
+		let resolved = resolve_capture_date(None, "DSC_0042.jpg", 1_689_330_121_000.0).unwrap();
+		assert_eq!(resolved.source, DateSource::FileModified);
+		assert_eq!(resolved.date, "2023-07-14T10:22:01Z");
+		assert!(resolve_capture_date(None, "DSC_0042.jpg", 0.0).is_none());
+	}
+
+	#[test]
+	fn test_mixed_sources_sort_in_utc() {
+		// Shot in Paris (UTC+2 in July) at 10:00, 09:30 and 09:45 local time
+		let paris = |exif: ExifData| ExifData {
+			timezone: Some("Europe/Paris".to_string()),
+			..exif
+		};
+		let original = paris(ExifData {
+			date_taken: Some("2023-07-14T10:00:00".to_string()),
+			date_taken_ms: Some(1_689_321_600_000.0),
+			date_taken_offset_inferred: Some(120),
+			..Default::default()
+		});
+		let digitized = paris(ExifData {
+			create_date: Some("2023-07-14T09:30:00".to_string()),
+			..Default::default()
+		});
+		let named = paris(ExifData::default());
+
+		let original = resolve_capture_date(Some(&original), "DSC_0001.jpg", 1.0).unwrap();
+		let digitized = resolve_capture_date(Some(&digitized), "DSC_0002.jpg", 1.0).unwrap();
+		let named = resolve_capture_date(Some(&named), "IMG_20230714_094500.jpg", 1.0).unwrap();
+		assert_eq!(digitized.source, DateSource::CreateDate);
+		assert_eq!(digitized.date, "2023-07-14T09:30:00");
+		assert_eq!(digitized.date_ms, 1_689_319_800_000.0);
+		assert_eq!(named.source, DateSource::Filename);
+		assert_eq!(named.date_ms, 1_689_320_700_000.0);
+		assert!(digitized.date_ms < named.date_ms && named.date_ms < original.date_ms);
+
+		// A recorded offset wins over the timezone
+		let recorded = paris(ExifData {
+			create_date: Some("2023-07-14T09:30:00".to_string()),
+			date_taken_offset_minutes: Some(-300),
+			..Default::default()
+		});
+		let recorded = resolve_capture_date(Some(&recorded), "DSC_0003.jpg", 1.0).unwrap();
+		assert_eq!(recorded.date_ms, 1_689_345_000_000.0);
+	}
+}
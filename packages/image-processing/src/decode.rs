@@ -0,0 +1,287 @@
+use image::{DynamicImage, ImageDecoder, ImageError, ImageReader};
+use moxcms::ColorProfile;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufRead, Cursor, Read, Seek};
+
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::features::{require, Component};
+use crate::heif::{
+	decode_heif, decode_heif_from_buffer, is_heif_by_magic_bytes, is_heif_bytes, is_heif_file,
+};
+use crate::preview::{extract_raw_preview, is_raw_file, raw_thumbnail_from_bytes};
+use crate::quality::sharpness;
+use crate::video::{extract_frame, is_video_file, poster_frame_seeks, probe_video};
+
+/// Standard image extensions (directly decodable by image crate)
+pub const STANDARD_EXTENSIONS: &[&str] = &[
+	".jpg", ".jpeg", ".png", ".gif", ".webp", ".bmp", ".tiff", ".tif",
+];
+
+/// Which decoder a file is routed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+	/// HEIC/HEIF decoded with libheif
+	Heif,
+	/// RAW file decoded from its embedded preview JPEG
+	RawPreview,
+	/// Format the image crate decodes directly
+	Standard,
+	/// Video container decoded from a poster frame with ffmpeg
+	Video,
+}
+
+impl SourceKind {
+	/// Whether files of this kind decode from their bytes once read into memory; RAW files
+	/// (often 50-100MB for a preview of a few MB) and videos are streamed from disk instead
+	pub fn decodes_from_memory(self) -> bool {
+		matches!(self, SourceKind::Heif | SourceKind::Standard)
+	}
+}
+
+/// Check if file is a standard image (directly decodable)
+pub fn is_standard_image(file_path: &str) -> bool {
+	let lower = file_path.to_lowercase();
+	STANDARD_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+/// Determine the decoder for a file
+/// Magic bytes are checked first to handle mislabeled HEIC files (e.g., iOS saving HEIC as .JPEG),
+/// and last for files without a known extension
+pub fn source_kind(file_path: &str) -> Option<SourceKind> {
+	if is_heif_file(file_path) || is_heif_by_magic_bytes(file_path) {
+		Some(SourceKind::Heif)
+	} else if is_raw_file(file_path) {
+		Some(SourceKind::RawPreview)
+	} else if is_standard_image(file_path) {
+		Some(SourceKind::Standard)
+	} else if is_video_file(file_path) {
+		Some(SourceKind::Video)
+	} else {
+		sniff_source_kind(file_path)
+	}
+}
+
+/// Determine the decoder for file contents held in memory, named by `virtual_path`
+/// Same order as `source_kind`, with the bytes checked instead of the file
+pub fn source_kind_from_bytes(virtual_path: &str, data: &[u8]) -> Option<SourceKind> {
+	if is_heif_file(virtual_path) || is_heif_bytes(data) {
+		Some(SourceKind::Heif)
+	} else if is_raw_file(virtual_path) {
+		Some(SourceKind::RawPreview)
+	} else if is_standard_image(virtual_path) {
+		Some(SourceKind::Standard)
+	} else if is_video_file(virtual_path) {
+		Some(SourceKind::Video)
+	} else {
+		sniff_bytes(data)
+	}
+}
+
+/// Determine the decoder from the first bytes of a file, e.g. one exported from a
+/// messaging app without an extension
+pub fn sniff_source_kind(file_path: &str) -> Option<SourceKind> {
+	let mut header = [0u8; 16];
+	let len = File::open(file_path)
+		.and_then(|mut file| file.read(&mut header))
+		.ok()?;
+	sniff_bytes(&header[..len])
+}
+
+/// Decoder for data starting with `header`, by its signature
+/// TIFF-based RAW files without a distinct signature (NEF, ARW, DNG) are read as TIFF
+fn sniff_bytes(header: &[u8]) -> Option<SourceKind> {
+	let tiff = header.starts_with(b"II*\0") || header.starts_with(b"MM\0*");
+	let raw = (tiff && header.get(8..10) == Some(b"CR"))
+		|| header.get(4..12) == Some(b"ftypcrx ")
+		|| header.starts_with(b"FUJIFILMCCD-RAW")
+		|| header.starts_with(b"IIRO")
+		|| header.starts_with(b"IIRS")
+		|| header.starts_with(b"MMOR")
+		|| header.starts_with(b"IIU\0");
+	let standard = tiff
+		|| header.starts_with(&[0xFF, 0xD8, 0xFF])
+		|| header.starts_with(b"\x89PNG\r\n\x1a\n")
+		|| header.starts_with(b"GIF8")
+		|| (header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP"))
+		|| header.starts_with(b"BM");
+
+	if is_heif_bytes(header) {
+		Some(SourceKind::Heif)
+	} else if raw {
+		Some(SourceKind::RawPreview)
+	} else if standard {
+		Some(SourceKind::Standard)
+	} else {
+		None
+	}
+}
+
+/// Error of data that failed to decode
+fn decode_failed(e: impl std::fmt::Display) -> ProcessingError {
+	ProcessingError::new(
+		ErrorCode::DecodeFailed,
+		format!("Failed to decode image: {}", e),
+	)
+}
+
+/// Error of the image crate, coded by its cause
+fn image_error(e: ImageError) -> ProcessingError {
+	match e {
+		ImageError::IoError(e) => ProcessingError::io(&e, "Failed to read image"),
+		ImageError::Unsupported(e) => ProcessingError::new(
+			ErrorCode::UnsupportedFormat,
+			format!("Failed to decode image: {}", e),
+		),
+		e => decode_failed(e),
+	}
+}
+
+/// Decode encoded image bytes, guessing the format from the content
+/// HEIF data goes through libheif
+pub fn decode_bytes(data: &[u8]) -> Result<DynamicImage, ProcessingError> {
+	let decoded = if is_heif_bytes(data) {
+		require(Component::Libheif)
+			.map_err(ProcessingError::from)
+			.and_then(|_| decode_heif_from_buffer(data).map_err(decode_failed))
+			.map(|decoded| decoded.image)
+	} else {
+		ImageReader::new(Cursor::new(data))
+			.with_guessed_format()
+			.map_err(|e| ProcessingError::io(&e, "Failed to read image"))
+			.and_then(|reader| reader.decode().map_err(image_error))
+	};
+	decoded.map_err(|e| e.at(ErrorStage::Decode))
+}
+
+/// Decode an image along with the ICC profile embedded in it
+fn decode_with_icc<R: BufRead + Seek>(
+	reader: ImageReader<R>,
+) -> Result<(DynamicImage, Option<ColorProfile>), ProcessingError> {
+	let mut decoder = reader.into_decoder().map_err(image_error)?;
+	let profile = decoder
+		.icc_profile()
+		.ok()
+		.flatten()
+		.and_then(|icc| ColorProfile::new_from_slice(&icc).ok());
+	let image = DynamicImage::from_decoder(decoder).map_err(image_error)?;
+	Ok((image, profile))
+}
+
+/// Decode a file with the decoder for its source kind
+/// The returned pixels are as stored; EXIF orientation is not applied
+/// Fails with a FeatureUnavailable error when the decoder's native component is missing
+pub fn decode_image(file_path: &str, kind: SourceKind) -> Result<DynamicImage, ProcessingError> {
+	decode_image_with_profile(file_path, kind).map(|(image, _)| image)
+}
+
+/// Decode a file like `decode_image`, along with its embedded color profile (see
+/// `embedded_profile`), read in the same pass instead of by opening the file again
+pub fn decode_image_with_profile(
+	file_path: &str,
+	kind: SourceKind,
+) -> Result<(DynamicImage, Option<ColorProfile>), ProcessingError> {
+	let decoded = match kind {
+		SourceKind::Heif => require(Component::Libheif)
+			.map_err(ProcessingError::from)
+			.and_then(|_| decode_heif(file_path).map_err(decode_failed))
+			.map(|decoded| (decoded.image, decoded.profile)),
+		SourceKind::RawPreview => match extract_raw_preview(file_path) {
+			Some(preview_bytes) => decode_bytes(&preview_bytes).map(|image| (image, None)),
+			None => Err(ProcessingError::new(
+				ErrorCode::DecodeFailed,
+				"No embedded preview found",
+			)),
+		},
+		// The content decides the format, so mislabeled and extensionless files decode too
+		SourceKind::Standard => ImageReader::open(file_path)
+			.and_then(|reader| reader.with_guessed_format())
+			.map_err(|e| ProcessingError::io(&e, "Failed to read image"))
+			.and_then(decode_with_icc),
+		SourceKind::Video => {
+			let duration = probe_video(file_path).and_then(|p| p.duration);
+			decode_poster_frame(file_path, duration).map(|image| (image, None))
+		}
+	};
+	decoded.map_err(|e| e.at(ErrorStage::Decode).for_file(file_path))
+}
+
+/// Decode a file whose contents were already read into memory, like
+/// `decode_image_with_profile` does from disk, so callers that also hash or sniff them read
+/// the file once
+/// Videos are still decoded from the file by ffmpeg, and RAW files whose preview LibRaw
+/// can't find fall back to exiftool on it
+pub fn decode_loaded_image(
+	file_path: &str,
+	data: &[u8],
+	kind: SourceKind,
+) -> Result<(DynamicImage, Option<ColorProfile>), ProcessingError> {
+	let decoded = match kind {
+		SourceKind::Heif => require(Component::Libheif)
+			.map_err(ProcessingError::from)
+			.and_then(|_| decode_heif_from_buffer(data).map_err(decode_failed))
+			.map(|decoded| (decoded.image, decoded.profile)),
+		SourceKind::RawPreview => {
+			match raw_thumbnail_from_bytes(data).or_else(|| extract_raw_preview(file_path)) {
+				Some(preview_bytes) => decode_bytes(&preview_bytes).map(|image| (image, None)),
+				None => Err(ProcessingError::new(
+					ErrorCode::DecodeFailed,
+					"No embedded preview found",
+				)),
+			}
+		}
+		SourceKind::Standard => ImageReader::new(Cursor::new(data))
+			.with_guessed_format()
+			.map_err(|e| ProcessingError::io(&e, "Failed to read image"))
+			.and_then(decode_with_icc),
+		SourceKind::Video => return decode_image_with_profile(file_path, kind),
+	};
+	decoded.map_err(|e| e.at(ErrorStage::Decode).for_file(file_path))
+}
+
+/// Decode a video's poster frame (already rotated upright by ffmpeg)
+/// Frames from several points of the clip are compared and the sharpest wins, so fades and
+/// motion-blurred moments don't end up as the thumbnail
+pub fn decode_poster_frame(
+	file_path: &str,
+	duration: Option<f64>,
+) -> Result<DynamicImage, ProcessingError> {
+	require(Component::Ffmpeg)?;
+	poster_frame_seeks(duration)
+		.par_iter()
+		.filter_map(|&seek| extract_frame(file_path, seek, None))
+		.filter_map(|frame_bytes| decode_bytes(&frame_bytes).ok())
+		.map(|frame| (sharpness(&frame), frame))
+		.max_by(|a, b| a.0.total_cmp(&b.0))
+		.map(|(_, frame)| frame)
+		.ok_or_else(|| {
+			ProcessingError::new(
+				ErrorCode::DecodeFailed,
+				"Failed to extract video frame (is ffmpeg installed?)",
+			)
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sniff_bytes() {
+		assert_eq!(
+			sniff_bytes(&[0xFF, 0xD8, 0xFF, 0xE1, 0, 0]),
+			Some(SourceKind::Standard)
+		);
+		assert_eq!(
+			sniff_bytes(b"\0\0\0\x18ftypheic\0\0\0\0"),
+			Some(SourceKind::Heif)
+		);
+		assert_eq!(
+			sniff_bytes(b"II*\0\x10\0\0\0CR\x02\0"),
+			Some(SourceKind::RawPreview)
+		);
+		assert_eq!(sniff_bytes(b"II*\0\x08\0\0\0"), Some(SourceKind::Standard));
+		assert_eq!(sniff_bytes(b"{\"name\": 1}"), None);
+		assert_eq!(sniff_bytes(b""), None);
+	}
+}
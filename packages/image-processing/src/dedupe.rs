@@ -0,0 +1,154 @@
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+use crate::cancellation::{is_cancelled, CancellationToken};
+use crate::content_hash::content_hash;
+
+/// Hash files in parallel, see `hash_files`
+/// Files not reached once `cancellation` is cancelled get None
+pub fn hash_files_internal(
+	file_paths: &[String],
+	cancellation: Option<&CancellationToken>,
+) -> Vec<Option<String>> {
+	file_paths
+		.par_iter()
+		.map(|file_path| {
+			if is_cancelled(cancellation) {
+				return None;
+			}
+			content_hash(file_path).ok()
+		})
+		.collect()
+}
+
+/// Hash the bytes of many files in parallel, for `find_exact_duplicates`
+/// Hashes are BLAKE3 (hex), the same as the `contentHash` of processing results
+/// Returns one hash per path, in order; unreadable files get null
+#[cfg_attr(feature = "node", napi)]
+pub fn hash_files(file_paths: Vec<String>) -> Vec<Option<String>> {
+	hash_files_internal(&file_paths, None)
+}
+
+/// Hashes files off the JS thread
+#[cfg(feature = "node")]
+pub struct HashFilesTask {
+	file_paths: Vec<String>,
+	cancellation: Option<CancellationToken>,
+}
+
+#[cfg(feature = "node")]
+impl Task for HashFilesTask {
+	type Output = Vec<Option<String>>;
+	type JsValue = Vec<Option<String>>;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		Ok(hash_files_internal(
+			&self.file_paths,
+			self.cancellation.as_ref(),
+		))
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+}
+
+/// Same as `hash_files`, off the JS thread, e.g. for a library on a network share
+/// Files not reached once `cancellation` is cancelled get null
+#[cfg(feature = "node")]
+#[napi]
+pub fn hash_files_async(
+	file_paths: Vec<String>,
+	cancellation: Option<&CancellationToken>,
+) -> AsyncTask<HashFilesTask> {
+	AsyncTask::new(HashFilesTask {
+		file_paths,
+		cancellation: cancellation.cloned(),
+	})
+}
+
+/// Group the positions of identical hashes, e.g. from `hash_files`
+/// Returns groups of 2 or more positions, each ascending, ordered by their first position;
+/// null hashes are skipped
+#[cfg_attr(feature = "node", napi)]
+pub fn find_exact_duplicates(hashes: Vec<Option<String>>) -> Vec<Vec<u32>> {
+	let mut by_hash: HashMap<&str, Vec<u32>> = HashMap::new();
+	for (index, hash) in hashes.iter().enumerate() {
+		if let Some(hash) = hash {
+			by_hash.entry(hash).or_default().push(index as u32);
+		}
+	}
+
+	let mut groups: Vec<Vec<u32>> = by_hash
+		.into_values()
+		.filter(|group| group.len() > 1)
+		.collect();
+	groups.sort_by_key(|group| group[0]);
+	groups
+}
+
+#[cfg(feature = "node")]
+pub struct FindExactDuplicatesTask {
+	hashes: Vec<Option<String>>,
+}
+
+#[cfg(feature = "node")]
+impl Task for FindExactDuplicatesTask {
+	type Output = Vec<Vec<u32>>;
+	type JsValue = Vec<Vec<u32>>;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		Ok(find_exact_duplicates(std::mem::take(&mut self.hashes)))
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+}
+
+/// Same as `find_exact_duplicates`, off the JS thread, e.g. for the hashes of a whole library
+#[cfg(feature = "node")]
+#[napi]
+pub fn find_exact_duplicates_async(
+	hashes: Vec<Option<String>>,
+) -> AsyncTask<FindExactDuplicatesTask> {
+	AsyncTask::new(FindExactDuplicatesTask { hashes })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::content_hash::{content_hash_bytes, CHUNK_SIZE};
+
+	#[test]
+	fn test_find_exact_duplicates() {
+		let dir = tempfile::tempdir().unwrap();
+		let data: Vec<u8> = (0..CHUNK_SIZE + 17).map(|i| i as u8).collect();
+		let mut paths = Vec::new();
+		for (name, bytes) in [
+			("a", &data[..]),
+			("b", &data[1..]),
+			("c", &data[..]),
+			("d", &data[1..]),
+		] {
+			let path = dir.path().join(name);
+			std::fs::write(&path, bytes).unwrap();
+			paths.push(path.to_string_lossy().to_string());
+		}
+		paths.push(dir.path().join("missing").to_string_lossy().to_string());
+
+		let hashes = hash_files(paths);
+		assert_eq!(
+			hashes[0].as_deref(),
+			Some(content_hash_bytes(&data).as_str())
+		);
+		assert_eq!(hashes[4], None);
+		assert_eq!(find_exact_duplicates(hashes), vec![vec![0, 2], vec![1, 3]]);
+	}
+}
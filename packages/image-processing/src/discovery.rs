@@ -1,63 +1,956 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
 use napi_derive::napi;
 use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
 use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use walkdir::{DirEntry, WalkDir};
 
 use crate::batch::is_supported_image;
+use crate::cancellation::{is_cancelled, CancellationToken};
+use crate::decode::sniff_source_kind;
+#[cfg(feature = "node")]
+use crate::error::reject_with_code;
+use crate::error::{ErrorCode, ProcessingError};
+use crate::heif::is_heif_file;
+use crate::live_photo::is_live_photo_pair;
+use crate::preview::is_raw_file;
+use crate::video::is_video_file;
+
+/// File at the top of a library listing extra exclude patterns, one per line
+const IGNORE_FILE: &str = ".photobrainignore";
+
+/// Folders that never hold library photos: NAS thumbnail caches and recycle bins,
+/// Lightroom previews
+const DEFAULT_EXCLUDES: &[&str] = &[
+	"@eaDir",
+	"#recycle",
+	"#snapshot",
+	"$RECYCLE.BIN",
+	"*.lrdata",
+];
+
+/// Companion files holding edits or metadata of a photo: XMP, Apple adjustments (AAE),
+/// DxO and RawTherapee settings, Canon video thumbnails
+const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "dop", "pp3", "thm"];
+
+/// Shortest time between two progress reports of a walk
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Longest gap between two shots of a burst, in milliseconds
+const BURST_MAX_GAP_MS: f64 = 1000.0;
+
+/// Fewest shots that make a burst
+const BURST_MIN_SHOTS: usize = 3;
+
+/// Discovery options
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct DiscoveryOptions {
+	/// Glob patterns of files and folders to skip, added to the .photobrainignore ones
+	/// Patterns without a slash match names anywhere ("@eaDir", "*.tmp"), others match
+	/// paths relative to the directory ("exports/**/small")
+	pub exclude: Option<Vec<String>>,
+	/// Follow symbolic links to files and folders (default true)
+	/// Each folder is walked once, so links back up the tree don't loop
+	pub follow_symlinks: Option<bool>,
+	/// Levels of subfolders to descend into; 0 only lists the directory itself
+	pub max_depth: Option<u32>,
+	/// Also count files per top-level folder in the summary
+	pub group_by_folder: Option<bool>,
+}
+
+/// Identity of a directory, to notice one reached again through a symlink
+#[cfg(unix)]
+fn directory_id(entry: &DirEntry) -> Option<(u64, u64)> {
+	use std::os::unix::fs::MetadataExt;
+	let metadata = entry.metadata().ok()?;
+	Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(entry: &DirEntry) -> Option<std::path::PathBuf> {
+	fs::canonicalize(entry.path()).ok()
+}
+
+/// Compiled exclude patterns
+pub(crate) struct Excludes {
+	names: GlobSet,
+	paths: GlobSet,
+}
+
+impl Excludes {
+	fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Result<Self, String> {
+		let mut names = GlobSetBuilder::new();
+		let mut paths = GlobSetBuilder::new();
+		for pattern in patterns {
+			let pattern = pattern.as_ref().trim().trim_end_matches('/');
+			let (builder, pattern) = match pattern.strip_prefix('/') {
+				Some(anchored) => (&mut paths, anchored),
+				None if pattern.contains('/') => (&mut paths, pattern),
+				None => (&mut names, pattern),
+			};
+			let glob = GlobBuilder::new(pattern)
+				.literal_separator(true)
+				.build()
+				.map_err(|e| format!("Invalid exclude pattern {}: {}", pattern, e))?;
+			builder.add(glob);
+		}
+
+		let build = |builder: GlobSetBuilder| {
+			builder
+				.build()
+				.map_err(|e| format!("Invalid exclude patterns: {}", e))
+		};
+		Ok(Self {
+			names: build(names)?,
+			paths: build(paths)?,
+		})
+	}
+
+	/// Whether a path relative to the directory is excluded
+	fn is_excluded(&self, relative: &Path) -> bool {
+		relative
+			.file_name()
+			.is_some_and(|name| self.names.is_match(name))
+			|| self.paths.is_match(relative)
+	}
+
+	/// Whether a path relative to the directory is excluded or inside an excluded folder,
+	/// for paths that don't come from a walk pruning excluded folders
+	pub(crate) fn is_excluded_or_inside(&self, relative: &Path) -> bool {
+		relative
+			.ancestors()
+			.any(|path| !path.as_os_str().is_empty() && self.is_excluded(path))
+	}
+}
+
+/// Exclude patterns of a library: the default ones, those of the .photobrainignore file at
+/// its top, then `extra`
+pub(crate) fn library_excludes(
+	directory: &Path,
+	extra: Option<Vec<String>>,
+) -> Result<Excludes, String> {
+	let ignored = fs::read_to_string(directory.join(IGNORE_FILE))
+		.map(|contents| parse_ignore_file(&contents))
+		.unwrap_or_default();
+	let patterns = DEFAULT_EXCLUDES
+		.iter()
+		.map(|pattern| pattern.to_string())
+		.chain(ignored)
+		.chain(extra.unwrap_or_default());
+	Excludes::new(patterns)
+}
+
+/// Patterns of an ignore file, skipping blank lines and # comments
+fn parse_ignore_file(contents: &str) -> Vec<String> {
+	contents
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(String::from)
+		.collect()
+}
 
 /// Result of directory discovery
-#[napi(object)]
+#[cfg_attr(feature = "node", napi(object))]
 pub struct DiscoveryResult {
 	pub file_paths: Vec<String>,
 	pub relative_paths: Vec<String>,
+	/// Size and timestamps of each file, read during the walk
+	/// Pass them to the batch functions (`BatchOptions.fileStats`) to skip a second stat
+	pub stats: Vec<FileStat>,
+	/// Files that belong together, to be stacked; a group's id is its index here
+	pub groups: Vec<AssetGroup>,
+	/// Group id of each file, if it's in one
+	pub group_ids: Vec<Option<u32>>,
+	/// Relative paths of the sidecars of each file (.xmp, .aae, ...)
+	pub sidecars: Vec<Vec<String>>,
+	/// Folders and links that couldn't be read and were skipped
+	pub errors: Vec<DiscoveryError>,
+	/// Overview of what was found, e.g. for an import preview
+	pub summary: DiscoverySummary,
 	pub total_count: u32,
 }
 
-/// Discover all supported image files in a directory (parallel)
-#[napi]
-pub fn discover_photos(directory: String) -> DiscoveryResult {
-	let base_path = Path::new(&directory);
+/// Number and total size of some of the discovered files
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCount {
+	pub name: String,
+	pub count: u32,
+	pub bytes: i64,
+}
 
-	// Use walkdir for fast directory traversal
-	let entries: Vec<DirEntry> = WalkDir::new(&directory)
-		.follow_links(true)
+/// Totals of discovered files, sorted by name
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoverySummary {
+	pub total_bytes: i64,
+	/// Files per type: "heif", "image", "raw" or "video"
+	pub types: Vec<FileCount>,
+	/// Files per lowercase extension, "" for files without one
+	pub extensions: Vec<FileCount>,
+	/// Range of modification times, in milliseconds since the epoch
+	pub earliest_modified_at: Option<f64>,
+	pub latest_modified_at: Option<f64>,
+	/// Files per top-level folder, "" for files directly in the directory
+	/// Only with the `groupByFolder` option
+	pub folders: Option<Vec<FileCount>>,
+}
+
+fn file_type(relative_path: &str) -> &'static str {
+	if is_heif_file(relative_path) {
+		"heif"
+	} else if is_raw_file(relative_path) {
+		"raw"
+	} else if is_video_file(relative_path) {
+		"video"
+	} else {
+		"image"
+	}
+}
+
+/// Summarize discovered files by type, extension and optionally top-level folder
+fn summarize(relative_paths: &[String], stats: &[FileStat], by_folder: bool) -> DiscoverySummary {
+	let mut types: BTreeMap<String, FileCount> = BTreeMap::new();
+	let mut extensions: BTreeMap<String, FileCount> = BTreeMap::new();
+	let mut folders: BTreeMap<String, FileCount> = BTreeMap::new();
+	let add = |counts: &mut BTreeMap<String, FileCount>, name: String, bytes: i64| {
+		let count = counts.entry(name.clone()).or_insert(FileCount {
+			name,
+			count: 0,
+			bytes: 0,
+		});
+		count.count += 1;
+		count.bytes += bytes;
+	};
+
+	for (relative_path, stat) in relative_paths.iter().zip(stats) {
+		let path = Path::new(relative_path);
+		add(&mut types, file_type(relative_path).to_string(), stat.size);
+		let extension = path.extension().unwrap_or_default();
+		add(
+			&mut extensions,
+			extension.to_string_lossy().to_lowercase(),
+			stat.size,
+		);
+		if by_folder {
+			let mut components = path.components();
+			let folder = match (components.next(), components.next()) {
+				(Some(folder), Some(_)) => folder.as_os_str().to_string_lossy().to_string(),
+				_ => String::new(),
+			};
+			add(&mut folders, folder, stat.size);
+		}
+	}
+
+	let modified = stats.iter().map(|stat| stat.modified_at);
+	DiscoverySummary {
+		total_bytes: stats.iter().map(|stat| stat.size).sum(),
+		types: types.into_values().collect(),
+		extensions: extensions.into_values().collect(),
+		earliest_modified_at: modified.clone().reduce(f64::min),
+		latest_modified_at: modified.reduce(f64::max),
+		folders: by_folder.then(|| folders.into_values().collect()),
+	}
+}
+
+/// Path skipped during discovery, e.g. a folder without read permission or on a lost mount
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone)]
+pub struct DiscoveryError {
+	pub path: String,
+	/// `PermissionDenied`, `NotFound` or `Io`
+	pub code: ErrorCode,
+	pub reason: String,
+}
+
+/// Why the files of a group belong together
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetGroupKind {
+	/// RAW file and the JPEG/HEIC the camera saved with it
+	RawJpeg,
+	/// Live Photo still and its video clip
+	LivePhoto,
+	/// Shots taken in quick succession
+	Burst,
+}
+
+/// Files shown as one stack, of which only the primary needs processing
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssetGroup {
+	pub kind: AssetGroupKind,
+	/// Index of the primary file in `filePaths`
+	pub primary: u32,
+	/// Indexes of all files of the group, primary included
+	pub members: Vec<u32>,
+}
+
+fn is_sidecar(path: &Path) -> bool {
+	path.extension().is_some_and(|ext| {
+		SIDECAR_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+	})
+}
+
+/// Sidecars of each file, matched within a folder by full file name ("IMG_0001.CR2.xmp")
+/// or else by name without extension ("IMG_0001.xmp", attached to all files named IMG_0001)
+/// Sidecars without a photo are left out
+fn attach_sidecars(relative_paths: &[String], sidecar_paths: &[String]) -> Vec<Vec<String>> {
+	let key = |path: &Path, name: &std::ffi::OsStr| {
+		let folder = path.parent().unwrap_or(Path::new("")).to_path_buf();
+		(folder, name.to_string_lossy().to_lowercase())
+	};
+	let mut by_name: HashMap<_, Vec<usize>> = HashMap::new();
+	let mut by_stem: HashMap<_, Vec<usize>> = HashMap::new();
+	for (index, relative_path) in relative_paths.iter().enumerate() {
+		let path = Path::new(relative_path);
+		if let (Some(name), Some(stem)) = (path.file_name(), path.file_stem()) {
+			by_name.entry(key(path, name)).or_default().push(index);
+			by_stem.entry(key(path, stem)).or_default().push(index);
+		}
+	}
+
+	let mut sidecars = vec![Vec::new(); relative_paths.len()];
+	for sidecar_path in sidecar_paths {
+		let path = Path::new(sidecar_path);
+		let Some(base) = path.file_stem().map(|stem| key(path, stem)) else {
+			continue;
+		};
+		let owners = by_name.get(&base).or_else(|| by_stem.get(&base));
+		for &index in owners.into_iter().flatten() {
+			sidecars[index].push(sidecar_path.clone());
+		}
+	}
+	sidecars
+}
+
+/// Files of one folder sharing a name, e.g. IMG_0001.CR2 and IMG_0001.JPG
+struct Sibling<'a> {
+	folder: &'a Path,
+	stem: String,
+	members: Vec<usize>,
+	primary: usize,
+	kind: Option<AssetGroupKind>,
+}
+
+/// Name prefix and number of a sequentially named file, e.g. ("IMG_", 1234)
+fn sequence_number(stem: &str) -> Option<(&str, u64)> {
+	let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+	let number = stem[prefix.len()..].parse().ok()?;
+	Some((prefix, number))
+}
+
+/// Group RAW+JPEG pairs and Live Photos by folder and file name, then bursts of
+/// consecutively numbered shots taken at most `BURST_MAX_GAP_MS` apart
+/// A still and a video only make a Live Photo when `is_live_photo` confirms them (by index)
+/// The still (JPEG/HEIC) is the primary of a pair, the first shot that of a burst
+fn group_assets(
+	relative_paths: &[String],
+	stats: &[FileStat],
+	is_live_photo: impl Fn(usize, usize) -> bool + Sync,
+) -> Vec<AssetGroup> {
+	let mut by_name: BTreeMap<(&Path, String), Vec<usize>> = BTreeMap::new();
+	for (index, relative_path) in relative_paths.iter().enumerate() {
+		let path = Path::new(relative_path);
+		let folder = path.parent().unwrap_or(Path::new(""));
+		let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+		by_name
+			.entry((folder, stem.to_string()))
+			.or_default()
+			.push(index);
+	}
+
+	let is_raw = |&i: &usize| is_raw_file(&relative_paths[i]);
+	let is_video = |&i: &usize| is_video_file(&relative_paths[i]);
+	let named: Vec<_> = by_name
 		.into_iter()
-		.filter_entry(|e: &DirEntry| {
-			// Skip hidden directories
-			!e.file_name()
-				.to_str()
-				.map(|s: &str| s.starts_with('.'))
-				.unwrap_or(false)
+		.map(|((folder, stem), members)| {
+			let still = members.iter().copied().find(|i| !is_raw(i) && !is_video(i));
+			(folder, stem, members, still)
+		})
+		.collect();
+
+	// Confirm the Live Photos among stills with a same-named video, in parallel
+	let candidates: Vec<(usize, usize)> = named
+		.iter()
+		.filter_map(|(.., members, still)| still.map(|still| (still, members)))
+		.flat_map(|(still, members)| {
+			members
+				.iter()
+				.filter(|i| is_video(i))
+				.map(move |&video| (still, video))
+		})
+		.collect();
+	let live_videos: HashSet<usize> = candidates
+		.into_par_iter()
+		.filter(|&(still, video)| is_live_photo(still, video))
+		.map(|(_, video)| video)
+		.collect();
+
+	let mut siblings = Vec::new();
+	for (folder, stem, members, still) in named {
+		let related = |i: &usize| (still.is_some() && !is_video(i)) || live_videos.contains(i);
+		let (related, unrelated): (Vec<usize>, Vec<usize>) = members.into_iter().partition(related);
+		let kind = if related.iter().any(is_video) {
+			Some(AssetGroupKind::LivePhoto)
+		} else if related.iter().any(is_raw) {
+			Some(AssetGroupKind::RawJpeg)
+		} else {
+			None
+		};
+		let singles = match (kind, still) {
+			(Some(_), Some(primary)) => {
+				siblings.push(Sibling {
+					folder,
+					stem: stem.clone(),
+					members: related,
+					primary,
+					kind,
+				});
+				unrelated
+			}
+			// Same name but unrelated, e.g. a video and a RAW file, or a lone file
+			_ => related.into_iter().chain(unrelated).collect(),
+		};
+		siblings.extend(singles.into_iter().map(|index| Sibling {
+			folder,
+			stem: stem.clone(),
+			members: vec![index],
+			primary: index,
+			kind: None,
+		}));
+	}
+
+	// Bursts: runs of stills with consecutive numbers in one folder
+	let mut numbered: Vec<(&Path, &str, u64, usize)> = siblings
+		.iter()
+		.enumerate()
+		.filter(|(_, sibling)| !is_video_file(&relative_paths[sibling.primary]))
+		.filter_map(|(i, sibling)| {
+			let (prefix, number) = sequence_number(&sibling.stem)?;
+			Some((sibling.folder, prefix, number, i))
 		})
-		.filter_map(|e: Result<DirEntry, walkdir::Error>| e.ok())
-		.filter(|e: &DirEntry| e.file_type().is_file())
 		.collect();
+	numbered.sort();
+
+	let mut groups = Vec::new();
+	let mut in_burst = vec![false; siblings.len()];
+	let mut start = 0;
+	for end in 1..=numbered.len() {
+		let continues = numbered
+			.get(end)
+			.is_some_and(|&(folder, prefix, number, i)| {
+				let (prev_folder, prev_prefix, prev_number, prev) = numbered[end - 1];
+				let gap = stats[siblings[i].primary].modified_at
+					- stats[siblings[prev].primary].modified_at;
+				folder == prev_folder
+					&& prefix == prev_prefix
+					&& number == prev_number + 1
+					&& gap.abs() <= BURST_MAX_GAP_MS
+			});
+		if continues {
+			continue;
+		}
+		if end - start >= BURST_MIN_SHOTS {
+			let shots = &numbered[start..end];
+			let mut members: Vec<u32> = shots
+				.iter()
+				.flat_map(|&(.., i)| siblings[i].members.iter().map(|&m| m as u32))
+				.collect();
+			members.sort_unstable();
+			groups.push(AssetGroup {
+				kind: AssetGroupKind::Burst,
+				primary: siblings[shots[0].3].primary as u32,
+				members,
+			});
+			for &(.., i) in shots {
+				in_burst[i] = true;
+			}
+		}
+		start = end;
+	}
+
+	groups.extend(
+		siblings
+			.iter()
+			.zip(in_burst)
+			.filter(|(_, in_burst)| !in_burst)
+			.filter_map(|(sibling, _)| {
+				Some(AssetGroup {
+					kind: sibling.kind?,
+					primary: sibling.primary as u32,
+					members: sibling.members.iter().map(|&m| m as u32).collect(),
+				})
+			}),
+	);
+	groups.sort_by_key(|group| group.primary);
+	groups
+}
+
+/// File size and timestamps, in milliseconds since the epoch
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileStat {
+	pub size: i64,
+	/// Birth time where the filesystem keeps one, else 0
+	pub created_at: f64,
+	pub modified_at: f64,
+	/// Inode change time (ctime), only on Unix
+	pub changed_at: Option<f64>,
+}
+
+fn millis(time: std::io::Result<SystemTime>) -> Option<f64> {
+	time.ok()
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_millis() as f64)
+}
+
+/// Size and timestamps of a file from its metadata
+pub fn file_stat(metadata: &fs::Metadata) -> FileStat {
+	#[cfg(unix)]
+	let changed_at = {
+		use std::os::unix::fs::MetadataExt;
+		Some(metadata.ctime() as f64 * 1000.0 + (metadata.ctime_nsec() / 1_000_000) as f64)
+	};
+	#[cfg(not(unix))]
+	let changed_at = None;
+
+	FileStat {
+		size: metadata.len() as i64,
+		created_at: millis(metadata.created()).unwrap_or(0.0),
+		modified_at: millis(metadata.modified()).unwrap_or(0.0),
+		changed_at,
+	}
+}
+
+/// Progress of a directory walk so far
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiscoveryProgress {
+	pub directories_scanned: u32,
+	pub files_seen: u32,
+	/// Files with a supported extension; the final report also counts sniffed files
+	pub supported_files: u32,
+}
+
+/// Error returned by a cancelled walk; a partial listing would look like deleted files
+fn cancelled_error() -> ProcessingError {
+	ProcessingError::new(ErrorCode::Cancelled, "Discovery was cancelled")
+}
+
+/// Walk a directory, reporting progress at most every `PROGRESS_INTERVAL` and once at the end
+/// See `discover_photos`
+pub fn discover_photos_internal(
+	directory: &str,
+	options: DiscoveryOptions,
+	cancellation: Option<&CancellationToken>,
+	mut on_progress: impl FnMut(DiscoveryProgress),
+) -> Result<DiscoveryResult, ProcessingError> {
+	let base_path = Path::new(directory);
+
+	let excludes = library_excludes(base_path, options.exclude)
+		.map_err(|e| ProcessingError::new(ErrorCode::InvalidArgument, e))?;
 
-	// Filter for supported images in parallel
-	let results: Vec<(String, String)> = entries
+	let follow_symlinks = options.follow_symlinks.unwrap_or(true);
+	let group_by_folder = options.group_by_folder.unwrap_or(false);
+	let mut walker = WalkDir::new(directory).follow_links(follow_symlinks);
+	if let Some(max_depth) = options.max_depth {
+		// Files of the deepest folders sit one level below it
+		walker = walker.max_depth(max_depth as usize + 1);
+	}
+	let mut visited_dirs = HashSet::new();
+	let mut progress = DiscoveryProgress::default();
+	let mut last_report = Instant::now();
+
+	// Use walkdir for fast directory traversal
+	let mut entries = Vec::new();
+	let mut errors = Vec::new();
+	let walk = walker.into_iter().filter_entry(|e: &DirEntry| {
+		// Skip hidden directories
+		let hidden = e
+			.file_name()
+			.to_str()
+			.map(|s: &str| s.starts_with('.'))
+			.unwrap_or(false);
+		let excluded = e.depth() > 0
+			&& e.path()
+				.strip_prefix(base_path)
+				.is_ok_and(|relative| excludes.is_excluded(relative));
+		// Skip folders already walked, e.g. reached again through a symlink cycle
+		let revisited = follow_symlinks
+			&& e.file_type().is_dir()
+			&& directory_id(e).is_some_and(|id| !visited_dirs.insert(id));
+		!hidden && !excluded && !revisited
+	});
+	for entry in walk {
+		if is_cancelled(cancellation) {
+			return Err(cancelled_error());
+		}
+		let entry = match entry {
+			Ok(entry) => entry,
+			// Symlink cycles are expected and already cut short
+			Err(e) if e.loop_ancestor().is_some() => continue,
+			Err(e) => {
+				errors.push(DiscoveryError {
+					path: e.path().unwrap_or(base_path).to_string_lossy().to_string(),
+					code: e.io_error().map_or(ErrorCode::Io, ErrorCode::from_io),
+					reason: e
+						.io_error()
+						.map_or_else(|| e.to_string(), |io_error| io_error.to_string()),
+				});
+				continue;
+			}
+		};
+		if entry.file_type().is_dir() {
+			progress.directories_scanned += 1;
+		} else if entry.file_type().is_file() {
+			progress.files_seen += 1;
+			if is_supported_image(entry.path().to_string_lossy().to_string()) {
+				progress.supported_files += 1;
+			}
+			entries.push(entry);
+		}
+		if last_report.elapsed() >= PROGRESS_INTERVAL {
+			on_progress(progress);
+			last_report = Instant::now();
+		}
+	}
+	// Sidecars are never photos themselves, even a THM thumbnail with JPEG content
+	let (sidecar_entries, entries): (Vec<DirEntry>, Vec<DirEntry>) =
+		entries.into_iter().partition(|e| is_sidecar(e.path()));
+	let relative = |path: &Path| {
+		path.strip_prefix(base_path)
+			.unwrap_or(path)
+			.to_string_lossy()
+			.to_string()
+	};
+
+	// Filter for supported images in parallel, reading their metadata while at it
+	// Files that vanished since the walk have none and are dropped
+	let results: Vec<(String, String, FileStat)> = entries
 		.par_iter()
 		.filter_map(|entry: &DirEntry| {
+			if is_cancelled(cancellation) {
+				return None;
+			}
 			let path = entry.path();
 			let path_str = path.to_string_lossy().to_string();
 
-			if is_supported_image(path_str.clone()) {
-				let relative = path
-					.strip_prefix(base_path)
-					.map(|p: &Path| p.to_string_lossy().to_string())
-					.unwrap_or_else(|_| path_str.clone());
-				Some((path_str, relative))
+			// Files without a known extension are kept if their content is an image
+			if is_supported_image(path_str.clone()) || sniff_source_kind(&path_str).is_some() {
+				let stat = file_stat(&entry.metadata().ok()?);
+				Some((path_str, relative(path), stat))
 			} else {
 				None
 			}
 		})
 		.collect();
+	if is_cancelled(cancellation) {
+		return Err(cancelled_error());
+	}
 
 	let total_count = results.len() as u32;
-	let (file_paths, relative_paths): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+	on_progress(DiscoveryProgress {
+		supported_files: total_count,
+		..progress
+	});
+	let mut file_paths = Vec::with_capacity(results.len());
+	let mut relative_paths = Vec::with_capacity(results.len());
+	let mut stats = Vec::with_capacity(results.len());
+	for (file_path, relative_path, stat) in results {
+		file_paths.push(file_path);
+		relative_paths.push(relative_path);
+		stats.push(stat);
+	}
+
+	let sidecar_paths: Vec<String> = sidecar_entries.iter().map(|e| relative(e.path())).collect();
+	let sidecars = attach_sidecars(&relative_paths, &sidecar_paths);
+	let summary = summarize(&relative_paths, &stats, group_by_folder);
+	let groups = group_assets(&relative_paths, &stats, |still, video| {
+		is_live_photo_pair(&file_paths[still], &file_paths[video])
+	});
+	let mut group_ids = vec![None; file_paths.len()];
+	for (id, group) in groups.iter().enumerate() {
+		for &member in &group.members {
+			group_ids[member as usize] = Some(id as u32);
+		}
+	}
 
-	DiscoveryResult {
+	Ok(DiscoveryResult {
 		file_paths,
 		relative_paths,
+		stats,
+		groups,
+		group_ids,
+		sidecars,
+		errors,
+		summary,
 		total_count,
+	})
+}
+
+/// Discover all supported image files in a directory (parallel)
+/// Skips hidden folders, NAS caches, recycle bins, Lightroom previews, and anything matching
+/// the exclude patterns or the .photobrainignore file at the top of the directory
+/// Blocks the JS thread until done, see `discover_photos_async` to cancel a walk
+#[cfg(feature = "node")]
+#[napi]
+pub fn discover_photos(
+	directory: String,
+	options: Option<DiscoveryOptions>,
+) -> napi::Result<DiscoveryResult, ErrorCode> {
+	Ok(discover_photos_internal(
+		&directory,
+		options.unwrap_or_default(),
+		None,
+		|_| {},
+	)?)
+}
+
+/// Walks a directory off the JS thread
+#[cfg(feature = "node")]
+pub struct DiscoverPhotosTask {
+	directory: String,
+	options: Option<DiscoveryOptions>,
+	on_progress: Option<ThreadsafeFunction<DiscoveryProgress>>,
+	cancellation: Option<CancellationToken>,
+}
+
+#[cfg(feature = "node")]
+impl Task for DiscoverPhotosTask {
+	type Output = DiscoveryResult;
+	type JsValue = DiscoveryResult;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		let options = self.options.take().unwrap_or_default();
+		let result = discover_photos_internal(
+			&self.directory,
+			options,
+			self.cancellation.as_ref(),
+			|progress| {
+				if let Some(on_progress) = &self.on_progress {
+					on_progress.call(Ok(progress), ThreadsafeFunctionCallMode::NonBlocking);
+				}
+			},
+		)?;
+		Ok(result)
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+
+	fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+		reject_with_code(env, err)
+	}
+}
+
+/// Discover photos like `discover_photos`, in the background, for walks of network shares
+/// that take minutes
+/// The optional callback receives the counts so far a few times a second
+/// Fails once `cancellation` is cancelled
+#[cfg(feature = "node")]
+#[napi]
+pub fn discover_photos_async(
+	directory: String,
+	options: Option<DiscoveryOptions>,
+	#[napi(ts_arg_type = "(err: Error | null, progress: DiscoveryProgress) => void")]
+	on_progress: Option<ThreadsafeFunction<DiscoveryProgress>>,
+	cancellation: Option<&CancellationToken>,
+) -> AsyncTask<DiscoverPhotosTask> {
+	AsyncTask::new(DiscoverPhotosTask {
+		directory,
+		options,
+		on_progress,
+		cancellation: cancellation.cloned(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_summarize() {
+		let paths = ["a.JPG", "2023/b.jpg", "2024/trip/c.CR2", "2024/d.mov"].map(String::from);
+		let stats =
+			[(100, 3.0), (200, 1.0), (300, 4.0), (400, 2.0)].map(|(size, modified_at)| FileStat {
+				size,
+				created_at: 0.0,
+				modified_at,
+				changed_at: None,
+			});
+		let summary = summarize(&paths, &stats, true);
+		let counts = |counts: &[FileCount]| {
+			counts
+				.iter()
+				.map(|c| (c.name.clone(), c.count, c.bytes))
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(summary.total_bytes, 1000);
+		assert_eq!(
+			counts(&summary.types),
+			[
+				("image".into(), 2, 300),
+				("raw".into(), 1, 300),
+				("video".into(), 1, 400)
+			]
+		);
+		assert_eq!(
+			counts(&summary.extensions),
+			[
+				("cr2".into(), 1, 300),
+				("jpg".into(), 2, 300),
+				("mov".into(), 1, 400)
+			]
+		);
+		assert_eq!(
+			counts(&summary.folders.unwrap()),
+			[
+				("".into(), 1, 100),
+				("2023".into(), 1, 200),
+				("2024".into(), 2, 700)
+			]
+		);
+		assert_eq!(summary.earliest_modified_at, Some(1.0));
+		assert_eq!(summary.latest_modified_at, Some(4.0));
+		assert_eq!(summarize(&paths, &stats, false).folders, None);
+	}
+
+	#[test]
+	fn test_attach_sidecars() {
+		let photos =
+			["a/IMG_1.CR2", "a/IMG_1.JPG", "a/IMG_2.HEIC", "b/IMG_2.HEIC"].map(String::from);
+		let sidecars = [
+			"a/IMG_1.xmp",
+			"a/IMG_1.CR2.dop",
+			"a/IMG_2.AAE",
+			"a/IMG_3.xmp",
+		]
+		.map(String::from);
+		assert_eq!(
+			attach_sidecars(&photos, &sidecars),
+			vec![
+				vec!["a/IMG_1.xmp", "a/IMG_1.CR2.dop"],
+				vec!["a/IMG_1.xmp"],
+				vec!["a/IMG_2.AAE"],
+				vec![],
+			]
+		);
+	}
+
+	#[test]
+	fn test_group_assets() {
+		let files = [
+			("2024/IMG_0001.CR2", 0.0),
+			("2024/IMG_0001.JPG", 0.0),
+			("2024/IMG_0002.HEIC", 60_000.0),
+			("2024/IMG_0002.MOV", 60_000.0),
+			("2024/IMG_0003.HEIC", 120_000.0),
+			("burst/DSC_0010.JPG", 0.0),
+			("burst/DSC_0011.JPG", 200.0),
+			("burst/DSC_0012.JPG", 400.0),
+			("burst/DSC_0013.JPG", 9_000.0),
+			("burst/DSC_0013.mp4", 9_000.0),
+		];
+		let relative_paths: Vec<String> = files.iter().map(|(path, _)| path.to_string()).collect();
+		let stats: Vec<FileStat> = files
+			.iter()
+			.map(|&(_, modified_at)| FileStat {
+				size: 0,
+				created_at: 0.0,
+				modified_at,
+				changed_at: None,
+			})
+			.collect();
+
+		// DSC_0013.mp4 isn't the clip of DSC_0013.JPG, only the HEIC and MOV pair up
+		let groups = group_assets(&relative_paths, &stats, |_, video| video == 3);
+		let summary: Vec<_> = groups
+			.iter()
+			.map(|group| (group.kind, group.primary, group.members.clone()))
+			.collect();
+		assert_eq!(
+			summary,
+			vec![
+				(AssetGroupKind::RawJpeg, 1, vec![0, 1]),
+				(AssetGroupKind::LivePhoto, 2, vec![2, 3]),
+				(AssetGroupKind::Burst, 5, vec![5, 6, 7]),
+			]
+		);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_traversal_controls() {
+		let dir = tempfile::tempdir().unwrap();
+		let library = dir.path().join("library");
+		let nested = library.join("2024").join("trip");
+		fs::create_dir_all(&nested).unwrap();
+		fs::write(library.join("a.jpg"), b"").unwrap();
+		fs::write(nested.join("b.jpg"), b"").unwrap();
+		// A link back to the top of the library
+		std::os::unix::fs::symlink(&library, nested.join("loop")).unwrap();
+
+		let discovered = |options: DiscoveryOptions| {
+			let directory = library.to_string_lossy();
+			let mut paths = discover_photos_internal(&directory, options, None, |_| {})
+				.unwrap()
+				.relative_paths;
+			paths.sort();
+			paths
+		};
+		assert_eq!(
+			discovered(DiscoveryOptions::default()),
+			vec!["2024/trip/b.jpg", "a.jpg"]
+		);
+		assert_eq!(
+			discovered(DiscoveryOptions {
+				max_depth: Some(1),
+				..Default::default()
+			}),
+			vec!["a.jpg"]
+		);
+
+		// A broken link is reported rather than silently dropped
+		std::os::unix::fs::symlink(dir.path().join("gone"), library.join("lost")).unwrap();
+		let directory = library.to_string_lossy();
+		let result =
+			discover_photos_internal(&directory, Default::default(), None, |_| {}).unwrap();
+		assert_eq!(result.total_count, 2);
+		assert_eq!(result.errors.len(), 1);
+		assert!(result.errors[0].path.ends_with("lost"));
+	}
+
+	#[test]
+	fn test_excludes() {
+		let ignored = parse_ignore_file("# NAS\n@eaDir\n\n/exports/small/\nraw/**/*.xmp\n");
+		assert_eq!(ignored, vec!["@eaDir", "/exports/small/", "raw/**/*.xmp"]);
+
+		let excludes = Excludes::new(DEFAULT_EXCLUDES.iter().copied().chain(["*.tmp"])).unwrap();
+		assert!(excludes.is_excluded(Path::new("2024/@eaDir")));
+		assert!(excludes.is_excluded(Path::new("Lightroom Catalog Previews.lrdata")));
+		assert!(excludes.is_excluded(Path::new("trip/IMG_1.tmp")));
+		assert!(!excludes.is_excluded(Path::new("trip/IMG_1.jpg")));
+		assert!(excludes.is_excluded_or_inside(Path::new("2024/@eaDir/IMG_1.jpg")));
+		assert!(!excludes.is_excluded_or_inside(Path::new("2024/IMG_1.jpg")));
+
+		let excludes = Excludes::new(&ignored).unwrap();
+		assert!(excludes.is_excluded(Path::new("exports/small")));
+		assert!(!excludes.is_excluded(Path::new("2024/exports/small")));
+		assert!(excludes.is_excluded(Path::new("raw/2024/trip/IMG_1.xmp")));
+		assert!(Excludes::new(["[unclosed"]).is_err());
 	}
 }
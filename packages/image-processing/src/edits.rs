@@ -0,0 +1,436 @@
+use image::{imageops, imageops::FilterType, DynamicImage, ImageFormat, RgbaImage};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+
+use crate::clip::decode_upright;
+#[cfg(feature = "node")]
+use crate::error::reject_with_code;
+use crate::error::{ErrorCode, ProcessingError};
+use crate::preview::PreviewImage;
+use crate::thumbnails::{encode_thumbnail, unsharp_mask, ThumbnailFormat, UnsharpMask};
+
+/// JPEG/WebP/AVIF quality of rendered edits when none is given
+const DEFAULT_QUALITY: u8 = 90;
+
+/// Largest straightening angle, in degrees
+const MAX_STRAIGHTEN_DEGREES: f64 = 45.0;
+
+/// Largest highlights/shadows change at the middle of their range (0-1 of the tonal range)
+const TONE_RANGE_STRENGTH: f64 = 0.15;
+
+/// Gain per channel, in stops, of temperature or tint at 100
+const WHITE_BALANCE_STOPS: f64 = 0.5;
+
+/// Part of an image kept, as fractions (0-1) of the rotated and straightened image
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditCrop {
+	pub x: f64,
+	pub y: f64,
+	pub width: f64,
+	pub height: f64,
+}
+
+/// Point of a tone curve, input to output level (0-1)
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+	pub x: f64,
+	pub y: f64,
+}
+
+/// Non-destructive edits of a photo, as the app stores them
+/// Sliders go from -100 to 100 like `AutoEnhance`, which can be applied as is; omitted
+/// edits leave the photo unchanged
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditRecipe {
+	/// Clockwise quarter turns
+	pub rotate: Option<u32>,
+	/// Clockwise rotation in degrees (-45 to 45), zoomed in so no corners are empty
+	pub straighten: Option<f64>,
+	pub crop: Option<EditCrop>,
+	/// Exposure change in stops
+	pub exposure: Option<f64>,
+	pub contrast: Option<f64>,
+	pub highlights: Option<f64>,
+	pub shadows: Option<f64>,
+	/// Positive to warm, negative to cool
+	pub temperature: Option<f64>,
+	/// Positive toward magenta, negative toward green
+	pub tint: Option<f64>,
+	/// Points of a curve applied to every channel, sorted by input; smooth between points
+	pub tone_curve: Option<Vec<CurvePoint>>,
+	pub saturation: Option<f64>,
+	/// Applied last, at the output size
+	pub sharpen: Option<UnsharpMask>,
+}
+
+/// sRGB value (0-1) to linear light
+fn srgb_to_linear(v: f64) -> f64 {
+	if v <= 0.04045 {
+		v / 12.92
+	} else {
+		((v + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Linear light to sRGB value (0-1)
+fn linear_to_srgb(v: f64) -> f64 {
+	if v <= 0.0031308 {
+		v * 12.92
+	} else {
+		1.055 * v.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+/// Monotone cubic interpolation (Fritsch-Carlson) of a tone curve, so curves never
+/// overshoot between points; flat before the first point and after the last
+fn evaluate_curve(points: &[(f64, f64)], x: f64) -> f64 {
+	let (first, last) = match (points.first(), points.last()) {
+		(Some(first), Some(last)) => (*first, *last),
+		_ => return x,
+	};
+	if x <= first.0 {
+		return first.1;
+	}
+	if x >= last.0 {
+		return last.1;
+	}
+
+	let secants: Vec<f64> = points
+		.windows(2)
+		.map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0).max(f64::EPSILON))
+		.collect();
+	let tangent = |i: usize| {
+		if i == 0 {
+			secants[0]
+		} else if i == secants.len() {
+			secants[i - 1]
+		} else if secants[i - 1] * secants[i] <= 0.0 {
+			0.0
+		} else {
+			// Harmonic mean keeps the curve monotone
+			2.0 / (1.0 / secants[i - 1] + 1.0 / secants[i])
+		}
+	};
+
+	let i = points.iter().rposition(|point| point.0 <= x).unwrap_or(0);
+	let ((x0, y0), (x1, y1)) = (points[i], points[i + 1]);
+	let h = x1 - x0;
+	let t = (x - x0) / h;
+	let (t2, t3) = (t * t, t * t * t);
+	(2.0 * t3 - 3.0 * t2 + 1.0) * y0
+		+ (t3 - 2.0 * t2 + t) * h * tangent(i)
+		+ (-2.0 * t3 + 3.0 * t2) * y1
+		+ (t3 - t2) * h * tangent(i + 1)
+}
+
+/// Lookup table per channel of every per-channel edit: exposure and white balance in linear
+/// light, then contrast, highlights/shadows and the tone curve
+fn tone_tables(recipe: &EditRecipe) -> [[u8; 256]; 3] {
+	let slider = |value: Option<f64>| value.unwrap_or(0.0).clamp(-100.0, 100.0) / 100.0;
+	let exposure = 2f64.powf(recipe.exposure.unwrap_or(0.0));
+	let (temperature, tint) = (slider(recipe.temperature), slider(recipe.tint));
+	// Warming raises red and lowers blue; magenta lowers green
+	let gains = [
+		2f64.powf((temperature + tint / 2.0) * WHITE_BALANCE_STOPS),
+		2f64.powf(-tint * WHITE_BALANCE_STOPS),
+		2f64.powf((-temperature + tint / 2.0) * WHITE_BALANCE_STOPS),
+	];
+	let contrast = 1.0 + slider(recipe.contrast);
+	let (highlights, shadows) = (slider(recipe.highlights), slider(recipe.shadows));
+	let curve: Option<Vec<(f64, f64)>> = recipe
+		.tone_curve
+		.as_ref()
+		.map(|points| points.iter().map(|p| (p.x, p.y)).collect());
+
+	let mut tables = [[0u8; 256]; 3];
+	for (table, gain) in tables.iter_mut().zip(gains) {
+		for (level, entry) in table.iter_mut().enumerate() {
+			let linear = srgb_to_linear(level as f64 / 255.0) * exposure * gain;
+			let mut v = linear_to_srgb(linear.min(1.0));
+			v = 0.5 + (v - 0.5) * contrast;
+			v = v.clamp(0.0, 1.0);
+			// Bell-shaped weights peaking at the dark and bright thirds, steep enough to
+			// move the tones without reversing them
+			v += shadows * TONE_RANGE_STRENGTH * 6.75 * v * (1.0 - v).powi(2);
+			v += highlights * TONE_RANGE_STRENGTH * 6.75 * v.powi(2) * (1.0 - v);
+			if let Some(curve) = &curve {
+				v = evaluate_curve(curve, v.clamp(0.0, 1.0));
+			}
+			*entry = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+		}
+	}
+	tables
+}
+
+/// Apply the tone tables and saturation to every pixel, leaving alpha untouched
+fn adjust_colors(img: &mut RgbaImage, recipe: &EditRecipe) {
+	let tables = tone_tables(recipe);
+	let saturation = 1.0 + recipe.saturation.unwrap_or(0.0).clamp(-100.0, 100.0) / 100.0;
+	img.par_chunks_mut(4).for_each(|pixel| {
+		let mut rgb = [0.0f32; 3];
+		for c in 0..3 {
+			rgb[c] = tables[c][pixel[c] as usize] as f32;
+		}
+		let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+		for c in 0..3 {
+			let value = luma + (rgb[c] - luma) * saturation as f32;
+			pixel[c] = value.round().clamp(0.0, 255.0) as u8;
+		}
+	});
+}
+
+/// Rotate an image clockwise by an angle about its center, keeping its size and zooming in
+/// so the rotated image covers every corner
+fn straighten(img: &RgbaImage, degrees: f64) -> RgbaImage {
+	let (width, height) = img.dimensions();
+	let angle = degrees.to_radians();
+	let (sin, cos) = (angle.sin().abs(), angle.cos());
+	let long_ratio = (width as f64 / height as f64).max(height as f64 / width as f64);
+	let zoom = cos + long_ratio * sin;
+	let (center_x, center_y) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+	let (sin, cos) = angle.sin_cos();
+
+	let mut output = RgbaImage::new(width, height);
+	output
+		.par_chunks_mut(4 * width as usize)
+		.enumerate()
+		.for_each(|(y, row)| {
+			for (x, pixel) in row.chunks_mut(4).enumerate() {
+				// Source point of each output pixel: rotated back and zoomed out
+				let (dx, dy) = (x as f64 - center_x, y as f64 - center_y);
+				let source_x = center_x + (dx * cos + dy * sin) / zoom;
+				let source_y = center_y + (-dx * sin + dy * cos) / zoom;
+				let source_x = source_x.clamp(0.0, width as f64 - 1.0) as f32;
+				let source_y = source_y.clamp(0.0, height as f64 - 1.0) as f32;
+				if let Some(sample) = imageops::interpolate_bilinear(img, source_x, source_y) {
+					pixel.copy_from_slice(&sample.0);
+				}
+			}
+		});
+	output
+}
+
+/// Apply the geometry of a recipe: quarter turns, straightening, then the crop
+fn apply_geometry(img: DynamicImage, recipe: &EditRecipe) -> Result<DynamicImage, ProcessingError> {
+	let img = match recipe.rotate.unwrap_or(0) % 4 {
+		1 => img.rotate90(),
+		2 => img.rotate180(),
+		3 => img.rotate270(),
+		_ => img,
+	};
+	let img = match recipe.straighten {
+		Some(degrees) if degrees != 0.0 => {
+			let degrees = degrees.clamp(-MAX_STRAIGHTEN_DEGREES, MAX_STRAIGHTEN_DEGREES);
+			DynamicImage::ImageRgba8(straighten(&img.to_rgba8(), degrees))
+		}
+		_ => img,
+	};
+	let crop = match recipe.crop {
+		Some(crop) => crop,
+		None => return Ok(img),
+	};
+
+	let (width, height) = (img.width() as f64, img.height() as f64);
+	let x = (crop.x.clamp(0.0, 1.0) * width).round();
+	let y = (crop.y.clamp(0.0, 1.0) * height).round();
+	let crop_width = (crop.width * width).round().min(width - x);
+	let crop_height = (crop.height * height).round().min(height - y);
+	if crop_width < 1.0 || crop_height < 1.0 {
+		return Err(ProcessingError::invalid("Crop is empty"));
+	}
+	Ok(img.crop_imm(x as u32, y as u32, crop_width as u32, crop_height as u32))
+}
+
+/// Apply an edit recipe to an upright image, scaled to fit `max_dimension` when given
+/// Scaling happens after cropping and before the color edits, so previews render fast
+pub fn apply_edits(
+	img: DynamicImage,
+	recipe: &EditRecipe,
+	max_dimension: Option<u32>,
+) -> Result<DynamicImage, ProcessingError> {
+	let img = apply_geometry(img, recipe)?;
+	let img = match max_dimension {
+		Some(max) if img.width().max(img.height()) > max => {
+			img.resize(max, max, FilterType::Lanczos3)
+		}
+		_ => img,
+	};
+
+	let has_alpha = img.color().has_alpha();
+	let mut pixels = img.to_rgba8();
+	adjust_colors(&mut pixels, recipe);
+	let img = if has_alpha {
+		DynamicImage::ImageRgba8(pixels)
+	} else {
+		DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8())
+	};
+	Ok(match recipe.sharpen {
+		Some(mask) => unsharp_mask(&img, mask),
+		None => img,
+	})
+}
+
+/// Render a photo with an edit recipe, see `render_edits`
+pub fn render_edits_internal(
+	file_path: &str,
+	recipe: &EditRecipe,
+	max_dimension: Option<u32>,
+	format: Option<ThumbnailFormat>,
+	quality: Option<u8>,
+) -> Result<PreviewImage, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	let edited =
+		apply_edits(img, recipe, max_dimension).map_err(|e| e.context("Failed to apply edits"))?;
+
+	let format = format.unwrap_or_default();
+	let data = encode_thumbnail(&edited, format, quality.unwrap_or(DEFAULT_QUALITY), false)
+		.map_err(|e| ProcessingError::from_message(ErrorCode::Internal, e))?;
+	let mime_type = match format {
+		ThumbnailFormat::Webp => ImageFormat::WebP,
+		ThumbnailFormat::Avif => ImageFormat::Avif,
+		ThumbnailFormat::Jpeg => ImageFormat::Jpeg,
+	}
+	.to_mime_type();
+	Ok(PreviewImage {
+		data: data.into(),
+		mime_type: mime_type.to_string(),
+		width: edited.width(),
+		height: edited.height(),
+	})
+}
+
+/// Render a photo with an edit recipe, e.g. for the editor's preview, a thumbnail or an
+/// export, scaled to fit `max_dimension` when given and encoded as WebP (default), AVIF or
+/// JPEG at `quality` (default 90)
+#[cfg(feature = "node")]
+#[napi]
+pub fn render_edits(
+	file_path: String,
+	recipe: EditRecipe,
+	max_dimension: Option<u32>,
+	format: Option<ThumbnailFormat>,
+	quality: Option<u8>,
+) -> napi::Result<PreviewImage, ErrorCode> {
+	render_edits_internal(&file_path, &recipe, max_dimension, format, quality)
+		.map_err(napi::Error::from)
+}
+
+#[cfg(feature = "node")]
+pub struct RenderEditsTask {
+	file_path: String,
+	recipe: EditRecipe,
+	max_dimension: Option<u32>,
+	format: Option<ThumbnailFormat>,
+	quality: Option<u8>,
+}
+
+#[cfg(feature = "node")]
+impl Task for RenderEditsTask {
+	type Output = PreviewImage;
+	type JsValue = PreviewImage;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		render_edits_internal(
+			&self.file_path,
+			&self.recipe,
+			self.max_dimension,
+			self.format,
+			self.quality,
+		)
+		.map_err(napi::Error::from)
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+
+	fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+		reject_with_code(env, err)
+	}
+}
+
+/// Same as `render_edits`, off the JS thread, e.g. for a full-size render or an export
+#[cfg(feature = "node")]
+#[napi]
+pub fn render_edits_async(
+	file_path: String,
+	recipe: EditRecipe,
+	max_dimension: Option<u32>,
+	format: Option<ThumbnailFormat>,
+	quality: Option<u8>,
+) -> AsyncTask<RenderEditsTask> {
+	AsyncTask::new(RenderEditsTask {
+		file_path,
+		recipe,
+		max_dimension,
+		format,
+		quality,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn test_tone_curve_is_smooth_and_monotone() {
+		let points = [
+			(0.0, 0.0),
+			(0.25, 0.15),
+			(0.5, 0.5),
+			(0.75, 0.85),
+			(1.0, 1.0),
+		];
+		assert_eq!(evaluate_curve(&points, 0.25), 0.15);
+		let mut previous = 0.0;
+		for step in 1..=100 {
+			let y = evaluate_curve(&points, step as f64 / 100.0);
+			assert!(y >= previous);
+			previous = y;
+		}
+		assert_eq!(evaluate_curve(&[], 0.3), 0.3);
+
+		// No edits leave every level as is
+		let identity = tone_tables(&EditRecipe::default());
+		assert!(identity
+			.iter()
+			.all(|table| (0..256).all(|level| table[level] == level as u8)));
+	}
+
+	#[test]
+	fn test_apply_edits() {
+		let img = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 100, |x, _| {
+			Rgb([(x / 2) as u8, 100, 150])
+		}));
+		let recipe = EditRecipe {
+			rotate: Some(1),
+			straighten: Some(5.0),
+			crop: Some(EditCrop {
+				x: 0.0,
+				y: 0.5,
+				width: 1.0,
+				height: 0.25,
+			}),
+			exposure: Some(1.0),
+			..Default::default()
+		};
+		let edited = apply_edits(img.clone(), &recipe, Some(50)).unwrap();
+		assert_eq!((edited.width(), edited.height()), (50, 25));
+		let brighter = edited.to_rgb8().get_pixel(25, 12)[1];
+		assert!(brighter > 130, "{}", brighter);
+
+		assert_eq!(
+			apply_edits(img.clone(), &EditRecipe::default(), None).unwrap(),
+			img
+		);
+	}
+}
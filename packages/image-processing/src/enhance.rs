@@ -0,0 +1,188 @@
+use image::DynamicImage;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+
+use crate::clip::decode_upright;
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::quality::{exposure, ExposureAnalysis};
+
+/// Longest side photos are scaled to before analysis
+const ANALYSIS_SIZE: u32 = 512;
+
+/// Luminance (0-255) the median is brought to, a little under mid-gray,
+/// which suits most scenes
+const TARGET_MEDIAN: f64 = 110.0;
+
+/// Largest exposure change suggested, in stops
+const MAX_EXPOSURE_EV: f64 = 2.0;
+
+/// Luminance range (0-255) between the 1st and 99th percentiles of a well-contrasted photo
+const TARGET_RANGE: f64 = 220.0;
+
+/// Luminance at or above which pixels count as highlights, and at or below which shadows
+const HIGHLIGHT_LEVEL: usize = 235;
+const SHADOW_LEVEL: usize = 20;
+
+/// Slider change per share of pixels in the highlights or shadows (a quarter of the photo
+/// moves the slider all the way)
+const TONE_STRENGTH: f64 = 400.0;
+
+/// Temperature and tint change per stop of color cast; half a correction, since gray world
+/// takes a sunset or a forest for a cast
+const WHITE_BALANCE_STRENGTH: f64 = 50.0;
+
+/// Mean saturation (HSV) of a natural-looking photo
+const TARGET_SATURATION: f64 = 0.3;
+
+/// Suggested non-destructive adjustments for a photo, for the editor to apply or tweak
+/// Sliders go from -100 to 100 and 0 leaves the photo unchanged
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AutoEnhance {
+	/// Exposure change in stops (-2 to 2)
+	pub exposure: f64,
+	pub contrast: f64,
+	/// Negative to recover bright areas
+	pub highlights: f64,
+	/// Positive to open up dark areas
+	pub shadows: f64,
+	/// Positive to warm, negative to cool
+	pub temperature: f64,
+	/// Positive toward magenta, negative toward green
+	pub tint: f64,
+	pub saturation: f64,
+}
+
+/// sRGB level (0-255) to linear light (0-1)
+fn srgb_to_linear(level: f64) -> f64 {
+	let v = level / 255.0;
+	if v <= 0.04045 {
+		v / 12.92
+	} else {
+		((v + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+/// Lowest luminance level with at least `share` of the pixels at or below it
+fn percentile(histogram: &[u32], share: f64) -> usize {
+	let total: u64 = histogram.iter().map(|&count| count as u64).sum();
+	let mut seen = 0u64;
+	for (level, &count) in histogram.iter().enumerate() {
+		seen += count as u64;
+		if seen as f64 >= share * total as f64 {
+			return level;
+		}
+	}
+	histogram.len() - 1
+}
+
+/// Exposure, contrast, highlights and shadows from the luminance histogram
+fn tone_adjustments(analysis: &ExposureAnalysis) -> (f64, f64, f64, f64) {
+	let histogram = &analysis.luminance;
+	let total = histogram
+		.iter()
+		.map(|&count| count as f64)
+		.sum::<f64>()
+		.max(1.0);
+	let share = |levels: &[u32]| levels.iter().map(|&count| count as f64).sum::<f64>() / total;
+
+	let median = percentile(histogram, 0.5).max(1) as f64;
+	let exposure = (srgb_to_linear(TARGET_MEDIAN) / srgb_to_linear(median))
+		.log2()
+		.clamp(-MAX_EXPOSURE_EV, MAX_EXPOSURE_EV);
+	let range = (percentile(histogram, 0.99) - percentile(histogram, 0.01)) as f64;
+	let contrast = ((TARGET_RANGE - range) / TARGET_RANGE * 100.0).clamp(-100.0, 100.0);
+	let highlights = -(share(&histogram[HIGHLIGHT_LEVEL..]) * TONE_STRENGTH).min(100.0);
+	let shadows = (share(&histogram[..=SHADOW_LEVEL]) * TONE_STRENGTH).min(100.0);
+	(exposure, contrast, highlights, shadows)
+}
+
+/// Temperature, tint and saturation from the colors of the pixels that aren't clipped or
+/// nearly black: gray world white balance, and saturation toward `TARGET_SATURATION`
+fn color_adjustments(img: &DynamicImage) -> (f64, f64, f64) {
+	let (mut sums, mut saturation, mut count) = ([0.0; 3], 0.0, 0usize);
+	for pixel in img.to_rgb8().pixels() {
+		let max = pixel.0.into_iter().max().unwrap_or(0);
+		let min = pixel.0.into_iter().min().unwrap_or(0);
+		if max == 255 || max <= SHADOW_LEVEL as u8 {
+			continue;
+		}
+		for (sum, &value) in sums.iter_mut().zip(&pixel.0) {
+			*sum += srgb_to_linear(value as f64);
+		}
+		saturation += (max - min) as f64 / max as f64;
+		count += 1;
+	}
+	if count == 0 {
+		return (0.0, 0.0, 0.0);
+	}
+
+	let [red, green, blue] = sums.map(|sum| sum.max(f64::EPSILON));
+	let limit = |value: f64| value.clamp(-100.0, 100.0);
+	let temperature = limit((blue / red).log2() * WHITE_BALANCE_STRENGTH);
+	let tint = limit((green / (red * blue).sqrt()).log2() * WHITE_BALANCE_STRENGTH);
+	let mean_saturation = saturation / count as f64;
+	let saturation = limit((TARGET_SATURATION - mean_saturation) / TARGET_SATURATION * 50.0);
+	(temperature, tint, saturation)
+}
+
+/// Suggested adjustments for an upright image, rounded to what sliders show
+fn auto_enhance(img: &DynamicImage) -> AutoEnhance {
+	let small = img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE);
+	let (exposure, contrast, highlights, shadows) = tone_adjustments(&exposure(&small));
+	let (temperature, tint, saturation) = color_adjustments(&small);
+	AutoEnhance {
+		exposure: (exposure * 100.0).round() / 100.0,
+		contrast: contrast.round(),
+		highlights: highlights.round(),
+		shadows: shadows.round(),
+		temperature: temperature.round(),
+		tint: tint.round(),
+		saturation: saturation.round(),
+	}
+}
+
+/// Suggest adjustments for a photo, see `compute_auto_enhance`
+pub fn compute_auto_enhance_internal(file_path: &str) -> Result<AutoEnhance, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	Ok(auto_enhance(&img))
+}
+
+/// Analyze a photo and suggest non-destructive adjustments (exposure, contrast,
+/// highlights/shadows, white balance, saturation) for the editor to apply or tweak
+#[cfg(feature = "node")]
+#[napi]
+pub fn compute_auto_enhance(file_path: String) -> napi::Result<AutoEnhance, ErrorCode> {
+	compute_auto_enhance_internal(&file_path).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn test_auto_enhance_corrects_dark_blue_photo() {
+		// Dark, flat and bluish
+		let dark = RgbImage::from_fn(64, 64, |x, y| {
+			let v = 30 + ((x + y) % 20) as u8;
+			Rgb([v, v + 5, v + 25])
+		});
+		let suggested = auto_enhance(&DynamicImage::ImageRgb8(dark));
+		assert!(suggested.exposure > 1.0);
+		assert!(suggested.contrast > 50.0);
+		assert!(suggested.temperature > 20.0, "{:?}", suggested);
+		assert_eq!(suggested.highlights, 0.0);
+
+		// Already balanced gray steps need little more than contrast
+		let gray = RgbImage::from_fn(64, 64, |x, _| {
+			let v = (x * 4) as u8;
+			Rgb([v, v, v])
+		});
+		let suggested = auto_enhance(&DynamicImage::ImageRgb8(gray));
+		assert!(suggested.exposure.abs() < 0.5, "{:?}", suggested);
+		assert_eq!((suggested.temperature, suggested.tint), (0.0, 0.0));
+	}
+}
@@ -0,0 +1,254 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::fmt;
+use std::io;
+
+use crate::features::FeatureUnavailable;
+
+/// Stable code of an error, for retry and skip policies in JavaScript
+/// Errors thrown to JavaScript carry it as `err.code`, and their message starts with it,
+/// e.g. "DecodeFailed: ..."
+#[cfg_attr(feature = "node", napi(string_enum))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+	/// The file doesn't exist (anymore)
+	NotFound,
+	/// The app isn't allowed to read or write the file
+	PermissionDenied,
+	/// Reading or writing failed, e.g. a network share dropped; worth retrying
+	Io,
+	/// Not a photo or video format this library reads
+	UnsupportedFormat,
+	/// The file is damaged or truncated; retrying won't help
+	DecodeFailed,
+	/// A native component (libheif, ONNX runtime, ffmpeg, exiftool) is missing
+	FeatureUnavailable,
+	/// An argument or option is invalid
+	InvalidArgument,
+	/// Stopped by a `CancellationToken`
+	Cancelled,
+	/// Anything else
+	Internal,
+}
+
+impl ErrorCode {
+	const ALL: [ErrorCode; 9] = [
+		ErrorCode::NotFound,
+		ErrorCode::PermissionDenied,
+		ErrorCode::Io,
+		ErrorCode::UnsupportedFormat,
+		ErrorCode::DecodeFailed,
+		ErrorCode::FeatureUnavailable,
+		ErrorCode::InvalidArgument,
+		ErrorCode::Cancelled,
+		ErrorCode::Internal,
+	];
+
+	/// Code of a failed file operation
+	pub fn from_io(e: &io::Error) -> Self {
+		match e.kind() {
+			io::ErrorKind::NotFound => ErrorCode::NotFound,
+			io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+			_ => ErrorCode::Io,
+		}
+	}
+
+	pub fn as_str(self) -> &'static str {
+		match self {
+			ErrorCode::NotFound => "NotFound",
+			ErrorCode::PermissionDenied => "PermissionDenied",
+			ErrorCode::Io => "Io",
+			ErrorCode::UnsupportedFormat => "UnsupportedFormat",
+			ErrorCode::DecodeFailed => "DecodeFailed",
+			ErrorCode::FeatureUnavailable => "FeatureUnavailable",
+			ErrorCode::InvalidArgument => "InvalidArgument",
+			ErrorCode::Cancelled => "Cancelled",
+			ErrorCode::Internal => "Internal",
+		}
+	}
+}
+
+impl AsRef<str> for ErrorCode {
+	fn as_ref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+/// Step of processing a file that failed
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorStage {
+	Read,
+	Decode,
+	Metadata,
+	Thumbnails,
+	Analysis,
+	Write,
+}
+
+/// Error with a stable code and the file and step it happened at
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessingError {
+	pub code: ErrorCode,
+	pub stage: Option<ErrorStage>,
+	pub file_path: Option<String>,
+	pub message: String,
+}
+
+impl ProcessingError {
+	pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+		Self {
+			code,
+			stage: None,
+			file_path: None,
+			message: message.into(),
+		}
+	}
+
+	/// Error of a failed file operation, coded by its kind
+	pub fn io(e: &io::Error, message: &str) -> Self {
+		Self::new(ErrorCode::from_io(e), format!("{}: {}", message, e))
+	}
+
+	/// Error of an invalid argument or option
+	pub fn invalid(message: impl Into<String>) -> Self {
+		Self::new(ErrorCode::InvalidArgument, message)
+	}
+
+	/// Error from a message of a lower layer, keeping the code it already starts with
+	/// (e.g. a decode error passed up as a string), else coded with `code`
+	pub fn from_message(code: ErrorCode, message: impl Into<String>) -> Self {
+		let message = message.into();
+		ErrorCode::ALL
+			.into_iter()
+			.find_map(|known| {
+				let rest = message.strip_prefix(known.as_str())?.strip_prefix(": ")?;
+				Some(Self::new(known, rest))
+			})
+			.unwrap_or_else(|| Self::new(code, message))
+	}
+
+	/// Error of a file whose type isn't supported
+	pub fn unsupported(file_path: &str) -> Self {
+		Self::new(ErrorCode::UnsupportedFormat, "Unsupported file type").for_file(file_path)
+	}
+
+	/// Prefix the message with what was being done, keeping the code
+	pub fn context(mut self, context: &str) -> Self {
+		self.message = format!("{}: {}", context, self.message);
+		self
+	}
+
+	pub fn at(mut self, stage: ErrorStage) -> Self {
+		self.stage = Some(stage);
+		self
+	}
+
+	pub fn for_file(mut self, file_path: &str) -> Self {
+		self.file_path = Some(file_path.to_string());
+		self
+	}
+}
+
+impl fmt::Display for ProcessingError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.code.as_str(), self.message)
+	}
+}
+
+impl From<FeatureUnavailable> for ProcessingError {
+	fn from(e: FeatureUnavailable) -> Self {
+		let message = format!("{} is not available ({})", e.component.name(), e.reason);
+		Self::new(ErrorCode::FeatureUnavailable, message)
+	}
+}
+
+impl From<ProcessingError> for String {
+	fn from(e: ProcessingError) -> Self {
+		e.to_string()
+	}
+}
+
+/// Thrown by synchronous calls, which return `napi::Result<T, ErrorCode>`
+#[cfg(feature = "node")]
+impl From<ProcessingError> for napi::Error<ErrorCode> {
+	fn from(e: ProcessingError) -> Self {
+		napi::Error::new(e.code, e.to_string())
+	}
+}
+
+/// For async tasks and callbacks, which napi types as `napi::Error`; the code only stays in
+/// the message until `reject_with_code` puts it back on `err.code`
+#[cfg(feature = "node")]
+impl From<ProcessingError> for napi::Error {
+	fn from(e: ProcessingError) -> Self {
+		napi::Error::from_reason(e.to_string())
+	}
+}
+
+/// Error of napi itself, e.g. converting a value, or of an async task (see above)
+#[cfg(feature = "node")]
+impl From<napi::Error> for ProcessingError {
+	fn from(e: napi::Error) -> Self {
+		Self::from_message(ErrorCode::Internal, e.reason)
+	}
+}
+
+/// Reject the promise of an async task with its error's code as `err.code`, like
+/// synchronous calls throw it; used as `Task::reject`
+#[cfg(feature = "node")]
+pub fn reject_with_code<T>(env: napi::Env, err: napi::Error) -> napi::Result<T> {
+	let coded = napi::Error::<ErrorCode>::from(ProcessingError::from(err));
+	Err(napi::JsError::from(coded).into_unknown(env).into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::features::Component;
+
+	#[test]
+	fn test_codes() {
+		let missing = io::Error::from(io::ErrorKind::NotFound);
+		let error = ProcessingError::io(&missing, "Failed to read file").at(ErrorStage::Read);
+		assert_eq!(error.code, ErrorCode::NotFound);
+		assert_eq!(error.stage, Some(ErrorStage::Read));
+		assert!(error
+			.to_string()
+			.starts_with("NotFound: Failed to read file: "));
+
+		let unavailable = FeatureUnavailable {
+			component: Component::Libheif,
+			reason: "no HEVC decoder".to_string(),
+		};
+		let expected = unavailable.to_string();
+		assert_eq!(ProcessingError::from(unavailable).to_string(), expected);
+	}
+
+	#[test]
+	fn test_from_message_keeps_code() {
+		let decode = ProcessingError::new(ErrorCode::DecodeFailed, "Truncated file").to_string();
+		let error = ProcessingError::from_message(ErrorCode::Internal, decode);
+		assert_eq!(error.code, ErrorCode::DecodeFailed);
+		assert_eq!(error.message, "Truncated file");
+
+		let error = ProcessingError::from_message(ErrorCode::Internal, "Model error: NaN");
+		assert_eq!(error.code, ErrorCode::Internal);
+		assert_eq!(error.to_string(), "Internal: Model error: NaN");
+	}
+
+	#[cfg(feature = "node")]
+	#[test]
+	fn test_js_error_code() {
+		let error = napi::Error::<ErrorCode>::from(ProcessingError::invalid("Unknown size"));
+		assert_eq!(error.status.as_ref(), "InvalidArgument");
+		assert_eq!(error.reason, "InvalidArgument: Unknown size");
+
+		// Async tasks get the code back from the message
+		let task_error =
+			napi::Error::from(ProcessingError::new(ErrorCode::DecodeFailed, "Truncated"));
+		let error = napi::Error::<ErrorCode>::from(ProcessingError::from(task_error));
+		assert_eq!(error.status.as_ref(), "DecodeFailed");
+	}
+}
@@ -1,8 +1,29 @@
+use chrono::{Local, TimeZone};
+use moxcms::{DataColorSpace, Xyzd};
+#[cfg(feature = "node")]
 use napi_derive::napi;
-use std::process::Command;
+use rayon::prelude::*;
+use rsraw::RawImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
 
-#[napi(object)]
-#[derive(Debug, Clone)]
+use crate::color::{classify_colorants, classify_profile, nclx_profile, ColorSpace};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::features::{require, Component};
+use crate::preview::{extract_embedded_jpeg, is_raw_file};
+use crate::timezone::{offset_minutes_at_local, timezone_at};
+use crate::xmp::RatingSources;
+#[cfg(feature = "node")]
+use crate::Buffer;
+
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ExifData {
 	// Camera info
 	pub camera_make: Option<String>,
@@ -11,7 +32,9 @@ pub struct ExifData {
 	// Lens info
 	pub lens_make: Option<String>,
 	pub lens_model: Option<String>,
-	pub focal_length: Option<u32>, // in mm
+	pub focal_length: Option<u32>,      // in mm
+	pub focal_length_35mm: Option<u32>, // full-frame equivalent, in mm
+	pub crop_factor: Option<f64>,       // e.g., 1.5 for APS-C, ~7 for phones
 
 	// Exposure settings
 	pub iso: Option<u32>,
@@ -19,54 +42,725 @@ pub struct ExifData {
 	pub shutter_speed: Option<String>, // e.g., "1/250"
 	pub exposure_bias: Option<String>, // e.g., "+0.3 EV"
 
+	// Numeric exposure values backing the display strings above
+	pub f_number: Option<f64>,
+	pub exposure_time: Option<f64>,         // in seconds
+	pub exposure_compensation: Option<f64>, // in EV
+
+	// Shooting modes (human-readable)
+	pub flash_fired: Option<bool>,
+	pub flash: Option<String>,            // e.g., "Fired, Auto"
+	pub metering_mode: Option<String>,    // e.g., "Spot"
+	pub exposure_program: Option<String>, // e.g., "Aperture-priority AE"
+	pub white_balance: Option<String>,    // "Auto" or "Manual"
+	pub color_space: Option<String>,      // e.g., "sRGB"
+
+	// Embedded ICC profile
+	pub icc_profile_name: Option<String>, // e.g., "Display P3"
+	pub color_gamut: Option<String>,      // "sRGB", "Display P3", "Adobe RGB", "ProPhoto RGB", ...
+	pub is_wide_gamut: Option<bool>,      // a known space larger than sRGB; thumbnails lose color
+
 	// DateTime
-	pub date_taken: Option<String>, // ISO 8601 format
+	pub date_taken: Option<String>,             // ISO 8601 without offset
+	pub date_taken_ms: Option<f64>,             // UTC epoch milliseconds
+	pub date_taken_offset_minutes: Option<i32>, // original offset, e.g. 120 for +02:00
+	pub date_taken_offset_inferred: Option<i32>, // offset of `timezone` when none was recorded
+	pub create_date: Option<String>,            // ISO 8601 without offset (digitized time)
+	pub gps_date_time_ms: Option<f64>,          // UTC epoch milliseconds from the GPS clock
+	pub timezone: Option<String>,               // IANA timezone at the GPS position
 
 	// GPS coordinates
 	pub gps_latitude: Option<f64>,
 	pub gps_longitude: Option<f64>,
-	pub gps_altitude: Option<f64>,
+	pub gps_altitude: Option<f64>,      // meters, negative below sea level
+	pub gps_img_direction: Option<f64>, // compass bearing of the camera, 0-360 degrees
+	pub gps_img_direction_ref: Option<String>, // "True" or "Magnetic" north
+	pub gps_speed: Option<f64>,         // km/h
+	pub gps_dop: Option<f64>,           // dilution of precision (lower is more accurate)
 
 	// Orientation (1-8, EXIF standard)
 	pub orientation: Option<u32>,
+
+	// Panorama (GPano XMP)
+	pub projection_type: Option<String>, // e.g., "equirectangular"
+	pub pano_coverage: Option<f64>,      // share of the full 360° captured (1.0 = full)
+
+	// Apple Live Photo: shared by the still and its paired video
+	pub content_identifier: Option<String>,
+
+	// Vendor MakerNotes
+	pub shutter_count: Option<u32>,     // shutter actuations
+	pub af_points_used: Option<String>, // e.g., "Center" or "C6, D6"
+	pub picture_style: Option<String>,  // e.g., "Standard", "Vivid", "Classic Chrome"
+
+	// Descriptive metadata (embedded XMP and IPTC)
+	pub keywords: Option<Vec<String>>,
+	pub title: Option<String>,
+	pub caption: Option<String>,
+	pub rating: Option<i32>,           // 0-5 stars, -1 = rejected
+	pub rating_source: Option<String>, // "sidecar", "xmp" or "exif"
+	pub color_label: Option<String>,   // e.g., "Red"
+	pub label_source: Option<String>,  // "sidecar" or "xmp"
 }
 
-/// Internal function to extract EXIF data using exiftool
-pub fn extract_exif_internal(file_path: &str) -> Option<ExifData> {
-	// Run exiftool with specific tags we need
-	// Using -n for numeric values, -s3 for bare tag values
-	let output = Command::new("exiftool")
-		.args([
-			"-json",
-			"-Make",
-			"-Model",
-			"-LensMake",
-			"-LensModel",
-			"-FocalLength",
-			"-ISO",
-			"-FNumber",
-			"-ExposureTime",
-			"-ExposureCompensation",
-			"-DateTimeOriginal",
-			"-GPSLatitude",
-			"-GPSLongitude",
-			"-GPSAltitude",
-			"-Orientation",
-			"-n", // Numeric output for GPS, orientation, etc.
-			file_path,
-		])
+/// Languages that use a comma as the decimal separator
+const COMMA_DECIMAL_LANGUAGES: &[&str] = &[
+	"de", "fr", "es", "it", "pt", "nl", "ru", "pl", "cs", "sk", "sv", "da", "nb", "nn", "no", "fi",
+	"tr", "el", "hu", "ro", "uk", "bg", "hr", "sl", "sr", "lt", "lv", "et", "id", "vi",
+];
+
+/// Get the decimal separator for a BCP 47 locale tag (e.g. "de-DE", "fr", "en_US")
+/// Defaults to a period when no locale is given or the language is unknown
+fn decimal_separator(locale: Option<&str>) -> char {
+	let language = locale
+		.and_then(|l| l.split(['-', '_']).next())
+		.map(|l| l.to_lowercase())
+		.unwrap_or_default();
+
+	if COMMA_DECIMAL_LANGUAGES.contains(&language.as_str()) {
+		','
+	} else {
+		'.'
+	}
+}
+
+/// Format a number with fixed precision using the locale's decimal separator
+fn format_decimal(value: f64, precision: usize, locale: Option<&str>) -> String {
+	let formatted = format!("{:.*}", precision, value);
+	match decimal_separator(locale) {
+		'.' => formatted,
+		sep => formatted.replace('.', &sep.to_string()),
+	}
+}
+
+/// Format an F-number for display, e.g. "f/2.8" or "f/2,8"
+pub fn format_aperture(f_number: f64, locale: Option<&str>) -> String {
+	format!("f/{}", format_decimal(f_number, 1, locale))
+}
+
+/// Format an exposure time in seconds for display, e.g. "1/250" or "2.5s"
+pub fn format_shutter_speed(exposure_time: f64, locale: Option<&str>) -> String {
+	if exposure_time >= 1.0 {
+		format!("{}s", format_decimal(exposure_time, 1, locale))
+	} else {
+		let denominator = (1.0 / exposure_time).round() as u32;
+		format!("1/{}", denominator)
+	}
+}
+
+/// Format an exposure compensation for display, e.g. "+0.3 EV" or "-1,0 EV"
+pub fn format_exposure_bias(bias: f64, locale: Option<&str>) -> String {
+	if bias > 0.0 {
+		format!("+{} EV", format_decimal(bias, 1, locale))
+	} else if bias < 0.0 {
+		format!("{} EV", format_decimal(bias, 1, locale))
+	} else {
+		"0 EV".to_string()
+	}
+}
+
+/// Describe the EXIF Flash bit field, e.g. "Fired, Auto, Red-eye reduction"
+pub fn describe_flash(flash: u32) -> String {
+	if flash & 0x20 != 0 {
+		return "No flash function".to_string();
+	}
+
+	let fired = if flash & 0x01 != 0 {
+		"Fired"
+	} else {
+		"Did not fire"
+	};
+	let mut parts = vec![fired];
+	match (flash >> 3) & 0x03 {
+		1 => parts.push("Compulsory"),
+		2 => parts.push("Off"),
+		3 => parts.push("Auto"),
+		_ => {}
+	}
+	if flash & 0x40 != 0 {
+		parts.push("Red-eye reduction");
+	}
+	parts.join(", ")
+}
+
+/// Describe the EXIF MeteringMode tag
+pub fn describe_metering_mode(mode: u32) -> Option<String> {
+	let description = match mode {
+		1 => "Average",
+		2 => "Center-weighted average",
+		3 => "Spot",
+		4 => "Multi-spot",
+		5 => "Multi-segment",
+		6 => "Partial",
+		255 => "Other",
+		_ => return None, // 0 = unknown
+	};
+	Some(description.to_string())
+}
+
+/// Describe the EXIF ExposureProgram tag
+pub fn describe_exposure_program(program: u32) -> Option<String> {
+	let description = match program {
+		1 => "Manual",
+		2 => "Program AE",
+		3 => "Aperture-priority AE",
+		4 => "Shutter speed priority AE",
+		5 => "Creative (Slow speed)",
+		6 => "Action (High speed)",
+		7 => "Portrait",
+		8 => "Landscape",
+		9 => "Bulb",
+		_ => return None, // 0 = not defined
+	};
+	Some(description.to_string())
+}
+
+/// Describe the EXIF WhiteBalance tag
+pub fn describe_white_balance(white_balance: u32) -> Option<String> {
+	match white_balance {
+		0 => Some("Auto".to_string()),
+		1 => Some("Manual".to_string()),
+		_ => None,
+	}
+}
+
+/// Describe the EXIF ColorSpace tag
+pub fn describe_color_space(color_space: u32) -> Option<String> {
+	let description = match color_space {
+		1 => "sRGB",
+		2 => "Adobe RGB",
+		0xFFFD => "Wide Gamut RGB",
+		0xFFFE => "ICC Profile",
+		0xFFFF => "Uncalibrated",
+		_ => return None,
+	};
+	Some(description.to_string())
+}
+
+/// Calendar fields of a validated capture date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTimeParts {
+	pub year: u32,
+	pub month: u32,
+	pub day: u32,
+	pub hour: u32,
+	pub minute: u32,
+	pub second: u32,
+}
+
+impl DateTimeParts {
+	/// Parse "YYYY:MM:DD HH:MM:SS" or "YYYY-MM-DDTHH:MM:SS", rejecting impossible dates
+	/// such as the "0000:00:00 00:00:00" placeholder some cameras write
+	pub fn parse(value: &str) -> Option<Self> {
+		let bytes = value.as_bytes();
+		if bytes.len() != 19
+			|| !matches!((bytes[4], bytes[7]), (b':', b':') | (b'-', b'-'))
+			|| !matches!(bytes[10], b' ' | b'T')
+			|| bytes[13] != b':'
+			|| bytes[16] != b':'
+		{
+			return None;
+		}
+
+		let number = |range: std::ops::Range<usize>| {
+			let digits = value.get(range)?;
+			if !digits.bytes().all(|b| b.is_ascii_digit()) {
+				return None;
+			}
+			digits.parse::<u32>().ok()
+		};
+		let parts = DateTimeParts {
+			year: number(0..4)?,
+			month: number(5..7)?,
+			day: number(8..10)?,
+			hour: number(11..13)?,
+			minute: number(14..16)?,
+			second: number(17..19)?,
+		};
+
+		let valid = parts.year > 0
+			&& (1..=12).contains(&parts.month)
+			&& (1..=days_in_month(parts.year, parts.month)).contains(&parts.day)
+			&& parts.hour < 24
+			&& parts.minute < 60
+			&& parts.second <= 60; // leap second
+		valid.then_some(parts)
+	}
+
+	/// ISO 8601 without offset, e.g. "2023-07-14T10:22:01"
+	pub fn to_iso(self) -> String {
+		format!(
+			"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+
+	/// EXIF form, e.g. "2023:07:14 10:22:01"
+	pub fn to_exif(self) -> String {
+		format!(
+			"{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+	let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+	match month {
+		2 if leap => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+/// Normalize an EXIF or ISO 8601 date to "YYYY-MM-DDTHH:MM:SS"
+/// Fractional seconds and offsets are dropped (see `date_taken_ms` and
+/// `date_taken_offset_minutes`); invalid dates return None
+pub fn normalize_date_taken(date_time: &str) -> Option<String> {
+	let date_time = date_time.trim();
+	DateTimeParts::parse(date_time.get(..19)?).map(DateTimeParts::to_iso)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month = month as i64;
+	let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+	let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse a timezone offset ("+02:00", "-0530", "Z") into minutes east of UTC
+pub fn parse_offset_minutes(value: &str) -> Option<i32> {
+	let value = value.trim();
+	if value == "Z" {
+		return Some(0);
+	}
+
+	let sign = match value.chars().next()? {
+		'+' => 1,
+		'-' => -1,
+		_ => return None,
+	};
+	let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+	if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+	let hours: i32 = digits[..2].parse().ok()?;
+	let minutes: i32 = digits[2..].parse().ok()?;
+	Some(sign * (hours * 60 + minutes))
+}
+
+/// Convert an EXIF ("2023:07:14 10:22:01") or ISO 8601 ("2023-07-14T10:22:01.5+02:00") date
+/// to UTC epoch milliseconds
+/// `subsec` is the SubSecTime digits ("045" = 45ms) and `offset` the OffsetTime tag; an offset
+/// embedded in the date string wins. Without any offset the local time is treated as UTC
+/// Returns the epoch and the offset used, if any
+pub fn date_taken_to_epoch_ms(
+	date_time: &str,
+	subsec: Option<&str>,
+	offset: Option<&str>,
+) -> Option<(f64, Option<i32>)> {
+	let date_time = date_time.trim();
+	if date_time.len() < 19 || !date_time.is_char_boundary(19) {
+		return None;
+	}
+	let (base, rest) = date_time.split_at(19);
+	let DateTimeParts {
+		year,
+		month,
+		day,
+		hour,
+		minute,
+		second,
+	} = DateTimeParts::parse(base)?;
+
+	// Fractional seconds and offset may follow in ISO strings
+	let (fraction, inline_offset) = match rest.strip_prefix('.') {
+		Some(after_dot) => {
+			let end = after_dot
+				.find(|c: char| !c.is_ascii_digit())
+				.unwrap_or(after_dot.len());
+			(Some(&after_dot[..end]), &after_dot[end..])
+		}
+		None => (None, rest),
+	};
+	let fraction = fraction.or(subsec.map(str::trim)).filter(|f| !f.is_empty());
+	let millis = fraction
+		.and_then(|f| format!("0.{}", f).parse::<f64>().ok())
+		.map(|f| (f * 1000.0).round())
+		.unwrap_or(0.0);
+
+	let offset_minutes =
+		parse_offset_minutes(inline_offset).or_else(|| offset.and_then(parse_offset_minutes));
+
+	let days = days_from_civil(year as i64, month, day);
+	let local_seconds = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+	let utc_seconds = local_seconds - offset_minutes.unwrap_or(0) as i64 * 60;
+	Some((utc_seconds as f64 * 1000.0 + millis, offset_minutes))
+}
+
+impl ExifData {
+	/// Set the capture date and derive its UTC epoch from a date string with an optional inline offset
+	pub fn set_date_taken(&mut self, date_time: String) {
+		let parsed = date_taken_to_epoch_ms(&date_time, None, None);
+		self.date_taken_ms = parsed.map(|(ms, _)| ms);
+		self.date_taken_offset_minutes = parsed.and_then(|(_, offset)| offset);
+		self.date_taken_offset_inferred = None;
+		self.date_taken = normalize_date_taken(&date_time);
+	}
+
+	/// Re-format the display strings from the numeric values for a locale
+	pub fn localize(&mut self, locale: Option<&str>) {
+		self.aperture = self.f_number.map(|f| format_aperture(f, locale));
+		self.shutter_speed = self.exposure_time.map(|t| format_shutter_speed(t, locale));
+		self.exposure_bias = self
+			.exposure_compensation
+			.map(|b| format_exposure_bias(b, locale));
+	}
+}
+
+/// One file's tags as printed by `exiftool -json`
+type JsonObject = serde_json::Map<String, serde_json::Value>;
+
+/// Read a list-type tag, which exiftool returns as an array or, for one item, a plain value
+pub(crate) fn get_list(obj: &JsonObject, key: &str) -> Vec<String> {
+	let values = match obj.get(key) {
+		Some(serde_json::Value::Array(items)) => items.iter().collect(),
+		Some(value) => vec![value],
+		None => Vec::new(),
+	};
+	values
+		.into_iter()
+		.map(|v| match v.as_str() {
+			Some(s) => s.trim().to_string(),
+			None => v.to_string(),
+		})
+		.filter(|s| !s.is_empty())
+		.collect()
+}
+
+/// Parse an ICC XYZ value the way exiftool prints it, e.g. "0.43607 0.22249 0.01392"
+fn parse_xyz(value: &str) -> Option<Xyzd> {
+	let mut values = value.split_whitespace().map(|v| v.parse::<f64>().ok());
+	let xyz = Xyzd::new(values.next()??, values.next()??, values.next()??);
+	values.next().is_none().then_some(xyz)
+}
+
+/// Classify an embedded ICC profile from its ColorSpaceData signature (e.g. "RGB " or
+/// "GRAY") and matrix colorants, like `read_color_space` does from the profile itself
+/// Vendor descriptions vary too much ("Apple Display P3", "Adobe RGB (1998)", "sRGB
+/// IEC61966-2.1") to be matched on, so the primaries decide
+fn classify_icc_profile(signature: &str, colorants: [Option<Xyzd>; 3]) -> ColorSpace {
+	let signature: Option<[u8; 4]> = format!("{:<4}", signature.trim())
+		.into_bytes()
+		.try_into()
+		.ok();
+	let Some(color_space) = signature
+		.and_then(|signature| DataColorSpace::try_from(u32::from_be_bytes(signature)).ok())
+	else {
+		return ColorSpace::Other;
+	};
+	let colorants = match colorants {
+		[Some(red), Some(green), Some(blue)] => Some([red, green, blue]),
+		_ => None,
+	};
+	classify_colorants(color_space, colorants.as_ref())
+}
+
+/// Derive the 35mm-equivalent focal length and crop factor
+/// Prefers the camera's FocalLengthIn35mmFilm tag, falling back to exiftool's sensor size estimate
+fn equivalent_focal_length(
+	focal_length: Option<f64>,
+	tagged_35mm: Option<f64>,
+	scale_factor: Option<f64>,
+) -> (Option<u32>, Option<f64>) {
+	let tagged_35mm = tagged_35mm.filter(|f| *f > 0.0);
+	let scale_factor = scale_factor.filter(|s| *s > 0.0);
+
+	let focal_length_35mm = tagged_35mm.or_else(|| Some(focal_length? * scale_factor?));
+	let crop_factor = match (tagged_35mm, focal_length) {
+		(Some(f35), Some(f)) => Some(f35 / f),
+		_ => scale_factor,
+	}
+	.map(|c| (c * 100.0).round() / 100.0);
+
+	(focal_length_35mm.map(|f| f.round() as u32), crop_factor)
+}
+
+/// Apply GPSAltitudeRef (1 = below sea level) to the unsigned EXIF altitude
+pub fn signed_altitude(altitude: f64, altitude_ref: Option<u32>) -> f64 {
+	match altitude_ref {
+		Some(1) => -altitude.abs(),
+		_ => altitude,
+	}
+}
+
+/// Convert a GPSSpeed value to km/h using GPSSpeedRef ("K" km/h, "M" mph, "N" knots)
+fn speed_to_kmh(speed: f64, speed_ref: Option<&str>) -> Option<f64> {
+	let factor = match speed_ref.unwrap_or("K") {
+		"K" => 1.0,
+		"M" => 1.609_344,
+		"N" => 1.852,
+		_ => return None,
+	};
+	Some(speed * factor).filter(|s| *s >= 0.0)
+}
+
+/// Whether exiftool output has any tags beyond its bookkeeping keys
+fn has_metadata_tags(obj: &JsonObject) -> bool {
+	obj.keys()
+		.any(|k| !matches!(k.as_str(), "SourceFile" | "Error" | "Warning"))
+}
+
+/// Tags requested from exiftool
+/// A trailing # requests the numeric value of a tag; the rest (MakerNotes in particular)
+/// use exiftool's human-readable print conversion
+const EXIFTOOL_TAGS: &[&str] = &[
+	"-Make",
+	"-Model",
+	"-LensMake",
+	"-LensModel",
+	"-FocalLength#",
+	"-FocalLengthIn35mmFormat#",
+	"-ScaleFactor35efl#", // estimated from the focal plane resolution and sensor size
+	"-ISO#",
+	"-FNumber#",
+	"-ExposureTime#",
+	"-ExposureCompensation#",
+	"-Flash#",
+	"-MeteringMode#",
+	"-ExposureProgram#",
+	"-WhiteBalance#",
+	"-ColorSpace#",
+	"-ICC_Profile:ProfileDescription",
+	"-ICC_Profile:ColorSpaceData",
+	"-ICC_Profile:RedMatrixColumn",
+	"-ICC_Profile:GreenMatrixColumn",
+	"-ICC_Profile:BlueMatrixColumn",
+	"-ColorPrimaries#", // HEIF nclx primaries, when there's no ICC profile
+	"-DateTimeOriginal",
+	"-SubSecTimeOriginal",
+	"-OffsetTimeOriginal",
+	"-OffsetTime",
+	"-CreateDate",
+	"-GPSDateTime",
+	"-GPSLatitude#",
+	"-GPSLongitude#",
+	"-EXIF:GPSAltitude#",
+	"-GPSAltitudeRef#",
+	"-GPSImgDirection#",
+	"-GPSImgDirectionRef#",
+	"-GPSSpeed#",
+	"-GPSSpeedRef#",
+	"-GPSDOP#",
+	"-Orientation#",
+	"-XMP-GPano:ProjectionType",
+	"-XMP-GPano:FullPanoWidthPixels#",
+	"-XMP-GPano:CroppedAreaImageWidthPixels#",
+	"-ContentIdentifier",
+	// MakerNotes (Canon, Nikon, Sony, Fujifilm)
+	"-ShutterCount#",
+	"-ShutterCount2#",
+	"-ShutterCount3#",
+	"-AFPointsUsed",
+	"-AFPointsInFocus",
+	"-PrimaryAFPoint",
+	"-PictureStyle",
+	"-PictureControlName",
+	"-CreativeStyle",
+	"-PictureProfile",
+	"-FilmMode",
+	// Descriptive metadata (XMP first, IPTC as fallback)
+	"-XMP:Subject",
+	"-IPTC:Keywords",
+	"-XMP:Title",
+	"-IPTC:ObjectName",
+	"-XMP:Description",
+	"-IPTC:Caption-Abstract",
+	"-EXIF:ImageDescription",
+	"-Rating#",
+	"-XMP:Label",
+];
+
+/// exiftool invocation reading `EXIFTOOL_TAGS` as JSON, before its input files are added
+fn exiftool_command() -> Command {
+	let mut command = Command::new("exiftool");
+	command
+		.arg("-json")
+		.args(EXIFTOOL_TAGS)
+		// Keep going past minor errors in proprietary containers (CR3, some RW2/ORF)
+		.arg("-m");
+	command
+}
+
+/// The EXIF (IFD0) and embedded XMP ratings, read separately
+/// A plain `-Rating` only returns exiftool's preferred one, so group names are requested
+const EXIFTOOL_RATING_TAGS: &[&str] = &[
+	"-json",
+	"-G1",
+	"-a",
+	"-IFD0:Rating#",
+	"-XMP-xmp:Rating#",
+	"-XMP-xmp:Label",
+	"-m",
+];
+
+/// Run exiftool over one or more files and return one JSON object per file
+/// The same process reads `EXIFTOOL_RATING_TAGS` as a second command, and those
+/// group-qualified tags are added to each file's object
+fn run_exiftool_json(file_paths: &[&str]) -> Option<Vec<JsonObject>> {
+	let output = exiftool_command()
+		.arg("-execute")
+		.args(EXIFTOOL_RATING_TAGS)
+		.arg("-common_args")
+		.args(file_paths)
 		.output()
 		.ok()?;
 
-	if !output.status.success() {
-		return None;
+	// One JSON array per command, printed one after the other
+	let mut outputs = serde_json::Deserializer::from_slice(&output.stdout)
+		.into_iter::<serde_json::Value>()
+		.map_while(Result::ok);
+	let mut objects = json_objects(outputs.next()?)?;
+	let ratings = outputs.next().and_then(json_objects).unwrap_or_default();
+	for (obj, ratings) in objects.iter_mut().zip(ratings) {
+		if obj.get("SourceFile") == ratings.get("SourceFile") {
+			obj.extend(ratings.into_iter().filter(|(key, _)| key.contains(':')));
+		}
 	}
+	Some(objects)
+}
 
-	let json_str = String::from_utf8_lossy(&output.stdout);
+/// Run exiftool over file contents held in memory, piped through stdin
+fn run_exiftool_json_stdin(data: &[u8]) -> Option<Vec<JsonObject>> {
+	let mut child = exiftool_command()
+		.arg("-")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()
+		.ok()?;
+	// Write from another thread so a full stdout pipe can't block exiftool while it reads
+	let mut stdin = child.stdin.take()?;
+	let output = std::thread::scope(|scope| {
+		scope.spawn(move || stdin.write_all(data));
+		child.wait_with_output()
+	})
+	.ok()?;
+	parse_exiftool_json(&output.stdout)
+}
 
-	// Parse JSON array (exiftool returns an array with one object)
-	let json: serde_json::Value = serde_json::from_str(&json_str).ok()?;
-	let obj = json.as_array()?.first()?.as_object()?;
+/// One JSON object per input from exiftool's `-json` output
+fn parse_exiftool_json(stdout: &[u8]) -> Option<Vec<JsonObject>> {
+	// exiftool exits non-zero when part of a file can't be parsed but still prints every
+	// tag it could read, so parse the output regardless
+	json_objects(serde_json::from_slice(stdout).ok()?)
+}
+
+/// The objects of one exiftool JSON array
+fn json_objects(value: serde_json::Value) -> Option<Vec<JsonObject>> {
+	match value {
+		serde_json::Value::Array(items) => Some(
+			items
+				.into_iter()
+				.filter_map(|item| match item {
+					serde_json::Value::Object(obj) => Some(obj),
+					_ => None,
+				})
+				.collect(),
+		),
+		_ => None,
+	}
+}
+
+/// Internal function to extract EXIF data using exiftool
+pub fn extract_exif_internal(file_path: &str) -> Option<ExifData> {
+	let exif = run_exiftool_json(&[file_path])
+		.and_then(|objects| objects.first().and_then(exif_from_json));
+	with_libraw_fallback(exif, file_path)
+}
+
+/// Camera metadata LibRaw reads from a RAW file itself (its `idata` and `imgother`
+/// structures), for containers exiftool can't fully parse, like some CR3s
+fn libraw_exif(file_path: &str) -> Option<ExifData> {
+	let data = fs::read(file_path).ok()?;
+	let info = RawImage::open(&data).ok()?.full_info();
+	let text = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+	let positive = |value: f32| (value > 0.0).then_some(value as f64);
+
+	let mut exif = ExifData {
+		camera_make: text(&info.make),
+		camera_model: text(&info.model),
+		iso: positive(info.iso_speed).map(|iso| iso.round() as u32),
+		f_number: positive(info.aperture),
+		exposure_time: positive(info.shutter),
+		focal_length: positive(info.focal_len).map(|f| f as u32),
+		..Default::default()
+	};
+	exif.localize(None);
+	// LibRaw turns the camera's local capture time into a timestamp with the system timezone
+	if let Some(local) = (info.timestamp > 0)
+		.then(|| Local.timestamp_opt(info.timestamp, 0).single())
+		.flatten()
+	{
+		exif.set_date_taken(local.naive_local().format("%Y-%m-%dT%H:%M:%S").to_string());
+	}
+	Some(exif).filter(|exif| exif.camera_make.is_some() || exif.date_taken.is_some())
+}
+
+/// Fill the camera, exposure and date fields exiftool didn't read for a RAW file from
+/// LibRaw; other files, and RAW files exiftool read fully, are returned as they are
+fn with_libraw_fallback(exif: Option<ExifData>, file_path: &str) -> Option<ExifData> {
+	let complete = exif
+		.as_ref()
+		.is_some_and(|exif| exif.camera_make.is_some() && exif.date_taken.is_some());
+	if complete || !is_raw_file(file_path) {
+		return exif;
+	}
+	let Some(fallback) = libraw_exif(file_path) else {
+		return exif;
+	};
+	let Some(mut exif) = exif else {
+		return Some(fallback);
+	};
+
+	exif.camera_make = exif.camera_make.or(fallback.camera_make);
+	exif.camera_model = exif.camera_model.or(fallback.camera_model);
+	exif.iso = exif.iso.or(fallback.iso);
+	exif.focal_length = exif.focal_length.or(fallback.focal_length);
+	if exif.f_number.is_none() {
+		exif.f_number = fallback.f_number;
+		exif.aperture = fallback.aperture;
+	}
+	if exif.exposure_time.is_none() {
+		exif.exposure_time = fallback.exposure_time;
+		exif.shutter_speed = fallback.shutter_speed;
+	}
+	if exif.date_taken.is_none() {
+		exif.date_taken = fallback.date_taken;
+		exif.date_taken_ms = fallback.date_taken_ms;
+		exif.date_taken_offset_minutes = fallback.date_taken_offset_minutes;
+		exif.date_taken_offset_inferred = fallback.date_taken_offset_inferred;
+	}
+	Some(exif)
+}
+
+/// Extract EXIF data from file contents held in memory, e.g. a photo fetched from the cloud
+pub fn extract_exif_from_bytes(data: &[u8]) -> Option<ExifData> {
+	let objects = run_exiftool_json_stdin(data)?;
+	exif_from_json(objects.first()?)
+}
+
+/// Build ExifData from one exiftool JSON object; None if it holds no metadata tags
+fn exif_from_json(obj: &JsonObject) -> Option<ExifData> {
+	if !has_metadata_tags(obj) {
+		return None;
+	}
 
 	// Helper to get string value
 	let get_str = |key: &str| -> Option<String> {
@@ -94,67 +788,496 @@ pub fn extract_exif_internal(file_path: &str) -> Option<ExifData> {
 	let lens_model = get_str("LensModel");
 
 	// Focal length - exiftool returns as number with -n flag
-	let focal_length = get_f64("FocalLength").map(|f| f as u32);
+	let focal_length_mm = get_f64("FocalLength").filter(|f| *f > 0.0);
+	let focal_length = focal_length_mm.map(|f| f as u32);
+	let (focal_length_35mm, crop_factor) = equivalent_focal_length(
+		focal_length_mm,
+		get_f64("FocalLengthIn35mmFormat"),
+		get_f64("ScaleFactor35efl"),
+	);
 
 	// ISO
 	let iso = get_u32("ISO");
 
-	// Aperture (F-number)
-	let aperture = get_f64("FNumber").map(|f| format!("f/{:.1}", f));
+	// Exposure settings (formatted with the default locale)
+	let f_number = get_f64("FNumber");
+	let exposure_time = get_f64("ExposureTime");
+	let exposure_compensation = get_f64("ExposureCompensation");
+	let aperture = f_number.map(|f| format_aperture(f, None));
+	let shutter_speed = exposure_time.map(|t| format_shutter_speed(t, None));
+	let exposure_bias = exposure_compensation.map(|b| format_exposure_bias(b, None));
 
-	// Shutter speed (exposure time in seconds)
-	let shutter_speed = get_f64("ExposureTime").map(|exposure| {
-		if exposure >= 1.0 {
-			format!("{:.1}s", exposure)
-		} else {
-			let denominator = (1.0 / exposure).round() as u32;
-			format!("1/{}", denominator)
-		}
-	});
-
-	// Exposure bias
-	let exposure_bias = get_f64("ExposureCompensation").map(|bias| {
-		if bias > 0.0 {
-			format!("+{:.1} EV", bias)
-		} else if bias < 0.0 {
-			format!("{:.1} EV", bias)
-		} else {
-			"0 EV".to_string()
-		}
-	});
+	// Shooting modes (numeric with -n, mapped to stable English descriptions)
+	let flash_value = get_u32("Flash");
+	let flash = flash_value.map(describe_flash);
+	let flash_fired = flash_value.map(|f| f & 0x01 != 0);
+	let metering_mode = get_u32("MeteringMode").and_then(describe_metering_mode);
+	let exposure_program = get_u32("ExposureProgram").and_then(describe_exposure_program);
+	let white_balance = get_u32("WhiteBalance").and_then(describe_white_balance);
+	let color_space = get_u32("ColorSpace").and_then(describe_color_space);
 
-	// Date taken
-	let date_taken = get_str("DateTimeOriginal");
+	// ICC profile, else HEIF nclx primaries, falling back to the EXIF ColorSpace tag when
+	// there is neither
+	let icc_profile_name = get_str("ProfileDescription")
+		.map(|p| p.trim().to_string())
+		.filter(|p| !p.is_empty());
+	let icc_colorants = ["RedMatrixColumn", "GreenMatrixColumn", "BlueMatrixColumn"]
+		.map(|tag| get_str(tag).as_deref().and_then(parse_xyz));
+	let color_gamut = get_str("ColorSpaceData")
+		.map(|signature| classify_icc_profile(&signature, icc_colorants))
+		.or_else(|| {
+			let profile = nclx_profile(get_u32("ColorPrimaries")?)?;
+			Some(classify_profile(&profile))
+		})
+		.or(match color_space.as_deref() {
+			Some("sRGB") => Some(ColorSpace::Srgb),
+			Some("Adobe RGB") => Some(ColorSpace::AdobeRgb),
+			_ => None,
+		});
+	let is_wide_gamut = color_gamut.map(ColorSpace::is_wide);
+	let color_gamut = color_gamut.map(|gamut| gamut.name().to_string());
+
+	// Date taken, with subseconds and the timezone offset when the camera recorded them
+	// date_taken is normalized to ISO 8601; placeholders and garbage become None
+	let raw_date = get_str("DateTimeOriginal");
+	let date_taken = raw_date.as_deref().and_then(normalize_date_taken);
+	let offset = get_str("OffsetTimeOriginal").or_else(|| get_str("OffsetTime"));
+	let subsec = get_str("SubSecTimeOriginal");
+	let parsed_date = raw_date
+		.as_deref()
+		.and_then(|d| date_taken_to_epoch_ms(d, subsec.as_deref(), offset.as_deref()));
+	let mut date_taken_ms = parsed_date.map(|(ms, _)| ms);
+	let date_taken_offset_minutes = parsed_date.and_then(|(_, offset)| offset);
+
+	// Fallback dates for resolving the capture date
+	let create_date = get_str("CreateDate").and_then(|d| normalize_date_taken(&d));
+	let gps_date_time_ms = get_str("GPSDateTime")
+		.and_then(|d| date_taken_to_epoch_ms(&d, None, Some("Z")))
+		.map(|(ms, _)| ms);
 
 	// GPS coordinates (already in decimal with -n flag)
 	let gps_latitude = get_f64("GPSLatitude");
 	let gps_longitude = get_f64("GPSLongitude");
-	let gps_altitude = get_f64("GPSAltitude");
+	let altitude_ref = get_u32("GPSAltitudeRef");
+	let gps_altitude = get_f64("GPSAltitude").map(|a| signed_altitude(a, altitude_ref));
+	let gps_img_direction = get_f64("GPSImgDirection").filter(|d| (0.0..=360.0).contains(d));
+	let gps_img_direction_ref = gps_img_direction
+		.and_then(|_| get_str("GPSImgDirectionRef"))
+		.and_then(|r| match r.as_str() {
+			"T" => Some("True".to_string()),
+			"M" => Some("Magnetic".to_string()),
+			_ => None,
+		});
+	let gps_speed = get_f64("GPSSpeed")
+		.and_then(|speed| speed_to_kmh(speed, get_str("GPSSpeedRef").as_deref()));
+	let gps_dop = get_f64("GPSDOP").filter(|d| *d > 0.0);
+
+	// Without a recorded offset the date is local time at the GPS position, so the timezone
+	// there gives the real UTC time (photos from a trip across zones sort correctly)
+	// The inferred offset is kept apart, as the recorded one is what the camera wrote
+	let tz = gps_latitude
+		.zip(gps_longitude)
+		.and_then(|(lat, lon)| timezone_at(lat, lon));
+	let date_taken_offset_inferred = match (tz, date_taken_ms, date_taken_offset_minutes) {
+		(Some(tz), Some(local_ms), None) => offset_minutes_at_local(tz, local_ms),
+		_ => None,
+	};
+	if let (Some(offset), Some(local_ms)) = (date_taken_offset_inferred, date_taken_ms) {
+		date_taken_ms = Some(local_ms - offset as f64 * 60_000.0);
+	}
+	let timezone = tz.map(|tz| tz.name().to_string());
 
 	// Orientation
 	let orientation = get_u32("Orientation");
 
+	// GPano: how much of the full panorama width this image covers
+	let projection_type = get_str("ProjectionType");
+	let pano_coverage = match (
+		get_f64("CroppedAreaImageWidthPixels"),
+		get_f64("FullPanoWidthPixels"),
+	) {
+		(Some(cropped), Some(full)) if full > 0.0 => Some((cropped / full).min(1.0)),
+		_ => None,
+	};
+	let content_identifier = get_str("ContentIdentifier").filter(|id| !id.is_empty());
+
+	// MakerNotes - tag names differ per vendor, so take the first one present
+	let first_str = |keys: &[&str]| {
+		keys.iter()
+			.find_map(|k| get_str(k))
+			.filter(|v| !v.is_empty())
+	};
+	let shutter_count = ["ShutterCount", "ShutterCount2", "ShutterCount3"]
+		.iter()
+		.find_map(|k| get_u32(k))
+		.filter(|&count| count > 0);
+	let af_points_used = first_str(&["AFPointsUsed", "AFPointsInFocus", "PrimaryAFPoint"]);
+	let picture_style = first_str(&[
+		"PictureStyle",       // Canon
+		"PictureControlName", // Nikon
+		"CreativeStyle",      // Sony
+		"PictureProfile",     // Sony video profiles
+		"FilmMode",           // Fujifilm
+	]);
+
+	// Keywords from XMP dc:subject and IPTC Keywords, merged without duplicates
+	let mut keywords: Vec<String> = get_list(obj, "Subject");
+	for keyword in get_list(obj, "Keywords") {
+		if !keywords.iter().any(|k| k.eq_ignore_ascii_case(&keyword)) {
+			keywords.push(keyword);
+		}
+	}
+	let keywords = Some(keywords).filter(|k| !k.is_empty());
+	let title = first_str(&["Title", "ObjectName"]);
+	// Some cameras fill ImageDescription with blanks or their own name
+	let caption = first_str(&["Description", "Caption-Abstract", "ImageDescription"])
+		.map(|c| c.trim().to_string())
+		.filter(|c| !c.is_empty() && !c.ends_with("DIGITAL CAMERA"));
+	// Ratings by group when they were read (see `EXIFTOOL_RATING_TAGS`), otherwise
+	// exiftool's preferred rating and label, taken as embedded XMP
+	let get_rating = |key: &str| get_f64(key).map(|r| r.round() as i32);
+	let ratings = if ["IFD0:Rating", "XMP-xmp:Rating", "XMP-xmp:Label"]
+		.iter()
+		.any(|key| obj.contains_key(*key))
+	{
+		RatingSources {
+			exif_rating: get_rating("IFD0:Rating"),
+			xmp_rating: get_rating("XMP-xmp:Rating"),
+			xmp_label: get_str("XMP-xmp:Label").map(|s| s.trim().to_string()),
+			..Default::default()
+		}
+	} else {
+		RatingSources {
+			xmp_rating: get_rating("Rating"),
+			xmp_label: first_str(&["Label"]),
+			..Default::default()
+		}
+	}
+	.resolve();
+
 	Some(ExifData {
 		camera_make,
 		camera_model,
 		lens_make,
 		lens_model,
 		focal_length,
+		focal_length_35mm,
+		crop_factor,
 		iso,
 		aperture,
 		shutter_speed,
 		exposure_bias,
+		f_number,
+		exposure_time,
+		exposure_compensation,
+		flash_fired,
+		flash,
+		metering_mode,
+		exposure_program,
+		white_balance,
+		color_space,
+		icc_profile_name,
+		color_gamut,
+		is_wide_gamut,
 		date_taken,
+		date_taken_ms,
+		date_taken_offset_minutes,
+		date_taken_offset_inferred,
+		create_date,
+		gps_date_time_ms,
+		timezone,
 		gps_latitude,
 		gps_longitude,
 		gps_altitude,
+		gps_img_direction,
+		gps_img_direction_ref,
+		gps_speed,
+		gps_dop,
 		orientation,
+		projection_type,
+		pano_coverage,
+		content_identifier,
+		shutter_count,
+		af_points_used,
+		picture_style,
+		keywords,
+		title,
+		caption,
+		rating: ratings.rating,
+		rating_source: ratings.rating_source.map(str::to_string),
+		color_label: ratings.label,
+		label_source: ratings.label_source.map(str::to_string),
 	})
 }
 
 /// Extract EXIF data from an image file
+/// Display strings are formatted for the given locale (e.g. "de-DE"), defaulting to English
 /// Returns None if the file has no EXIF data or cannot be read
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_exif(
+	file_path: String,
+	locale: Option<String>,
+) -> napi::Result<Option<ExifData>, ErrorCode> {
+	require(Component::Exiftool)?;
+	let Some(mut exif) = extract_exif_internal(&file_path) else {
+		return Ok(None);
+	};
+	if locale.is_some() {
+		exif.localize(locale.as_deref());
+	}
+	Ok(Some(exif))
+}
+
+/// Embedded EXIF thumbnail of a photo, see `extract_exif_thumbnail`
+pub fn extract_exif_thumbnail_internal(
+	file_path: &str,
+) -> Result<Option<Vec<u8>>, ProcessingError> {
+	require(Component::Exiftool)?;
+	Ok(extract_embedded_jpeg(file_path, "ThumbnailImage"))
+}
+
+/// Extract the small thumbnail JPEG embedded in IFD1, without decoding the image
+/// Returns None if the file has no embedded thumbnail
+#[cfg(feature = "node")]
 #[napi]
-pub fn extract_exif(file_path: String) -> Option<ExifData> {
-	extract_exif_internal(&file_path)
+pub fn extract_exif_thumbnail(file_path: String) -> napi::Result<Option<Buffer>, ErrorCode> {
+	Ok(extract_exif_thumbnail_internal(&file_path)?.map(Buffer::from))
+}
+
+/// Files per exiftool invocation in `extract_exif_batch`, to amortize exiftool's startup time
+const EXIF_BATCH_CHUNK: usize = 64;
+
+/// A file path as compared with exiftool's SourceFile, with "/" as the only separator
+fn source_file_key(path: &str) -> String {
+	path.replace('\\', "/")
+}
+
+/// Extract EXIF data from many files in parallel, see `extract_exif_batch`
+pub fn extract_exif_batch_internal(
+	file_paths: &[String],
+	locale: Option<&str>,
+) -> Result<Vec<Option<ExifData>>, ProcessingError> {
+	require(Component::Exiftool)?;
+
+	let results = file_paths
+		.par_chunks(EXIF_BATCH_CHUNK)
+		.flat_map_iter(|chunk| {
+			let paths: Vec<&str> = chunk.iter().map(String::as_str).collect();
+			// exiftool reports each file's path as given in SourceFile, except for Windows
+			// separators, which it turns into "/"
+			let by_path: HashMap<String, ExifData> = run_exiftool_json(&paths)
+				.unwrap_or_default()
+				.iter()
+				.filter_map(|obj| {
+					let source = obj.get("SourceFile")?.as_str()?;
+					Some((source_file_key(source), exif_from_json(obj)?))
+				})
+				.collect();
+			chunk
+				.iter()
+				.map(|path| {
+					let exif = by_path.get(&source_file_key(path)).cloned();
+					with_libraw_fallback(exif, path)
+				})
+				.collect::<Vec<_>>()
+		})
+		.map(|exif| {
+			exif.map(|mut exif| {
+				if locale.is_some() {
+					exif.localize(locale);
+				}
+				exif
+			})
+		})
+		.collect();
+	Ok(results)
+}
+
+/// Extract EXIF data from many files in parallel, without decoding any pixels
+/// Results are in input order, with None for files that have no EXIF data or can't be read
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_exif_batch(
+	file_paths: Vec<String>,
+	locale: Option<String>,
+) -> napi::Result<Vec<Option<ExifData>>, ErrorCode> {
+	Ok(extract_exif_batch_internal(&file_paths, locale.as_deref())?)
+}
+
+/// Re-format the display strings of previously extracted EXIF data for a locale
+#[cfg_attr(feature = "node", napi)]
+pub fn format_exif_values(exif: ExifData, locale: String) -> ExifData {
+	let mut exif = exif;
+	exif.localize(Some(&locale));
+	exif
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_format_defaults_to_period() {
+		assert_eq!(format_aperture(2.8, None), "f/2.8");
+		assert_eq!(format_shutter_speed(2.5, None), "2.5s");
+		assert_eq!(format_shutter_speed(0.004, None), "1/250");
+		assert_eq!(format_exposure_bias(0.33, Some("en-US")), "+0.3 EV");
+		assert_eq!(format_exposure_bias(0.0, None), "0 EV");
+	}
+
+	#[test]
+	fn test_format_comma_locales() {
+		assert_eq!(format_aperture(2.8, Some("de-DE")), "f/2,8");
+		assert_eq!(format_shutter_speed(2.5, Some("fr")), "2,5s");
+		assert_eq!(format_exposure_bias(-1.0, Some("pt_BR")), "-1,0 EV");
+	}
+
+	#[test]
+	fn test_describe_flash() {
+		assert_eq!(describe_flash(0x00), "Did not fire");
+		assert_eq!(describe_flash(0x10), "Did not fire, Off");
+		assert_eq!(describe_flash(0x19), "Fired, Auto");
+		assert_eq!(describe_flash(0x49), "Fired, Compulsory, Red-eye reduction");
+		assert_eq!(describe_flash(0x20), "No flash function");
+	}
+
+	#[test]
+	fn test_classify_icc_profile() {
+		// Colorants of Apple's Display P3 and the sRGB IEC61966-2.1 profiles, as exiftool prints them
+		let p3 = [
+			"0.51512 0.2412 -0.00105",
+			"0.29198 0.69225 0.04189",
+			"0.1571 0.06657 0.78407",
+		]
+		.map(parse_xyz);
+		let srgb = [
+			"0.43607 0.22249 0.01392",
+			"0.38515 0.71687 0.09708",
+			"0.14307 0.06061 0.7141",
+		]
+		.map(parse_xyz);
+		assert_eq!(classify_icc_profile("RGB ", p3), ColorSpace::DisplayP3);
+		assert_eq!(classify_icc_profile("RGB", srgb), ColorSpace::Srgb);
+		assert_eq!(classify_icc_profile("RGB ", [None; 3]), ColorSpace::Other);
+		assert_eq!(
+			classify_icc_profile("GRAY", [None; 3]),
+			ColorSpace::Grayscale
+		);
+		assert_eq!(classify_icc_profile("CMYK", [None; 3]), ColorSpace::Cmyk);
+		assert!(ColorSpace::DisplayP3.is_wide());
+		assert!(!ColorSpace::Other.is_wide());
+		assert_eq!(parse_xyz("0.5 0.2"), None);
+	}
+
+	#[test]
+	fn test_equivalent_focal_length() {
+		// iPhone main camera tags its 35mm equivalent
+		assert_eq!(
+			equivalent_focal_length(Some(6.86), Some(26.0), None),
+			(Some(26), Some(3.79))
+		);
+		// APS-C body without the tag
+		assert_eq!(
+			equivalent_focal_length(Some(35.0), None, Some(1.53)),
+			(Some(54), Some(1.53))
+		);
+		assert_eq!(
+			equivalent_focal_length(Some(50.0), None, None),
+			(None, None)
+		);
+	}
+
+	#[test]
+	fn test_gps_refs() {
+		assert_eq!(signed_altitude(12.5, Some(1)), -12.5);
+		assert_eq!(signed_altitude(12.5, Some(0)), 12.5);
+		assert_eq!(speed_to_kmh(10.0, Some("N")), Some(18.52));
+		assert_eq!(speed_to_kmh(10.0, None), Some(10.0));
+		assert_eq!(speed_to_kmh(10.0, Some("X")), None);
+	}
+
+	#[test]
+	fn test_get_list_accepts_single_values() {
+		let json: serde_json::Value =
+			serde_json::json!({ "Subject": ["Paris", " travel "], "Keywords": 2023 });
+		let obj = json.as_object().unwrap();
+		assert_eq!(get_list(obj, "Subject"), vec!["Paris", "travel"]);
+		assert_eq!(get_list(obj, "Keywords"), vec!["2023"]);
+		assert!(get_list(obj, "Title").is_empty());
+	}
+
+	#[test]
+	fn test_source_file_key() {
+		assert_eq!(
+			source_file_key(r"C:\Photos\2023\IMG_0001.JPG"),
+			source_file_key("C:/Photos/2023/IMG_0001.JPG")
+		);
+		assert_eq!(
+			source_file_key("/photos/IMG_0001.JPG"),
+			"/photos/IMG_0001.JPG"
+		);
+	}
+
+	#[test]
+	fn test_rating_sources() {
+		let json = serde_json::json!({
+			"Rating": 4,
+			"IFD0:Rating": 2,
+			"XMP-xmp:Rating": 4,
+			"XMP-xmp:Label": " Red ",
+		});
+		let exif = exif_from_json(json.as_object().unwrap()).unwrap();
+		assert_eq!(exif.rating, Some(4));
+		assert_eq!(exif.rating_source.as_deref(), Some("xmp"));
+		assert_eq!(exif.color_label.as_deref(), Some("Red"));
+		assert_eq!(exif.label_source.as_deref(), Some("xmp"));
+
+		let json = serde_json::json!({ "Rating": 3, "IFD0:Rating": 3 });
+		let exif = exif_from_json(json.as_object().unwrap()).unwrap();
+		assert_eq!(exif.rating_source.as_deref(), Some("exif"));
+
+		// Without the grouped tags, e.g. read from stdin
+		let json = serde_json::json!({ "Rating": 5 });
+		let exif = exif_from_json(json.as_object().unwrap()).unwrap();
+		assert_eq!(exif.rating_source.as_deref(), Some("xmp"));
+	}
+
+	#[test]
+	fn test_normalize_date_taken() {
+		assert_eq!(
+			normalize_date_taken("2023:07:14 10:22:01"),
+			Some("2023-07-14T10:22:01".to_string())
+		);
+		assert_eq!(
+			normalize_date_taken(" 2024-02-29T23:59:59.5+02:00"),
+			Some("2024-02-29T23:59:59".to_string())
+		);
+		assert_eq!(normalize_date_taken("0000:00:00 00:00:00"), None);
+		assert_eq!(normalize_date_taken("2023:02:29 10:22:01"), None);
+		assert_eq!(normalize_date_taken("2023:07:14 25:00:00"), None);
+		assert_eq!(normalize_date_taken("    :  :     :  :  "), None);
+	}
+
+	#[test]
+	fn test_date_taken_to_epoch_ms() {
+		// 2023-07-14 10:22:01 UTC = 1689330121
+		assert_eq!(
+			date_taken_to_epoch_ms("2023:07:14 10:22:01", None, None),
+			Some((1_689_330_121_000.0, None))
+		);
+		assert_eq!(
+			date_taken_to_epoch_ms("2023:07:14 12:22:01", Some("045"), Some("+02:00")),
+			Some((1_689_330_121_045.0, Some(120)))
+		);
+		assert_eq!(
+			date_taken_to_epoch_ms("2023-07-14T05:52:01.5-04:30", None, Some("+09:00")),
+			Some((1_689_330_121_500.0, Some(-270)))
+		);
+		assert!(date_taken_to_epoch_ms("0000:00:00 00:00:00", None, None).is_none());
+
+		let (before_epoch, _) =
+			date_taken_to_epoch_ms("1969:12:31 23:59:59", None, Some("Z")).unwrap();
+		assert_eq!(before_epoch, -1000.0);
+	}
 }
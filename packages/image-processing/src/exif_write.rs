@@ -0,0 +1,261 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::exif::{date_taken_to_epoch_ms, DateTimeParts};
+use crate::features::{require, Component};
+use crate::preview::is_raw_file;
+use crate::video::is_video_file;
+use crate::xmp::{find_sidecar, sidecar_path};
+
+/// GPS position to write
+#[cfg_attr(feature = "node", napi(object))]
+pub struct GpsCoordinates {
+	pub latitude: f64,
+	pub longitude: f64,
+	pub altitude: Option<f64>, // meters, negative below sea level
+}
+
+/// Fields to write back; omitted fields are left untouched
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ExifWriteFields {
+	pub gps: Option<GpsCoordinates>,
+	/// "2023:07:14 10:22:01" or ISO 8601 ("2023-07-14T10:22:01+02:00")
+	pub date_taken: Option<String>,
+	/// EXIF orientation (1-8)
+	pub orientation: Option<u32>,
+}
+
+/// Where the metadata was written
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ExifWriteResult {
+	/// The photo itself, or the XMP sidecar for RAW files
+	pub written_path: String,
+	pub sidecar: bool,
+}
+
+/// Split a date into the EXIF "YYYY:MM:DD HH:MM:SS" form and its timezone offset ("+02:00")
+fn exif_date_parts(date_taken: &str) -> Result<(String, Option<String>), ProcessingError> {
+	let invalid = || ProcessingError::invalid(format!("Invalid date: {}", date_taken));
+	let (_, offset_minutes) = date_taken_to_epoch_ms(date_taken, None, None).ok_or_else(invalid)?;
+	let parts = date_taken
+		.trim()
+		.get(..19)
+		.and_then(DateTimeParts::parse)
+		.ok_or_else(invalid)?;
+
+	let date = parts.to_exif();
+	let offset = offset_minutes.map(|minutes| {
+		let sign = if minutes < 0 { '-' } else { '+' };
+		format!(
+			"{}{:02}:{:02}",
+			sign,
+			minutes.abs() / 60,
+			minutes.abs() % 60
+		)
+	});
+	Ok((date, offset))
+}
+
+/// Build exiftool assignments for the fields, in EXIF or XMP form
+fn tag_assignments(fields: &ExifWriteFields, xmp: bool) -> Result<Vec<String>, ProcessingError> {
+	let mut args = Vec::new();
+
+	if let Some(gps) = &fields.gps {
+		if !(-90.0..=90.0).contains(&gps.latitude) || !(-180.0..=180.0).contains(&gps.longitude) {
+			return Err(ProcessingError::invalid(format!(
+				"Invalid GPS position: {}, {}",
+				gps.latitude, gps.longitude
+			)));
+		}
+
+		if xmp {
+			// XMP stores the hemisphere in the value itself
+			args.push(format!("-XMP-exif:GPSLatitude={}", gps.latitude));
+			args.push(format!("-XMP-exif:GPSLongitude={}", gps.longitude));
+		} else {
+			let lat_ref = if gps.latitude < 0.0 { "S" } else { "N" };
+			let lon_ref = if gps.longitude < 0.0 { "W" } else { "E" };
+			args.push(format!("-EXIF:GPSLatitude={}", gps.latitude.abs()));
+			args.push(format!("-EXIF:GPSLatitudeRef={}", lat_ref));
+			args.push(format!("-EXIF:GPSLongitude={}", gps.longitude.abs()));
+			args.push(format!("-EXIF:GPSLongitudeRef={}", lon_ref));
+		}
+
+		if let Some(altitude) = gps.altitude {
+			let group = if xmp { "XMP-exif" } else { "EXIF" };
+			let altitude_ref = if altitude < 0.0 { 1 } else { 0 };
+			args.push(format!("-{}:GPSAltitude={}", group, altitude.abs()));
+			args.push(format!("-{}:GPSAltitudeRef#={}", group, altitude_ref));
+		}
+	}
+
+	if let Some(date_taken) = &fields.date_taken {
+		let (date, offset) = exif_date_parts(date_taken)?;
+		if xmp {
+			// XMP dates carry their offset inline
+			let xmp_date = format!("{}{}", date, offset.unwrap_or_default());
+			args.push(format!("-XMP-exif:DateTimeOriginal={}", xmp_date));
+			args.push(format!("-XMP-xmp:CreateDate={}", xmp_date));
+		} else {
+			args.push(format!("-EXIF:DateTimeOriginal={}", date));
+			args.push(format!("-EXIF:CreateDate={}", date));
+			if let Some(offset) = offset {
+				args.push(format!("-EXIF:OffsetTimeOriginal={}", offset));
+				args.push(format!("-EXIF:OffsetTimeDigitized={}", offset));
+			}
+		}
+	}
+
+	if let Some(orientation) = fields.orientation {
+		if !(1..=8).contains(&orientation) {
+			return Err(ProcessingError::invalid(format!(
+				"Invalid orientation: {}",
+				orientation
+			)));
+		}
+		let group = if xmp { "XMP-tiff" } else { "EXIF" };
+		args.push(format!("-{}:Orientation#={}", group, orientation));
+	}
+
+	Ok(args)
+}
+
+/// Run exiftool with the given assignments against `target`
+/// exiftool writes to a temporary file and renames it, and never re-encodes image data
+pub(crate) fn run_exiftool(
+	assignments: &[String],
+	extra: &[&str],
+	target: &str,
+) -> Result<(), ProcessingError> {
+	let output = Command::new("exiftool")
+		.args(["-overwrite_original", "-m"])
+		.args(assignments)
+		.args(extra)
+		.arg(target)
+		.output()
+		.map_err(|e| {
+			let message = format!("Failed to run exiftool: {}", e);
+			ProcessingError::new(ErrorCode::FeatureUnavailable, message)
+		})?;
+
+	if !output.status.success() {
+		let message = format!(
+			"exiftool failed: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		);
+		return Err(ProcessingError::new(ErrorCode::Internal, message).at(ErrorStage::Write));
+	}
+	Ok(())
+}
+
+/// Write metadata back to a photo, see `write_exif_fields`
+pub fn write_exif_fields_internal(
+	file_path: &str,
+	fields: &ExifWriteFields,
+) -> Result<ExifWriteResult, ProcessingError> {
+	require(Component::Exiftool)?;
+
+	if !Path::new(file_path).exists() {
+		let message = format!("File not found: {}", file_path);
+		return Err(ProcessingError::new(ErrorCode::NotFound, message));
+	}
+	if is_video_file(file_path) {
+		return Err(ProcessingError::new(
+			ErrorCode::UnsupportedFormat,
+			"Writing metadata to videos is not supported",
+		));
+	}
+
+	let sidecar = is_raw_file(file_path);
+	let assignments = tag_assignments(fields, sidecar)?;
+	if assignments.is_empty() {
+		return Err(ProcessingError::invalid("No fields to write"));
+	}
+
+	let written_path = if sidecar {
+		match find_sidecar(file_path) {
+			Some(xmp_path) => {
+				run_exiftool(&assignments, &[], &xmp_path)?;
+				xmp_path
+			}
+			None => {
+				let xmp_path = sidecar_path(file_path);
+				// Create the sidecar with only the assigned tags
+				run_exiftool(&assignments, &["-o", &xmp_path], file_path)?;
+				xmp_path
+			}
+		}
+	} else {
+		run_exiftool(&assignments, &[], file_path)?;
+		file_path.to_string()
+	};
+
+	Ok(ExifWriteResult {
+		written_path,
+		sidecar,
+	})
+}
+
+/// Write GPS, capture date and orientation back to a photo
+/// JPEG/TIFF (and other EXIF containers) are updated in place; RAW files update their
+/// existing XMP sidecar ("IMG_0001.CR2.xmp", then "IMG_0001.xmp") or get a new "IMG_0001.xmp"
+#[cfg(feature = "node")]
+#[napi]
+pub fn write_exif_fields(
+	file_path: String,
+	fields: ExifWriteFields,
+) -> napi::Result<ExifWriteResult, ErrorCode> {
+	write_exif_fields_internal(&file_path, &fields).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_exif_assignments_use_hemisphere_refs() {
+		let fields = ExifWriteFields {
+			gps: Some(GpsCoordinates {
+				latitude: -33.8568,
+				longitude: 151.2153,
+				altitude: Some(-4.0),
+			}),
+			date_taken: Some("2023-07-14T10:22:01+10:00".to_string()),
+			orientation: Some(6),
+		};
+
+		let args = tag_assignments(&fields, false).unwrap();
+		assert!(args.contains(&"-EXIF:GPSLatitude=33.8568".to_string()));
+		assert!(args.contains(&"-EXIF:GPSLatitudeRef=S".to_string()));
+		assert!(args.contains(&"-EXIF:GPSAltitudeRef#=1".to_string()));
+		assert!(args.contains(&"-EXIF:DateTimeOriginal=2023:07:14 10:22:01".to_string()));
+		assert!(args.contains(&"-EXIF:OffsetTimeOriginal=+10:00".to_string()));
+		assert!(args.contains(&"-EXIF:Orientation#=6".to_string()));
+
+		let xmp_args = tag_assignments(&fields, true).unwrap();
+		assert!(xmp_args.contains(&"-XMP-exif:GPSLatitude=-33.8568".to_string()));
+		assert!(
+			xmp_args.contains(&"-XMP-exif:DateTimeOriginal=2023:07:14 10:22:01+10:00".to_string())
+		);
+	}
+
+	#[test]
+	fn test_rejects_invalid_values() {
+		let fields = ExifWriteFields {
+			gps: None,
+			date_taken: Some("yesterday".to_string()),
+			orientation: None,
+		};
+		assert!(tag_assignments(&fields, false).is_err());
+
+		let fields = ExifWriteFields {
+			gps: None,
+			date_taken: None,
+			orientation: Some(9),
+		};
+		assert!(tag_assignments(&fields, false).is_err());
+	}
+}
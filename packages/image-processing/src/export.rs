@@ -0,0 +1,299 @@
+use image::imageops::FilterType;
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::clip::decode_oriented;
+#[cfg(feature = "node")]
+use crate::error::reject_with_code;
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::exif_write::run_exiftool;
+use crate::features::{require, Component};
+use crate::runtime::tmp_dir;
+use crate::thumbnails::{encode_thumbnail, ThumbnailFormat};
+use crate::watermark::{apply_watermark, Watermark};
+
+/// Quality of exported photos when none is given
+const DEFAULT_QUALITY: u8 = 90;
+
+/// Metadata carried over to an exported photo
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportMetadata {
+	/// Everything but the color profile and orientation, which no longer apply
+	Keep,
+	/// Like `keep`, without the GPS position
+	StripGps,
+	/// Nothing
+	#[default]
+	Strip,
+}
+
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct ExportOptions {
+	/// Longest edge in pixels; smaller photos aren't upscaled
+	pub max_dimension: Option<u32>,
+	/// Output format; taken from the output extension when omitted
+	pub format: Option<ThumbnailFormat>,
+	/// Encoding quality (0-100, default 90)
+	pub quality: Option<u8>,
+	/// Progressive encoding when the format is JPEG
+	pub progressive: Option<bool>,
+	/// Metadata to copy from the original (default: strip)
+	pub metadata: Option<ExportMetadata>,
+	/// Convert wide-gamut photos to sRGB (default true), since the export carries no profile
+	pub color_manage: Option<bool>,
+	/// Keep the original's colors and embed its ICC profile instead of converting to sRGB,
+	/// for exports to wide-gamut aware apps (default false)
+	pub keep_profile: Option<bool>,
+	/// Text or PNG drawn over the photo, e.g. a photographer credit
+	pub watermark: Option<Watermark>,
+}
+
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ExportResult {
+	pub output_path: String,
+	pub format: ThumbnailFormat,
+	pub width: u32,
+	pub height: u32,
+	/// Size of the written file in bytes
+	pub size: i64,
+}
+
+/// Export format: the requested one, or the one matching the output extension
+fn export_format(
+	output_path: &str,
+	format: Option<ThumbnailFormat>,
+) -> Result<ThumbnailFormat, ProcessingError> {
+	if let Some(format) = format {
+		return Ok(format);
+	}
+	let extension = Path::new(output_path)
+		.extension()
+		.map(|e| e.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+	match extension.as_str() {
+		"jpg" | "jpeg" => Ok(ThumbnailFormat::Jpeg),
+		"webp" => Ok(ThumbnailFormat::Webp),
+		"avif" => Ok(ThumbnailFormat::Avif),
+		_ => Err(ProcessingError::invalid(format!(
+			"Unsupported export format: {}",
+			output_path
+		))),
+	}
+}
+
+/// exiftool arguments copying the original's metadata and color profile to the export, if
+/// any; the orientation is reset since pixels are written upright
+fn metadata_arguments(
+	input_path: &str,
+	metadata: ExportMetadata,
+	keep_profile: bool,
+) -> Vec<String> {
+	let mut args = vec!["-TagsFromFile".to_string(), input_path.to_string()];
+	match (metadata, keep_profile) {
+		(ExportMetadata::Strip, false) => return Vec::new(),
+		(ExportMetadata::Strip, true) => args.push("-ICC_Profile".to_string()),
+		(_, keep_profile) => {
+			args.push("-all:all".to_string());
+			if !keep_profile {
+				args.push("--ICC_Profile:all".to_string());
+			}
+			args.push("-Orientation#=1".to_string());
+		}
+	}
+	if metadata == ExportMetadata::StripGps {
+		// EXIF GPS and XMP-exif GPS tags alike
+		args.push("-GPS*=".to_string());
+	}
+	args
+}
+
+/// Path in the temp directory an export is written to before its metadata
+fn staging_path(format: ThumbnailFormat) -> PathBuf {
+	static NEXT: AtomicU64 = AtomicU64::new(0);
+	let name = format!(
+		"photobrain-export-{}-{}.{}",
+		std::process::id(),
+		NEXT.fetch_add(1, Ordering::Relaxed),
+		format.extension()
+	);
+	tmp_dir().join(name)
+}
+
+/// Error of a file that couldn't be written
+fn write_error(e: &io::Error, file_path: &str) -> ProcessingError {
+	ProcessingError::io(e, "Failed to write export")
+		.at(ErrorStage::Write)
+		.for_file(file_path)
+}
+
+/// Move a finished export into place, copying when the temp directory is on another volume
+fn move_into_place(staged: &Path, output_path: &str) -> Result<(), ProcessingError> {
+	if fs::rename(staged, output_path).is_ok() {
+		return Ok(());
+	}
+	let copied = fs::copy(staged, output_path);
+	let _ = fs::remove_file(staged);
+	copied.map(|_| ()).map_err(|e| write_error(&e, output_path))
+}
+
+/// Export a photo, see `export_photo`
+pub fn export_photo_internal(
+	input_path: &str,
+	output_path: &str,
+	options: Option<ExportOptions>,
+) -> Result<ExportResult, ProcessingError> {
+	let options = options.unwrap_or_default();
+	if Path::new(input_path) == Path::new(output_path) {
+		return Err(ProcessingError::invalid(
+			"Export would overwrite the original",
+		));
+	}
+	let format = export_format(output_path, options.format)?;
+	let metadata = options.metadata.unwrap_or_default();
+	let keep_profile = options.keep_profile.unwrap_or(false);
+	if metadata != ExportMetadata::Strip || keep_profile {
+		require(Component::Exiftool)?;
+	}
+
+	let color_manage = options.color_manage.unwrap_or(true) && !keep_profile;
+	let img = decode_oriented(input_path, color_manage)?;
+	let img = match options.max_dimension {
+		Some(max) if img.width().max(img.height()) > max => {
+			img.resize(max, max, FilterType::Lanczos3)
+		}
+		_ => img,
+	};
+	let img = match &options.watermark {
+		Some(watermark) => apply_watermark(img, watermark).map_err(|e| {
+			ProcessingError::from_message(ErrorCode::InvalidArgument, e)
+				.context("Failed to apply watermark")
+		})?,
+		None => img,
+	};
+
+	let data = encode_thumbnail(
+		&img,
+		format,
+		options.quality.unwrap_or(DEFAULT_QUALITY),
+		options.progressive.unwrap_or(false),
+	)
+	.map_err(|e| ProcessingError::from_message(ErrorCode::Internal, e).at(ErrorStage::Write))?;
+	let staged = staging_path(format);
+	fs::write(&staged, &data).map_err(|e| write_error(&e, &staged.to_string_lossy()))?;
+
+	let assignments = metadata_arguments(input_path, metadata, keep_profile);
+	if !assignments.is_empty() {
+		let tagged = run_exiftool(&assignments, &[], &staged.to_string_lossy());
+		if let Err(e) = tagged {
+			let _ = fs::remove_file(&staged);
+			return Err(e);
+		}
+	}
+	move_into_place(&staged, output_path)?;
+	let size = fs::metadata(output_path).map_or(data.len() as u64, |m| m.len());
+
+	Ok(ExportResult {
+		output_path: output_path.to_string(),
+		format,
+		width: img.width(),
+		height: img.height(),
+		size: size as i64,
+	})
+}
+
+/// Export a photo for the web or sharing: resized to `max_dimension`, converted to sRGB and
+/// encoded as JPEG, WebP or AVIF, with its metadata kept, stripped of GPS or stripped, and
+/// an optional watermark
+/// The export is finished in the temp directory, so a failed metadata copy leaves no file
+/// behind; works for every decodable source, RAW and HEIF included
+#[cfg(feature = "node")]
+#[napi]
+pub fn export_photo(
+	input_path: String,
+	output_path: String,
+	options: Option<ExportOptions>,
+) -> napi::Result<ExportResult, ErrorCode> {
+	export_photo_internal(&input_path, &output_path, options).map_err(napi::Error::from)
+}
+
+#[cfg(feature = "node")]
+pub struct ExportPhotoTask {
+	input_path: String,
+	output_path: String,
+	options: Option<ExportOptions>,
+}
+
+#[cfg(feature = "node")]
+impl Task for ExportPhotoTask {
+	type Output = ExportResult;
+	type JsValue = ExportResult;
+
+	fn compute(&mut self) -> napi::Result<Self::Output> {
+		export_photo_internal(&self.input_path, &self.output_path, self.options.take())
+			.map_err(napi::Error::from)
+	}
+
+	fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+		Ok(output)
+	}
+
+	fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+		reject_with_code(env, err)
+	}
+}
+
+/// Same as `export_photo`, off the JS thread, e.g. while exporting a selection
+#[cfg(feature = "node")]
+#[napi]
+pub fn export_photo_async(
+	input_path: String,
+	output_path: String,
+	options: Option<ExportOptions>,
+) -> AsyncTask<ExportPhotoTask> {
+	AsyncTask::new(ExportPhotoTask {
+		input_path,
+		output_path,
+		options,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_export_format_and_metadata() {
+		assert_eq!(
+			export_format("out/IMG_1.JPG", None),
+			Ok(ThumbnailFormat::Jpeg)
+		);
+		assert_eq!(
+			export_format("out/IMG_1.jpg", Some(ThumbnailFormat::Avif)),
+			Ok(ThumbnailFormat::Avif)
+		);
+		assert!(export_format("out/IMG_1.png", None).is_err());
+
+		assert!(metadata_arguments("IMG_1.HEIC", ExportMetadata::Strip, false).is_empty());
+		assert_eq!(
+			metadata_arguments("IMG_1.HEIC", ExportMetadata::Strip, true),
+			["-TagsFromFile", "IMG_1.HEIC", "-ICC_Profile"]
+		);
+		let keep = metadata_arguments("IMG_1.HEIC", ExportMetadata::Keep, false);
+		assert_eq!(keep[..2], ["-TagsFromFile", "IMG_1.HEIC"]);
+		assert!(keep.contains(&"--ICC_Profile:all".to_string()));
+		assert!(!keep.contains(&"-GPS*=".to_string()));
+		let strip_gps = metadata_arguments("IMG_1.HEIC", ExportMetadata::StripGps, true);
+		assert!(!strip_gps.contains(&"--ICC_Profile:all".to_string()));
+		assert_eq!(strip_gps.last().map(String::as_str), Some("-GPS*="));
+	}
+}
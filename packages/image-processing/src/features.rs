@@ -0,0 +1,263 @@
+use libheif_rs::{CompressionFormat, LibHeif};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use once_cell::sync::Lazy;
+use std::fmt;
+use std::process::Command;
+use std::sync::Mutex;
+
+#[cfg(feature = "node")]
+use crate::error::{ErrorCode, ProcessingError};
+
+/// Optional native component that may be missing from a packaged build
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+	/// libheif with an HEVC decoder plugin (HEIC decoding)
+	Libheif,
+	/// ONNX runtime used by fastembed (CLIP embeddings)
+	OnnxRuntime,
+	/// ffmpeg and ffprobe binaries (video support)
+	Ffmpeg,
+	/// exiftool binary (EXIF, and RAW previews LibRaw can't read)
+	Exiftool,
+}
+
+impl Component {
+	pub const ALL: [Component; 4] = [
+		Component::Libheif,
+		Component::OnnxRuntime,
+		Component::Ffmpeg,
+		Component::Exiftool,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Component::Libheif => "libheif",
+			Component::OnnxRuntime => "onnxruntime",
+			Component::Ffmpeg => "ffmpeg",
+			Component::Exiftool => "exiftool",
+		}
+	}
+
+	/// Features of the crate that depend on this component
+	fn provides(self) -> &'static [&'static str] {
+		match self {
+			Component::Libheif => &["heic"],
+			Component::OnnxRuntime => &["clip", "tagging", "ocr", "nsfw", "presence"],
+			Component::Ffmpeg => &["video"],
+			Component::Exiftool => &["exif"],
+		}
+	}
+}
+
+/// Error returned by calls that need a component which isn't available
+#[derive(Debug, Clone)]
+pub struct FeatureUnavailable {
+	pub component: Component,
+	pub reason: String,
+}
+
+impl fmt::Display for FeatureUnavailable {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"FeatureUnavailable: {} is not available ({})",
+			self.component.name(),
+			self.reason
+		)
+	}
+}
+
+impl From<FeatureUnavailable> for String {
+	fn from(e: FeatureUnavailable) -> Self {
+		e.to_string()
+	}
+}
+
+#[cfg(feature = "node")]
+impl From<FeatureUnavailable> for napi::Error<ErrorCode> {
+	fn from(e: FeatureUnavailable) -> Self {
+		ProcessingError::from(e).into()
+	}
+}
+
+#[cfg(feature = "node")]
+impl From<FeatureUnavailable> for napi::Error {
+	fn from(e: FeatureUnavailable) -> Self {
+		ProcessingError::from(e).into()
+	}
+}
+
+/// Result of probing a component: Ok(detail such as the version) or Err(reason)
+type Probe = Result<String, String>;
+
+static LIBHEIF_PROBE: Lazy<Probe> = Lazy::new(probe_libheif);
+static FFMPEG_PROBE: Lazy<Probe> = Lazy::new(probe_ffmpeg);
+static EXIFTOOL_PROBE: Lazy<Probe> = Lazy::new(probe_exiftool);
+
+/// ONNX runtime can only be probed by loading a model, so failures are recorded when that happens
+static ONNX_RUNTIME_FAILURE: Mutex<Option<String>> = Mutex::new(None);
+
+fn probe_libheif() -> Probe {
+	let lib_heif = LibHeif::new();
+	let [major, minor, patch] = lib_heif.version();
+	if lib_heif
+		.decoder_descriptors(1, Some(CompressionFormat::Hevc))
+		.is_empty()
+	{
+		return Err(format!(
+			"libheif {}.{}.{} has no HEVC decoder plugin",
+			major, minor, patch
+		));
+	}
+	Ok(format!("{}.{}.{}", major, minor, patch))
+}
+
+/// Run `program args` and return the first line of its output
+fn probe_binary(program: &str, args: &[&str]) -> Probe {
+	let output = Command::new(program)
+		.args(args)
+		.output()
+		.map_err(|e| format!("{} not found: {}", program, e))?;
+	if !output.status.success() {
+		return Err(format!("{} exited with {}", program, output.status));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.next()
+		.unwrap_or_default()
+		.trim()
+		.to_string())
+}
+
+fn probe_ffmpeg() -> Probe {
+	probe_binary("ffprobe", &["-version"])?;
+	probe_binary("ffmpeg", &["-version"])
+}
+
+fn probe_exiftool() -> Probe {
+	probe_binary("exiftool", &["-ver"])
+}
+
+fn probe(component: Component) -> Probe {
+	match component {
+		Component::Libheif => LIBHEIF_PROBE.clone(),
+		Component::Ffmpeg => FFMPEG_PROBE.clone(),
+		Component::Exiftool => EXIFTOOL_PROBE.clone(),
+		Component::OnnxRuntime => {
+			let failure = ONNX_RUNTIME_FAILURE
+				.lock()
+				.unwrap_or_else(|e| e.into_inner());
+			match failure.as_ref() {
+				Some(reason) => Err(reason.clone()),
+				None => Ok("loaded on first use".to_string()),
+			}
+		}
+	}
+}
+
+/// Check that a component is available before using it
+/// Probes run once per process and are cached
+pub fn require(component: Component) -> Result<(), FeatureUnavailable> {
+	probe(component)
+		.map(|_| ())
+		.map_err(|reason| FeatureUnavailable { component, reason })
+}
+
+/// Record that a component failed to load so later calls fail fast with a clear error
+pub fn mark_unavailable(component: Component, reason: String) -> FeatureUnavailable {
+	if component == Component::OnnxRuntime {
+		let mut failure = ONNX_RUNTIME_FAILURE
+			.lock()
+			.unwrap_or_else(|e| e.into_inner());
+		*failure = Some(reason.clone());
+	}
+	FeatureUnavailable { component, reason }
+}
+
+/// Availability of one optional component
+#[cfg_attr(feature = "node", napi(object))]
+pub struct FeatureInfo {
+	/// Component name ("libheif", "onnxruntime", "ffmpeg" or "exiftool")
+	pub name: String,
+	pub available: bool,
+	/// Version when available, otherwise why it isn't
+	pub detail: Option<String>,
+	/// Features that depend on this component (e.g. "heic", "clip", "video")
+	pub provides: Vec<String>,
+}
+
+/// Report which optional components this build and machine support
+#[cfg_attr(feature = "node", napi)]
+pub fn get_available_features() -> Vec<FeatureInfo> {
+	Component::ALL
+		.iter()
+		.map(|&component| {
+			let probe = probe(component);
+			FeatureInfo {
+				name: component.name().to_string(),
+				available: probe.is_ok(),
+				detail: Some(probe.unwrap_or_else(|reason| reason)).filter(|d| !d.is_empty()),
+				provides: component.provides().iter().map(|s| s.to_string()).collect(),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::error::{ErrorCode, ProcessingError};
+
+	#[test]
+	fn test_reports_every_component() {
+		let features = get_available_features();
+		let names: Vec<&str> = features.iter().map(|f| f.name.as_str()).collect();
+		assert_eq!(names, ["libheif", "onnxruntime", "ffmpeg", "exiftool"]);
+		for (feature, component) in features.iter().zip(Component::ALL) {
+			assert_eq!(feature.available, require(component).is_ok());
+			assert!(!feature.provides.is_empty());
+		}
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_probe_binary() {
+		assert_eq!(
+			probe_binary("sh", &["-c", "echo ' 1.2.3 '; echo more"]),
+			Ok("1.2.3".to_string())
+		);
+		assert!(probe_binary("sh", &["-c", "exit 3"]).is_err_and(|e| e.contains("exited")));
+		assert!(
+			probe_binary("photobrain-missing-tool", &[]).is_err_and(|e| e.contains("not found"))
+		);
+	}
+
+	#[test]
+	fn test_unavailable_component_fails_fast() {
+		let error = mark_unavailable(Component::OnnxRuntime, "libonnxruntime.so missing".into());
+		assert_eq!(
+			error.to_string(),
+			"FeatureUnavailable: onnxruntime is not available (libonnxruntime.so missing)"
+		);
+		// Later calls fail with the recorded reason instead of loading again
+		let recorded = require(Component::OnnxRuntime).unwrap_err();
+		assert_eq!(recorded.reason, "libonnxruntime.so missing");
+		let onnx = get_available_features()
+			.into_iter()
+			.find(|f| f.name == "onnxruntime")
+			.unwrap();
+		assert!(!onnx.available);
+		assert_eq!(onnx.detail.as_deref(), Some("libonnxruntime.so missing"));
+		assert_eq!(
+			ProcessingError::from(recorded).code,
+			ErrorCode::FeatureUnavailable
+		);
+
+		ONNX_RUNTIME_FAILURE
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.take();
+		assert!(require(Component::OnnxRuntime).is_ok());
+	}
+}
@@ -0,0 +1,292 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::process::Command;
+
+use crate::decode::{decode_bytes, source_kind, SourceKind};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::heif::heif_gain_map;
+use crate::orientation::apply_orientation;
+use crate::preview::extract_embedded_jpeg;
+use crate::Buffer;
+
+/// Headroom (in stops) assumed for Apple gain maps without HDR maker notes
+const DEFAULT_APPLE_HEADROOM_STOPS: f64 = 2.0;
+
+/// Linear level above which boosted highlights are compressed back into SDR range
+const TONE_MAP_KNEE: f32 = 0.8;
+
+/// Entries of the linear to sRGB lookup table used when tone mapping
+const ENCODE_LUT_SIZE: usize = 4096;
+
+/// Where a gain map was found
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GainMapSource {
+	/// Apple HDR gain map, an auxiliary image of iPhone HEICs
+	AppleHeif,
+	/// Ultra HDR / ISO 21496-1 gain map, the second image of a JPEG's MPF container
+	UltraHdrJpeg,
+}
+
+/// HDR gain map of a photo, to be applied on top of its SDR pixels
+#[derive(Debug, Clone)]
+pub struct GainMap {
+	pub source: GainMapSource,
+	pub image: GrayImage,
+	/// Boost (in stops) encoded by a gain map value of 0 and of 255
+	pub min_stops: f64,
+	pub max_stops: f64,
+	pub gamma: f64,
+}
+
+/// Gain map returned to JS, kept so HDR photos can be rendered on HDR displays later
+#[cfg_attr(feature = "node", napi(object))]
+pub struct GainMapData {
+	pub source: GainMapSource,
+	pub width: u32,
+	pub height: u32,
+	/// 8-bit gain map values, one byte per pixel, row by row
+	pub data: Buffer,
+	/// Brightness boost (in stops) encoded by the lowest and highest gain map values
+	pub min_stops: f64,
+	pub max_stops: f64,
+	pub gamma: f64,
+}
+
+impl GainMap {
+	/// Linear brightness multiplier for a gain map value
+	fn multiplier(&self, value: u8) -> f32 {
+		let value = value as f64 / 255.0;
+		match self.source {
+			// Apple encodes the boost with the sRGB curve: 1 + (headroom - 1) * gain
+			GainMapSource::AppleHeif => {
+				let gain = srgb_to_linear(value as f32) as f64;
+				(1.0 + (self.max_stops.exp2() - 1.0) * gain) as f32
+			}
+			GainMapSource::UltraHdrJpeg => {
+				let gain = value.powf(1.0 / self.gamma.max(0.01));
+				(self.min_stops + (self.max_stops - self.min_stops) * gain).exp2() as f32
+			}
+		}
+	}
+
+	/// Rotate/flip the gain map like its photo
+	pub fn oriented(self, orientation: Option<u32>) -> Self {
+		let image = apply_orientation(DynamicImage::ImageLuma8(self.image), orientation).to_luma8();
+		Self { image, ..self }
+	}
+}
+
+/// Read the gain map of an Apple HDR HEIC or an Ultra HDR JPEG, as stored (not oriented)
+pub fn read_gain_map(file_path: &str, kind: SourceKind) -> Option<GainMap> {
+	match kind {
+		SourceKind::Heif => Some(GainMap {
+			source: GainMapSource::AppleHeif,
+			image: heif_gain_map(file_path)?,
+			min_stops: 0.0,
+			max_stops: apple_headroom_stops(file_path).unwrap_or(DEFAULT_APPLE_HEADROOM_STOPS),
+			gamma: 1.0,
+		}),
+		SourceKind::Standard => {
+			let lower = file_path.to_lowercase();
+			if !(lower.ends_with(".jpg") || lower.ends_with(".jpeg")) {
+				return None;
+			}
+			let data = extract_embedded_jpeg(file_path, "MPImage2")?;
+			let xmp = String::from_utf8_lossy(&data);
+			// Only MPF images tagged as gain maps; others are e.g. stereo pairs or previews
+			let max_stops = xmp_number(&xmp, "hdrgm:GainMapMax")?;
+			Some(GainMap {
+				source: GainMapSource::UltraHdrJpeg,
+				image: decode_bytes(&data).ok()?.to_luma8(),
+				min_stops: xmp_number(&xmp, "hdrgm:GainMapMin").unwrap_or(0.0),
+				max_stops,
+				gamma: xmp_number(&xmp, "hdrgm:Gamma").unwrap_or(1.0),
+			})
+		}
+		SourceKind::RawPreview | SourceKind::Video => None,
+	}
+}
+
+/// HDR headroom of an iPhone photo in stops, from the HDRHeadroom/HDRGain maker notes
+/// Follows Apple's published mapping of the two values to a headroom
+fn apple_headroom_stops(file_path: &str) -> Option<f64> {
+	let output = Command::new("exiftool")
+		.args([
+			"-json",
+			"-MakerNotes:HDRHeadroom#",
+			"-MakerNotes:HDRGain#",
+			file_path,
+		])
+		.output()
+		.ok()?;
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+	let obj = json.as_array()?.first()?.as_object()?;
+	let headroom = obj.get("HDRHeadroom")?.as_f64()?;
+	let gain = obj.get("HDRGain")?.as_f64()?;
+
+	let stops = match (headroom < 1.0, gain <= 0.01) {
+		(true, true) => -20.0 * gain + 1.8,
+		(true, false) => -0.101 * gain + 1.601,
+		(false, true) => -70.0 * gain + 3.0,
+		(false, false) => -0.303 * gain + 2.303,
+	};
+	Some(stops.max(0.0))
+}
+
+/// Read a number from XMP, stored either as an attribute or as an element
+/// (`hdrgm:GainMapMax="2.5"` or `<hdrgm:GainMapMax>2.5</hdrgm:GainMapMax>`)
+/// Multi-channel values (rdf:Seq) use their first entry
+pub fn xmp_number(xmp: &str, name: &str) -> Option<f64> {
+	let attribute = format!("{}=\"", name);
+	let element = format!("<{}>", name);
+	let value = if let Some(start) = xmp.find(&attribute) {
+		let rest = &xmp[start + attribute.len()..];
+		&rest[..rest.find('"')?]
+	} else {
+		let rest = &xmp[xmp.find(&element)? + element.len()..];
+		let rest = match rest.find("<rdf:li>") {
+			Some(li) if li < rest.find(&format!("</{}>", name))? => &rest[li + "<rdf:li>".len()..],
+			_ => rest,
+		};
+		&rest[..rest.find('<')?]
+	};
+	value.trim().parse().ok()
+}
+
+/// Apply a gain map at full strength and compress the boosted highlights back into SDR,
+/// so thumbnails keep some of the HDR look (brighter highlights, more local contrast)
+/// Returns the image unchanged when the gain map doesn't match its aspect ratio
+pub fn tone_map_hdr(img: &DynamicImage, gain_map: &GainMap) -> DynamicImage {
+	let (width, height) = (img.width(), img.height());
+	let (map_width, map_height) = gain_map.image.dimensions();
+	if (width >= height) != (map_width >= map_height) {
+		return img.clone();
+	}
+
+	let gains = image::imageops::resize(&gain_map.image, width, height, FilterType::Triangle);
+	let multipliers: Vec<f32> = (0..=255).map(|v| gain_map.multiplier(v)).collect();
+	let white = multipliers.iter().copied().fold(1.0, f32::max);
+	let linear: Vec<f32> = (0..=255)
+		.map(|v| srgb_to_linear(v as f32 / 255.0))
+		.collect();
+	let scale = (ENCODE_LUT_SIZE - 1) as f32;
+	let encode: Vec<u8> = (0..ENCODE_LUT_SIZE)
+		.map(|i| (linear_to_srgb(i as f32 / scale) * 255.0).round() as u8)
+		.collect();
+
+	let map_channel = |value: u8, multiplier: f32| {
+		let boosted = linear[value as usize] * multiplier;
+		encode[(compress_highlights(boosted, white).clamp(0.0, 1.0) * scale).round() as usize]
+	};
+
+	let has_alpha = img.color().has_alpha();
+	let mut pixels = img.to_rgba8();
+	for (pixel, gain) in pixels.pixels_mut().zip(gains.pixels()) {
+		let multiplier = multipliers[gain[0] as usize];
+		for c in 0..3 {
+			pixel[c] = map_channel(pixel[c], multiplier);
+		}
+	}
+
+	if has_alpha {
+		DynamicImage::ImageRgba8(pixels)
+	} else {
+		DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8())
+	}
+}
+
+/// Keep linear values below the knee and roll values up to `white` off towards 1.0
+/// (extended Reinhard on the part above the knee)
+pub fn compress_highlights(value: f32, white: f32) -> f32 {
+	if value <= TONE_MAP_KNEE {
+		return value;
+	}
+	let range = 1.0 - TONE_MAP_KNEE;
+	let x = (value - TONE_MAP_KNEE) / range;
+	let x_white = ((white - TONE_MAP_KNEE) / range).max(1.0);
+	let y = x * (1.0 + x / (x_white * x_white)) / (1.0 + x);
+	TONE_MAP_KNEE + range * y.min(1.0)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+	if value <= 0.04045 {
+		value / 12.92
+	} else {
+		((value + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+	let value = value.clamp(0.0, 1.0);
+	if value <= 0.0031308 {
+		value * 12.92
+	} else {
+		1.055 * value.powf(1.0 / 2.4) - 0.055
+	}
+}
+
+/// Extract the HDR gain map of a photo, see `extract_gain_map`
+pub fn extract_gain_map_internal(file_path: &str) -> Result<Option<GainMapData>, ProcessingError> {
+	let kind = source_kind(file_path).ok_or_else(|| ProcessingError::unsupported(file_path))?;
+
+	Ok(read_gain_map(file_path, kind).map(|gain_map| GainMapData {
+		source: gain_map.source,
+		width: gain_map.image.width(),
+		height: gain_map.image.height(),
+		data: gain_map.image.into_raw().into(),
+		min_stops: gain_map.min_stops,
+		max_stops: gain_map.max_stops,
+		gamma: gain_map.gamma,
+	}))
+}
+
+/// Extract the HDR gain map of an Apple HDR HEIC or Ultra HDR JPEG
+/// Returns null for SDR photos
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_gain_map(file_path: String) -> napi::Result<Option<GainMapData>, ErrorCode> {
+	Ok(extract_gain_map_internal(&file_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, Rgb, RgbImage};
+
+	#[test]
+	fn test_xmp_number() {
+		let attributes =
+			r#"<rdf:Description hdrgm:Version="1.0" hdrgm:GainMapMax="2.3" hdrgm:Gamma="1"/>"#;
+		assert_eq!(xmp_number(attributes, "hdrgm:GainMapMax"), Some(2.3));
+		assert_eq!(xmp_number(attributes, "hdrgm:GainMapMin"), None);
+
+		let elements = "<hdrgm:GainMapMax><rdf:Seq><rdf:li>1.5</rdf:li><rdf:li>1.6</rdf:li>\
+			</rdf:Seq></hdrgm:GainMapMax><hdrgm:GainMapMin>0</hdrgm:GainMapMin>";
+		assert_eq!(xmp_number(elements, "hdrgm:GainMapMax"), Some(1.5));
+		assert_eq!(xmp_number(elements, "hdrgm:GainMapMin"), Some(0.0));
+	}
+
+	#[test]
+	fn test_tone_map_brightens_boosted_areas() {
+		let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([100, 100, 100])));
+		let mut map = GrayImage::from_pixel(4, 2, Luma([0]));
+		map.put_pixel(3, 0, Luma([255]));
+		let gain_map = GainMap {
+			source: GainMapSource::UltraHdrJpeg,
+			image: map,
+			min_stops: 0.0,
+			max_stops: 2.0,
+			gamma: 1.0,
+		};
+
+		let mapped = tone_map_hdr(&img, &gain_map).to_rgb8();
+		// No boost leaves pixels untouched; boosted ones get brighter but stay in range
+		assert_eq!(mapped.get_pixel(0, 1)[0], 100);
+		assert!(mapped.get_pixel(3, 0)[0] > 150);
+		assert!(compress_highlights(4.0, 4.0) <= 1.0);
+	}
+}
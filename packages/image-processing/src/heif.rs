@@ -1,95 +1,700 @@
-use image::{DynamicImage, RgbImage, RgbaImage};
-use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+use image::metadata::Orientation;
+use image::{DynamicImage, GrayImage, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+use libheif_rs::{
+	AuxiliaryImagesFilter, ColorPrimaries, ColorSpace, HeifContext, ImageHandle, LibHeif,
+	MatrixCoefficients, RgbChroma, TransferCharacteristics,
+};
+use moxcms::ColorProfile;
+#[cfg(feature = "node")]
+use napi_derive::napi;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
-/// Decode a HEIF/HEIC file to a DynamicImage
-pub fn decode_heif(file_path: &str) -> Result<DynamicImage, String> {
+use crate::color::{nclx_profile, SrgbConverter};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::hdr::{compress_highlights, linear_to_srgb};
+use crate::thumbnails::{
+	generate_all_thumbnails_internal, ThumbnailFormat, ThumbnailPlan, ThumbnailSize,
+};
+use crate::Buffer;
+
+/// SDR reference white of HDR (PQ/HLG) images in nits, mapped to white in 8-bit output
+const HDR_REFERENCE_WHITE_NITS: f32 = 203.0;
+
+/// Peak brightness HDR images are tone mapped from, typical of iPhone HDR HEICs
+const HDR_PEAK_NITS: f32 = 1000.0;
+
+/// HDR transfer function of a HEIF image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrTransfer {
+	/// SMPTE ST 2084 perceptual quantizer
+	Pq,
+	/// Hybrid log-gamma
+	Hlg,
+}
+
+/// nclx color description of a HEIF image, as ITU-T H.273 code points
+/// Unknown values are left out
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeifColorInfo {
+	/// e.g. 1 (BT.709/sRGB), 9 (BT.2020) or 12 (Display P3)
+	pub color_primaries: Option<u32>,
+	/// e.g. 13 (sRGB), 16 (PQ) or 18 (HLG)
+	pub transfer_characteristics: Option<u32>,
+	pub matrix_coefficients: Option<u32>,
+	pub full_range: Option<bool>,
+	/// An embedded ICC profile, which takes precedence over the nclx values
+	pub has_icc_profile: bool,
+}
+
+/// Primary image of a HEIF file with the color information and EXIF block stored next to it
+pub struct HeifDecoded {
+	pub image: DynamicImage,
+	pub color: HeifColorInfo,
+	/// Color profile to convert from, see `heif_color_profile`
+	pub profile: Option<ColorProfile>,
+	/// Raw EXIF block, from the TIFF header on
+	pub exif: Option<Vec<u8>>,
+}
+
+/// Color information and metadata of a HEIF file, read without decoding it
+#[cfg_attr(feature = "node", napi(object))]
+pub struct HeifMetadata {
+	pub width: u32,
+	pub height: u32,
+	/// Bits per channel of the primary image, e.g. 10 for HDR iPhone photos
+	pub bit_depth: u32,
+	pub color: HeifColorInfo,
+	/// Raw EXIF block, from the TIFF header on
+	pub exif: Option<Buffer>,
+	/// EXIF orientation, for reference only: libheif already applies the irot/imir
+	/// transforms, so decoded pixels are upright and must not be rotated again
+	pub exif_orientation: Option<u32>,
+}
+
+/// Decode a HEIF/HEIC file along with its color information and EXIF block
+/// 10/12-bit images are decoded at full depth and tone mapped to 8 bits
+pub fn decode_heif(file_path: &str) -> Result<HeifDecoded, String> {
 	let path = Path::new(file_path);
 	if !path.exists() {
 		return Err(format!("File not found: {}", file_path));
 	}
 
+	// Create HEIF context and read from file
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+
+	decode_primary(&ctx)
+}
+
+/// Decode HEIF/HEIC data held in memory (e.g. a still extracted from another container)
+pub fn decode_heif_from_buffer(data: &[u8]) -> Result<HeifDecoded, String> {
+	let ctx = HeifContext::read_from_bytes(data)
+		.map_err(|e| format!("Failed to read HEIF data: {}", e))?;
+
+	decode_primary(&ctx)
+}
+
+/// Decode the primary image of a HEIF context
+fn decode_primary(ctx: &HeifContext) -> Result<HeifDecoded, String> {
 	// Initialize libheif
 	let lib_heif = LibHeif::new();
 
-	// Create HEIF context and read from file
+	// Get the primary image handle
+	let handle = ctx
+		.primary_image_handle()
+		.map_err(|e| format!("Failed to get primary image handle: {}", e))?;
+
+	let image = if handle.luma_bits_per_pixel() > 8 {
+		let img = decode_handle_16(&lib_heif, &handle)?;
+		tone_map_to_8bit(&img, handle_hdr_transfer(&handle))
+	} else {
+		decode_handle(&lib_heif, &handle)?
+	};
+	Ok(HeifDecoded {
+		image,
+		color: handle_color_info(&handle),
+		profile: handle_color_profile(&handle),
+		exif: handle_exif(&handle),
+	})
+}
+
+/// nclx color description of an image (see `HeifColorInfo`)
+fn handle_color_info(handle: &ImageHandle) -> HeifColorInfo {
+	let nclx = handle.color_profile_nclx();
+	HeifColorInfo {
+		color_primaries: nclx
+			.as_ref()
+			.map(|nclx| nclx.color_primaries())
+			.filter(|&primaries| primaries != ColorPrimaries::Unknown)
+			.map(|primaries| primaries as u32),
+		transfer_characteristics: nclx
+			.as_ref()
+			.map(|nclx| nclx.transfer_characteristics())
+			.filter(|&transfer| transfer != TransferCharacteristics::Unknown)
+			.map(|transfer| transfer as u32),
+		matrix_coefficients: nclx
+			.as_ref()
+			.map(|nclx| nclx.matrix_coefficients())
+			.filter(|&matrix| matrix != MatrixCoefficients::Unknown)
+			.map(|matrix| matrix as u32),
+		full_range: nclx.as_ref().map(|nclx| nclx.full_range_flag() != 0),
+		has_icc_profile: handle.color_profile_raw().is_some(),
+	}
+}
+
+/// Raw EXIF block of an image, from the TIFF header on
+fn handle_exif(handle: &ImageHandle) -> Option<Vec<u8>> {
+	let mut ids = [0; 1];
+	if handle.metadata_block_ids(&mut ids, b"Exif") == 0 {
+		return None;
+	}
+	let block = handle.metadata(ids[0]).ok()?;
+	exif_tiff_data(&block).map(<[u8]>::to_vec)
+}
+
+/// Skip the header of a HEIF EXIF block: a big-endian offset to the TIFF header
+fn exif_tiff_data(block: &[u8]) -> Option<&[u8]> {
+	let offset = u32::from_be_bytes(block.get(..4)?.try_into().ok()?) as usize;
+	let tiff = block.get(4usize.checked_add(offset)?..)?;
+	(tiff.starts_with(b"II*\0") || tiff.starts_with(b"MM\0*")).then_some(tiff)
+}
+
+/// Read the metadata of a HEIF file without decoding it, see `read_heif_metadata`
+pub fn read_heif_metadata_internal(file_path: &str) -> Result<HeifMetadata, String> {
 	let ctx = HeifContext::read_from_file(file_path)
 		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+	let handle = ctx
+		.primary_image_handle()
+		.map_err(|e| format!("Failed to get primary image handle: {}", e))?;
+	let exif = handle_exif(&handle);
 
-	// Get the primary image handle
+	Ok(HeifMetadata {
+		width: handle.width(),
+		height: handle.height(),
+		bit_depth: handle.luma_bits_per_pixel() as u32,
+		color: handle_color_info(&handle),
+		exif_orientation: exif
+			.as_deref()
+			.and_then(Orientation::from_exif_chunk)
+			.map(|orientation| orientation.to_exif() as u32),
+		exif: exif.map(Buffer::from),
+	})
+}
+
+/// Error of reading a HEIF file for JavaScript, a decode failure unless the message
+/// already starts with a code
+#[cfg(feature = "node")]
+fn heif_error(message: String) -> napi::Error<ErrorCode> {
+	ProcessingError::from_message(ErrorCode::DecodeFailed, message).into()
+}
+
+/// Read the size, bit depth, color information and EXIF block of a HEIF file without decoding it
+/// The color information tells wide-gamut (Display P3) and HDR (PQ/HLG) photos apart
+#[cfg(feature = "node")]
+#[napi]
+pub fn read_heif_metadata(file_path: String) -> napi::Result<HeifMetadata, ErrorCode> {
+	read_heif_metadata_internal(&file_path).map_err(heif_error)
+}
+
+/// Size of the primary image of HEIF data held in memory, without decoding it
+pub fn heif_dimensions_from_bytes(data: &[u8]) -> Option<(u32, u32)> {
+	let ctx = HeifContext::read_from_bytes(data).ok()?;
+	let handle = ctx.primary_image_handle().ok()?;
+	Some((handle.width(), handle.height()))
+}
+
+/// Decode the largest thumbnail embedded next to the primary image of a HEIF file
+/// Far cheaper than decoding the primary image (48MP on recent iPhones) for small sizes
+/// `usable` is checked against the thumbnail's size before it's decoded
+pub fn decode_heif_thumbnail(
+	file_path: &str,
+	usable: impl Fn(u32, u32) -> bool,
+) -> Option<DynamicImage> {
+	let ctx = HeifContext::read_from_file(file_path).ok()?;
+	handle_thumbnail(&ctx.primary_image_handle().ok()?, usable)
+}
+
+/// Decode the largest embedded thumbnail of an image, if it has the image's aspect ratio
+/// and `usable` accepts its size; HDR thumbnails are skipped, they'd need the primary's tone mapping
+fn handle_thumbnail(
+	handle: &ImageHandle,
+	usable: impl Fn(u32, u32) -> bool,
+) -> Option<DynamicImage> {
+	let mut ids = vec![0; handle.number_of_thumbnails()];
+	let count = handle.thumbnail_ids(&mut ids);
+	let thumbnail = ids[..count]
+		.iter()
+		.filter_map(|&id| handle.thumbnail(id).ok())
+		.max_by_key(|thumbnail| thumbnail.width().max(thumbnail.height()))?;
+
+	let aspect_ratio = |width: u32, height: u32| width as f64 / height.max(1) as f64;
+	let expected = aspect_ratio(handle.width(), handle.height());
+	let actual = aspect_ratio(thumbnail.width(), thumbnail.height());
+	if (actual / expected - 1.0).abs() > 0.02
+		|| thumbnail.luma_bits_per_pixel() > 8
+		|| !usable(thumbnail.width(), thumbnail.height())
+	{
+		return None;
+	}
+	decode_handle(&LibHeif::new(), &thumbnail).ok()
+}
+
+/// Decode a HEIF/HEIC file at full bit depth, as Rgb16 or Rgba16 (8-bit images are widened)
+pub fn decode_heif_rgb16(file_path: &str) -> Result<DynamicImage, String> {
+	let lib_heif = LibHeif::new();
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
 	let handle = ctx
 		.primary_image_handle()
 		.map_err(|e| format!("Failed to get primary image handle: {}", e))?;
 
+	if handle.luma_bits_per_pixel() > 8 {
+		decode_handle_16(&lib_heif, &handle)
+	} else {
+		let img = decode_handle(&lib_heif, &handle)?;
+		Ok(if img.color().has_alpha() {
+			DynamicImage::ImageRgba16(img.to_rgba16())
+		} else {
+			DynamicImage::ImageRgb16(img.to_rgb16())
+		})
+	}
+}
+
+/// Decode a high bit depth image to Rgb16/Rgba16, scaling samples to the full 16-bit range
+fn decode_handle_16(lib_heif: &LibHeif, handle: &ImageHandle) -> Result<DynamicImage, String> {
+	let has_alpha = handle.has_alpha_channel();
+	let chroma = if has_alpha {
+		RgbChroma::HdrRgbaLe
+	} else {
+		RgbChroma::HdrRgbLe
+	};
+	let decoded = lib_heif
+		.decode(handle, ColorSpace::Rgb(chroma), None)
+		.map_err(|e| format!("Failed to decode HEIF image: {}", e))?;
+	let plane = decoded
+		.planes()
+		.interleaved
+		.ok_or_else(|| "Failed to get interleaved plane".to_string())?;
+
+	let (width, height) = (plane.width, plane.height);
+	let channels = if has_alpha { 4 } else { 3 };
+	let max = (1u32 << plane.bits_per_pixel.clamp(1, 16)) - 1;
+	let row_bytes = width as usize * channels * 2;
+	let data = copy_rows(plane.data, plane.stride, row_bytes, height as usize)
+		.ok_or_else(|| "HEIF plane is smaller than the image".to_string())?;
+
+	let samples: Vec<u16> = data
+		.chunks_exact(2)
+		.map(|bytes| {
+			let value = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+			((value.min(max) * 65535 + max / 2) / max) as u16
+		})
+		.collect();
+
+	let image = if has_alpha {
+		ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+	} else {
+		ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgb16)
+	};
+	image.ok_or_else(|| "Failed to create 16-bit image".to_string())
+}
+
+/// HDR transfer function of a HEIF image, from its NCLX color profile
+fn handle_hdr_transfer(handle: &ImageHandle) -> Option<HdrTransfer> {
+	match handle.color_profile_nclx()?.transfer_characteristics() {
+		TransferCharacteristics::ITU_R_BT_2100_0_PQ => Some(HdrTransfer::Pq),
+		TransferCharacteristics::ITU_R_BT_2100_0_HLG => Some(HdrTransfer::Hlg),
+		_ => None,
+	}
+}
+
+/// Convert a 16-bit image to 8 bits for thumbnails
+/// PQ and HLG images are tone mapped (reference white to white, highlights rolled off up to
+/// 1000 nits) instead of clipping; other images are only scaled
+pub fn tone_map_to_8bit(img: &DynamicImage, transfer: Option<HdrTransfer>) -> DynamicImage {
+	let Some(transfer) = transfer else {
+		return if img.color().has_alpha() {
+			DynamicImage::ImageRgba8(img.to_rgba8())
+		} else {
+			DynamicImage::ImageRgb8(img.to_rgb8())
+		};
+	};
+
+	let white = HDR_PEAK_NITS / HDR_REFERENCE_WHITE_NITS;
+	let lut: Vec<u8> = (0..=u16::MAX)
+		.map(|value| {
+			let signal = value as f32 / u16::MAX as f32;
+			let relative = match transfer {
+				HdrTransfer::Pq => pq_to_nits(signal) / HDR_REFERENCE_WHITE_NITS,
+				// HLG with the 1000-nit reference display OOTF (system gamma 1.2)
+				HdrTransfer::Hlg => hlg_to_scene_linear(signal).powf(1.2) * white,
+			};
+			(linear_to_srgb(compress_highlights(relative, white)) * 255.0).round() as u8
+		})
+		.collect();
+
+	let rgba = img.to_rgba16();
+	let mapped = RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+		let [r, g, b, a] = rgba.get_pixel(x, y).0;
+		let alpha = (a as u32 * 255 / 65535) as u8;
+		Rgba([lut[r as usize], lut[g as usize], lut[b as usize], alpha])
+	});
+
+	if img.color().has_alpha() {
+		DynamicImage::ImageRgba8(mapped)
+	} else {
+		DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(mapped).to_rgb8())
+	}
+}
+
+/// SMPTE ST 2084 (PQ) EOTF: signal (0-1) to absolute luminance in nits
+fn pq_to_nits(signal: f32) -> f32 {
+	const M1: f32 = 2610.0 / 16384.0;
+	const M2: f32 = 2523.0 / 4096.0 * 128.0;
+	const C1: f32 = 3424.0 / 4096.0;
+	const C2: f32 = 2413.0 / 4096.0 * 32.0;
+	const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+	let power = signal.max(0.0).powf(1.0 / M2);
+	let linear = ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1);
+	linear * 10000.0
+}
+
+/// HLG inverse OETF: signal (0-1) to relative scene light (0-1)
+fn hlg_to_scene_linear(signal: f32) -> f32 {
+	const A: f32 = 0.17883277;
+	const B: f32 = 0.28466892;
+	const C: f32 = 0.559_910_7;
+
+	if signal <= 0.5 {
+		signal * signal / 3.0
+	} else {
+		(((signal - C) / A).exp() + B) / 12.0
+	}
+}
+
+/// Decode an image of a HEIF file to RGB, or RGBA when it has an alpha channel
+fn decode_handle(lib_heif: &LibHeif, handle: &ImageHandle) -> Result<DynamicImage, String> {
 	let width = handle.width();
 	let height = handle.height();
 	let has_alpha = handle.has_alpha_channel();
+	let (chroma, channels) = if has_alpha {
+		(RgbChroma::Rgba, 4)
+	} else {
+		(RgbChroma::Rgb, 3)
+	};
 
 	// Decode the image to RGB or RGBA
-	let image = if has_alpha {
-		// Decode with alpha channel
-		let decoded = lib_heif
-			.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
-			.map_err(|e| format!("Failed to decode HEIF image: {}", e))?;
-
-		let plane = decoded
-			.planes()
-			.interleaved
-			.ok_or_else(|| "Failed to get interleaved plane".to_string())?;
-
-		let stride = plane.stride;
-		let data = plane.data;
-
-		// Convert to RgbaImage
-		let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
-		for y in 0..height {
-			let row_start = (y as usize) * stride;
-			for x in 0..width {
-				let pixel_start = row_start + (x as usize) * 4;
-				rgba_data.push(data[pixel_start]); // R
-				rgba_data.push(data[pixel_start + 1]); // G
-				rgba_data.push(data[pixel_start + 2]); // B
-				rgba_data.push(data[pixel_start + 3]); // A
-			}
-		}
+	let decoded = lib_heif
+		.decode(handle, ColorSpace::Rgb(chroma), None)
+		.map_err(|e| format!("Failed to decode HEIF image: {}", e))?;
 
-		RgbaImage::from_raw(width, height, rgba_data)
+	let plane = decoded
+		.planes()
+		.interleaved
+		.ok_or_else(|| "Failed to get interleaved plane".to_string())?;
+
+	let data = copy_rows(
+		plane.data,
+		plane.stride,
+		width as usize * channels,
+		height as usize,
+	)
+	.ok_or_else(|| "HEIF plane is smaller than the image".to_string())?;
+
+	if has_alpha {
+		RgbaImage::from_raw(width, height, data)
 			.map(DynamicImage::ImageRgba8)
-			.ok_or_else(|| "Failed to create RGBA image".to_string())?
+			.ok_or_else(|| "Failed to create RGBA image".to_string())
 	} else {
-		// Decode without alpha channel
-		let decoded = lib_heif
-			.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
-			.map_err(|e| format!("Failed to decode HEIF image: {}", e))?;
-
-		let plane = decoded
-			.planes()
-			.interleaved
-			.ok_or_else(|| "Failed to get interleaved plane".to_string())?;
-
-		let stride = plane.stride;
-		let data = plane.data;
-
-		// Convert to RgbImage
-		let mut rgb_data = Vec::with_capacity((width * height * 3) as usize);
-		for y in 0..height {
-			let row_start = (y as usize) * stride;
-			for x in 0..width {
-				let pixel_start = row_start + (x as usize) * 3;
-				rgb_data.push(data[pixel_start]); // R
-				rgb_data.push(data[pixel_start + 1]); // G
-				rgb_data.push(data[pixel_start + 2]); // B
-			}
-		}
-
-		RgbImage::from_raw(width, height, rgb_data)
+		RgbImage::from_raw(width, height, data)
 			.map(DynamicImage::ImageRgb8)
-			.ok_or_else(|| "Failed to create RGB image".to_string())?
+			.ok_or_else(|| "Failed to create RGB image".to_string())
+	}
+}
+
+/// Copy the rows of a plane into a packed buffer, dropping the padding at the end of each row
+/// Rows are copied whole rather than pixel by pixel, which matters on 48MP images
+fn copy_rows(data: &[u8], stride: usize, row_bytes: usize, height: usize) -> Option<Vec<u8>> {
+	if stride == row_bytes {
+		return data.get(..row_bytes * height).map(<[u8]>::to_vec);
+	}
+
+	let mut packed = Vec::with_capacity(row_bytes * height);
+	for row in data.chunks(stride).take(height) {
+		packed.extend_from_slice(row.get(..row_bytes)?);
+	}
+	(packed.len() == row_bytes * height).then_some(packed)
+}
+
+/// Image of a HEIF container, e.g. one frame of a burst
+#[cfg_attr(feature = "node", napi(object))]
+pub struct HeifImageInfo {
+	/// Position among the top-level images, as passed to `decode_heif_image`
+	pub index: u32,
+	pub width: u32,
+	pub height: u32,
+	/// The image shown by default (the burst's pick)
+	pub is_primary: bool,
+	pub has_alpha: bool,
+}
+
+/// List the top-level images of a HEIF file; single-image files return one entry
+pub fn list_heif_images_internal(file_path: &str) -> Result<Vec<HeifImageInfo>, String> {
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+
+	Ok(ctx
+		.top_level_image_handles()
+		.iter()
+		.enumerate()
+		.map(|(index, handle)| HeifImageInfo {
+			index: index as u32,
+			width: handle.width(),
+			height: handle.height(),
+			is_primary: handle.is_primary(),
+			has_alpha: handle.has_alpha_channel(),
+		})
+		.collect())
+}
+
+/// List every image of a HEIF file, so burst shots and multi-image files can be grouped
+#[cfg(feature = "node")]
+#[napi]
+pub fn list_heif_images(file_path: String) -> napi::Result<Vec<HeifImageInfo>, ErrorCode> {
+	list_heif_images_internal(&file_path).map_err(heif_error)
+}
+
+/// Handle of a top-level image of a HEIF file by index (see `list_heif_images`)
+fn heif_image_handle(ctx: &HeifContext, index: u32) -> Result<ImageHandle, String> {
+	ctx.top_level_image_handles()
+		.into_iter()
+		.nth(index as usize)
+		.ok_or_else(|| {
+			ProcessingError::invalid(format!("HEIF image {} not found", index)).to_string()
+		})
+}
+
+/// Decode one top-level image of a HEIF file by index (see `list_heif_images`)
+pub fn decode_heif_image(file_path: &str, index: u32) -> Result<DynamicImage, String> {
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+	decode_handle(&LibHeif::new(), &heif_image_handle(&ctx, index)?)
+}
+
+/// Generate thumbnails for one image of a HEIF file, see `generate_heif_image_thumbnails`
+pub fn generate_heif_image_thumbnails_internal(
+	file_path: &str,
+	index: u32,
+	relative_path: &str,
+	thumbnails_dir: &str,
+	format: Option<ThumbnailFormat>,
+	sizes: Option<Vec<ThumbnailSize>>,
+) -> Result<(), String> {
+	let plan = ThumbnailPlan::new(sizes, format)?;
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+	let handle = heif_image_handle(&ctx, index)?;
+	let srgb = handle_color_profile(&handle).and_then(|profile| SrgbConverter::new(&profile));
+	// The image itself is only decoded for the sizes its embedded thumbnail is too small for
+	let embedded = handle_thumbnail(&handle, |width, height| plan.any_fits(width, height));
+	let (img, embedded) = match embedded {
+		Some(thumbnail) if plan.all_fit(thumbnail.width(), thumbnail.height()) => (thumbnail, None),
+		embedded => (decode_handle(&LibHeif::new(), &handle)?, embedded),
 	};
 
-	Ok(image)
+	generate_all_thumbnails_internal(
+		&img,
+		embedded.as_ref(),
+		relative_path,
+		thumbnails_dir,
+		&plan,
+		srgb.as_ref(),
+		None,
+	)
+}
+
+/// Generate thumbnails for one image of a multi-image HEIF file (e.g. a burst frame)
+/// `relative_path` names the thumbnails, so each image needs its own (e.g. "2024/IMG_1234_2.heic")
+#[cfg(feature = "node")]
+#[napi]
+pub fn generate_heif_image_thumbnails(
+	file_path: String,
+	index: u32,
+	relative_path: String,
+	thumbnails_dir: String,
+	format: Option<ThumbnailFormat>,
+	sizes: Option<Vec<ThumbnailSize>>,
+) -> napi::Result<(), ErrorCode> {
+	generate_heif_image_thumbnails_internal(
+		&file_path,
+		index,
+		&relative_path,
+		&thumbnails_dir,
+		format,
+		sizes,
+	)
+	.map_err(heif_error)
+}
+
+/// What an auxiliary image of a HEIF photo holds
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeifAuxiliaryKind {
+	/// Depth or disparity map
+	Depth,
+	/// Apple portrait effects matte (the subject's silhouette)
+	PortraitMatte,
+	SkinMatte,
+	HairMatte,
+	TeethMatte,
+	GlassesMatte,
+	SkyMatte,
+	/// Apple HDR gain map
+	HdrGainMap,
+	Other,
+}
+
+/// Auxiliary image of a HEIF photo, as 8-bit grayscale
+#[cfg_attr(feature = "node", napi(object))]
+pub struct HeifAuxiliaryImage {
+	pub kind: HeifAuxiliaryKind,
+	/// Auxiliary type URN, e.g. "urn:com:apple:photo:2018:aux:portraiteffectsmatte"
+	pub aux_type: String,
+	pub width: u32,
+	pub height: u32,
+	/// One byte per pixel, row by row
+	pub data: Buffer,
+}
+
+/// Classify an auxiliary image by its type URN
+fn auxiliary_kind(aux_type: &str) -> HeifAuxiliaryKind {
+	let aux_type = aux_type.to_lowercase();
+	let kinds = [
+		("portraiteffectsmatte", HeifAuxiliaryKind::PortraitMatte),
+		("skinmatte", HeifAuxiliaryKind::SkinMatte),
+		("hairmatte", HeifAuxiliaryKind::HairMatte),
+		("teethmatte", HeifAuxiliaryKind::TeethMatte),
+		("glassesmatte", HeifAuxiliaryKind::GlassesMatte),
+		("skymatte", HeifAuxiliaryKind::SkyMatte),
+		("hdrgainmap", HeifAuxiliaryKind::HdrGainMap),
+		// MPEG depth (auxid:2) and Apple depth/disparity
+		("auxid:2", HeifAuxiliaryKind::Depth),
+		("depth", HeifAuxiliaryKind::Depth),
+		("disparity", HeifAuxiliaryKind::Depth),
+	];
+	kinds
+		.into_iter()
+		.find(|(pattern, _)| aux_type.contains(pattern))
+		.map_or(HeifAuxiliaryKind::Other, |(_, kind)| kind)
+}
+
+/// Decode an auxiliary image to 8-bit grayscale, keeping the top 8 bits of deeper maps
+fn decode_auxiliary(
+	lib_heif: &LibHeif,
+	handle: &ImageHandle,
+) -> Result<(u32, u32, Vec<u8>), String> {
+	let decoded = lib_heif
+		.decode(handle, ColorSpace::Monochrome, None)
+		.map_err(|e| format!("Failed to decode HEIF auxiliary image: {}", e))?;
+	let plane = decoded
+		.planes()
+		.y
+		.ok_or_else(|| "Failed to get grayscale plane".to_string())?;
+
+	let (width, height) = (plane.width, plane.height);
+	let bytes_per_sample = plane.storage_bits_per_pixel.div_ceil(8).max(1) as usize;
+	let shift = plane.bits_per_pixel.saturating_sub(8);
+	let mut gray = Vec::with_capacity((width * height) as usize);
+	for y in 0..height as usize {
+		let row = &plane.data[y * plane.stride..];
+		for x in 0..width as usize {
+			let value = if bytes_per_sample == 1 {
+				row[x] as u16
+			} else {
+				u16::from_le_bytes([row[x * 2], row[x * 2 + 1]])
+			};
+			gray.push((value >> shift) as u8);
+		}
+	}
+	Ok((width, height, gray))
+}
+
+/// Extract the depth map and mattes of a HEIF photo (e.g. iPhone portrait mode)
+/// Alpha channels are skipped; photos without auxiliary images return an empty list
+pub fn extract_heif_auxiliary_images_internal(
+	file_path: &str,
+) -> Result<Vec<HeifAuxiliaryImage>, String> {
+	let lib_heif = LibHeif::new();
+	let ctx = HeifContext::read_from_file(file_path)
+		.map_err(|e| format!("Failed to read HEIF file: {}", e))?;
+	let handle = ctx
+		.primary_image_handle()
+		.map_err(|e| format!("Failed to get primary image handle: {}", e))?;
+
+	handle
+		.auxiliary_images(AuxiliaryImagesFilter::new().omit_alpha())
+		.iter()
+		.map(|aux| {
+			let aux_type = aux.auxiliary_type().unwrap_or_default();
+			let (width, height, data) = decode_auxiliary(&lib_heif, aux)?;
+			Ok(HeifAuxiliaryImage {
+				kind: auxiliary_kind(&aux_type),
+				aux_type,
+				width,
+				height,
+				data: data.into(),
+			})
+		})
+		.collect()
+}
+
+/// Apple HDR gain map of a HEIF photo, as 8-bit grayscale
+pub fn heif_gain_map(file_path: &str) -> Option<GrayImage> {
+	let lib_heif = LibHeif::new();
+	let ctx = HeifContext::read_from_file(file_path).ok()?;
+	let handle = ctx.primary_image_handle().ok()?;
+
+	let gain_map = handle
+		.auxiliary_images(AuxiliaryImagesFilter::new().omit_alpha())
+		.into_iter()
+		.find(|aux| {
+			aux.auxiliary_type()
+				.is_ok_and(|aux_type| auxiliary_kind(&aux_type) == HeifAuxiliaryKind::HdrGainMap)
+		})?;
+	let (width, height, data) = decode_auxiliary(&lib_heif, &gain_map).ok()?;
+	GrayImage::from_raw(width, height, data)
+}
+
+/// Extract depth maps and portrait mattes of a HEIF photo as grayscale buffers,
+/// for background-blur editing and subject detection
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_heif_auxiliary_images(
+	file_path: String,
+) -> napi::Result<Vec<HeifAuxiliaryImage>, ErrorCode> {
+	extract_heif_auxiliary_images_internal(&file_path).map_err(heif_error)
+}
+
+/// Read the color profile of a HEIF file: an embedded ICC profile, or the standard profile
+/// for its NCLX primaries (iPhones tag Display P3 photos either way)
+pub fn heif_color_profile(file_path: &str) -> Option<ColorProfile> {
+	let ctx = HeifContext::read_from_file(file_path).ok()?;
+	let handle = ctx.primary_image_handle().ok()?;
+	handle_color_profile(&handle)
+}
+
+/// Color profile of one image of a HEIF file
+fn handle_color_profile(handle: &ImageHandle) -> Option<ColorProfile> {
+	if let Some(raw) = handle.color_profile_raw() {
+		return ColorProfile::new_from_slice(&raw.data).ok();
+	}
+	nclx_profile(handle.color_profile_nclx()?.color_primaries() as u32)
 }
 
 /// Check if a file is a HEIF/HEIC file by extension
@@ -112,6 +717,14 @@ pub fn is_heif_by_magic_bytes(file_path: &str) -> bool {
 	if file.read_exact(&mut buffer).is_err() {
 		return false;
 	}
+	is_heif_bytes(&buffer)
+}
+
+/// Check if in-memory data starts with a HEIF signature (see `is_heif_by_magic_bytes`)
+pub fn is_heif_bytes(buffer: &[u8]) -> bool {
+	if buffer.len() < 12 {
+		return false;
+	}
 
 	// HEIF files have "ftyp" at offset 4
 	if &buffer[4..8] != b"ftyp" {
@@ -144,6 +757,67 @@ mod tests {
 		assert!(!is_heif_file("photo.png"));
 	}
 
+	#[test]
+	fn test_copy_rows_drops_padding() {
+		let data = [1, 2, 3, 0, 4, 5, 6, 0];
+		assert_eq!(copy_rows(&data, 4, 3, 2), Some(vec![1, 2, 3, 4, 5, 6]));
+		assert_eq!(copy_rows(&data, 4, 4, 2), Some(data.to_vec()));
+		assert_eq!(copy_rows(&data, 4, 3, 3), None);
+	}
+
+	#[test]
+	fn test_tone_map_to_8bit() {
+		assert!((pq_to_nits(1.0) - 10000.0).abs() < 1.0);
+		assert!((pq_to_nits(0.58) - 203.0).abs() < 5.0);
+		assert!((hlg_to_scene_linear(1.0) - 1.0).abs() < 0.001);
+
+		let img = DynamicImage::ImageRgb16(ImageBuffer::from_fn(3, 1, |x, _| {
+			// PQ signals for ~5, ~203 and 10000 nits
+			let signal = [0.25, 0.58, 1.0][x as usize];
+			Rgb([(signal * 65535.0) as u16; 3])
+		}));
+		let mapped = tone_map_to_8bit(&img, Some(HdrTransfer::Pq)).to_rgb8();
+		assert!(mapped.get_pixel(0, 0)[0] < 60);
+		assert!(mapped.get_pixel(1, 0)[0] >= 240);
+		assert_eq!(mapped.get_pixel(2, 0)[0], 255);
+
+		// Without an HDR transfer, samples are only scaled
+		let plain = tone_map_to_8bit(&img, None).to_rgb8();
+		assert_eq!(plain.get_pixel(1, 0)[0], (0.58f32 * 255.0).round() as u8);
+	}
+
+	#[test]
+	fn test_auxiliary_kind() {
+		assert_eq!(
+			auxiliary_kind("urn:com:apple:photo:2018:aux:portraiteffectsmatte"),
+			HeifAuxiliaryKind::PortraitMatte
+		);
+		assert_eq!(
+			auxiliary_kind("urn:com:apple:photo:2020:aux:semanticskinmatte"),
+			HeifAuxiliaryKind::SkinMatte
+		);
+		assert_eq!(
+			auxiliary_kind("urn:mpeg:hevc:2015:auxid:2"),
+			HeifAuxiliaryKind::Depth
+		);
+		assert_eq!(
+			auxiliary_kind("urn:example:unknown"),
+			HeifAuxiliaryKind::Other
+		);
+	}
+
+	#[test]
+	fn test_exif_tiff_data() {
+		let mut block = 6u32.to_be_bytes().to_vec();
+		block.extend_from_slice(b"Exif\0\0MM\0*\0\0\0\x08");
+		assert_eq!(exif_tiff_data(&block), Some(&b"MM\0*\0\0\0\x08"[..]));
+
+		let little_endian = [0, 0, 0, 0, b'I', b'I', b'*', 0];
+		assert_eq!(exif_tiff_data(&little_endian), Some(&little_endian[4..]));
+		assert_eq!(exif_tiff_data(&[0, 0, 0, 9, b'I', b'I', b'*', 0]), None);
+		assert_eq!(exif_tiff_data(&[0, 0]), None);
+	}
+
 	#[test]
 	fn test_is_heif_by_magic_bytes() {
 		// Create a temp file with HEIC magic bytes
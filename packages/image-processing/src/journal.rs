@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use crate::error::ProcessingError;
+use crate::logging::{log_event, LogLevel};
+
+/// Append-only ndjson journal of files finished by a batch
+/// Each line records one file: {"path": "...", "relativePath": "...", "success": true, "completedAt": 1700000000000}
+pub struct Journal {
+	file: Mutex<File>,
+}
+
+impl Journal {
+	/// Open (or create) a journal for appending
+	pub fn open(journal_path: &str) -> Result<Self, ProcessingError> {
+		let mut file = OpenOptions::new()
+			.create(true)
+			.read(true)
+			.append(true)
+			.open(journal_path)
+			.map_err(|e| {
+				ProcessingError::io(&e, &format!("Failed to open journal {}", journal_path))
+			})?;
+
+		// Terminate a line truncated by a previous crash so new entries start on their own line
+		if file.seek(SeekFrom::End(-1)).is_ok() {
+			let mut last = [0u8; 1];
+			if file.read_exact(&mut last).is_ok() && last[0] != b'\n' {
+				file.write_all(b"\n").map_err(|e| {
+					ProcessingError::io(&e, &format!("Failed to write journal {}", journal_path))
+				})?;
+			}
+		}
+
+		Ok(Self {
+			file: Mutex::new(file),
+		})
+	}
+
+	/// Record a finished file
+	/// Each entry is written with a single call so a crash can at worst truncate the last line
+	pub fn record(&self, file_path: &str, relative_path: &str, success: bool) {
+		let completed_at = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|d| d.as_millis() as u64)
+			.unwrap_or(0);
+		let entry = serde_json::json!({
+			"path": file_path,
+			"relativePath": relative_path,
+			"success": success,
+			"completedAt": completed_at,
+		});
+
+		let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+		if let Err(e) = file.write_all(format!("{}\n", entry).as_bytes()) {
+			log_event(
+				LogLevel::Warn,
+				"batch",
+				Some(file_path),
+				format!("Failed to write journal entry: {}", e),
+			);
+		}
+	}
+}
+
+/// Read the paths a journal marks as successfully processed
+/// Unreadable or truncated lines are ignored; failed files are not included so they get retried
+pub fn read_completed_paths(journal_path: &str) -> HashSet<String> {
+	let Ok(file) = File::open(journal_path) else {
+		return HashSet::new();
+	};
+
+	BufReader::new(file)
+		.lines()
+		.map_while(Result::ok)
+		.filter_map(|line| serde_json::from_str::<serde_json::Value>(&line).ok())
+		.filter(|entry| entry.get("success").and_then(|s| s.as_bool()) == Some(true))
+		.filter_map(|entry| entry.get("path")?.as_str().map(|p| p.to_string()))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_completed_paths_skip_failures_and_truncated_lines() {
+		let dir = tempfile::tempdir().unwrap();
+		let journal_path = dir.path().join("import.ndjson");
+		let journal_path = journal_path.to_str().unwrap();
+
+		let journal = Journal::open(journal_path).unwrap();
+		journal.record("/photos/a.jpg", "a.jpg", true);
+		journal.record("/photos/b.jpg", "b.jpg", false);
+		drop(journal);
+
+		// Simulate a crash in the middle of writing an entry
+		let mut file = OpenOptions::new().append(true).open(journal_path).unwrap();
+		file.write_all(b"{\"path\": \"/photos/c.jpg\", \"succ")
+			.unwrap();
+
+		// Resuming appends after the truncated line
+		let journal = Journal::open(journal_path).unwrap();
+		journal.record("/photos/d.jpg", "d.jpg", true);
+		drop(journal);
+
+		let completed = read_completed_paths(journal_path);
+		assert_eq!(
+			completed,
+			HashSet::from(["/photos/a.jpg".to_string(), "/photos/d.jpg".to_string()])
+		);
+	}
+}
@@ -1,22 +1,245 @@
 #![deny(clippy::all)]
+// Without `node`, `Buffer` is `Vec<u8>` and conversions into it are no-ops
+#![cfg_attr(not(feature = "node"), allow(clippy::useless_conversion))]
 
+mod animation;
 mod batch;
+mod benchmark;
+mod bracket;
+mod cache;
+mod cancellation;
 mod clip;
+mod color;
+mod content_hash;
+mod crop;
+mod dates;
+mod decode;
+mod dedupe;
 mod discovery;
+mod edits;
+mod enhance;
+mod error;
 mod exif;
+mod exif_write;
+mod export;
+mod features;
+mod hdr;
 mod heif;
+mod journal;
+mod live_photo;
+mod logging;
+mod memory;
+mod nsfw;
+mod ocr;
 mod orientation;
+mod panorama;
 mod phash;
+mod presence;
 mod preview;
+mod privacy;
+mod quality;
+mod runtime;
+mod screenshot;
+mod tagging;
 mod thumbnails;
+mod tiles;
+mod timezone;
+mod video;
+mod watch;
+mod watermark;
+mod xmp;
+
+/// Binary data: a Node.js Buffer with the `node` feature, plain bytes without it
+#[cfg(feature = "node")]
+pub use napi::bindgen_prelude::Buffer;
+#[cfg(not(feature = "node"))]
+pub type Buffer = Vec<u8>;
 
 // Re-export public functions and types
 pub use batch::{
-	get_supported_extensions, is_supported_image, process_photo, process_photos_batch,
-	process_photos_with_callback, PhotoProcessingResult,
-};
-pub use clip::{batch_generate_clip_embeddings, clip_text_embedding};
-pub use discovery::{discover_photos, DiscoveryResult};
-pub use exif::{extract_exif, ExifData};
-pub use phash::generate_phash;
-pub use thumbnails::{generate_thumbnails_from_file, ThumbnailConfig, ThumbnailSizes};
+	extract_photo_metadata, extract_photo_metadata_from_buffer, get_supported_extensions,
+	is_supported_image, process_photo_internal, process_photos_batch_internal,
+	process_photos_with_callback_internal, resume_photos_batch_internal,
+	unlock_private_photo_internal, BatchOptions, BatchSummary, PhotoProcessingResult,
+};
+pub use benchmark::{run_benchmark_internal, SelfBenchmarkOptions, SelfBenchmarkReport};
+pub use bracket::{find_exposure_brackets_internal, merge_exposures_internal, MergedExposure};
+pub use cache::{close_processing_cache, open_processing_cache_internal};
+pub use cancellation::CancellationToken;
+pub use clip::{
+	batch_embeddings, batch_generate_clip_embeddings, batch_generate_clip_embeddings_quantized,
+	buffer_embedding, classify_image_internal, configure_embedding_postprocessing_internal,
+	configure_models_internal, cosine_similarity_internal, decode_upright,
+	dequantize_embedding_internal, find_similar_images_from_bytes, find_similar_images_internal,
+	get_clip_model_status, get_embedding_model, get_execution_provider, preload_models,
+	quantize_embedding_internal, quantized_similarity_internal, set_embedding_model_internal,
+	set_execution_provider, set_multilingual_text_model_internal, text_embedding, top_k,
+	top_k_similar_quantized, unload_clip_models, video_clip_embedding_internal, BatchEmbeddings,
+	ClipBatchEmbeddings, ClipBatchQuantizedEmbeddings, ClipModelStatus, EmbeddingModelInfo,
+	EmbeddingModelName, EmbeddingPostProcessOptions, EmbeddingQuantization, ExecutionProviderInfo,
+	ExecutionProviderName, LabelScore, ModelOptions, QuantizedEmbedding, SimilarityMatch,
+	VideoEmbedding,
+};
+pub use color::{read_color_space, ColorSpace};
+pub use content_hash::content_hash;
+pub use dates::DateSource;
+pub use decode::{decode_bytes, decode_image, source_kind, SourceKind};
+pub use dedupe::{find_exact_duplicates, hash_files, hash_files_internal};
+pub use discovery::{
+	discover_photos_internal, AssetGroup, AssetGroupKind, DiscoveryError, DiscoveryOptions,
+	DiscoveryProgress, DiscoveryResult, DiscoverySummary, FileCount, FileStat,
+};
+pub use edits::{render_edits_internal, CurvePoint, EditCrop, EditRecipe};
+pub use enhance::{compute_auto_enhance_internal, AutoEnhance};
+pub use error::{ErrorCode, ErrorStage, ProcessingError};
+pub use exif::{
+	extract_exif_batch_internal, extract_exif_from_bytes, extract_exif_internal,
+	extract_exif_thumbnail_internal, format_exif_values, ExifData,
+};
+pub use exif_write::{
+	write_exif_fields_internal, ExifWriteFields, ExifWriteResult, GpsCoordinates,
+};
+pub use export::{export_photo_internal, ExportMetadata, ExportOptions, ExportResult};
+pub use features::{get_available_features, FeatureInfo};
+pub use hdr::{extract_gain_map_internal, GainMapData, GainMapSource};
+pub use heif::{
+	decode_heif, decode_heif_image, decode_heif_rgb16, extract_heif_auxiliary_images_internal,
+	generate_heif_image_thumbnails_internal, list_heif_images_internal,
+	read_heif_metadata_internal, tone_map_to_8bit, HdrTransfer, HeifAuxiliaryImage,
+	HeifAuxiliaryKind, HeifColorInfo, HeifDecoded, HeifImageInfo, HeifMetadata,
+};
+pub use live_photo::{process_live_photo_video_internal, LivePhotoMotion};
+pub use logging::{set_log_handler, LogEvent, LogHandler, LogLevel};
+pub use nsfw::{
+	configure_nsfw_model_internal, nsfw_score_internal, score_nsfw_image, NsfwModelOptions,
+};
+pub use ocr::{
+	configure_ocr_models_internal, extract_text_internal, recognize_text, ExtractedText,
+	OcrModelOptions, TextLine,
+};
+pub use orientation::{normalize_orientation_internal, NormalizedImage};
+pub use phash::{
+	cluster_near_duplicates, compute_hashes_internal, find_near_duplicates,
+	generate_phash_from_image, perceptual_hash_batch, perceptual_hash_internal,
+	phash_distance_internal, robust_perceptual_hash_internal, robust_phash_distance_internal,
+	video_phash_internal, ImageHashes, NearDuplicatePair,
+};
+pub use presence::{
+	configure_presence_model_internal, detect_presence_image, detect_presence_internal,
+	PresenceCounts, PresenceModelOptions,
+};
+pub use preview::{
+	extract_motion_photo_video_internal, extract_preview_for_size_internal,
+	extract_preview_image_internal, needs_preview_extraction, PreviewImage,
+};
+pub use privacy::{decrypt_private_metadata_internal, PrivateMetadata};
+pub use quality::{
+	analyze_exposure_from_bytes, analyze_exposure_internal, pick_best_shot, ExposureAnalysis,
+	ShotScore,
+};
+pub use runtime::{configure_runtime_internal, RuntimeOptions};
+pub use tagging::{
+	configure_tagging_model_internal, tag_image, tag_photo_internal, TaggingModelOptions,
+};
+pub use thumbnails::{
+	delete_thumbnails_internal, generate_thumbnail_buffers_from_file,
+	generate_thumbnails_from_file_internal, prune_thumbnails, ThumbnailBuffer, ThumbnailCleanup,
+	ThumbnailConfig, ThumbnailCrop, ThumbnailFormat, ThumbnailSize, ThumbnailSizes, UnsharpMask,
+};
+pub use tiles::{generate_tiles_from_file_internal, TilePyramid};
+pub use video::{extract_video_metadata_internal, VideoMetadata};
+pub use watch::{
+	unwatch_directory, watch_directory_internal, WatchEvent, WatchEventKind, WatchOptions,
+};
+pub use watermark::{Watermark, WatermarkPosition};
+pub use xmp::{
+	read_xmp_sidecar_internal, write_xmp_sidecar_internal, XmpSidecar, XmpSidecarUpdate,
+};
+
+// Node.js bindings over the functions above
+#[cfg(feature = "node")]
+pub use batch::{
+	process_photo, process_photos_batch, process_photos_batch_async, process_photos_streaming,
+	process_photos_with_callback, resume_photos_batch, resume_photos_batch_async,
+	scan_photos_metadata, scan_photos_metadata_async, unlock_private_photo,
+};
+#[cfg(feature = "node")]
+pub use benchmark::{run_benchmark, run_self_benchmark};
+#[cfg(feature = "node")]
+pub use bracket::{find_exposure_brackets, merge_exposures, merge_exposures_async};
+#[cfg(feature = "node")]
+pub use cache::open_processing_cache;
+#[cfg(feature = "node")]
+pub use clip::{
+	batch_generate_clip_embeddings_async, batch_generate_clip_embeddings_f32,
+	batch_generate_clip_embeddings_f32_async, batch_generate_clip_embeddings_quantized_async,
+	classify_image, clip_embedding_from_buffer, clip_embedding_from_buffer_f32,
+	clip_text_embedding, clip_text_embedding_async, clip_text_embedding_f32,
+	configure_embedding_postprocessing, configure_models, cosine_similarity, dequantize_embedding,
+	find_similar_images, preload_clip_models, quantize_embedding, quantized_similarity,
+	set_embedding_model, set_multilingual_text_model, top_k_similar, video_clip_embedding,
+	video_clip_embedding_async, ClipBatchEmbeddingsF32,
+};
+#[cfg(feature = "node")]
+pub use content_hash::compute_content_hash;
+#[cfg(feature = "node")]
+pub use dedupe::{find_exact_duplicates_async, hash_files_async};
+#[cfg(feature = "node")]
+pub use discovery::{discover_photos, discover_photos_async};
+#[cfg(feature = "node")]
+pub use edits::{render_edits, render_edits_async};
+#[cfg(feature = "node")]
+pub use enhance::compute_auto_enhance;
+#[cfg(feature = "node")]
+pub use exif::{extract_exif, extract_exif_batch, extract_exif_thumbnail};
+#[cfg(feature = "node")]
+pub use exif_write::write_exif_fields;
+#[cfg(feature = "node")]
+pub use export::{export_photo, export_photo_async};
+#[cfg(feature = "node")]
+pub use hdr::extract_gain_map;
+#[cfg(feature = "node")]
+pub use heif::{
+	extract_heif_auxiliary_images, generate_heif_image_thumbnails, list_heif_images,
+	read_heif_metadata,
+};
+#[cfg(feature = "node")]
+pub use live_photo::process_live_photo_video;
+#[cfg(feature = "node")]
+pub use logging::set_log_callback;
+#[cfg(feature = "node")]
+pub use nsfw::{configure_nsfw_model, nsfw_score};
+#[cfg(feature = "node")]
+pub use ocr::{configure_ocr_models, extract_text};
+#[cfg(feature = "node")]
+pub use orientation::normalize_orientation;
+#[cfg(feature = "node")]
+pub use phash::{
+	compute_hashes, generate_phash, perceptual_hash_async, perceptual_hash_batch_async,
+	perceptual_hash_from_buffer, phash_distance, robust_perceptual_hash, robust_phash_distance,
+	video_phash,
+};
+#[cfg(feature = "node")]
+pub use presence::{configure_presence_model, detect_presence};
+#[cfg(feature = "node")]
+pub use preview::{extract_motion_photo_video, extract_preview_for_size, extract_preview_image};
+#[cfg(feature = "node")]
+pub use privacy::decrypt_private_metadata;
+#[cfg(feature = "node")]
+pub use quality::analyze_exposure;
+#[cfg(feature = "node")]
+pub use runtime::configure_runtime;
+#[cfg(feature = "node")]
+pub use tagging::{configure_tagging_model, tag_photo};
+#[cfg(feature = "node")]
+pub use thumbnails::{
+	delete_thumbnails, generate_thumbnail_buffers, generate_thumbnails_from_file,
+};
+#[cfg(feature = "node")]
+pub use tiles::generate_tiles_from_file;
+#[cfg(feature = "node")]
+pub use video::extract_video_metadata;
+#[cfg(feature = "node")]
+pub use watch::watch_directory;
+#[cfg(feature = "node")]
+pub use xmp::{read_xmp_sidecar, write_xmp_sidecar};
@@ -0,0 +1,272 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::clip::decode_upright;
+use crate::decode::decode_bytes;
+use crate::error::{ErrorCode, ProcessingError};
+use crate::features::{require, Component};
+use crate::preview::PreviewImage;
+use crate::quality::sharpness;
+use crate::video::{extract_frame, probe_video};
+
+/// Extensions of the clip Apple stores next to a Live Photo still
+const LIVE_PHOTO_VIDEO_EXTENSIONS: &[&str] = &["mov", "MOV"];
+
+/// Frames of a Live Photo clip compared when picking its key frame
+const KEY_FRAME_CANDIDATES: u32 = 9;
+
+/// Width candidate frames are scaled down to before scoring
+const CANDIDATE_WIDTH: u32 = 512;
+
+/// Side of the grayscale frames compared to measure camera motion
+const MOTION_SIZE: u32 = 64;
+
+/// How much sharper (x) than the still a key frame must be to replace it as the cover
+const SHARPER_MARGIN: f64 = 1.2;
+
+/// Find the video next to a still with the same file name stem, e.g. IMG_0001.MOV
+fn find_sibling_video(file_path: &Path) -> Option<PathBuf> {
+	let stem = file_path.file_stem()?.to_str()?;
+	let dir = file_path.parent()?;
+	LIVE_PHOTO_VIDEO_EXTENSIONS
+		.iter()
+		.map(|ext| dir.join(format!("{}.{}", stem, ext)))
+		.find(|candidate| candidate.is_file())
+}
+
+/// Apple content identifiers of files in one exiftool run (QuickTime Keys:ContentIdentifier
+/// of a clip, the maker notes' of a still), in order; None if a file couldn't be read
+fn content_identifiers(paths: &[&Path]) -> Option<Vec<Option<String>>> {
+	let output = Command::new("exiftool")
+		.args(["-T", "-ContentIdentifier"])
+		.args(paths)
+		.output()
+		.ok()?;
+	// One line per file, "-" when it has no identifier
+	let identifiers: Vec<Option<String>> = String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.map(|line| Some(line.trim().to_string()).filter(|id| !id.is_empty() && id != "-"))
+		.collect();
+	(identifiers.len() == paths.len()).then_some(identifiers)
+}
+
+/// Read the Apple content identifier of a video
+fn video_content_identifier(video_path: &Path) -> Option<String> {
+	content_identifiers(&[video_path])?.pop().flatten()
+}
+
+/// Whether a still and a clip are the two halves of one Live Photo: both carry the same
+/// content identifier, so an unrelated video with the still's name doesn't count
+pub fn is_live_photo_pair(still_path: &str, video_path: &str) -> bool {
+	let paths = [Path::new(still_path), Path::new(video_path)];
+	match content_identifiers(&paths).as_deref() {
+		Some([Some(still), Some(video)]) => still.eq_ignore_ascii_case(video),
+		_ => false,
+	}
+}
+
+/// Find the paired clip of a Live Photo: a .mov next to the still whose content
+/// identifier matches the still's, so unrelated videos with the same name don't count
+/// Returns the clip's relative path, next to `relative_path`
+pub fn find_live_photo_video(
+	file_path: &str,
+	relative_path: &str,
+	content_identifier: Option<&str>,
+) -> Option<String> {
+	let content_identifier = content_identifier?.trim();
+	if content_identifier.is_empty() {
+		return None;
+	}
+
+	let video_path = find_sibling_video(Path::new(file_path))?;
+	if !video_content_identifier(&video_path)?.eq_ignore_ascii_case(content_identifier) {
+		return None;
+	}
+
+	let video_name = video_path.file_name()?.to_string_lossy();
+	Some(match relative_path.rfind('/') {
+		Some(slash) => format!("{}/{}", &relative_path[..slash], video_name),
+		None => video_name.to_string(),
+	})
+}
+
+/// Video side of a Live Photo
+#[cfg_attr(feature = "node", napi(object))]
+pub struct LivePhotoMotion {
+	/// Duration of the clip in seconds
+	pub duration: f64,
+	/// Frame halfway through the clip, around where the still is taken
+	pub mid_frame: PreviewImage,
+	/// Seconds into the clip of the best key frame: sharp, taken while the camera was steady
+	pub key_frame_time: f64,
+	pub key_frame: PreviewImage,
+	/// Sharpness of the key frame and of the still, as in processing results
+	pub key_frame_sharpness: f64,
+	pub still_sharpness: Option<f64>,
+	/// Whether the key frame is clearly sharper than the still, so it makes a better cover
+	pub key_frame_is_sharper: bool,
+}
+
+/// Full-resolution frame at `seek` seconds, encoded and decoded
+fn frame_at(video_path: &str, seek: f64) -> Option<(PreviewImage, DynamicImage)> {
+	let data = extract_frame(video_path, seek, None)?;
+	let img = decode_bytes(&data).ok()?;
+	let preview = PreviewImage {
+		data: data.into(),
+		mime_type: "image/png".to_string(),
+		width: img.width(),
+		height: img.height(),
+	};
+	Some((preview, img))
+}
+
+/// Mean absolute difference (0-255) of each frame to its neighbours, a measure of how much
+/// the camera or subject moved around it
+fn frame_motion(frames: &[GrayImage]) -> Vec<f64> {
+	let difference = |a: &GrayImage, b: &GrayImage| {
+		let total: u64 = a
+			.as_raw()
+			.iter()
+			.zip(b.as_raw())
+			.map(|(x, y)| x.abs_diff(*y) as u64)
+			.sum();
+		total as f64 / a.as_raw().len().max(1) as f64
+	};
+	(0..frames.len())
+		.map(|i| {
+			let neighbours: Vec<f64> = [i.checked_sub(1), Some(i + 1)]
+				.into_iter()
+				.flatten()
+				.filter_map(|j| frames.get(j))
+				.map(|neighbour| difference(&frames[i], neighbour))
+				.collect();
+			neighbours.iter().sum::<f64>() / neighbours.len().max(1) as f64
+		})
+		.collect()
+}
+
+/// Position of the best key frame: the sharpest once discounted by its motion, so a frame
+/// mid-pan doesn't win on edges that are really blur or rolling shutter
+fn pick_key_frame(sharpness: &[f64], motion: &[f64]) -> Option<usize> {
+	sharpness
+		.iter()
+		.zip(motion)
+		.map(|(sharpness, motion)| sharpness / (1.0 + motion))
+		.enumerate()
+		.max_by(|a, b| a.1.total_cmp(&b.1))
+		.map(|(i, _)| i)
+}
+
+/// Process the clip of a Live Photo, see `process_live_photo_video`
+pub fn process_live_photo_video_internal(
+	video_path: &str,
+	still_path: Option<&str>,
+) -> Result<LivePhotoMotion, ProcessingError> {
+	require(Component::Ffmpeg)?;
+	let decode_failed = |message: String| ProcessingError::new(ErrorCode::DecodeFailed, message);
+	let duration = probe_video(video_path)
+		.and_then(|probe| probe.duration)
+		.filter(|duration| *duration > 0.0)
+		.ok_or_else(|| decode_failed("Failed to read video duration".to_string()))?;
+
+	let (seeks, candidates): (Vec<f64>, Vec<DynamicImage>) = (0..KEY_FRAME_CANDIDATES)
+		.into_par_iter()
+		.filter_map(|i| {
+			let seek = duration * (i as f64 + 0.5) / KEY_FRAME_CANDIDATES as f64;
+			let frame = extract_frame(video_path, seek, Some(CANDIDATE_WIDTH))?;
+			decode_bytes(&frame).ok().map(|frame| (seek, frame))
+		})
+		.unzip();
+	let scores: Vec<f64> = candidates.par_iter().map(sharpness).collect();
+	let small: Vec<GrayImage> = candidates
+		.iter()
+		.map(|frame| {
+			frame
+				.resize_exact(MOTION_SIZE, MOTION_SIZE, FilterType::Triangle)
+				.to_luma8()
+		})
+		.collect();
+	let key_frame_time = pick_key_frame(&scores, &frame_motion(&small))
+		.map(|i| seeks[i])
+		.ok_or_else(|| decode_failed("Failed to extract video frames".to_string()))?;
+
+	let extract = |seek: f64| {
+		frame_at(video_path, seek)
+			.ok_or_else(|| decode_failed(format!("Failed to extract frame at {:.1}s", seek)))
+	};
+	let (key_frame, key_img) = extract(key_frame_time)?;
+	let (mid_frame, _) = extract(duration / 2.0)?;
+	let key_frame_sharpness = sharpness(&key_img);
+	let still_sharpness = match still_path {
+		Some(still_path) => Some(sharpness(&decode_upright(still_path)?)),
+		None => None,
+	};
+
+	Ok(LivePhotoMotion {
+		duration,
+		mid_frame,
+		key_frame_time,
+		key_frame,
+		key_frame_sharpness,
+		still_sharpness,
+		key_frame_is_sharper: still_sharpness
+			.is_some_and(|still| key_frame_sharpness > still * SHARPER_MARGIN),
+	})
+}
+
+/// Process the clip of a Live Photo: its duration, the frame halfway through and the best
+/// key frame, compared with the still (when given) so a blurry still can be replaced
+/// Frames are PNG; they're rotated upright like the still
+#[cfg(feature = "node")]
+#[napi]
+pub fn process_live_photo_video(
+	video_path: String,
+	still_path: Option<String>,
+) -> napi::Result<LivePhotoMotion, ErrorCode> {
+	process_live_photo_video_internal(&video_path, still_path.as_deref()).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+
+	#[test]
+	fn test_find_sibling_video() {
+		let dir = tempfile::tempdir().unwrap();
+		let still = dir.path().join("IMG_0001.HEIC");
+		fs::write(&still, b"").unwrap();
+		assert_eq!(find_sibling_video(&still), None);
+
+		fs::write(dir.path().join("IMG_0001.MOV"), b"").unwrap();
+		fs::write(dir.path().join("IMG_0002.mov"), b"").unwrap();
+		assert_eq!(
+			find_sibling_video(&still),
+			Some(dir.path().join("IMG_0001.MOV"))
+		);
+
+		// No identifier on the still means it isn't a Live Photo
+		assert_eq!(
+			find_live_photo_video(&still.to_string_lossy(), "2024/IMG_0001.HEIC", None),
+			None
+		);
+	}
+
+	#[test]
+	fn test_pick_steady_key_frame() {
+		let frame = |value: u8| GrayImage::from_pixel(4, 4, image::Luma([value]));
+		let motion = frame_motion(&[frame(10), frame(10), frame(40)]);
+		assert_eq!(motion, vec![0.0, 15.0, 30.0]);
+
+		// The sharpest frame loses to a nearly as sharp one taken while steady
+		assert_eq!(
+			pick_key_frame(&[100.0, 150.0, 160.0], &[1.0, 1.0, 20.0]),
+			Some(1)
+		);
+		assert_eq!(pick_key_frame(&[], &[]), None);
+	}
+}
@@ -0,0 +1,102 @@
+#[cfg(feature = "node")]
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+#[cfg(feature = "node")]
+use napi::Status;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::sync::{Arc, RwLock};
+
+/// Severity of a log event, least severe first
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+	Debug,
+	Info,
+	Warn,
+	Error,
+}
+
+/// Something worth reporting that doesn't fail the call, e.g. a photo whose thumbnails
+/// couldn't be written during a batch
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEvent {
+	pub level: LogLevel,
+	/// Part of the library the event comes from, e.g. "batch", "raw" or "thumbnails"
+	pub module: String,
+	/// File the event is about, if any
+	pub file_path: Option<String>,
+	pub message: String,
+}
+
+/// Receives log events, e.g. a CLI printing them or a server forwarding them to its logger
+pub type LogHandler = Box<dyn Fn(LogEvent) + Send + Sync>;
+
+/// Weak, so a registered callback doesn't keep the app from exiting
+#[cfg(feature = "node")]
+type LogCallback = ThreadsafeFunction<LogEvent, (), LogEvent, Status, true, true>;
+
+struct LogSink {
+	handler: LogHandler,
+	min_level: LogLevel,
+}
+
+static LOG_SINK: RwLock<Option<Arc<LogSink>>> = RwLock::new(None);
+
+/// Send log events from `min_level` up (default info) to `handler`, see `set_log_callback`
+pub fn set_log_handler(handler: Option<LogHandler>, min_level: Option<LogLevel>) {
+	let sink = handler.map(|handler| {
+		Arc::new(LogSink {
+			handler,
+			min_level: min_level.unwrap_or(LogLevel::Info),
+		})
+	});
+	*LOG_SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+}
+
+/// Receive log events in JavaScript from `min_level` up (default info), e.g. to forward
+/// them to the app's log file; pass null to go back to printing warnings and errors to
+/// stderr, where packaged apps lose them
+#[cfg(feature = "node")]
+#[napi]
+pub fn set_log_callback(
+	#[napi(ts_arg_type = "((event: LogEvent) => void) | null")] callback: Option<LogCallback>,
+	min_level: Option<LogLevel>,
+) {
+	let handler = callback.map(|callback| -> LogHandler {
+		Box::new(move |event| {
+			callback.call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking);
+		})
+	});
+	set_log_handler(handler, min_level);
+}
+
+/// Report an event to the log handler, or print warnings and errors to stderr when none
+/// is set
+pub fn log_event(level: LogLevel, module: &str, file_path: Option<&str>, message: String) {
+	let sink = LOG_SINK.read().unwrap_or_else(|e| e.into_inner()).clone();
+	match sink {
+		Some(sink) if level >= sink.min_level => {
+			let event = LogEvent {
+				level,
+				module: module.to_string(),
+				file_path: file_path.map(str::to_string),
+				message,
+			};
+			(sink.handler)(event);
+		}
+		Some(_) => {}
+		None if level >= LogLevel::Warn => {
+			let prefix = if level == LogLevel::Error {
+				"Error"
+			} else {
+				"Warning"
+			};
+			match file_path {
+				Some(file_path) => eprintln!("{} [{}] {}: {}", prefix, module, file_path, message),
+				None => eprintln!("{} [{}] {}", prefix, module, message),
+			}
+		}
+		None => {}
+	}
+}
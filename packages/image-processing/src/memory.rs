@@ -0,0 +1,170 @@
+use libheif_rs::HeifContext;
+use std::fs;
+use std::sync::{Condvar, Mutex};
+
+use crate::decode::SourceKind;
+use crate::video::probe_video;
+
+/// Estimated bytes per pixel while processing, per source kind
+/// RAW holds the compressed file, decoded preview and resized copies; JPEG roughly half that
+const RAW_MB_PER_MEGAPIXEL: f64 = 6.0;
+const HEIF_MB_PER_MEGAPIXEL: f64 = 4.0;
+const STANDARD_MB_PER_MEGAPIXEL: f64 = 3.0;
+
+/// Compressed RAW files store roughly 1.2 bytes per pixel
+const RAW_BYTES_PER_PIXEL: f64 = 1.2;
+
+/// Fallback when dimensions can't be read from the header (a 24MP photo)
+const DEFAULT_MEGAPIXELS: f64 = 24.0;
+
+/// Read image dimensions without decoding pixels
+fn header_megapixels(file_path: &str, kind: SourceKind) -> Option<f64> {
+	let (width, height) = match kind {
+		SourceKind::Standard => image::image_dimensions(file_path).ok()?,
+		SourceKind::Heif => {
+			let ctx = HeifContext::read_from_file(file_path).ok()?;
+			let handle = ctx.primary_image_handle().ok()?;
+			(handle.width(), handle.height())
+		}
+		SourceKind::Video => {
+			let probe = probe_video(file_path)?;
+			(probe.width?, probe.height?)
+		}
+		SourceKind::RawPreview => {
+			// RAW headers vary per vendor; infer the sensor size from the file size
+			let size = fs::metadata(file_path).ok()?.len() as f64;
+			return Some(size / RAW_BYTES_PER_PIXEL / 1_000_000.0);
+		}
+	};
+	Some(width as f64 * height as f64 / 1_000_000.0)
+}
+
+/// Estimate peak memory in MB needed to process one file
+/// Files decoded from memory hold their whole contents on top of the pixels
+pub fn estimate_footprint_mb(file_path: &str, kind: Option<SourceKind>) -> u64 {
+	let Some(kind) = kind else {
+		return 1;
+	};
+
+	let megapixels = header_megapixels(file_path, kind).unwrap_or(DEFAULT_MEGAPIXELS);
+	let per_megapixel = match kind {
+		SourceKind::RawPreview => RAW_MB_PER_MEGAPIXEL,
+		SourceKind::Heif => HEIF_MB_PER_MEGAPIXEL,
+		SourceKind::Standard | SourceKind::Video => STANDARD_MB_PER_MEGAPIXEL,
+	};
+	let file_mb = if kind.decodes_from_memory() {
+		fs::metadata(file_path).map_or(0.0, |m| m.len() as f64 / 1_000_000.0)
+	} else {
+		0.0
+	};
+	(megapixels * per_megapixel + file_mb).ceil().max(1.0) as u64
+}
+
+/// Limits the total estimated memory of files processed at the same time
+/// Workers block in `acquire` until enough of the budget has been released
+pub struct MemoryBudget {
+	budget_mb: u64,
+	in_use_mb: Mutex<u64>,
+	released: Condvar,
+}
+
+/// Reservation that returns its share of the budget when dropped
+pub struct MemoryReservation<'a> {
+	budget: &'a MemoryBudget,
+	cost_mb: u64,
+}
+
+impl MemoryBudget {
+	pub fn new(budget_mb: u64) -> Self {
+		Self {
+			budget_mb: budget_mb.max(1),
+			in_use_mb: Mutex::new(0),
+			released: Condvar::new(),
+		}
+	}
+
+	/// Reserve memory for one file, waiting for in-flight work to finish if needed
+	/// A file larger than the whole budget still runs, but only on its own
+	pub fn acquire(&self, cost_mb: u64) -> MemoryReservation<'_> {
+		let cost_mb = cost_mb.min(self.budget_mb);
+		let mut in_use = self.in_use_mb.lock().unwrap_or_else(|e| e.into_inner());
+		while *in_use + cost_mb > self.budget_mb {
+			in_use = self
+				.released
+				.wait(in_use)
+				.unwrap_or_else(|e| e.into_inner());
+		}
+		*in_use += cost_mb;
+
+		MemoryReservation {
+			budget: self,
+			cost_mb,
+		}
+	}
+}
+
+impl Drop for MemoryReservation<'_> {
+	fn drop(&mut self) {
+		let mut in_use = self
+			.budget
+			.in_use_mb
+			.lock()
+			.unwrap_or_else(|e| e.into_inner());
+		*in_use -= self.cost_mb;
+		self.budget.released.notify_all();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicU64, Ordering};
+	use std::sync::Arc;
+	use std::thread;
+
+	#[test]
+	fn test_budget_limits_concurrent_reservations() {
+		let budget = Arc::new(MemoryBudget::new(100));
+		let in_flight = Arc::new(AtomicU64::new(0));
+		let peak = Arc::new(AtomicU64::new(0));
+
+		let handles: Vec<_> = (0..8)
+			.map(|_| {
+				let (budget, in_flight, peak) = (budget.clone(), in_flight.clone(), peak.clone());
+				thread::spawn(move || {
+					let _reservation = budget.acquire(40);
+					let now = in_flight.fetch_add(40, Ordering::SeqCst) + 40;
+					peak.fetch_max(now, Ordering::SeqCst);
+					thread::sleep(std::time::Duration::from_millis(5));
+					in_flight.fetch_sub(40, Ordering::SeqCst);
+				})
+			})
+			.collect();
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		assert!(peak.load(Ordering::SeqCst) <= 100);
+	}
+
+	#[test]
+	fn test_oversized_file_runs_alone() {
+		let budget = MemoryBudget::new(100);
+		let reservation = budget.acquire(500);
+		assert_eq!(reservation.cost_mb, 100);
+	}
+
+	#[test]
+	fn test_footprint_counts_file_in_memory() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("padded.png");
+		image::RgbImage::new(100, 100).save(&path).unwrap();
+		let mut data = fs::read(&path).unwrap();
+		data.resize(data.len() + 5_000_000, 0);
+		fs::write(&path, data).unwrap();
+
+		let path = path.to_string_lossy();
+		assert_eq!(estimate_footprint_mb(&path, Some(SourceKind::Standard)), 6);
+		assert!(!SourceKind::RawPreview.decodes_from_memory());
+	}
+}
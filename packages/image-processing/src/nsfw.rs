@@ -0,0 +1,159 @@
+use image::DynamicImage;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use ort::session::Session;
+
+use crate::clip::{decode_upright, load_onnx_session, ConfiguredModel};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::tagging::Classifier;
+
+/// Classes counted as sensitive when the options don't list them, covering the common
+/// open models (GantMan's five classes, Falconsai's normal/nsfw)
+const DEFAULT_NSFW_LABELS: &[&str] = &["porn", "hentai", "sexy", "nsfw"];
+
+/// NSFW model to load, set by `configure_nsfw_model`
+#[cfg_attr(feature = "node", napi(object))]
+pub struct NsfwModelOptions {
+	/// ONNX image classifier with a 1x3xNxN float input and one score per class
+	pub model_path: String,
+	/// Text file with the label of each output class, one per line
+	pub labels_path: String,
+	/// Labels of the sensitive classes; the score is their total probability
+	/// (default "porn", "hentai", "sexy" and "nsfw")
+	pub nsfw_labels: Option<Vec<String>>,
+	/// Side of the model's square input (default 224)
+	pub input_size: Option<u32>,
+	/// Per-channel RGB normalization of the input (default ImageNet's)
+	pub mean: Option<Vec<f64>>,
+	pub std: Option<Vec<f64>>,
+}
+
+/// Validated NSFW model settings
+#[derive(Debug, Clone)]
+struct NsfwConfig {
+	classifier: Classifier,
+	/// Whether each class of the classifier is sensitive
+	sensitive: Vec<bool>,
+}
+
+/// Global NSFW model, loaded on first use
+static NSFW_MODEL: ConfiguredModel<NsfwConfig, Session> =
+	ConfiguredModel::new("No NSFW model configured");
+
+/// Whether an NSFW model is configured, so photos can be scored
+pub fn has_nsfw_model() -> bool {
+	NSFW_MODEL.is_configured()
+}
+
+fn load_nsfw_model(config: &NsfwConfig) -> Result<Session, ProcessingError> {
+	load_onnx_session(&config.classifier.model_path)
+}
+
+fn nsfw_config_from_options(options: NsfwModelOptions) -> Result<NsfwConfig, ProcessingError> {
+	let classifier = Classifier::new(
+		&options.model_path,
+		&options.labels_path,
+		options.input_size,
+		options.mean,
+		options.std,
+	)
+	.map_err(|e| e.context("Invalid NSFW model"))?;
+	let nsfw_labels = options.nsfw_labels.unwrap_or_else(|| {
+		DEFAULT_NSFW_LABELS
+			.iter()
+			.map(|label| label.to_string())
+			.collect()
+	});
+	let sensitive: Vec<bool> = classifier
+		.labels
+		.iter()
+		.map(|label| {
+			nsfw_labels
+				.iter()
+				.any(|nsfw| nsfw.eq_ignore_ascii_case(label))
+		})
+		.collect();
+	if !sensitive.contains(&true) {
+		return Err(ProcessingError::invalid(format!(
+			"None of the NSFW labels are in {}",
+			options.labels_path
+		)));
+	}
+
+	Ok(NsfwConfig {
+		classifier,
+		sensitive,
+	})
+}
+
+/// Set the NSFW model, see `configure_nsfw_model`
+pub fn configure_nsfw_model_internal(
+	options: Option<NsfwModelOptions>,
+) -> Result<(), ProcessingError> {
+	let config = options.map(nsfw_config_from_options).transpose()?;
+	NSFW_MODEL.configure(config);
+	Ok(())
+}
+
+/// Set the ONNX model photos are scored for sensitive content with, or remove it with null
+/// Fails if none of the sensitive labels are among the model's; the model itself loads with
+/// the first photo scored
+#[cfg(feature = "node")]
+#[napi]
+pub fn configure_nsfw_model(options: Option<NsfwModelOptions>) -> napi::Result<(), ErrorCode> {
+	configure_nsfw_model_internal(options).map_err(napi::Error::from)
+}
+
+/// Total score of the sensitive classes, clamped to 0-1 (multi-label scores can add up
+/// past 1)
+fn sensitive_score(scores: &[f32], sensitive: &[bool]) -> f64 {
+	let total: f32 = scores
+		.iter()
+		.zip(sensitive)
+		.filter(|&(_, &sensitive)| sensitive)
+		.map(|(score, _)| score)
+		.sum();
+	total.clamp(0.0, 1.0) as f64
+}
+
+/// Probability (0-1) that an upright image is sensitive, from the configured NSFW model
+pub fn score_nsfw_image(img: &DynamicImage) -> Result<f64, ProcessingError> {
+	let (config, model) = NSFW_MODEL.get(load_nsfw_model)?;
+
+	let session = model.lock().unwrap_or_else(|e| e.into_inner());
+	let scores = config
+		.classifier
+		.scores(&session, img)
+		.map_err(|e| e.context("NSFW model error"))?;
+	Ok(sensitive_score(&scores, &config.sensitive))
+}
+
+/// Probability that a photo is sensitive, see `nsfw_score`
+pub fn nsfw_score_internal(file_path: &str) -> Result<f64, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	score_nsfw_image(&img)
+}
+
+/// Score a photo for sensitive content (0-1) with the model set with
+/// `configure_nsfw_model`, e.g. to hide it from shared or kid-safe views
+#[cfg(feature = "node")]
+#[napi]
+pub fn nsfw_score(file_path: String) -> napi::Result<f64, ErrorCode> {
+	nsfw_score_internal(&file_path).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sensitive_score() {
+		// drawings, hentai, neutral, porn, sexy
+		let sensitive = [false, true, false, true, true];
+		let score = sensitive_score(&[0.1, 0.05, 0.6, 0.15, 0.1], &sensitive);
+		assert!((score - 0.3).abs() < 1e-6);
+		assert_eq!(sensitive_score(&[0.9, 0.9], &[true, true]), 1.0);
+	}
+}
@@ -0,0 +1,460 @@
+use image::{imageops::FilterType, DynamicImage, RgbImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use ort::session::Session;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::clip::{decode_upright, load_onnx_session, run_onnx_session, ConfiguredModel};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+
+/// Longest side of the image given to the detection model; larger photos are scaled down
+const DET_MAX_SIDE: u32 = 960;
+
+/// Probability above which a pixel of the detection map is text
+const DET_THRESHOLD: f32 = 0.3;
+
+/// Mean probability a text region needs to be kept
+const DET_BOX_THRESHOLD: f32 = 0.6;
+
+/// Regions thinner than this (in detection pixels) are noise
+const DET_MIN_SIDE: usize = 3;
+
+/// How far regions are grown: the detection model outputs shrunk text cores
+const DET_UNCLIP_RATIO: f32 = 1.5;
+
+/// Height of the text lines given to the recognition model
+const REC_HEIGHT: u32 = 48;
+
+/// Width bounds of the text lines given to the recognition model
+const REC_MIN_WIDTH: u32 = 16;
+const REC_MAX_WIDTH: u32 = 1600;
+
+/// Recognized lines with a lower mean character probability are dropped
+const MIN_LINE_CONFIDENCE: f32 = 0.5;
+
+/// Per-channel normalization of the detection model
+const DET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const DET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+/// Per-channel normalization of the recognition model, to [-1, 1]
+const REC_MEAN: [f32; 3] = [0.5, 0.5, 0.5];
+const REC_STD: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// OCR models to load, set by `configure_ocr_models`
+#[cfg_attr(feature = "node", napi(object))]
+pub struct OcrModelOptions {
+	/// Text detection model (PaddleOCR DB) outputting a text probability map
+	pub detection_model_path: String,
+	/// Text recognition model (PaddleOCR CRNN/SVTR) taking 48px high lines
+	pub recognition_model_path: String,
+	/// Characters of the recognition model, one per line (e.g. ppocr_keys_v1.txt)
+	pub dictionary_path: String,
+}
+
+/// Validated OCR model settings
+#[derive(Debug, Clone)]
+struct OcrConfig {
+	detection_model_path: PathBuf,
+	recognition_model_path: PathBuf,
+	dictionary: Vec<String>,
+}
+
+/// Detection and recognition sessions, which always run one after the other
+struct OcrSessions {
+	detection: Session,
+	recognition: Session,
+}
+
+/// Global OCR models, both loaded on first use
+static OCR_MODELS: ConfiguredModel<OcrConfig, OcrSessions> =
+	ConfiguredModel::new("No OCR models configured");
+
+/// Whether OCR models are configured, so text can be extracted
+pub fn has_ocr_models() -> bool {
+	OCR_MODELS.is_configured()
+}
+
+fn load_ocr_models(config: &OcrConfig) -> Result<OcrSessions, ProcessingError> {
+	Ok(OcrSessions {
+		detection: load_onnx_session(&config.detection_model_path)?,
+		recognition: load_onnx_session(&config.recognition_model_path)?,
+	})
+}
+
+fn ocr_config_from_options(options: OcrModelOptions) -> Result<OcrConfig, ProcessingError> {
+	for path in [
+		&options.detection_model_path,
+		&options.recognition_model_path,
+	] {
+		if !PathBuf::from(path).is_file() {
+			return Err(ProcessingError::invalid(format!(
+				"OCR model {} doesn't exist",
+				path
+			)));
+		}
+	}
+	// Lines aren't trimmed: the dictionary may hold a space character
+	let dictionary: Vec<String> = fs::read_to_string(&options.dictionary_path)
+		.map_err(|e| {
+			ProcessingError::io(&e, &format!("Failed to read {}", options.dictionary_path))
+		})?
+		.lines()
+		.map(|line| line.trim_end_matches('\r').to_string())
+		.collect();
+	if dictionary.is_empty() {
+		return Err(ProcessingError::invalid(format!(
+			"{} has no characters",
+			options.dictionary_path
+		)));
+	}
+
+	Ok(OcrConfig {
+		detection_model_path: PathBuf::from(options.detection_model_path),
+		recognition_model_path: PathBuf::from(options.recognition_model_path),
+		dictionary,
+	})
+}
+
+/// Set the OCR models, see `configure_ocr_models`
+pub fn configure_ocr_models_internal(
+	options: Option<OcrModelOptions>,
+) -> Result<(), ProcessingError> {
+	let config = options.map(ocr_config_from_options).transpose()?;
+	OCR_MODELS.configure(config);
+	Ok(())
+}
+
+/// Set the ONNX models text is extracted with, or remove them with null
+/// The dictionary must match the recognition model, which outputs one class per character
+#[cfg(feature = "node")]
+#[napi]
+pub fn configure_ocr_models(options: Option<OcrModelOptions>) -> napi::Result<(), ErrorCode> {
+	configure_ocr_models_internal(options).map_err(napi::Error::from)
+}
+
+/// Line of text found in a photo
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+	pub text: String,
+	/// Mean probability of the recognized characters (0-1)
+	pub confidence: f64,
+	/// Bounding box in pixels of the upright photo
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+/// Text found in a photo
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ExtractedText {
+	/// All lines in reading order, lines of a row separated by spaces and rows by newlines
+	pub text: String,
+	pub lines: Vec<TextLine>,
+}
+
+/// Text region of the detection map, in detection pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+	x: f32,
+	y: f32,
+	width: f32,
+	height: f32,
+}
+
+/// Model input of an RGB image in NCHW order, with channels in the BGR order
+/// PaddleOCR models are trained with
+fn bgr_planes(rgb: &RgbImage, mean: [f32; 3], std: [f32; 3]) -> Vec<f32> {
+	let plane = (rgb.width() * rgb.height()) as usize;
+	let mut values = vec![0.0; 3 * plane];
+	for (i, pixel) in rgb.pixels().enumerate() {
+		for c in 0..3 {
+			values[c * plane + i] = (pixel[2 - c] as f32 / 255.0 - mean[c]) / std[c];
+		}
+	}
+	values
+}
+
+/// Size of the detection input: at most `DET_MAX_SIDE`, both sides multiples of 32
+fn detection_size(width: u32, height: u32) -> (u32, u32) {
+	let scale = (DET_MAX_SIDE as f32 / width.max(height) as f32).min(1.0);
+	let round = |side: u32| ((side as f32 * scale / 32.0).round() as u32).max(1) * 32;
+	(round(width), round(height))
+}
+
+/// Text regions of a detection probability map: connected text pixels, grown back to
+/// the full text size
+fn text_regions(probs: &[f32], width: usize, height: usize) -> Vec<Region> {
+	let mut visited = vec![false; probs.len()];
+	let mut stack = Vec::new();
+	let mut regions = Vec::new();
+
+	for start in 0..probs.len() {
+		if visited[start] || probs[start] < DET_THRESHOLD {
+			continue;
+		}
+		visited[start] = true;
+		stack.push(start);
+		let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+		let (mut sum, mut count) = (0.0, 0);
+		while let Some(i) = stack.pop() {
+			let (x, y) = (i % width, i / width);
+			x0 = x0.min(x);
+			y0 = y0.min(y);
+			x1 = x1.max(x + 1);
+			y1 = y1.max(y + 1);
+			sum += probs[i];
+			count += 1;
+			let neighbors = [
+				(x > 0).then(|| i - 1),
+				(x + 1 < width).then(|| i + 1),
+				(y > 0).then(|| i - width),
+				(y + 1 < height).then(|| i + width),
+			];
+			for j in neighbors.into_iter().flatten() {
+				if !visited[j] && probs[j] >= DET_THRESHOLD {
+					visited[j] = true;
+					stack.push(j);
+				}
+			}
+		}
+
+		let (w, h) = (x1 - x0, y1 - y0);
+		if sum / (count as f32) < DET_BOX_THRESHOLD || w.min(h) < DET_MIN_SIDE {
+			continue;
+		}
+		// Grow by the distance DB uses to unclip its shrunk polygons
+		let (w, h) = (w as f32, h as f32);
+		let distance = w * h * DET_UNCLIP_RATIO / (2.0 * (w + h));
+		regions.push(Region {
+			x: x0 as f32 - distance,
+			y: y0 as f32 - distance,
+			width: w + 2.0 * distance,
+			height: h + 2.0 * distance,
+		});
+	}
+	regions
+}
+
+/// Greedy CTC decoding of per-step class probabilities: class 0 is the blank, class i
+/// the dictionary's character i - 1 and the one past the dictionary a space
+/// Returns the text and the mean probability of its characters
+fn ctc_decode(probs: &[f32], classes: usize, dictionary: &[String]) -> (String, f32) {
+	let mut text = String::new();
+	let mut confidences = Vec::new();
+	let mut previous = 0;
+	for step in probs.chunks_exact(classes) {
+		let (index, prob) = step
+			.iter()
+			.copied()
+			.enumerate()
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+			.unwrap_or((0, 0.0));
+		if index != 0 && index != previous {
+			text.push_str(dictionary.get(index - 1).map_or(" ", String::as_str));
+			confidences.push(prob);
+		}
+		previous = index;
+	}
+	let confidence = if confidences.is_empty() {
+		0.0
+	} else {
+		confidences.iter().sum::<f32>() / confidences.len() as f32
+	};
+	(text, confidence)
+}
+
+/// Recognize the text of a cropped line
+fn recognize_line(
+	session: &Session,
+	line: &DynamicImage,
+	dictionary: &[String],
+) -> Result<(String, f32), ProcessingError> {
+	let ratio = line.width() as f32 / line.height().max(1) as f32;
+	let width = ((REC_HEIGHT as f32 * ratio).ceil() as u32).clamp(REC_MIN_WIDTH, REC_MAX_WIDTH);
+	let rgb = line
+		.resize_exact(width, REC_HEIGHT, FilterType::Triangle)
+		.to_rgb8();
+	let shape = [1, 3, REC_HEIGHT as usize, width as usize];
+	let (output_shape, probs) =
+		run_onnx_session(session, shape, bgr_planes(&rgb, REC_MEAN, REC_STD))?;
+	let classes = output_shape
+		.last()
+		.map(|&classes| classes as usize)
+		.filter(|&classes| classes > 0)
+		.ok_or_else(|| ProcessingError::invalid("Unexpected recognition output"))?;
+	Ok(ctc_decode(&probs, classes, dictionary))
+}
+
+/// Whether two lines start within half a line height of each other
+fn same_row(a: &TextLine, b: &TextLine) -> bool {
+	a.y.abs_diff(b.y) < a.height.min(b.height) / 2
+}
+
+/// Sort lines top to bottom, and left to right within a row
+fn sort_reading_order(lines: &mut [TextLine]) {
+	lines.sort_by_key(|line| (line.y, line.x));
+	for i in 1..lines.len() {
+		let mut j = i;
+		while j > 0 && same_row(&lines[j - 1], &lines[j]) && lines[j].x < lines[j - 1].x {
+			lines.swap(j - 1, j);
+			j -= 1;
+		}
+	}
+}
+
+/// Text of lines in reading order
+fn joined_text(lines: &[TextLine]) -> String {
+	let mut text = String::new();
+	for (i, line) in lines.iter().enumerate() {
+		if i > 0 {
+			text.push(if same_row(&lines[i - 1], line) {
+				' '
+			} else {
+				'\n'
+			});
+		}
+		text.push_str(&line.text);
+	}
+	text
+}
+
+/// Detect and recognize the text of an upright image with the configured OCR models
+/// Text regions are axis-aligned, so strongly rotated text isn't read
+pub fn recognize_text(img: &DynamicImage) -> Result<ExtractedText, ProcessingError> {
+	let (config, models) = OCR_MODELS.get(load_ocr_models)?;
+	let sessions = models.lock().unwrap_or_else(|e| e.into_inner());
+
+	let (det_width, det_height) = detection_size(img.width(), img.height());
+	let resized = img
+		.resize_exact(det_width, det_height, FilterType::Triangle)
+		.to_rgb8();
+	let shape = [1, 3, det_height as usize, det_width as usize];
+	let (_, probs) = run_onnx_session(
+		&sessions.detection,
+		shape,
+		bgr_planes(&resized, DET_MEAN, DET_STD),
+	)
+	.map_err(|e| e.context("Text detection error"))?;
+	if probs.len() != (det_width * det_height) as usize {
+		return Err(ProcessingError::invalid(
+			"Text detection output doesn't match its input",
+		));
+	}
+
+	let scale_x = img.width() as f32 / det_width as f32;
+	let scale_y = img.height() as f32 / det_height as f32;
+	let mut lines = Vec::new();
+	for region in text_regions(&probs, det_width as usize, det_height as usize) {
+		let x0 = (region.x * scale_x).clamp(0.0, img.width() as f32) as u32;
+		let y0 = (region.y * scale_y).clamp(0.0, img.height() as f32) as u32;
+		let x1 = ((region.x + region.width) * scale_x).clamp(0.0, img.width() as f32) as u32;
+		let y1 = ((region.y + region.height) * scale_y).clamp(0.0, img.height() as f32) as u32;
+		if x1 <= x0 || y1 <= y0 {
+			continue;
+		}
+
+		let crop = img.crop_imm(x0, y0, x1 - x0, y1 - y0);
+		// Vertical text is read rotated into a horizontal line
+		let crop = if crop.height() as f32 >= crop.width() as f32 * 1.5 {
+			crop.rotate270()
+		} else {
+			crop
+		};
+		let (text, confidence) = recognize_line(&sessions.recognition, &crop, &config.dictionary)
+			.map_err(|e| e.context("Text recognition error"))?;
+		let text = text.trim();
+		if confidence >= MIN_LINE_CONFIDENCE && !text.is_empty() {
+			lines.push(TextLine {
+				text: text.to_string(),
+				confidence: confidence as f64,
+				x: x0,
+				y: y0,
+				width: x1 - x0,
+				height: y1 - y0,
+			});
+		}
+	}
+
+	sort_reading_order(&mut lines);
+	Ok(ExtractedText {
+		text: joined_text(&lines),
+		lines,
+	})
+}
+
+/// Recognize the text of a photo, see `extract_text`
+pub fn extract_text_internal(file_path: &str) -> Result<ExtractedText, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	recognize_text(&img)
+}
+
+/// Extract the text of a photo (screenshots, receipts, whiteboards) with the models set
+/// with `configure_ocr_models`, so it can be searched
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_text(file_path: String) -> napi::Result<ExtractedText, ErrorCode> {
+	extract_text_internal(&file_path).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_text_regions() {
+		// Two blobs on a 12x6 map: a text line and a speck too thin to keep
+		let (width, height) = (12, 6);
+		let mut probs = vec![0.0; width * height];
+		for y in 1..5 {
+			for x in 1..8 {
+				probs[y * width + x] = 0.9;
+			}
+		}
+		probs[2 * width + 10] = 0.9;
+
+		let regions = text_regions(&probs, width, height);
+		assert_eq!(regions.len(), 1);
+		let region = regions[0];
+		assert!(region.x < 1.0 && region.y < 1.0);
+		assert!(region.width > 7.0 && region.height > 4.0);
+		assert_eq!(detection_size(1920, 1080), (960, 544));
+	}
+
+	#[test]
+	fn test_ctc_decode_and_reading_order() {
+		let dictionary: Vec<String> = ["a", "b"].iter().map(|c| c.to_string()).collect();
+		// Steps: a, a (repeat), blank, a, b, space
+		let probs = [
+			0.1, 0.8, 0.1, 0.0, //
+			0.1, 0.8, 0.1, 0.0, //
+			0.9, 0.05, 0.05, 0.0, //
+			0.1, 0.7, 0.2, 0.0, //
+			0.1, 0.1, 0.8, 0.0, //
+			0.1, 0.0, 0.0, 0.9,
+		];
+		let (text, confidence) = ctc_decode(&probs, 4, &dictionary);
+		assert_eq!(text, "aab ");
+		assert!(confidence > 0.7);
+
+		let line = |text: &str, x, y| TextLine {
+			text: text.to_string(),
+			confidence: 1.0,
+			x,
+			y,
+			width: 50,
+			height: 20,
+		};
+		let mut lines = vec![
+			line("total", 10, 52),
+			line("12.50", 200, 48),
+			line("receipt", 10, 5),
+		];
+		sort_reading_order(&mut lines);
+		assert_eq!(joined_text(&lines), "receipt\ntotal 12.50");
+	}
+}
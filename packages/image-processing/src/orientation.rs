@@ -1,4 +1,12 @@
-use image::DynamicImage;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+
+use crate::decode::{decode_image, source_kind, SourceKind};
+use crate::error::{ErrorCode, ProcessingError};
+use crate::exif::extract_exif_internal;
+use crate::Buffer;
 
 /// Apply EXIF orientation to an image
 /// Orientation values follow EXIF specification:
@@ -6,19 +14,251 @@ use image::DynamicImage;
 /// 2 = Flip horizontal
 /// 3 = Rotate 180
 /// 4 = Flip vertical
-/// 5 = Rotate 270 CW + flip horizontal
+/// 5 = Transpose (rotate 90 CW + flip horizontal)
 /// 6 = Rotate 90 CW
-/// 7 = Rotate 90 CW + flip horizontal
+/// 7 = Transverse (rotate 270 CW + flip horizontal)
 /// 8 = Rotate 270 CW
 pub fn apply_orientation(img: DynamicImage, orientation: Option<u32>) -> DynamicImage {
 	match orientation {
 		Some(2) => img.fliph(),
 		Some(3) => img.rotate180(),
 		Some(4) => img.flipv(),
-		Some(5) => img.rotate270().fliph(),
+		Some(5) => img.rotate90().fliph(),
 		Some(6) => img.rotate90(),
-		Some(7) => img.rotate90().fliph(),
+		Some(7) => img.rotate270().fliph(),
 		Some(8) => img.rotate270(),
 		_ => img,
 	}
 }
+
+/// Read the EXIF orientation natively from the image container (JPEG, TIFF, WebP, PNG)
+/// Used when exiftool is unavailable or returned no orientation
+pub fn read_native_orientation(file_path: &str) -> Option<u32> {
+	let mut decoder = ImageReader::open(file_path)
+		.ok()?
+		.with_guessed_format()
+		.ok()?
+		.into_decoder()
+		.ok()?;
+	decoder.orientation().ok().map(|o| o.to_exif() as u32)
+}
+
+/// Resolve the orientation that still has to be applied after decoding
+/// libheif already applies the irot/imir transforms and ffmpeg applies video rotation,
+/// so HEIF and video output is upright
+pub fn resolve_orientation(
+	file_path: &str,
+	kind: SourceKind,
+	exif_orientation: Option<u32>,
+) -> Option<u32> {
+	match kind {
+		SourceKind::Heif | SourceKind::Video => None,
+		SourceKind::RawPreview | SourceKind::Standard => {
+			exif_orientation.or_else(|| read_native_orientation(file_path))
+		}
+	}
+}
+
+/// Upright version of a photo
+#[cfg_attr(feature = "node", napi(object))]
+pub struct NormalizedImage {
+	/// EXIF orientation that was applied (1 when the pixels were already upright)
+	pub orientation: u32,
+	pub width: u32,
+	pub height: u32,
+	/// JPEG-encoded upright image
+	pub data: Buffer,
+}
+
+/// Decode a photo and return it upright, see `normalize_orientation`
+pub fn normalize_orientation_internal(file_path: &str) -> Result<NormalizedImage, ProcessingError> {
+	let kind = source_kind(file_path).ok_or_else(|| ProcessingError::unsupported(file_path))?;
+
+	let img = decode_image(file_path, kind)?;
+
+	let exif_orientation = extract_exif_internal(file_path).and_then(|e| e.orientation);
+	let orientation = resolve_orientation(file_path, kind, exif_orientation);
+	let img = apply_orientation(img, orientation);
+
+	let rgb = img.to_rgb8();
+	let mut data = Vec::new();
+	JpegEncoder::new_with_quality(&mut data, 92)
+		.encode_image(&rgb)
+		.map_err(|e| {
+			let message = format!("Failed to encode image: {}", e);
+			ProcessingError::new(ErrorCode::Internal, message)
+		})?;
+
+	Ok(NormalizedImage {
+		orientation: orientation.filter(|o| (1..=8).contains(o)).unwrap_or(1),
+		width: rgb.width(),
+		height: rgb.height(),
+		data: data.into(),
+	})
+}
+
+/// Decode a photo with the matching decoder and return it rotated/flipped upright
+#[cfg(feature = "node")]
+#[napi]
+pub fn normalize_orientation(file_path: String) -> napi::Result<NormalizedImage, ErrorCode> {
+	normalize_orientation_internal(&file_path).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::decode::decode_bytes;
+	use image::metadata::Orientation;
+	use image::{ImageEncoder, Rgb, RgbImage};
+	use proptest::prelude::*;
+	use std::io::Write;
+	use tempfile::Builder;
+
+	/// Encode a pixel coordinate as a unique color
+	fn coord_image(width: u32, height: u32) -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+			Rgb([x as u8, y as u8, 0])
+		}))
+	}
+
+	/// Where a stored pixel ends up on screen, per the EXIF specification
+	fn expected_position(orientation: u32, x: u32, y: u32, w: u32, h: u32) -> (u32, u32) {
+		match orientation {
+			2 => (w - 1 - x, y),
+			3 => (w - 1 - x, h - 1 - y),
+			4 => (x, h - 1 - y),
+			5 => (y, x),
+			6 => (h - 1 - y, x),
+			7 => (h - 1 - y, w - 1 - x),
+			8 => (y, w - 1 - x),
+			_ => (x, y),
+		}
+	}
+
+	/// Minimal little-endian TIFF chunk holding only an Orientation tag
+	fn exif_chunk(orientation: u16) -> Vec<u8> {
+		let mut chunk = b"II*\0".to_vec();
+		chunk.extend_from_slice(&8u32.to_le_bytes());
+		chunk.extend_from_slice(&1u16.to_le_bytes());
+		chunk.extend_from_slice(&0x0112u16.to_le_bytes());
+		chunk.extend_from_slice(&3u16.to_le_bytes());
+		chunk.extend_from_slice(&1u32.to_le_bytes());
+		chunk.extend_from_slice(&orientation.to_le_bytes());
+		chunk.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+		chunk
+	}
+
+	/// Non-square image made of four solid quadrants so JPEG artifacts don't matter
+	fn quadrant_image() -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(64, 32, |x, y| match (x < 32, y < 16) {
+			(true, true) => Rgb([255, 0, 0]),
+			(false, true) => Rgb([0, 255, 0]),
+			(true, false) => Rgb([0, 0, 255]),
+			(false, false) => Rgb([255, 255, 255]),
+		}))
+	}
+
+	fn jpeg_with_orientation(img: &DynamicImage, orientation: u16) -> Vec<u8> {
+		let rgb = img.to_rgb8();
+		let mut data = Vec::new();
+		let mut encoder = JpegEncoder::new_with_quality(&mut data, 95);
+		encoder.set_exif_metadata(exif_chunk(orientation)).unwrap();
+		encoder
+			.write_image(
+				rgb.as_raw(),
+				rgb.width(),
+				rgb.height(),
+				image::ExtendedColorType::Rgb8,
+			)
+			.unwrap();
+		data
+	}
+
+	fn assert_same_quadrants(actual: &DynamicImage, expected: &DynamicImage) {
+		assert_eq!(actual.width(), expected.width());
+		assert_eq!(actual.height(), expected.height());
+		let (actual, expected) = (actual.to_rgb8(), expected.to_rgb8());
+		let (w, h) = (expected.width(), expected.height());
+		let samples = [
+			(w / 4, h / 4),
+			(3 * w / 4, h / 4),
+			(w / 4, 3 * h / 4),
+			(3 * w / 4, 3 * h / 4),
+		];
+		for (x, y) in samples {
+			let (a, e) = (actual.get_pixel(x, y), expected.get_pixel(x, y));
+			for c in 0..3 {
+				assert!(
+					(a[c] as i32 - e[c] as i32).abs() < 40,
+					"pixel ({}, {}) differs",
+					x,
+					y
+				);
+			}
+		}
+	}
+
+	proptest! {
+		#[test]
+		fn prop_orientation_matches_exif_spec(w in 1u32..16, h in 1u32..16, orientation in 1u32..=8) {
+			let oriented = apply_orientation(coord_image(w, h), Some(orientation)).to_rgb8();
+			for y in 0..h {
+				for x in 0..w {
+					let (ex, ey) = expected_position(orientation, x, y, w, h);
+					prop_assert_eq!(oriented.get_pixel(ex, ey), &Rgb([x as u8, y as u8, 0]));
+				}
+			}
+		}
+
+		#[test]
+		fn prop_orientation_matches_image_crate(w in 1u32..16, h in 1u32..16, orientation in 1u8..=8) {
+			let mut reference = coord_image(w, h);
+			reference.apply_orientation(Orientation::from_exif(orientation).unwrap());
+			let oriented = apply_orientation(coord_image(w, h), Some(orientation as u32));
+			prop_assert_eq!(oriented.to_rgb8(), reference.to_rgb8());
+		}
+	}
+
+	#[test]
+	fn test_jpeg_decoder_reads_and_applies_orientation() {
+		let source = quadrant_image();
+		for orientation in 1u16..=8 {
+			let mut file = Builder::new().suffix(".jpg").tempfile().unwrap();
+			file.write_all(&jpeg_with_orientation(&source, orientation))
+				.unwrap();
+			file.flush().unwrap();
+			let path = file.path().to_str().unwrap();
+
+			let kind = source_kind(path).unwrap();
+			assert_eq!(kind, SourceKind::Standard);
+			let resolved = resolve_orientation(path, kind, None);
+			assert_eq!(resolved, Some(orientation as u32));
+
+			let decoded = apply_orientation(decode_image(path, kind).unwrap(), resolved);
+			let expected = apply_orientation(source.clone(), Some(orientation as u32));
+			assert_same_quadrants(&decoded, &expected);
+		}
+	}
+
+	#[test]
+	fn test_raw_preview_bytes_use_container_orientation() {
+		// RAW previews are stored unrotated; orientation comes from the RAW container's EXIF
+		let source = quadrant_image();
+		let preview = jpeg_with_orientation(&source, 1);
+		for orientation in 1u32..=8 {
+			let decoded = apply_orientation(decode_bytes(&preview).unwrap(), Some(orientation));
+			let expected = apply_orientation(source.clone(), Some(orientation));
+			assert_same_quadrants(&decoded, &expected);
+		}
+	}
+
+	#[test]
+	fn test_heif_orientation_is_applied_by_decoder() {
+		for orientation in 1u32..=8 {
+			assert_eq!(
+				resolve_orientation("photo.heic", SourceKind::Heif, Some(orientation)),
+				None
+			);
+		}
+	}
+}
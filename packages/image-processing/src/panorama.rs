@@ -0,0 +1,135 @@
+use crate::exif::ExifData;
+
+/// Long edge / short edge ratio above which a photo counts as a panorama
+pub(crate) const PANORAMA_ASPECT_RATIO: f64 = 2.5;
+
+/// Most tiles a panorama is split into for embedding
+const MAX_EMBEDDING_TILES: u32 = 8;
+
+/// Share of the full 360° an equirectangular image must cover to be shown as a photosphere
+const PHOTOSPHERE_MIN_COVERAGE: f64 = 0.95;
+
+/// How a photo should be displayed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PanoramaInfo {
+	pub is_panorama: bool,
+	/// Full 360° equirectangular image (needs a spherical viewer)
+	pub is_photosphere: bool,
+	/// GPano projection, e.g. "equirectangular" or "cylindrical"
+	pub projection_type: Option<String>,
+}
+
+/// Detect panoramas from GPano XMP metadata, falling back to extreme aspect ratios
+pub fn detect_panorama(width: u32, height: u32, exif: Option<&ExifData>) -> PanoramaInfo {
+	let projection_type = exif
+		.and_then(|e| e.projection_type.as_deref())
+		.map(|p| p.trim().to_lowercase())
+		.filter(|p| !p.is_empty());
+	let coverage = exif.and_then(|e| e.pano_coverage);
+
+	let aspect_ratio = aspect_ratio(width, height);
+
+	let is_photosphere = projection_type.as_deref() == Some("equirectangular")
+		&& match coverage {
+			Some(coverage) => coverage >= PHOTOSPHERE_MIN_COVERAGE,
+			// Without cropping info a full sphere is exactly 2:1
+			None => width > height && (aspect_ratio - 2.0).abs() < 0.02,
+		};
+
+	PanoramaInfo {
+		is_panorama: is_photosphere
+			|| projection_type.is_some()
+			|| aspect_ratio >= PANORAMA_ASPECT_RATIO,
+		is_photosphere,
+		projection_type,
+	}
+}
+
+/// Long edge / short edge ratio of an image
+pub fn aspect_ratio(width: u32, height: u32) -> f64 {
+	width.max(height) as f64 / width.min(height).max(1) as f64
+}
+
+/// Regions (x, y, width, height) a panorama is embedded as: squares along its long side,
+/// overlapping to cover it evenly, so CLIP's square center crop doesn't drop most of it
+/// Past `MAX_EMBEDDING_TILES` squares the tiles stretch instead; other images are one region
+pub fn embedding_tiles(width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+	if width.min(height) == 0 || aspect_ratio(width, height) < PANORAMA_ASPECT_RATIO {
+		return vec![(0, 0, width, height)];
+	}
+
+	let (long, short) = (width.max(height), width.min(height));
+	let count = long.div_ceil(short).min(MAX_EMBEDDING_TILES);
+	let tile_long = long.div_ceil(count).max(short);
+	(0..count)
+		.map(|i| {
+			let offset = (long - tile_long) as u64 * i as u64 / (count - 1) as u64;
+			let offset = offset as u32;
+			if width >= height {
+				(offset, 0, tile_long, short)
+			} else {
+				(0, offset, short, tile_long)
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn gpano(projection: &str, coverage: Option<f64>) -> ExifData {
+		ExifData {
+			projection_type: Some(projection.to_string()),
+			pano_coverage: coverage,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_detects_photospheres_and_wide_panoramas() {
+		let sphere = detect_panorama(8192, 4096, Some(&gpano("equirectangular", None)));
+		assert!(sphere.is_panorama && sphere.is_photosphere);
+
+		// Partial sphere from a phone panorama mode
+		let partial = detect_panorama(9000, 2000, Some(&gpano("equirectangular", Some(0.5))));
+		assert!(partial.is_panorama && !partial.is_photosphere);
+
+		// Stitched without metadata
+		let wide = detect_panorama(12000, 3000, None);
+		assert_eq!(
+			wide,
+			PanoramaInfo {
+				is_panorama: true,
+				is_photosphere: false,
+				projection_type: None,
+			}
+		);
+
+		assert!(!detect_panorama(4032, 3024, None).is_panorama);
+	}
+
+	#[test]
+	fn test_embedding_tiles() {
+		assert_eq!(embedding_tiles(4032, 3024), [(0, 0, 4032, 3024)]);
+		assert_eq!(
+			embedding_tiles(2600, 1000),
+			[
+				(0, 0, 1000, 1000),
+				(800, 0, 1000, 1000),
+				(1600, 0, 1000, 1000)
+			]
+		);
+		assert_eq!(
+			embedding_tiles(1000, 3000),
+			[
+				(0, 0, 1000, 1000),
+				(0, 1000, 1000, 1000),
+				(0, 2000, 1000, 1000)
+			]
+		);
+		let strip = embedding_tiles(20000, 1000);
+		assert_eq!(strip.len(), 8);
+		assert_eq!(strip[7], (17500, 0, 2500, 1000));
+	}
+}
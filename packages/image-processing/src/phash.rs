@@ -1,13 +1,41 @@
-use image::{DynamicImage, ImageReader};
-use image_hasher::{HashAlg, HasherConfig};
+use image::DynamicImage;
+use image_hasher::{HashAlg, HasherConfig, ImageHash};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::AsyncTask;
+#[cfg(feature = "node")]
+use napi::{Env, Task};
+#[cfg(feature = "node")]
 use napi_derive::napi;
+use rayon::prelude::*;
+use std::collections::HashMap;
 
-#[napi]
-pub fn perceptual_hash(file_path: String) -> napi::Result<String> {
-  let img = ImageReader::open(&file_path)
-    .map_err(|e| napi::Error::from_reason(format!("Failed to open image: {}", e)))?
-    .decode()
-    .map_err(|e| napi::Error::from_reason(format!("Failed to decode image: {}", e)))?;
+use crate::batch::DEFAULT_MAX_CONCURRENT;
+use crate::cancellation::{is_cancelled, CancellationToken};
+use crate::color::convert_to_srgb;
+use crate::decode::{decode_bytes, decode_image_with_profile, SourceKind};
+#[cfg(feature = "node")]
+use crate::error::reject_with_code;
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::runtime::rayon_threads;
+use crate::video::{extract_frame, probe_video};
+#[cfg(feature = "node")]
+use crate::Buffer;
+
+/// Central crops hashed by the robust hash, as a fraction of each side
+const ROBUST_CROPS: [f32; 3] = [0.9, 0.8, 0.7];
+
+/// Separator of the component hashes of a robust hash (not part of the base64 alphabet)
+const ROBUST_SEPARATOR: char = '.';
+
+/// Decode an image file, converted to sRGB so wide-gamut copies hash like sRGB ones
+fn open_image(file_path: &str) -> Result<DynamicImage, ProcessingError> {
+  let (img, profile) = decode_image_with_profile(file_path, SourceKind::Standard)?;
+  Ok(convert_to_srgb(img, profile.as_ref()))
+}
+
+/// Perceptual hash of an image file, see `perceptual_hash`
+pub fn perceptual_hash_internal(file_path: &str) -> Result<String, ProcessingError> {
+  let img = open_image(file_path)?;
 
   // Create hasher with DCT-based perceptual hash (pHash)
   let hasher = HasherConfig::new()
@@ -21,19 +49,630 @@ pub fn perceptual_hash(file_path: String) -> napi::Result<String> {
   Ok(hash.to_base64())
 }
 
-pub fn generate_phash_from_image(img: &DynamicImage) -> String {
+#[cfg(feature = "node")]
+#[napi]
+pub fn perceptual_hash(file_path: String) -> napi::Result<String, ErrorCode> {
+  perceptual_hash_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Hashes one photo off the JS thread
+#[cfg(feature = "node")]
+pub struct PerceptualHashTask {
+  file_path: String,
+}
+
+#[cfg(feature = "node")]
+impl Task for PerceptualHashTask {
+  type Output = String;
+  type JsValue = String;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    perceptual_hash_internal(&self.file_path).map_err(napi::Error::from)
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    Ok(output)
+  }
+
+  fn reject(&mut self, env: Env, err: napi::Error) -> napi::Result<Self::JsValue> {
+    reject_with_code(env, err)
+  }
+}
+
+/// Same as `perceptual_hash`, off the JS thread
+#[cfg(feature = "node")]
+#[napi]
+pub fn perceptual_hash_async(file_path: String) -> AsyncTask<PerceptualHashTask> {
+  AsyncTask::new(PerceptualHashTask { file_path })
+}
+
+/// 8×8 hash of an image with the given algorithm
+fn image_hash(img: &DynamicImage, alg: HashAlg) -> ImageHash {
   let hasher = HasherConfig::new()
-    .hash_alg(HashAlg::DoubleGradient)
+    .hash_alg(alg)
     .hash_size(8, 8)
     .to_hasher();
 
-  let hash = hasher.hash_image(img);
-  hash.to_base64()
+  hasher.hash_image(img)
+}
+
+/// 8×8 hash of an image with the given algorithm, as base64
+fn hash_with(img: &DynamicImage, alg: HashAlg) -> String {
+  image_hash(img, alg).to_base64()
+}
+
+pub fn generate_phash_from_image(img: &DynamicImage) -> String {
+  hash_with(img, HashAlg::DoubleGradient)
+}
+
+/// Hash many photos in parallel, e.g. to re-hash the library after changing hash settings
+/// Runs on its own pool of `max_concurrent` threads (default: the `configure_runtime`
+/// threads, else 4; at most one per core)
+/// so the rest of the app stays responsive
+/// Returns hashes in input order, null for files that fail to decode
+#[cfg_attr(feature = "node", napi)]
+pub fn perceptual_hash_batch(
+  file_paths: Vec<String>,
+  max_concurrent: Option<u32>,
+) -> Vec<Option<String>> {
+  hash_batch(&file_paths, max_concurrent, None)
+}
+
+/// Hash photos in parallel on a pool of `max_concurrent` threads, see `perceptual_hash_batch`
+fn hash_batch(
+  file_paths: &[String],
+  max_concurrent: Option<u32>,
+  cancellation: Option<&CancellationToken>,
+) -> Vec<Option<String>> {
+  let threads = max_concurrent
+    .map(|n| n.max(1) as usize)
+    .unwrap_or_else(|| rayon_threads().unwrap_or(DEFAULT_MAX_CONCURRENT))
+    .min(num_cpus::get());
+  let hash_all = || {
+    file_paths
+      .par_iter()
+      .map(|file_path| {
+        if is_cancelled(cancellation) {
+          return None;
+        }
+        open_image(file_path)
+          .ok()
+          .map(|img| generate_phash_from_image(&img))
+      })
+      .collect()
+  };
+
+  match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+    Ok(pool) => pool.install(hash_all),
+    Err(_) => hash_all(),
+  }
+}
+
+/// Hashes many photos off the JS thread
+#[cfg(feature = "node")]
+pub struct PerceptualHashBatchTask {
+  file_paths: Vec<String>,
+  max_concurrent: Option<u32>,
+  cancellation: Option<CancellationToken>,
+}
+
+#[cfg(feature = "node")]
+impl Task for PerceptualHashBatchTask {
+  type Output = Vec<Option<String>>;
+  type JsValue = Vec<Option<String>>;
+
+  fn compute(&mut self) -> napi::Result<Self::Output> {
+    Ok(hash_batch(
+      &self.file_paths,
+      self.max_concurrent,
+      self.cancellation.as_ref(),
+    ))
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+/// Same as `perceptual_hash_batch`, off the JS thread
+/// Files not reached once `cancellation` is cancelled get null
+#[cfg(feature = "node")]
+#[napi]
+pub fn perceptual_hash_batch_async(
+  file_paths: Vec<String>,
+  max_concurrent: Option<u32>,
+  cancellation: Option<&CancellationToken>,
+) -> AsyncTask<PerceptualHashBatchTask> {
+  AsyncTask::new(PerceptualHashBatchTask {
+    file_paths,
+    max_concurrent,
+    cancellation: cancellation.cloned(),
+  })
+}
+
+/// Frames hashed by `video_phash`, evenly spaced through the clip
+const VIDEO_HASH_FRAMES: usize = 8;
+
+/// Width video frames are scaled down to before hashing, far more than an 8×8 hash needs
+const VIDEO_FRAME_WIDTH: u32 = 256;
+
+/// Perceptual hash of a video, see `video_phash`
+pub fn video_phash_internal(file_path: &str) -> Result<String, ProcessingError> {
+  let decode_error = |message: String| {
+    ProcessingError::new(ErrorCode::DecodeFailed, message)
+      .at(ErrorStage::Decode)
+      .for_file(file_path)
+  };
+  let duration = probe_video(file_path)
+    .and_then(|probe| probe.duration)
+    .filter(|duration| *duration > 0.0)
+    .ok_or_else(|| decode_error("Failed to read video duration".to_string()))?;
+
+  let frame_hashes = (0..VIDEO_HASH_FRAMES)
+    .into_par_iter()
+    .map(|i| {
+      let seek = duration * (i as f64 + 0.5) / VIDEO_HASH_FRAMES as f64;
+      let frame = extract_frame(file_path, seek, Some(VIDEO_FRAME_WIDTH))
+        .and_then(|data| decode_bytes(&data).ok())
+        .ok_or_else(|| decode_error(format!("Failed to extract frame at {:.1}s", seek)))?;
+      let hash = image_hash(&frame, HashAlg::DoubleGradient);
+      Ok(hash.as_bytes().to_vec())
+    })
+    .collect::<Result<Vec<_>, ProcessingError>>()?;
+
+  ImageHash::<Box<[u8]>>::from_bytes(&frame_hashes.concat())
+    .map(|hash| hash.to_base64())
+    .map_err(|e| {
+      ProcessingError::new(
+        ErrorCode::Internal,
+        format!("Failed to encode video hash: {:?}", e),
+      )
+    })
+}
+
+/// Perceptual hash of a video: the hashes of evenly spaced frames, concatenated
+/// Re-encoded, resized or re-muxed copies and duplicate screen recordings stay within a
+/// few bits per frame; compare with `phash_distance` or `find_near_duplicates`, scaling
+/// photo thresholds by the 8 frames
+#[cfg(feature = "node")]
+#[napi]
+pub fn video_phash(file_path: String) -> napi::Result<String, ErrorCode> {
+  video_phash_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Hashes of every family, for the different duplicate detection strategies
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ImageHashes {
+  /// Average hash: which pixels are brighter than the mean
+  pub ahash: String,
+  /// Difference hash: whether each pixel is brighter than its right neighbour
+  pub dhash: String,
+  /// Same as `perceptual_hash`
+  pub phash: String,
+}
+
+/// Compute the average, difference and perceptual hashes of an image, see `compute_hashes`
+pub fn compute_hashes_internal(file_path: &str) -> Result<ImageHashes, ProcessingError> {
+  let img = open_image(file_path)?;
+  Ok(ImageHashes {
+    ahash: hash_with(&img, HashAlg::Mean),
+    dhash: hash_with(&img, HashAlg::Gradient),
+    phash: generate_phash_from_image(&img),
+  })
+}
+
+/// Compute the average, difference and perceptual hashes of an image, decoding it once
+/// since decoding dominates the cost
+#[cfg(feature = "node")]
+#[napi]
+pub fn compute_hashes(file_path: String) -> napi::Result<ImageHashes, ErrorCode> {
+  compute_hashes_internal(&file_path).map_err(napi::Error::from)
 }
 
 /// Generate perceptual hash from a file path
 /// Alias for perceptual_hash with a more consistent naming scheme
+#[cfg(feature = "node")]
 #[napi]
-pub fn generate_phash(file_path: String) -> napi::Result<String> {
+pub fn generate_phash(file_path: String) -> napi::Result<String, ErrorCode> {
   perceptual_hash(file_path)
 }
+
+/// Generate perceptual hash from encoded image bytes (JPEG, PNG, WebP, HEIC, ...)
+/// For extracted RAW previews, Live Photo stills and streamed files, without a temp file
+#[cfg(feature = "node")]
+#[napi]
+pub fn perceptual_hash_from_buffer(data: Buffer) -> napi::Result<String, ErrorCode> {
+  let img = decode_bytes(&data)?;
+  Ok(generate_phash_from_image(&img))
+}
+
+/// Hashes of the full image, its 90°, 180° and 270° rotations, then its central crops
+pub fn generate_robust_phash_from_image(img: &DynamicImage) -> String {
+  let (width, height) = (img.width(), img.height());
+  let crops = ROBUST_CROPS.iter().map(|fraction| {
+    let crop_width = ((width as f32 * fraction).round() as u32).max(1);
+    let crop_height = ((height as f32 * fraction).round() as u32).max(1);
+    img.crop_imm(
+      (width - crop_width) / 2,
+      (height - crop_height) / 2,
+      crop_width,
+      crop_height,
+    )
+  });
+  let variants = [
+    img.clone(),
+    img.rotate90(),
+    img.rotate180(),
+    img.rotate270(),
+  ]
+  .into_iter()
+  .chain(crops);
+
+  variants
+    .map(|variant| generate_phash_from_image(&variant))
+    .collect::<Vec<_>>()
+    .join(&ROBUST_SEPARATOR.to_string())
+}
+
+/// Crop- and rotation-tolerant perceptual hash, see `robust_perceptual_hash`
+pub fn robust_perceptual_hash_internal(file_path: &str) -> Result<String, ProcessingError> {
+  Ok(generate_robust_phash_from_image(&open_image(file_path)?))
+}
+
+/// Crop- and rotation-tolerant perceptual hash, to match rotated copies, lightly cropped
+/// exports and screenshots of a photo; about 7× the size of `perceptual_hash`
+/// Compare with `robust_phash_distance`
+#[cfg(feature = "node")]
+#[napi]
+pub fn robust_perceptual_hash(file_path: String) -> napi::Result<String, ErrorCode> {
+  robust_perceptual_hash_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Smallest distance between the full image of one robust hash and any variant of the
+/// other (a rotation, or a crop matching the other being a crop or a screenshot of it)
+fn robust_distance(a: &[Vec<u8>], b: &[Vec<u8>]) -> Option<u32> {
+  let (full_a, full_b) = (a.first()?, b.first()?);
+  let distances = |full: &Vec<u8>, variants: &[Vec<u8>]| {
+    variants
+      .iter()
+      .filter(|variant| variant.len() == full.len())
+      .map(|variant| hamming(full, variant))
+      .collect::<Vec<_>>()
+  };
+  distances(full_a, b)
+    .into_iter()
+    .chain(distances(full_b, a))
+    .min()
+}
+
+/// Distance between two robust hashes, see `robust_phash_distance`
+pub fn robust_phash_distance_internal(a: &str, b: &str) -> Result<u32, ProcessingError> {
+  let parse = |hash: &str| {
+    hash
+      .split(ROBUST_SEPARATOR)
+      .map(hash_bytes)
+      .collect::<Option<Vec<_>>>()
+      .ok_or_else(|| ProcessingError::invalid(format!("Invalid robust hash: {}", hash)))
+  };
+  robust_distance(&parse(a)?, &parse(b)?)
+    .ok_or_else(|| ProcessingError::invalid("Robust hashes have different lengths"))
+}
+
+/// Distance between two robust hashes from `robust_perceptual_hash`, comparable to
+/// `phash_distance` for unrotated, uncropped copies
+#[cfg(feature = "node")]
+#[napi]
+pub fn robust_phash_distance(a: String, b: String) -> napi::Result<u32, ErrorCode> {
+  robust_phash_distance_internal(&a, &b).map_err(napi::Error::from)
+}
+
+/// Bytes of a base64 hash from `perceptual_hash`, None if it isn't one
+fn hash_bytes(hash: &str) -> Option<Vec<u8>> {
+  ImageHash::<Box<[u8]>>::from_base64(hash)
+    .ok()
+    .map(|hash| hash.as_bytes().to_vec())
+}
+
+/// Number of differing bits of two hashes of the same length
+fn hamming(a: &[u8], b: &[u8]) -> u32 {
+  a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Hamming distance between two perceptual hashes, see `phash_distance`
+pub fn phash_distance_internal(a: &str, b: &str) -> Result<u32, ProcessingError> {
+  let parse = |hash: &str| {
+    hash_bytes(hash)
+      .ok_or_else(|| ProcessingError::invalid(format!("Invalid perceptual hash: {}", hash)))
+  };
+  let (a, b) = (parse(a)?, parse(b)?);
+  if a.len() != b.len() {
+    return Err(ProcessingError::invalid(
+      "Perceptual hashes have different lengths",
+    ));
+  }
+  Ok(hamming(&a, &b))
+}
+
+/// Hamming distance between two perceptual hashes: 0 for identical images, a few bits
+/// for resized or recompressed copies
+#[cfg(feature = "node")]
+#[napi]
+pub fn phash_distance(a: String, b: String) -> napi::Result<u32, ErrorCode> {
+  phash_distance_internal(&a, &b).map_err(napi::Error::from)
+}
+
+struct BkNode<'a> {
+  index: usize,
+  hash: &'a [u8],
+  /// Child nodes, keyed by their distance to this node
+  children: Vec<(u32, usize)>,
+}
+
+/// BK-tree over hashes of the same length, to find every hash within a distance of a
+/// query without comparing against all of them
+struct BkTree<'a> {
+  nodes: Vec<BkNode<'a>>,
+}
+
+impl<'a> BkTree<'a> {
+  fn new(hashes: impl IntoIterator<Item = (usize, &'a [u8])>) -> Self {
+    let mut tree = Self { nodes: Vec::new() };
+    for (index, hash) in hashes {
+      tree.insert(index, hash);
+    }
+    tree
+  }
+
+  fn insert(&mut self, index: usize, hash: &'a [u8]) {
+    let new_node = self.nodes.len();
+    if new_node > 0 {
+      let mut node = 0;
+      loop {
+        let distance = hamming(self.nodes[node].hash, hash);
+        match self.nodes[node]
+          .children
+          .iter()
+          .find(|(d, _)| *d == distance)
+        {
+          Some(&(_, child)) => node = child,
+          None => {
+            self.nodes[node].children.push((distance, new_node));
+            break;
+          }
+        }
+      }
+    }
+    self.nodes.push(BkNode {
+      index,
+      hash,
+      children: Vec::new(),
+    });
+  }
+
+  /// Indices of the hashes within `max_distance` of a hash, with their distance
+  fn find(&self, hash: &[u8], max_distance: u32) -> Vec<(usize, u32)> {
+    let mut matches = Vec::new();
+    let mut stack = if self.nodes.is_empty() {
+      vec![]
+    } else {
+      vec![0]
+    };
+    while let Some(node) = stack.pop() {
+      let node = &self.nodes[node];
+      let distance = hamming(node.hash, hash);
+      if distance <= max_distance {
+        matches.push((node.index, distance));
+      }
+      // Triangle inequality: only subtrees in this distance band can hold matches
+      stack.extend(
+        node
+          .children
+          .iter()
+          .filter(|(d, _)| d.abs_diff(distance) <= max_distance)
+          .map(|&(_, child)| child),
+      );
+    }
+    matches
+  }
+}
+
+/// Two photos whose perceptual hashes are within the searched distance
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearDuplicatePair {
+  /// Positions in the input, `a` < `b`
+  pub a: u32,
+  pub b: u32,
+  pub distance: u32,
+}
+
+/// Every pair of hashes within `max_distance`, closest first
+/// Missing hashes are skipped; only hashes of the same length are compared
+fn near_duplicate_pairs(hashes: &[Option<Vec<u8>>], max_distance: u32) -> Vec<NearDuplicatePair> {
+  let mut by_length: HashMap<usize, Vec<(usize, &[u8])>> = HashMap::new();
+  for (index, hash) in hashes.iter().enumerate() {
+    if let Some(hash) = hash {
+      by_length.entry(hash.len()).or_default().push((index, hash));
+    }
+  }
+
+  let mut pairs: Vec<NearDuplicatePair> = by_length
+    .into_values()
+    .flat_map(|group| {
+      let tree = BkTree::new(group.iter().copied());
+      group
+        .par_iter()
+        .flat_map_iter(|&(a, hash)| {
+          tree
+            .find(hash, max_distance)
+            .into_iter()
+            .filter(move |&(b, _)| b > a)
+            .map(move |(b, distance)| NearDuplicatePair {
+              a: a as u32,
+              b: b as u32,
+              distance,
+            })
+        })
+        .collect::<Vec<_>>()
+    })
+    .collect();
+  pairs.sort_unstable_by_key(|pair| (pair.distance, pair.a, pair.b));
+  pairs
+}
+
+/// Find all pairs of near-duplicate photos among perceptual hashes from `perceptual_hash`
+/// Runs natively and in parallel with a BK-tree; for 300k photos and small distances this
+/// takes seconds rather than the minutes of comparing every pair
+/// Invalid hashes (e.g. empty strings for photos without one) are skipped
+#[cfg_attr(feature = "node", napi)]
+pub fn find_near_duplicates(hashes: Vec<String>, max_distance: u32) -> Vec<NearDuplicatePair> {
+  let hashes: Vec<Option<Vec<u8>>> = hashes.par_iter().map(|hash| hash_bytes(hash)).collect();
+  near_duplicate_pairs(&hashes, max_distance)
+}
+
+/// Union-find over photo indices, with path halving and union by size
+struct DisjointSet {
+  parent: Vec<usize>,
+  size: Vec<usize>,
+}
+
+impl DisjointSet {
+  fn new(len: usize) -> Self {
+    Self {
+      parent: (0..len).collect(),
+      size: vec![1; len],
+    }
+  }
+
+  fn find(&mut self, mut x: usize) -> usize {
+    while self.parent[x] != x {
+      self.parent[x] = self.parent[self.parent[x]];
+      x = self.parent[x];
+    }
+    x
+  }
+
+  fn union(&mut self, a: usize, b: usize) {
+    let (mut a, mut b) = (self.find(a), self.find(b));
+    if a == b {
+      return;
+    }
+    if self.size[a] < self.size[b] {
+      std::mem::swap(&mut a, &mut b);
+    }
+    self.parent[b] = a;
+    self.size[a] += self.size[b];
+  }
+}
+
+/// Connected components of the near-duplicate graph with at least two photos, each
+/// sorted by index, largest first
+fn near_duplicate_clusters(hashes: &[Option<Vec<u8>>], max_distance: u32) -> Vec<Vec<u32>> {
+  let mut set = DisjointSet::new(hashes.len());
+  for pair in near_duplicate_pairs(hashes, max_distance) {
+    set.union(pair.a as usize, pair.b as usize);
+  }
+
+  let mut clusters: HashMap<usize, Vec<u32>> = HashMap::new();
+  for index in 0..hashes.len() {
+    let root = set.find(index);
+    clusters.entry(root).or_default().push(index as u32);
+  }
+  let mut clusters: Vec<Vec<u32>> = clusters
+    .into_values()
+    .filter(|cluster| cluster.len() > 1)
+    .collect();
+  clusters.sort_unstable_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+  clusters
+}
+
+/// Group near-duplicate photos into stacks: photos linked by a chain of matches within
+/// `max_distance` end up in the same stack
+/// Returns stacks of input positions, largest first; photos without duplicates are left out
+#[cfg_attr(feature = "node", napi)]
+pub fn cluster_near_duplicates(hashes: Vec<String>, max_distance: u32) -> Vec<Vec<u32>> {
+  let hashes: Vec<Option<Vec<u8>>> = hashes.par_iter().map(|hash| hash_bytes(hash)).collect();
+  near_duplicate_clusters(&hashes, max_distance)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_near_duplicate_pairs_match_brute_force() {
+    // Deterministic pseudo-random 8-byte hashes, some of them with a few flipped bits
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state
+    };
+    let mut hashes: Vec<Option<Vec<u8>>> = Vec::new();
+    for i in 0..200 {
+      let value = if i % 3 == 1 {
+        let base = u64::from_le_bytes(hashes[i - 1].clone().unwrap().try_into().unwrap());
+        base ^ (1 << (next() % 64)) ^ (1 << (next() % 64))
+      } else {
+        next()
+      };
+      hashes.push(Some(value.to_le_bytes().to_vec()));
+    }
+    hashes[5] = None;
+    hashes.push(Some(vec![0; 4]));
+
+    let pairs = near_duplicate_pairs(&hashes, 6);
+    let mut expected = Vec::new();
+    for a in 0..hashes.len() {
+      for b in a + 1..hashes.len() {
+        if let (Some(x), Some(y)) = (&hashes[a], &hashes[b]) {
+          let distance = hamming(x, y);
+          if x.len() == y.len() && distance <= 6 {
+            expected.push((distance, a as u32, b as u32));
+          }
+        }
+      }
+    }
+    expected.sort_unstable();
+
+    assert!(expected.len() >= 60);
+    let pairs: Vec<_> = pairs.iter().map(|p| (p.distance, p.a, p.b)).collect();
+    assert_eq!(pairs, expected);
+  }
+
+  #[test]
+  fn test_near_duplicate_clusters() {
+    let hash = |value: u64| Some(value.to_le_bytes().to_vec());
+    let hashes = vec![
+      hash(0b0000),
+      hash(u64::MAX),
+      hash(0b0011),
+      None,
+      hash(0b1111),
+      hash(u64::MAX ^ 1),
+      hash(0xF0F0_F0F0),
+    ];
+
+    // 0-2-4 are chained by 2-bit steps even though 0 and 4 are 4 bits apart
+    assert_eq!(
+      near_duplicate_clusters(&hashes, 2),
+      vec![vec![0, 2, 4], vec![1, 5]]
+    );
+    assert_eq!(near_duplicate_clusters(&hashes, 1), vec![vec![1, 5]]);
+  }
+
+  #[test]
+  fn test_robust_distance() {
+    // Full image, one rotation and one crop
+    let a = vec![vec![0b1111_0000], vec![0b0000_1111], vec![0b1100_1100]];
+
+    // A rotated copy matches the rotation of the original
+    assert_eq!(robust_distance(&a, &[vec![0b0000_1111]]), Some(0));
+    // A crop of the original matches one of its crops
+    assert_eq!(robust_distance(&a, &[vec![0b1100_1101]]), Some(1));
+    // The original matches a crop of a screenshot of it
+    assert_eq!(robust_distance(&[vec![0], vec![0b1111_0001]], &a), Some(1));
+    assert_eq!(robust_distance(&a, &[vec![0, 0]]), None);
+  }
+}
@@ -0,0 +1,317 @@
+use image::{imageops, imageops::FilterType, DynamicImage, Rgb, RgbImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use ort::session::Session;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clip::{decode_upright, load_onnx_session, run_onnx_session, ConfiguredModel};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+
+/// Input side of the stock YOLO detectors
+const DEFAULT_INPUT_SIZE: u32 = 640;
+
+/// Detections scoring below this are dropped unless the model options set another threshold
+const DEFAULT_MIN_SCORE: f64 = 0.4;
+
+/// Boxes of the same kind overlapping more than this (intersection over union) are one
+/// person or pet
+const NMS_IOU: f32 = 0.5;
+
+/// Padding color of the letterboxed input, as YOLO models are trained with
+const LETTERBOX_GRAY: u8 = 114;
+
+/// Classes counted as people
+const PERSON_LABELS: &[&str] = &["person"];
+
+/// Classes counted as pets, among COCO's animals
+const PET_LABELS: &[&str] = &["cat", "dog", "bird", "horse"];
+
+/// Person/pet detection model to load, set by `configure_presence_model`
+#[cfg_attr(feature = "node", napi(object))]
+pub struct PresenceModelOptions {
+	/// ONNX YOLOv8-style detector (e.g. YOLOv8n or YOLO11n exported by Ultralytics) with a
+	/// 1x3xNxN float input and a 1x(4 + classes)xM output of center boxes and class scores
+	pub model_path: String,
+	/// Text file with the label of each class, one per line (COCO's 80 for stock models)
+	pub labels_path: String,
+	/// Side of the model's square input (default 640)
+	pub input_size: Option<u32>,
+	/// Drop detections scoring below this (default 0.4)
+	pub min_score: Option<f64>,
+}
+
+/// What a detected class counts as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subject {
+	Person,
+	Pet,
+}
+
+/// Validated person/pet detection settings
+#[derive(Debug, Clone)]
+struct PresenceConfig {
+	model_path: PathBuf,
+	/// What each class of the model counts as, if anything
+	subjects: Vec<Option<Subject>>,
+	input_size: u32,
+	min_score: f32,
+}
+
+/// Global detection model, loaded on first use
+static PRESENCE_MODEL: ConfiguredModel<PresenceConfig, Session> =
+	ConfiguredModel::new("No person/pet detection model configured");
+
+/// Whether a person/pet detection model is configured
+pub fn has_presence_model() -> bool {
+	PRESENCE_MODEL.is_configured()
+}
+
+fn load_presence_model(config: &PresenceConfig) -> Result<Session, ProcessingError> {
+	load_onnx_session(&config.model_path)
+}
+
+fn presence_config_from_options(
+	options: PresenceModelOptions,
+) -> Result<PresenceConfig, ProcessingError> {
+	if !Path::new(&options.model_path).is_file() {
+		return Err(ProcessingError::invalid(format!(
+			"Model {} doesn't exist",
+			options.model_path
+		)));
+	}
+	let subjects: Vec<Option<Subject>> = fs::read_to_string(&options.labels_path)
+		.map_err(|e| ProcessingError::io(&e, &format!("Failed to read {}", options.labels_path)))?
+		.lines()
+		.map(|label| {
+			let label = label.trim().to_lowercase();
+			if PERSON_LABELS.contains(&label.as_str()) {
+				Some(Subject::Person)
+			} else if PET_LABELS.contains(&label.as_str()) {
+				Some(Subject::Pet)
+			} else {
+				None
+			}
+		})
+		.collect();
+	if subjects.iter().all(Option::is_none) {
+		return Err(ProcessingError::invalid(format!(
+			"{} has no person or pet labels",
+			options.labels_path
+		)));
+	}
+	let input_size = options.input_size.unwrap_or(DEFAULT_INPUT_SIZE);
+	if input_size == 0 {
+		return Err(ProcessingError::invalid(
+			"Model input size must be positive",
+		));
+	}
+
+	Ok(PresenceConfig {
+		model_path: PathBuf::from(options.model_path),
+		subjects,
+		input_size,
+		min_score: options.min_score.unwrap_or(DEFAULT_MIN_SCORE) as f32,
+	})
+}
+
+/// Set the person/pet detection model, see `configure_presence_model`
+pub fn configure_presence_model_internal(
+	options: Option<PresenceModelOptions>,
+) -> Result<(), ProcessingError> {
+	let config = options
+		.map(presence_config_from_options)
+		.transpose()
+		.map_err(|e| e.context("Invalid detection model"))?;
+	PRESENCE_MODEL.configure(config);
+	Ok(())
+}
+
+/// Set the ONNX model people and pets are detected with, or remove it with null
+/// Only the labels file's person and pet classes are counted; it fails if it has none
+#[cfg(feature = "node")]
+#[napi]
+pub fn configure_presence_model(
+	options: Option<PresenceModelOptions>,
+) -> napi::Result<(), ErrorCode> {
+	configure_presence_model_internal(options).map_err(napi::Error::from)
+}
+
+/// Model input: the image scaled to fit the square input, padded with gray, in NCHW order
+fn letterbox_values(img: &DynamicImage, size: u32) -> Vec<f32> {
+	let scaled = img.resize(size, size, FilterType::Triangle).to_rgb8();
+	let mut canvas = RgbImage::from_pixel(size, size, Rgb([LETTERBOX_GRAY; 3]));
+	let x = (size - scaled.width()) / 2;
+	let y = (size - scaled.height()) / 2;
+	imageops::overlay(&mut canvas, &scaled, x as i64, y as i64);
+
+	let plane = (size * size) as usize;
+	let mut values = vec![0.0; 3 * plane];
+	for (i, pixel) in canvas.pixels().enumerate() {
+		for c in 0..3 {
+			values[c * plane + i] = pixel[c] as f32 / 255.0;
+		}
+	}
+	values
+}
+
+/// A person or pet found by the model, with its box corners in input pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Detection {
+	subject: Subject,
+	score: f32,
+	bbox: [f32; 4],
+}
+
+/// Person and pet detections in a YOLOv8-style output: for each of `anchors` candidates, a
+/// center box then one score per class, stored channel by channel
+fn subject_detections(
+	output: &[f32],
+	anchors: usize,
+	subjects: &[Option<Subject>],
+	min_score: f32,
+) -> Vec<Detection> {
+	let value = |channel: usize, anchor: usize| output[channel * anchors + anchor];
+	(0..anchors)
+		.filter_map(|anchor| {
+			let (subject, score) = subjects
+				.iter()
+				.enumerate()
+				.filter_map(|(class, subject)| subject.map(|s| (s, value(4 + class, anchor))))
+				.max_by(|a, b| a.1.total_cmp(&b.1))?;
+			if score < min_score {
+				return None;
+			}
+			let (cx, cy) = (value(0, anchor), value(1, anchor));
+			let (w, h) = (value(2, anchor), value(3, anchor));
+			Some(Detection {
+				subject,
+				score,
+				bbox: [cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0],
+			})
+		})
+		.collect()
+}
+
+/// Intersection over union of two boxes
+fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+	let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+	let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+	let intersection = width * height;
+	let area = |r: &[f32; 4]| (r[2] - r[0]) * (r[3] - r[1]);
+	let union = area(a) + area(b) - intersection;
+	if union > 0.0 {
+		intersection / union
+	} else {
+		0.0
+	}
+}
+
+/// Count the people and pets left after non-maximum suppression, which merges the
+/// overlapping boxes the model outputs for one subject
+fn count_subjects(mut detections: Vec<Detection>) -> PresenceCounts {
+	detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+	let mut kept: Vec<Detection> = Vec::new();
+	for detection in detections {
+		let duplicate = kept.iter().any(|other| {
+			other.subject == detection.subject && iou(&other.bbox, &detection.bbox) > NMS_IOU
+		});
+		if !duplicate {
+			kept.push(detection);
+		}
+	}
+
+	let count = |subject| kept.iter().filter(|d| d.subject == subject).count() as u32;
+	let (people_count, pet_count) = (count(Subject::Person), count(Subject::Pet));
+	PresenceCounts {
+		has_people: people_count > 0,
+		people_count,
+		has_pets: pet_count > 0,
+		pet_count,
+	}
+}
+
+/// People and pets found in a photo
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PresenceCounts {
+	pub has_people: bool,
+	pub people_count: u32,
+	pub has_pets: bool,
+	pub pet_count: u32,
+}
+
+/// Count the people and pets in an upright image with the configured detection model
+pub fn detect_presence_image(img: &DynamicImage) -> Result<PresenceCounts, ProcessingError> {
+	let (config, model) = PRESENCE_MODEL.get(load_presence_model)?;
+
+	let size = config.input_size as usize;
+	let values = letterbox_values(img, config.input_size);
+	let (shape, output) = {
+		let session = model.lock().unwrap_or_else(|e| e.into_inner());
+		run_onnx_session(&session, [1, 3, size, size], values)
+			.map_err(|e| e.context("Detection model error"))?
+	};
+	let channels = 4 + config.subjects.len();
+	let anchors = match shape.as_slice() {
+		[1, c, anchors] if *c as usize == channels => *anchors as usize,
+		_ => {
+			return Err(ProcessingError::invalid(format!(
+				"Detection model output {:?} doesn't match {} labels",
+				shape,
+				config.subjects.len()
+			)))
+		}
+	};
+
+	let detections = subject_detections(&output, anchors, &config.subjects, config.min_score);
+	Ok(count_subjects(detections))
+}
+
+/// Count the people and pets in a photo, see `detect_presence`
+pub fn detect_presence_internal(file_path: &str) -> Result<PresenceCounts, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	detect_presence_image(&img)
+}
+
+/// Detect whether a photo shows people or pets, and how many, with the model set with
+/// `configure_presence_model`; cheaper than face recognition, for filters like "pets"
+#[cfg(feature = "node")]
+#[napi]
+pub fn detect_presence(file_path: String) -> napi::Result<PresenceCounts, ErrorCode> {
+	detect_presence_internal(&file_path).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_counts_subjects_after_suppression() {
+		// Classes: person, car, dog; anchors: two overlapping people, a dog, a car, noise
+		let subjects = [Some(Subject::Person), None, Some(Subject::Pet)];
+		let output = [
+			[100.0, 104.0, 300.0, 50.0, 200.0], // cx
+			[100.0, 102.0, 300.0, 50.0, 200.0], // cy
+			[80.0, 80.0, 60.0, 40.0, 10.0],     // w
+			[160.0, 160.0, 60.0, 40.0, 10.0],   // h
+			[0.9, 0.7, 0.0, 0.0, 0.1],          // person
+			[0.0, 0.0, 0.0, 0.95, 0.0],         // car
+			[0.0, 0.1, 0.8, 0.0, 0.2],          // dog
+		]
+		.concat();
+		let detections = subject_detections(&output, 5, &subjects, 0.4);
+		assert_eq!(detections.len(), 3);
+		assert_eq!(
+			count_subjects(detections),
+			PresenceCounts {
+				has_people: true,
+				people_count: 1,
+				has_pets: true,
+				pet_count: 1,
+			}
+		);
+	}
+}
@@ -1,11 +1,70 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageReader};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rsraw::{RawImage, ThumbFormat};
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
 use std::process::Command;
 
+use crate::error::{ErrorCode, ProcessingError};
+use crate::features::{require, Component};
+use crate::hdr::xmp_number;
+use crate::heif::{decode_heif, decode_heif_thumbnail, is_heif_file};
+use crate::logging::{log_event, LogLevel};
+use crate::runtime::acquire_raw_permit;
+use crate::Buffer;
+
 /// RAW file extensions that require preview extraction
 const RAW_EXTENSIONS: &[&str] = &[
 	".cr2", ".cr3", ".nef", ".arw", ".dng", ".raf", ".orf", ".rw2", ".pef", ".srw", ".x3f",
 	".3fr", ".iiq", ".rwl",
 ];
 
+/// Longest side of a HEIF preview decoded from the primary image
+const HEIF_PREVIEW_SIZE: u32 = 1024;
+
+/// JPEG quality of HEIF previews
+const HEIF_PREVIEW_QUALITY: u8 = 85;
+
+/// Name of the block of Samsung's SEF trailer holding the video of a Motion Photo
+const SAMSUNG_MOTION_PHOTO_MARKER: &[u8] = b"MotionPhoto_Data";
+
+/// Most entries read from the index of a SEF trailer
+const SEF_MAX_ENTRIES: u32 = 64;
+
+/// Bytes at the start of a file searched for its XMP packet
+const XMP_SEARCH_LIMIT: usize = 256 * 1024;
+
+/// exiftool tags holding embedded JPEGs, usually from smallest to largest
+const EXIFTOOL_PREVIEW_TAGS: &[&str] = &["ThumbnailImage", "PreviewImage", "JpgFromRaw"];
+
+/// JPEG embedded in a photo file, with its dimensions
+struct EmbeddedJpeg {
+	data: Vec<u8>,
+	width: u32,
+	height: u32,
+}
+
+impl EmbeddedJpeg {
+	fn new(data: Vec<u8>) -> Option<Self> {
+		let (width, height) = ImageReader::new(Cursor::new(&data))
+			.with_guessed_format()
+			.ok()?
+			.into_dimensions()
+			.ok()?;
+		Some(Self {
+			data,
+			width,
+			height,
+		})
+	}
+
+	fn longest_side(&self) -> u32 {
+		self.width.max(self.height)
+	}
+}
+
 /// Check if a file is a RAW file (needs preview extraction)
 pub fn is_raw_file(file_path: &str) -> bool {
 	let lower = file_path.to_lowercase();
@@ -25,33 +84,457 @@ pub fn get_raw_format(file_path: &str) -> Option<String> {
 		.map(|ext| ext.to_uppercase())
 }
 
-/// Extract embedded preview JPEG from RAW or HEIF files using exiftool
-/// Returns the JPEG bytes if successful
-pub fn extract_preview(file_path: &str) -> Option<Vec<u8>> {
-	// Try PreviewImage first (works for most RAW and HEIF)
+/// Extract an embedded JPEG stored in a binary tag (e.g. "PreviewImage", "ThumbnailImage")
+/// Returns None if the tag is missing or doesn't hold a JPEG
+pub fn extract_embedded_jpeg(file_path: &str, tag: &str) -> Option<Vec<u8>> {
 	let output = Command::new("exiftool")
-		.args(["-b", "-PreviewImage", file_path])
+		.args(["-b", &format!("-{}", tag), file_path])
 		.output()
 		.ok()?;
 
-	if output.status.success() && !output.stdout.is_empty() {
-		// Verify it's a JPEG (starts with FFD8)
-		if output.stdout.len() > 2 && output.stdout[0] == 0xFF && output.stdout[1] == 0xD8 {
-			return Some(output.stdout);
+	// Verify it's a JPEG (starts with FFD8)
+	let is_jpeg = output.stdout.len() > 2 && output.stdout.starts_with(&[0xFF, 0xD8]);
+	(output.status.success() && is_jpeg).then_some(output.stdout)
+}
+
+/// JPEG thumbnails embedded in a RAW file, read in-process with LibRaw
+fn raw_thumbnails(file_path: &str) -> Vec<EmbeddedJpeg> {
+	let _permit = acquire_raw_permit();
+	let thumbnails = match fs::read(file_path) {
+		Ok(data) => libraw_thumbnails(&data),
+		Err(e) => {
+			let message = format!("Failed to read RAW file: {}", e);
+			log_event(LogLevel::Warn, "raw", Some(file_path), message);
+			return Vec::new();
 		}
+	};
+	if thumbnails.is_empty() {
+		let message = "LibRaw found no JPEG preview".to_string();
+		log_event(LogLevel::Debug, "raw", Some(file_path), message);
 	}
+	thumbnails
+}
 
-	// Fallback: try JpgFromRaw (some cameras use this tag)
-	let output = Command::new("exiftool")
-		.args(["-b", "-JpgFromRaw", file_path])
-		.output()
+/// JPEG thumbnails embedded in RAW data held in memory
+fn raw_thumbnails_from_bytes(data: &[u8]) -> Vec<EmbeddedJpeg> {
+	let _permit = acquire_raw_permit();
+	libraw_thumbnails(data)
+}
+
+/// JPEG thumbnails LibRaw finds in RAW data; callers hold a RAW decoding slot
+fn libraw_thumbnails(data: &[u8]) -> Vec<EmbeddedJpeg> {
+	let thumbnails = RawImage::open(data)
+		.ok()
+		.and_then(|mut raw| raw.extract_thumbs().ok())
+		.unwrap_or_default();
+	thumbnails
+		.into_iter()
+		.filter(|thumb| thumb.format == ThumbFormat::Jpeg && thumb.data.starts_with(&[0xFF, 0xD8]))
+		.filter_map(|thumb| EmbeddedJpeg::new(thumb.data))
+		.collect()
+}
+
+/// Largest JPEG thumbnail embedded in a RAW file
+fn raw_thumbnail(file_path: &str) -> Option<Vec<u8>> {
+	raw_thumbnails(file_path)
+		.into_iter()
+		.max_by_key(EmbeddedJpeg::longest_side)
+		.map(|thumb| thumb.data)
+}
+
+/// Largest JPEG thumbnail embedded in RAW data held in memory
+pub fn raw_thumbnail_from_bytes(data: &[u8]) -> Option<Vec<u8>> {
+	raw_thumbnails_from_bytes(data)
+		.into_iter()
+		.max_by_key(EmbeddedJpeg::longest_side)
+		.map(|thumb| thumb.data)
+}
+
+/// JPEGs embedded in a file, read with exiftool
+fn exiftool_previews(file_path: &str) -> Vec<EmbeddedJpeg> {
+	EXIFTOOL_PREVIEW_TAGS
+		.iter()
+		.filter_map(|tag| EmbeddedJpeg::new(extract_embedded_jpeg(file_path, tag)?))
+		.collect()
+}
+
+/// Smallest preview whose longest side is at least `min_dimension`, else the largest one
+fn pick_for_size(previews: Vec<EmbeddedJpeg>, min_dimension: u32) -> Option<EmbeddedJpeg> {
+	let (large_enough, smaller): (Vec<_>, Vec<_>) = previews
+		.into_iter()
+		.partition(|preview| preview.longest_side() >= min_dimension);
+	let smallest = large_enough
+		.into_iter()
+		.min_by_key(EmbeddedJpeg::longest_side);
+	smallest.or_else(|| smaller.into_iter().max_by_key(EmbeddedJpeg::longest_side))
+}
+
+fn encode_preview(img: &DynamicImage) -> Option<Vec<u8>> {
+	let mut data = Vec::new();
+	let encoder = JpegEncoder::new_with_quality(&mut data, HEIF_PREVIEW_QUALITY);
+	DynamicImage::ImageRgb8(img.to_rgb8())
+		.write_with_encoder(encoder)
 		.ok()?;
+	Some(data)
+}
+
+/// Preview of a HEIF file from its embedded thumbnail item, or else its primary image
+/// scaled down, as a JPEG (upright, libheif applies the file's transforms)
+fn heif_preview(file_path: &str) -> Option<Vec<u8>> {
+	require(Component::Libheif).ok()?;
+	let img = match decode_heif_thumbnail(file_path, |_, _| true) {
+		Some(thumbnail) => thumbnail,
+		None => {
+			let primary = decode_heif(file_path).ok()?.image;
+			primary.thumbnail(HEIF_PREVIEW_SIZE, HEIF_PREVIEW_SIZE)
+		}
+	};
+	encode_preview(&img)
+}
 
-	if output.status.success() && !output.stdout.is_empty() {
-		if output.stdout.len() > 2 && output.stdout[0] == 0xFF && output.stdout[1] == 0xD8 {
-			return Some(output.stdout);
+/// HEIF preview at least `min_dimension` on its longest side: the embedded thumbnail when
+/// it's large enough, else the primary image scaled down to that size
+fn heif_preview_for_size(file_path: &str, min_dimension: u32) -> Option<Vec<u8>> {
+	require(Component::Libheif).ok()?;
+	let thumbnail = decode_heif_thumbnail(file_path, |width, height| {
+		width.max(height) >= min_dimension
+	});
+	let img = match thumbnail {
+		Some(thumbnail) => thumbnail,
+		None => {
+			let primary = decode_heif(file_path).ok()?.image;
+			if primary.width().max(primary.height()) > min_dimension {
+				primary.thumbnail(min_dimension, min_dimension)
+			} else {
+				primary
+			}
 		}
+	};
+	encode_preview(&img)
+}
+
+/// Extract the embedded preview JPEG of a RAW or HEIF file
+/// RAW files are read natively with LibRaw and HEIF files with libheif; exiftool, when
+/// installed, is the fallback for files they can't read
+/// Returns the JPEG bytes if successful
+pub fn extract_preview(file_path: &str) -> Option<Vec<u8>> {
+	if is_raw_file(file_path) {
+		return extract_raw_preview(file_path);
+	}
+	let native = is_heif_file(file_path)
+		.then(|| heif_preview(file_path))
+		.flatten();
+	native_or_exiftool_preview(file_path, native, "heif")
+}
+
+/// Extract the embedded preview JPEG of a file known to be RAW, whatever its extension
+/// (e.g. one whose content was sniffed as RAW), see `extract_preview`
+pub fn extract_raw_preview(file_path: &str) -> Option<Vec<u8>> {
+	native_or_exiftool_preview(file_path, raw_thumbnail(file_path), "raw")
+}
+
+/// The natively read preview, else the one exiftool finds
+fn native_or_exiftool_preview(
+	file_path: &str,
+	native: Option<Vec<u8>>,
+	module: &str,
+) -> Option<Vec<u8>> {
+	// Try PreviewImage first (works for most RAW and HEIF)
+	// Fallback: try JpgFromRaw (some cameras use this tag)
+	let preview = native
+		.or_else(|| extract_embedded_jpeg(file_path, "PreviewImage"))
+		.or_else(|| extract_embedded_jpeg(file_path, "JpgFromRaw"));
+	if preview.is_none() {
+		let message = "No embedded preview found".to_string();
+		log_event(LogLevel::Warn, module, Some(file_path), message);
+	}
+	preview
+}
+
+/// Extract the smallest preview of a RAW or HEIF file that's at least `min_dimension` on its
+/// longest side, or the largest one when none is, so screen-sized uses skip decoding large
+/// previews
+pub fn extract_preview_for_size_internal(file_path: &str, min_dimension: u32) -> Option<Vec<u8>> {
+	let heif = is_heif_file(file_path)
+		.then(|| heif_preview_for_size(file_path, min_dimension))
+		.flatten();
+	if heif.is_some() {
+		return heif;
 	}
 
-	None
+	let mut previews = if is_raw_file(file_path) {
+		raw_thumbnails(file_path)
+	} else {
+		Vec::new()
+	};
+	if previews.is_empty() {
+		previews = exiftool_previews(file_path);
+	}
+	pick_for_size(previews, min_dimension).map(|preview| preview.data)
+}
+
+/// Length of the video item of a Google Motion Photo container (Item:Semantic="MotionPhoto")
+fn motion_photo_item_length(xmp: &str) -> Option<f64> {
+	let semantic = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+	let start = xmp[..semantic].rfind('<')?;
+	let end = semantic + xmp[semantic..].find('>')?;
+	xmp_number(&xmp[start..end], "Item:Length")
+}
+
+/// Fill `buf` from `position` of a file or buffer
+fn read_at<R: Read + Seek>(reader: &mut R, position: u64, buf: &mut [u8]) -> Option<()> {
+	reader.seek(SeekFrom::Start(position)).ok()?;
+	reader.read_exact(buf).ok()
+}
+
+fn le_u32(bytes: &[u8]) -> u64 {
+	u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64
+}
+
+/// Start of the video of a Samsung Motion Photo, from the index of the SEF trailer Samsung
+/// appends to its photos: a "SEFH" directory of (type, offset back from the directory, size)
+/// entries, its length and "SEFT"; each block starts with its type and name
+fn samsung_video_offset<R: Read + Seek>(reader: &mut R, len: u64) -> Option<u64> {
+	let mut footer = [0; 8];
+	read_at(reader, len.checked_sub(8)?, &mut footer)?;
+	if &footer[4..] != b"SEFT" {
+		return None;
+	}
+	let directory = len.checked_sub(8 + le_u32(&footer[..4]))?;
+	let mut header = [0; 12];
+	read_at(reader, directory, &mut header)?;
+	if &header[..4] != b"SEFH" {
+		return None;
+	}
+
+	let count = le_u32(&header[8..]).min(SEF_MAX_ENTRIES as u64);
+	let mut name = [0; SAMSUNG_MOTION_PHOTO_MARKER.len()];
+	(0..count).find_map(|i| {
+		let mut entry = [0; 12];
+		read_at(reader, directory + 12 + i * 12, &mut entry)?;
+		let block = directory.checked_sub(le_u32(&entry[4..8]))?;
+		let mut block_header = [0; 8];
+		read_at(reader, block, &mut block_header)?;
+		if le_u32(&block_header[4..]) != name.len() as u64 {
+			return None;
+		}
+		read_at(reader, block + 8, &mut name)?;
+		(name == SAMSUNG_MOTION_PHOTO_MARKER).then_some(block + 8 + name.len() as u64)
+	})
+}
+
+/// Start of the MP4 appended to an Android Motion Photo, from the Google XMP (the container
+/// item's length, or the older MicroVideoOffset, both counted from the end of the file) or
+/// Samsung's SEF trailer
+/// Only the head (`XMP_SEARCH_LIMIT`) and a few bytes around the video are read
+fn motion_photo_offset<R: Read + Seek>(reader: &mut R, len: u64) -> Option<u64> {
+	let mut head = Vec::new();
+	reader.seek(SeekFrom::Start(0)).ok()?;
+	reader
+		.by_ref()
+		.take(XMP_SEARCH_LIMIT as u64)
+		.read_to_end(&mut head)
+		.ok()?;
+	let head = String::from_utf8_lossy(&head);
+	let from_end = motion_photo_item_length(&head)
+		.or_else(|| xmp_number(&head, "GCamera:MicroVideoOffset"))
+		.filter(|&length| length > 0.0);
+	let offset = match from_end {
+		Some(length) => len.checked_sub(length as u64)?,
+		None => samsung_video_offset(reader, len)?,
+	};
+	// The video must be an MP4, starting with its ftyp box
+	let mut box_header = [0; 8];
+	read_at(reader, offset, &mut box_header)?;
+	(&box_header[4..] == b"ftyp").then_some(offset)
+}
+
+fn motion_photo_video_offset(data: &[u8]) -> Option<usize> {
+	motion_photo_offset(&mut Cursor::new(data), data.len() as u64).map(|offset| offset as usize)
+}
+
+/// Whether a photo is an Android Motion Photo with an embedded video
+pub fn is_motion_photo(file_path: &str) -> bool {
+	let Ok(mut file) = fs::File::open(file_path) else {
+		return false;
+	};
+	let len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+	motion_photo_offset(&mut file, len).is_some()
+}
+
+/// Whether photo contents held in memory are an Android Motion Photo
+pub fn is_motion_photo_data(data: &[u8]) -> bool {
+	motion_photo_video_offset(data).is_some()
+}
+
+/// MP4 embedded in an Android Motion Photo, see `extract_motion_photo_video`
+pub fn extract_motion_photo_video_internal(
+	file_path: &str,
+) -> Result<Option<Vec<u8>>, ProcessingError> {
+	let read_error =
+		|e: std::io::Error| ProcessingError::io(&e, &format!("Failed to read {}", file_path));
+	let mut file = fs::File::open(file_path).map_err(read_error)?;
+	let len = file.metadata().map_err(read_error)?.len();
+	let Some(offset) = motion_photo_offset(&mut file, len) else {
+		return Ok(None);
+	};
+	let mut video = Vec::new();
+	file.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+	file.read_to_end(&mut video).map_err(read_error)?;
+	Ok(Some(video))
+}
+
+/// Extract the MP4 embedded in an Android Motion Photo (Pixel MicroVideo/MotionPhoto, Samsung)
+/// Returns null for other photos
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_motion_photo_video(file_path: String) -> napi::Result<Option<Buffer>, ErrorCode> {
+	extract_motion_photo_video_internal(&file_path)
+		.map(|video| video.map(Buffer::from))
+		.map_err(napi::Error::from)
+}
+
+/// Embedded preview of a photo, for a quick look before full processing
+#[cfg_attr(feature = "node", napi(object))]
+pub struct PreviewImage {
+	pub data: Buffer,
+	/// MIME type of `data`, e.g. "image/jpeg"
+	pub mime_type: String,
+	pub width: u32,
+	pub height: u32,
+}
+
+/// Whether a file is shown through its embedded preview (RAW and HEIF) rather than decoded directly
+#[cfg_attr(feature = "node", napi)]
+pub fn needs_preview_extraction(file_path: String) -> bool {
+	is_raw_file(&file_path) || is_heif_file(&file_path)
+}
+
+/// Extract the embedded preview of a RAW or HEIF file, see `extract_preview_image`
+pub fn extract_preview_image_internal(
+	file_path: &str,
+) -> Result<Option<PreviewImage>, ProcessingError> {
+	extract_preview(file_path).map(preview_image).transpose()
+}
+
+/// Extract the embedded preview of a RAW or HEIF file
+/// RAW previews are as stored (not oriented), HEIF previews are upright
+/// Returns null when the file has none
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_preview_image(file_path: String) -> napi::Result<Option<PreviewImage>, ErrorCode> {
+	extract_preview_image_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Extract the smallest embedded preview of a RAW or HEIF file that's at least
+/// `min_dimension` pixels on its longest side (or the largest one), see `extractPreviewImage`
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_preview_for_size(
+	file_path: String,
+	min_dimension: u32,
+) -> napi::Result<Option<PreviewImage>, ErrorCode> {
+	extract_preview_for_size_internal(&file_path, min_dimension)
+		.map(preview_image)
+		.transpose()
+		.map_err(napi::Error::from)
+}
+
+fn preview_image(data: Vec<u8>) -> Result<PreviewImage, ProcessingError> {
+	let failed = |e: image::ImageError| {
+		let message = format!("Failed to read preview: {}", e);
+		ProcessingError::new(ErrorCode::DecodeFailed, message)
+	};
+	let reader = ImageReader::new(Cursor::new(&data))
+		.with_guessed_format()
+		.map_err(|e| ProcessingError::io(&e, "Failed to read preview"))?;
+	let format = reader.format().ok_or_else(|| {
+		ProcessingError::new(ErrorCode::UnsupportedFormat, "Unknown preview format")
+	})?;
+	let (width, height) = reader.into_dimensions().map_err(failed)?;
+
+	Ok(PreviewImage {
+		mime_type: format.to_mime_type().to_string(),
+		width,
+		height,
+		data: data.into(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_motion_photo_video_offset() {
+		let jpeg = |xmp: &str| [&[0xFF, 0xD8][..], xmp.as_bytes(), &[0xFF, 0xD9]].concat();
+		let video = b"\0\0\0\x18ftypmp42isom";
+
+		let container = jpeg(
+			r#"<Container:Item Item:Mime="video/mp4" Item:Semantic="MotionPhoto" Item:Length="16"/>"#,
+		);
+		let pixel = [&container[..], video].concat();
+		assert_eq!(motion_photo_video_offset(&pixel), Some(container.len()));
+
+		let micro_video = jpeg(r#"GCamera:MicroVideo="1" GCamera:MicroVideoOffset="16""#);
+		let older_pixel = [&micro_video[..], video].concat();
+		assert_eq!(
+			motion_photo_video_offset(&older_pixel),
+			Some(micro_video.len())
+		);
+
+		// Samsung: a MotionPhoto_Data block, then the SEF index pointing back at it
+		let still = jpeg("");
+		let name_len = SAMSUNG_MOTION_PHOTO_MARKER.len() as u32;
+		let block = [
+			&[0, 0, 0x30, 0x0a][..],
+			&name_len.to_le_bytes(),
+			SAMSUNG_MOTION_PHOTO_MARKER,
+			video,
+		]
+		.concat();
+		let directory = [
+			&b"SEFH"[..],
+			&106u32.to_le_bytes(),
+			&1u32.to_le_bytes(),
+			&[0, 0, 0x30, 0x0a],
+			&(block.len() as u32).to_le_bytes(),
+			&(block.len() as u32).to_le_bytes(),
+		]
+		.concat();
+		let footer = [&(directory.len() as u32).to_le_bytes()[..], b"SEFT"].concat();
+		let samsung = [&still[..], &block, &directory, &footer].concat();
+		assert_eq!(
+			motion_photo_video_offset(&samsung),
+			Some(still.len() + 8 + SAMSUNG_MOTION_PHOTO_MARKER.len())
+		);
+
+		// Wrong offsets and plain photos don't count
+		let wrong = jpeg(r#"GCamera:MicroVideoOffset="8""#);
+		assert_eq!(
+			motion_photo_video_offset(&[&wrong[..], video].concat()),
+			None
+		);
+		assert_eq!(motion_photo_video_offset(&jpeg("")), None);
+	}
+
+	#[test]
+	fn test_pick_for_size() {
+		let previews = || {
+			[(160, 120), (1616, 1080), (6000, 4000)]
+				.map(|(width, height)| EmbeddedJpeg {
+					data: vec![],
+					width,
+					height,
+				})
+				.into()
+		};
+		let picked = |min_dimension| pick_for_size(previews(), min_dimension).map(|p| p.width);
+
+		assert_eq!(picked(100), Some(160));
+		assert_eq!(picked(1024), Some(1616));
+		assert_eq!(picked(2000), Some(6000));
+		assert_eq!(picked(8000), Some(6000));
+		assert!(pick_for_size(Vec::new(), 100).is_none());
+	}
 }
@@ -0,0 +1,140 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ErrorCode, ProcessingError};
+use crate::exif::ExifData;
+use crate::video::VideoMetadata;
+#[cfg(feature = "node")]
+use crate::Buffer;
+
+/// AES-GCM nonce length in bytes (prepended to the ciphertext)
+const NONCE_LEN: usize = 12;
+
+/// Metadata of a private file, stored only in encrypted form
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivateMetadata {
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	pub mime_type: Option<String>,
+	pub phash: Option<String>,
+	pub exif: Option<ExifData>,
+	pub duration: Option<f64>,
+	pub video: Option<VideoMetadata>,
+}
+
+/// Check whether a relative path lies inside one of the private folders
+pub fn is_private_path(relative_path: &str, private_folders: &[String]) -> bool {
+	let relative_path = relative_path.replace('\\', "/");
+	private_folders.iter().any(|folder| {
+		let folder = folder.replace('\\', "/");
+		let folder = folder.trim_matches('/');
+		!folder.is_empty()
+			&& relative_path
+				.strip_prefix(folder)
+				.is_some_and(|rest| rest.starts_with('/'))
+	})
+}
+
+/// Parse a 256-bit key
+pub fn parse_key(key: &[u8]) -> Result<Aes256Gcm, ProcessingError> {
+	Aes256Gcm::new_from_slice(key)
+		.map_err(|_| ProcessingError::invalid("Private metadata key must be 32 bytes"))
+}
+
+/// Encrypt metadata as base64(nonce || ciphertext)
+pub fn encrypt_metadata(
+	metadata: &PrivateMetadata,
+	cipher: &Aes256Gcm,
+) -> Result<String, ProcessingError> {
+	let failed = || ProcessingError::new(ErrorCode::Internal, "Failed to encrypt metadata");
+	let plaintext = serde_json::to_vec(metadata).map_err(|_| failed())?;
+	let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+	let ciphertext = cipher
+		.encrypt(&nonce, plaintext.as_slice())
+		.map_err(|_| failed())?;
+
+	let mut sealed = nonce.to_vec();
+	sealed.extend_from_slice(&ciphertext);
+	Ok(BASE64.encode(sealed))
+}
+
+/// Decrypt metadata produced by `encrypt_metadata`
+pub fn decrypt_metadata(
+	encrypted: &str,
+	cipher: &Aes256Gcm,
+) -> Result<PrivateMetadata, ProcessingError> {
+	let sealed = BASE64
+		.decode(encrypted)
+		.map_err(|e| ProcessingError::invalid(format!("Invalid encrypted metadata: {}", e)))?;
+	if sealed.len() < NONCE_LEN {
+		return Err(ProcessingError::invalid(
+			"Invalid encrypted metadata: too short",
+		));
+	}
+
+	let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+	let plaintext = cipher
+		.decrypt(Nonce::from_slice(nonce), ciphertext)
+		.map_err(|_| ProcessingError::invalid("Failed to decrypt metadata (wrong key?)"))?;
+	serde_json::from_slice(&plaintext)
+		.map_err(|e| ProcessingError::invalid(format!("Invalid encrypted metadata: {}", e)))
+}
+
+/// Decrypt the metadata of a private file, see `decrypt_private_metadata`
+pub fn decrypt_private_metadata_internal(
+	encrypted_metadata: &str,
+	key: &[u8],
+) -> Result<PrivateMetadata, ProcessingError> {
+	let cipher = parse_key(key)?;
+	decrypt_metadata(encrypted_metadata, &cipher)
+}
+
+/// Decrypt the metadata of a private file with the key used during import
+#[cfg(feature = "node")]
+#[napi]
+pub fn decrypt_private_metadata(
+	encrypted_metadata: String,
+	key: Buffer,
+) -> napi::Result<PrivateMetadata, ErrorCode> {
+	decrypt_private_metadata_internal(&encrypted_metadata, &key).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_private_path_matches_whole_folders() {
+		let folders = vec!["Documents/ID".to_string()];
+		assert!(is_private_path("Documents/ID/passport.jpg", &folders));
+		assert!(is_private_path("Documents\\ID\\scans\\license.jpg", &folders));
+		assert!(!is_private_path("Documents/IDEAS/sketch.jpg", &folders));
+		assert!(!is_private_path("Vacation/beach.jpg", &folders));
+	}
+
+	#[test]
+	fn test_metadata_round_trip_requires_key() {
+		let cipher = parse_key(&[7u8; 32]).unwrap();
+		let metadata = PrivateMetadata {
+			width: Some(4032),
+			height: Some(3024),
+			phash: Some("abc".to_string()),
+			..Default::default()
+		};
+
+		let encrypted = encrypt_metadata(&metadata, &cipher).unwrap();
+		let decrypted = decrypt_metadata(&encrypted, &cipher).unwrap();
+		assert_eq!(decrypted.width, Some(4032));
+		assert_eq!(decrypted.phash.as_deref(), Some("abc"));
+
+		let wrong = parse_key(&[8u8; 32]).unwrap();
+		assert!(decrypt_metadata(&encrypted, &wrong).is_err());
+	}
+}
@@ -0,0 +1,250 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+#[cfg(feature = "node")]
+use napi::bindgen_prelude::Either;
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+
+use crate::clip::decode_upright;
+use crate::decode::decode_bytes;
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+#[cfg(feature = "node")]
+use crate::Buffer;
+
+/// Longest side photos are scaled to before measuring sharpness, so scores compare across
+/// resolutions (downscaling alone makes large photos look sharper)
+pub(crate) const SHARPNESS_SIZE: u32 = 512;
+
+/// Weight of sharpness in a shot's score; exposure makes up the rest
+const SHOT_SHARPNESS_WEIGHT: f64 = 0.7;
+
+/// Sharpness of a photo: variance of the Laplacian of its grayscale at `SHARPNESS_SIZE`
+/// Higher is sharper; below ~100 a photo is usually blurry or out of focus, though flat
+/// scenes (sky, fog) score low too
+pub fn sharpness(img: &DynamicImage) -> f64 {
+	let gray = if img.width().max(img.height()) > SHARPNESS_SIZE {
+		img.resize(SHARPNESS_SIZE, SHARPNESS_SIZE, FilterType::Triangle)
+			.to_luma8()
+	} else {
+		img.to_luma8()
+	};
+	laplacian_variance(&gray)
+}
+
+/// Variance of the 4-neighbour Laplacian over the inner pixels of an image
+fn laplacian_variance(gray: &GrayImage) -> f64 {
+	let (width, height) = gray.dimensions();
+	if width < 3 || height < 3 {
+		return 0.0;
+	}
+
+	let pixel = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+	let (mut sum, mut sum_sq) = (0.0, 0.0);
+	for y in 1..height - 1 {
+		for x in 1..width - 1 {
+			let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1)
+				- 4.0 * pixel(x, y);
+			sum += laplacian;
+			sum_sq += laplacian * laplacian;
+		}
+	}
+	let count = ((width - 2) * (height - 2)) as f64;
+	let mean = sum / count;
+	sum_sq / count - mean * mean
+}
+
+/// Tonal distribution of a photo, for drawing histograms and spotting clipped exposure
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExposureAnalysis {
+	/// Pixel counts of each of the 256 levels of Rec. 709 luminance
+	pub luminance: Vec<u32>,
+	/// Pixel counts of each of the 256 levels of a channel
+	pub red: Vec<u32>,
+	pub green: Vec<u32>,
+	pub blue: Vec<u32>,
+	/// Percentage of pixels with a channel at 255
+	pub clipped_highlights: f64,
+	/// Percentage of pixels with a channel at 0
+	pub clipped_shadows: f64,
+	/// Mean luminance (0-255)
+	pub mean_luminance: f64,
+}
+
+/// Histograms and clipping of every pixel of an image
+pub(crate) fn exposure(img: &DynamicImage) -> ExposureAnalysis {
+	let rgb = img.to_rgb8();
+	let mut luminance = vec![0; 256];
+	let mut channels = [vec![0; 256], vec![0; 256], vec![0; 256]];
+	let (mut highlights, mut shadows, mut luminance_sum) = (0u64, 0u64, 0u64);
+	for pixel in rgb.pixels() {
+		let [r, g, b] = pixel.0;
+		let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as usize;
+		luminance[luma.min(255)] += 1;
+		luminance_sum += luma as u64;
+		for (channel, value) in channels.iter_mut().zip(pixel.0) {
+			channel[value as usize] += 1;
+		}
+		highlights += pixel.0.contains(&255) as u64;
+		shadows += pixel.0.contains(&0) as u64;
+	}
+
+	let count = (rgb.width() as u64 * rgb.height() as u64).max(1) as f64;
+	let [red, green, blue] = channels;
+	ExposureAnalysis {
+		luminance,
+		red,
+		green,
+		blue,
+		clipped_highlights: highlights as f64 * 100.0 / count,
+		clipped_shadows: shadows as f64 * 100.0 / count,
+		mean_luminance: luminance_sum as f64 / count,
+	}
+}
+
+/// Histograms and clipping of a photo file, see `analyze_exposure`
+pub fn analyze_exposure_internal(file_path: &str) -> Result<ExposureAnalysis, ProcessingError> {
+	Ok(exposure(&decode_upright(file_path)?))
+}
+
+/// Histograms and clipping of encoded image bytes, see `analyze_exposure`
+pub fn analyze_exposure_from_bytes(data: &[u8]) -> Result<ExposureAnalysis, ProcessingError> {
+	Ok(exposure(&decode_bytes(data)?))
+}
+
+/// Luminance and RGB histograms of a photo (file path or encoded bytes) with the share of
+/// clipped highlights and shadows, so the editor can draw them without decoding in JS
+#[cfg(feature = "node")]
+#[napi]
+pub fn analyze_exposure(
+	source: Either<String, Buffer>,
+) -> napi::Result<ExposureAnalysis, ErrorCode> {
+	match &source {
+		Either::A(file_path) => analyze_exposure_internal(file_path),
+		Either::B(data) => analyze_exposure_from_bytes(data),
+	}
+	.map_err(napi::Error::from)
+}
+
+/// How well exposed a photo is (0-1): 1 for a mid-gray mean with nothing clipped, lower as
+/// the mean drifts to black or white and as highlights or shadows clip
+fn exposure_score(analysis: &ExposureAnalysis) -> f64 {
+	let balance = 1.0 - (analysis.mean_luminance - 128.0).abs() / 128.0;
+	let clipped = (analysis.clipped_highlights + analysis.clipped_shadows) / 100.0;
+	(balance * (1.0 - 2.0 * clipped)).clamp(0.0, 1.0)
+}
+
+/// Rank of a shot within a burst or stack
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShotScore {
+	/// Position in the input
+	pub index: u32,
+	/// Overall score (0-1), higher is better
+	pub score: f64,
+	/// Variance of the Laplacian, as in processing results
+	pub sharpness: f64,
+	/// How well exposed the shot is (0-1)
+	pub exposure_score: f64,
+}
+
+/// Score shots from their sharpness, relative to the sharpest, and exposure; best first
+fn rank_shots(measures: Vec<(usize, f64, f64)>) -> Vec<ShotScore> {
+	let sharpest = measures
+		.iter()
+		.map(|&(_, sharpness, _)| sharpness)
+		.fold(0.0, f64::max);
+	let mut shots: Vec<ShotScore> = measures
+		.into_iter()
+		.map(|(index, sharpness, exposure_score)| {
+			let relative_sharpness = if sharpest > 0.0 {
+				sharpness / sharpest
+			} else {
+				0.0
+			};
+			ShotScore {
+				index: index as u32,
+				score: SHOT_SHARPNESS_WEIGHT * relative_sharpness
+					+ (1.0 - SHOT_SHARPNESS_WEIGHT) * exposure_score,
+				sharpness,
+				exposure_score,
+			}
+		})
+		.collect();
+	shots.sort_by(|a, b| b.score.total_cmp(&a.score));
+	shots
+}
+
+/// Rank the frames of a burst or a stack of near-duplicates by sharpness and exposure, so
+/// the stack can use the best one as its cover
+/// Returns the scores best first; files that can't be decoded are left out
+#[cfg_attr(feature = "node", napi)]
+pub fn pick_best_shot(file_paths: Vec<String>) -> Vec<ShotScore> {
+	let measures = file_paths
+		.par_iter()
+		.enumerate()
+		.filter_map(|(index, file_path)| {
+			let img = decode_upright(file_path).ok()?;
+			let small = img.thumbnail(SHARPNESS_SIZE, SHARPNESS_SIZE);
+			Some((index, sharpness(&img), exposure_score(&exposure(&small))))
+		})
+		.collect();
+	rank_shots(measures)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Luma, Rgb, RgbImage};
+
+	#[test]
+	fn test_sharpness_drops_with_blur() {
+		let checkerboard = GrayImage::from_fn(64, 64, |x, y| {
+			Luma([if (x / 4 + y / 4) % 2 == 0 { 0 } else { 255 }])
+		});
+		let sharp = DynamicImage::ImageLuma8(checkerboard);
+		let blurred = sharp.blur(3.0);
+
+		assert!(sharpness(&sharp) > sharpness(&blurred) * 10.0);
+		let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(64, 64, Luma([128])));
+		assert_eq!(sharpness(&flat), 0.0);
+	}
+
+	#[test]
+	fn test_exposure_histograms() {
+		let mut img = RgbImage::from_pixel(2, 2, Rgb([255, 255, 255]));
+		img.put_pixel(0, 0, Rgb([0, 0, 0]));
+		img.put_pixel(1, 0, Rgb([255, 0, 0]));
+		let analysis = exposure(&DynamicImage::ImageRgb8(img));
+
+		assert_eq!(analysis.luminance[255], 2);
+		assert_eq!(analysis.luminance[0], 1);
+		assert_eq!(analysis.luminance[54], 1);
+		assert_eq!(
+			(analysis.red[255], analysis.green[0], analysis.blue[0]),
+			(3, 2, 2)
+		);
+		assert_eq!(analysis.clipped_highlights, 75.0);
+		assert_eq!(analysis.clipped_shadows, 50.0);
+	}
+
+	#[test]
+	fn test_rank_shots() {
+		let mut mid_gray = RgbImage::from_pixel(4, 4, Rgb([128, 128, 128]));
+		assert_eq!(
+			exposure_score(&exposure(&DynamicImage::ImageRgb8(mid_gray.clone()))),
+			1.0
+		);
+		mid_gray.put_pixel(0, 0, Rgb([255, 255, 255]));
+		let clipped = exposure_score(&exposure(&DynamicImage::ImageRgb8(mid_gray)));
+		assert!(clipped > 0.8 && clipped < 1.0);
+
+		// A sharp but dark shot loses to a nearly as sharp, well exposed one
+		let shots = rank_shots(vec![(0, 50.0, 0.9), (1, 200.0, 0.1), (2, 180.0, 0.9)]);
+		let order: Vec<u32> = shots.iter().map(|shot| shot.index).collect();
+		assert_eq!(order, [2, 1, 0]);
+		assert!((shots[0].score - (0.7 * 0.9 + 0.3 * 0.9)).abs() < 1e-9);
+	}
+}
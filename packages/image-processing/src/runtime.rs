@@ -0,0 +1,161 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, RwLock};
+
+use crate::clip::set_model_cache_dir;
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::thumbnails::ThumbnailFormat;
+
+/// Process-wide settings from `configure_runtime`
+#[derive(Debug, Clone, Default)]
+struct RuntimeConfig {
+	rayon_threads: Option<usize>,
+	thumbnail_format: Option<ThumbnailFormat>,
+	tmp_dir: Option<PathBuf>,
+}
+
+static RUNTIME: RwLock<RuntimeConfig> = RwLock::new(RuntimeConfig {
+	rayon_threads: None,
+	thumbnail_format: None,
+	tmp_dir: None,
+});
+
+fn runtime() -> RuntimeConfig {
+	RUNTIME.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct RuntimeOptions {
+	/// Threads of the shared pool and of each batch pool (default: one per core, and at
+	/// most 4 for batches without a memory budget)
+	/// The shared pool is sized once, so this must be set before any processing starts
+	pub rayon_threads: Option<u32>,
+	/// RAW files decoded at the same time across all calls (default: no limit), since
+	/// LibRaw holds the whole file and sensor data in memory
+	pub raw_concurrency: Option<u32>,
+	/// Directory holding downloaded models, as with `configure_models`
+	pub model_cache_dir: Option<String>,
+	/// Thumbnail format used when a call doesn't give one (default WebP)
+	pub thumbnails_format: Option<ThumbnailFormat>,
+	/// Directory for intermediate files, e.g. exports while their metadata is written
+	/// (default: the system's temp directory)
+	pub tmp_dir: Option<String>,
+}
+
+/// Tune the native layer once at startup, see `configure_runtime`
+pub fn configure_runtime_internal(options: RuntimeOptions) -> Result<(), ProcessingError> {
+	let tmp_dir = options.tmp_dir.map(PathBuf::from);
+	if let Some(dir) = tmp_dir.as_ref().filter(|dir| !dir.is_dir()) {
+		let message = format!("Temp directory {} doesn't exist", dir.display());
+		return Err(ProcessingError::invalid(message));
+	}
+	if let Some(threads) = options.rayon_threads {
+		size_global_pool(threads.max(1) as usize)?;
+	}
+	if options.model_cache_dir.is_some() {
+		set_model_cache_dir(options.model_cache_dir)?;
+	}
+
+	let mut config = RUNTIME.write().unwrap_or_else(|e| e.into_inner());
+	if let Some(threads) = options.rayon_threads {
+		config.rayon_threads = Some(threads.max(1) as usize);
+	}
+	if let Some(format) = options.thumbnails_format {
+		config.thumbnail_format = Some(format);
+	}
+	if tmp_dir.is_some() {
+		config.tmp_dir = tmp_dir;
+	}
+	if let Some(limit) = options.raw_concurrency {
+		let mut slots = RAW_SLOTS.state.lock().unwrap_or_else(|e| e.into_inner());
+		slots.limit = Some(limit.max(1) as usize);
+		RAW_SLOTS.released.notify_all();
+	}
+	Ok(())
+}
+
+/// Tune the native layer once at startup, e.g. fewer threads on a NAS or a model cache
+/// bundled with the app
+/// Settings left out keep their current value
+#[cfg(feature = "node")]
+#[napi]
+pub fn configure_runtime(options: RuntimeOptions) -> napi::Result<(), ErrorCode> {
+	configure_runtime_internal(options).map_err(napi::Error::from)
+}
+
+/// Size rayon's shared pool, which can only happen before its first use
+fn size_global_pool(threads: usize) -> Result<(), ProcessingError> {
+	let built = rayon::ThreadPoolBuilder::new()
+		.num_threads(threads)
+		.build_global();
+	if built.is_ok() || rayon::current_num_threads() == threads {
+		Ok(())
+	} else {
+		Err(ProcessingError::invalid(format!(
+			"The thread pool already runs {} threads; set rayon_threads before processing",
+			rayon::current_num_threads()
+		)))
+	}
+}
+
+/// Configured thread count for batch pools, if any
+pub(crate) fn rayon_threads() -> Option<usize> {
+	runtime().rayon_threads
+}
+
+/// Thumbnail format used when a call doesn't give one
+pub(crate) fn default_thumbnail_format() -> ThumbnailFormat {
+	runtime().thumbnail_format.unwrap_or_default()
+}
+
+/// Directory for intermediate files
+pub(crate) fn tmp_dir() -> PathBuf {
+	runtime().tmp_dir.unwrap_or_else(std::env::temp_dir)
+}
+
+/// RAW decodes in flight and their limit from `raw_concurrency`
+struct RawSlotState {
+	limit: Option<usize>,
+	in_use: usize,
+}
+
+struct RawSlots {
+	state: Mutex<RawSlotState>,
+	released: Condvar,
+}
+
+static RAW_SLOTS: RawSlots = RawSlots {
+	state: Mutex::new(RawSlotState {
+		limit: None,
+		in_use: 0,
+	}),
+	released: Condvar::new(),
+};
+
+/// Slot held while decoding a RAW file, released when dropped
+pub(crate) struct RawPermit;
+
+/// Wait for a RAW decoding slot
+pub(crate) fn acquire_raw_permit() -> RawPermit {
+	let mut slots = RAW_SLOTS.state.lock().unwrap_or_else(|e| e.into_inner());
+	while slots.limit.is_some_and(|limit| slots.in_use >= limit) {
+		slots = RAW_SLOTS
+			.released
+			.wait(slots)
+			.unwrap_or_else(|e| e.into_inner());
+	}
+	slots.in_use += 1;
+	RawPermit
+}
+
+impl Drop for RawPermit {
+	fn drop(&mut self) {
+		let mut slots = RAW_SLOTS.state.lock().unwrap_or_else(|e| e.into_inner());
+		slots.in_use -= 1;
+		RAW_SLOTS.released.notify_all();
+	}
+}
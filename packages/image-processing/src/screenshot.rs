@@ -0,0 +1,172 @@
+use image::{imageops::FilterType, DynamicImage};
+
+use crate::exif::ExifData;
+
+/// Screen resolutions (short side, long side) of common phones, tablets and computers
+const SCREEN_SIZES: &[(u32, u32)] = &[
+	// iPhone
+	(640, 1136),
+	(750, 1334),
+	(828, 1792),
+	(1080, 1920),
+	(1125, 2436),
+	(1170, 2532),
+	(1179, 2556),
+	(1242, 2208),
+	(1242, 2688),
+	(1284, 2778),
+	(1290, 2796),
+	(1206, 2622),
+	(1320, 2868),
+	// Android
+	(720, 1280),
+	(720, 1600),
+	(1080, 2220),
+	(1080, 2280),
+	(1080, 2340),
+	(1080, 2400),
+	(1440, 2560),
+	(1440, 2960),
+	(1440, 3040),
+	(1440, 3120),
+	(1440, 3200),
+	// iPad
+	(1536, 2048),
+	(1620, 2160),
+	(1640, 2360),
+	(1668, 2224),
+	(1668, 2388),
+	(2048, 2732),
+	// Desktop and laptop
+	(768, 1366),
+	(900, 1440),
+	(1200, 1920),
+	(1440, 2560),
+	(1600, 2560),
+	(1800, 2880),
+	(1964, 3024),
+	(2234, 3456),
+	(2160, 3840),
+];
+
+/// Side images are scaled to before measuring their colors; nearest neighbour keeps flat
+/// areas flat
+const COLOR_SAMPLE_SIZE: u32 = 256;
+
+/// Share of pixels identical to their right neighbour above which an image looks like UI
+/// (flat backgrounds, solid bars); sensor noise keeps photos far below
+const UI_FLAT_SHARE: f64 = 0.5;
+
+/// Recognized characters above which an image reads like a screen
+const TEXT_HINT_LENGTH: usize = 80;
+
+/// Score at which an image counts as a screenshot
+const SCREENSHOT_THRESHOLD: f64 = 1.0;
+
+/// Share of pixels identical to their right neighbour in a downscaled copy of an image
+fn flat_share(img: &DynamicImage) -> f64 {
+	let rgb = img
+		.resize(COLOR_SAMPLE_SIZE, COLOR_SAMPLE_SIZE, FilterType::Nearest)
+		.to_rgb8();
+	let (width, height) = rgb.dimensions();
+	if width < 2 {
+		return 0.0;
+	}
+	let flat = rgb
+		.rows()
+		.map(|row| {
+			let row: Vec<_> = row.collect();
+			row.windows(2).filter(|pair| pair[0] == pair[1]).count()
+		})
+		.sum::<usize>();
+	flat as f64 / ((width - 1) * height) as f64
+}
+
+/// Screenshot likelihood from each signal; `SCREENSHOT_THRESHOLD` or more is a screenshot
+/// A screenshot name decides on its own, camera EXIF rules one out, and otherwise it takes
+/// two of a screen-sized image, UI-like colors and lots of text besides missing camera EXIF
+fn screenshot_score(
+	width: u32,
+	height: u32,
+	file_name: &str,
+	exif: Option<&ExifData>,
+	flat_share: f64,
+	text: Option<&str>,
+) -> f64 {
+	let name: String = file_name
+		.to_lowercase()
+		.chars()
+		.filter(|c| c.is_alphanumeric())
+		.collect();
+	let has_camera_exif = exif.is_some_and(|e| {
+		e.camera_model.is_some() || e.exposure_time.is_some() || e.f_number.is_some()
+	});
+	let screen_sized = SCREEN_SIZES.contains(&(width.min(height), width.max(height)));
+	let text_length = text.map_or(0, |text| text.chars().count());
+
+	let mut score = 0.0;
+	if name.contains("screenshot") || name.contains("bildschirmfoto") {
+		score += 1.0;
+	}
+	score += if has_camera_exif { -1.0 } else { 0.25 };
+	if screen_sized {
+		score += 0.5;
+	}
+	if flat_share >= UI_FLAT_SHARE {
+		score += 0.5;
+	}
+	if text_length >= TEXT_HINT_LENGTH {
+		score += 0.25;
+	}
+	score
+}
+
+/// Whether an upright image looks like a screenshot, from its file name, screen-sized
+/// dimensions, missing camera EXIF, flat UI-like colors and recognized text (when OCR ran)
+pub fn is_screenshot(
+	img: &DynamicImage,
+	file_name: &str,
+	exif: Option<&ExifData>,
+	text: Option<&str>,
+) -> bool {
+	let score = screenshot_score(
+		img.width(),
+		img.height(),
+		file_name,
+		exif,
+		flat_share(img),
+		text,
+	);
+	score >= SCREENSHOT_THRESHOLD
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn test_screenshot_signals() {
+		let camera = ExifData {
+			camera_model: Some("iPhone 15 Pro".to_string()),
+			f_number: Some(1.78),
+			..Default::default()
+		};
+		assert!(screenshot_score(1179, 2556, "IMG_0001.PNG", None, 0.8, None) >= 1.0);
+		assert!(screenshot_score(3024, 4032, "Screenshot 2024-05-01.png", None, 0.0, None) >= 1.0);
+		assert!(screenshot_score(1179, 2556, "IMG_0002.HEIC", Some(&camera), 0.8, None) < 1.0);
+		// A screen-sized photo needs another hint
+		assert!(screenshot_score(1080, 1920, "IMG_0003.JPG", None, 0.1, None) < 1.0);
+
+		let ui = RgbImage::from_fn(300, 600, |_, y| {
+			Rgb(if y < 60 {
+				[30, 30, 30]
+			} else {
+				[255, 255, 255]
+			})
+		});
+		let noise = RgbImage::from_fn(300, 600, |x, y| Rgb([(x * 7 + y * 13) as u8, 90, 120]));
+		assert!(flat_share(&DynamicImage::ImageRgb8(ui)) > 0.99);
+		assert!(flat_share(&DynamicImage::ImageRgb8(noise)) < 0.01);
+	}
+}
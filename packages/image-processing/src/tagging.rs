@@ -0,0 +1,296 @@
+use image::{imageops::FilterType, DynamicImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use ort::session::Session;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clip::{
+	decode_upright, load_onnx_session, run_onnx_session, softmax, ConfiguredModel, LabelScore,
+};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+
+/// Input side of most off-the-shelf classifiers (MobileNet, EfficientNet-B0, ResNet)
+const DEFAULT_INPUT_SIZE: u32 = 224;
+
+/// Per-channel normalization of ImageNet-trained models
+const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+/// Tags scoring below this are dropped unless the model options set another threshold
+const DEFAULT_MIN_SCORE: f64 = 0.1;
+
+/// Tags returned by `tag_photo` when no count is given
+const DEFAULT_MAX_TAGS: u32 = 5;
+
+/// Tagging model to load, set by `configure_tagging_model`
+#[cfg_attr(feature = "node", napi(object))]
+pub struct TaggingModelOptions {
+	/// ONNX image classifier with a 1x3xNxN float input and one score per class,
+	/// e.g. MobileNetV3 trained on Places365 (scenes) or ImageNet (objects)
+	pub model_path: String,
+	/// Text file with the label of each output class, one per line
+	pub labels_path: String,
+	/// Side of the model's square input (default 224)
+	pub input_size: Option<u32>,
+	/// Per-channel RGB normalization of the input (default ImageNet's)
+	pub mean: Option<Vec<f64>>,
+	pub std: Option<Vec<f64>>,
+	/// Score each class on its own with a sigmoid (multi-label taggers) instead of a
+	/// softmax over all classes (default false)
+	pub multi_label: Option<bool>,
+	/// Drop tags scoring below this (default 0.1)
+	pub min_score: Option<f64>,
+}
+
+/// An ONNX image classifier: its labels and how images are prepared for it
+#[derive(Debug, Clone)]
+pub(crate) struct Classifier {
+	pub model_path: PathBuf,
+	pub labels: Vec<String>,
+	pub input_size: u32,
+	pub mean: [f32; 3],
+	pub std: [f32; 3],
+	/// Score each class on its own with a sigmoid instead of a softmax over all classes
+	pub multi_label: bool,
+}
+
+/// Three channel values from the options, or the ImageNet ones
+fn channel_values(
+	values: Option<Vec<f64>>,
+	default: [f32; 3],
+	name: &str,
+) -> Result<[f32; 3], ProcessingError> {
+	match values.as_deref() {
+		None => Ok(default),
+		Some(&[r, g, b]) => Ok([r as f32, g as f32, b as f32]),
+		Some(_) => Err(ProcessingError::invalid(format!(
+			"Model {} needs 3 values (R, G, B)",
+			name
+		))),
+	}
+}
+
+impl Classifier {
+	/// Validate a classifier's model and read its labels, one per line
+	/// Input size and normalization default to those of ImageNet-trained models
+	pub fn new(
+		model_path: &str,
+		labels_path: &str,
+		input_size: Option<u32>,
+		mean: Option<Vec<f64>>,
+		std: Option<Vec<f64>>,
+	) -> Result<Self, ProcessingError> {
+		if !Path::new(model_path).is_file() {
+			return Err(ProcessingError::invalid(format!(
+				"Model {} doesn't exist",
+				model_path
+			)));
+		}
+		let labels: Vec<String> = fs::read_to_string(labels_path)
+			.map_err(|e| ProcessingError::io(&e, &format!("Failed to read {}", labels_path)))?
+			.lines()
+			.map(|label| label.trim().to_string())
+			.collect();
+		if labels.is_empty() {
+			return Err(ProcessingError::invalid(format!(
+				"{} has no labels",
+				labels_path
+			)));
+		}
+		let input_size = input_size.unwrap_or(DEFAULT_INPUT_SIZE);
+		if input_size == 0 {
+			return Err(ProcessingError::invalid(
+				"Model input size must be positive",
+			));
+		}
+
+		Ok(Self {
+			model_path: PathBuf::from(model_path),
+			labels,
+			input_size,
+			mean: channel_values(mean, IMAGENET_MEAN, "mean")?,
+			std: channel_values(std, IMAGENET_STD, "std")?,
+			multi_label: false,
+		})
+	}
+
+	/// Model input: the image center-cropped to a square, normalized, in NCHW order
+	fn input_values(&self, img: &DynamicImage) -> Vec<f32> {
+		let size = self.input_size;
+		let rgb = img
+			.resize_to_fill(size, size, FilterType::Triangle)
+			.to_rgb8();
+		let plane = (size * size) as usize;
+		let mut values = vec![0.0; 3 * plane];
+		for (i, pixel) in rgb.pixels().enumerate() {
+			for c in 0..3 {
+				values[c * plane + i] = (pixel[c] as f32 / 255.0 - self.mean[c]) / self.std[c];
+			}
+		}
+		values
+	}
+
+	/// Score of each label for an upright image, in the order of the labels
+	pub fn scores(
+		&self,
+		session: &Session,
+		img: &DynamicImage,
+	) -> Result<Vec<f32>, ProcessingError> {
+		let size = self.input_size as usize;
+		let (_, logits) = run_onnx_session(session, [1, 3, size, size], self.input_values(img))?;
+		if logits.len() != self.labels.len() {
+			return Err(ProcessingError::invalid(format!(
+				"Model has {} classes but {} labels",
+				logits.len(),
+				self.labels.len()
+			)));
+		}
+
+		Ok(if self.multi_label {
+			logits
+				.iter()
+				.map(|&logit| 1.0 / (1.0 + (-logit).exp()))
+				.collect()
+		} else {
+			softmax(&logits)
+		})
+	}
+}
+
+/// Validated tagging model settings
+#[derive(Debug, Clone)]
+struct TaggingConfig {
+	classifier: Classifier,
+	min_score: f32,
+}
+
+/// Global tagging model, loaded on first use
+static TAGGING_MODEL: ConfiguredModel<TaggingConfig, Session> =
+	ConfiguredModel::new("No tagging model configured");
+
+/// Whether a tagging model is configured, so photos can be tagged
+pub fn has_tagging_model() -> bool {
+	TAGGING_MODEL.is_configured()
+}
+
+fn load_tagging_model(config: &TaggingConfig) -> Result<Session, ProcessingError> {
+	load_onnx_session(&config.classifier.model_path)
+}
+
+fn tagging_config_from_options(
+	options: TaggingModelOptions,
+) -> Result<TaggingConfig, ProcessingError> {
+	let mut classifier = Classifier::new(
+		&options.model_path,
+		&options.labels_path,
+		options.input_size,
+		options.mean,
+		options.std,
+	)
+	.map_err(|e| e.context("Invalid tagging model"))?;
+	classifier.multi_label = options.multi_label.unwrap_or(false);
+
+	Ok(TaggingConfig {
+		classifier,
+		min_score: options.min_score.unwrap_or(DEFAULT_MIN_SCORE) as f32,
+	})
+}
+
+/// Set the tagging model, see `configure_tagging_model`
+pub fn configure_tagging_model_internal(
+	options: Option<TaggingModelOptions>,
+) -> Result<(), ProcessingError> {
+	let config = options.map(tagging_config_from_options).transpose()?;
+	TAGGING_MODEL.configure(config);
+	Ok(())
+}
+
+/// Set the ONNX model photos are tagged with, or remove it with null
+/// Its labels are read now; the model itself loads with the first photo tagged
+#[cfg(feature = "node")]
+#[napi]
+pub fn configure_tagging_model(
+	options: Option<TaggingModelOptions>,
+) -> napi::Result<(), ErrorCode> {
+	configure_tagging_model_internal(options).map_err(napi::Error::from)
+}
+
+/// Best `max_tags` labels scoring at least `min_score`, best first
+fn top_tags(scores: &[f32], labels: &[String], max_tags: usize, min_score: f32) -> Vec<LabelScore> {
+	let mut ranked: Vec<(usize, f32)> = scores
+		.iter()
+		.copied()
+		.enumerate()
+		.filter(|&(_, score)| score >= min_score)
+		.collect();
+	ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+	ranked
+		.into_iter()
+		.take(max_tags)
+		.map(|(i, score)| LabelScore {
+			label: labels[i].clone(),
+			score: score as f64,
+		})
+		.collect()
+}
+
+/// Scene/object tags of an upright image from the configured tagging model
+pub fn tag_image(img: &DynamicImage, max_tags: u32) -> Result<Vec<LabelScore>, ProcessingError> {
+	let (config, model) = TAGGING_MODEL.get(load_tagging_model)?;
+
+	let session = model.lock().unwrap_or_else(|e| e.into_inner());
+	let scores = config
+		.classifier
+		.scores(&session, img)
+		.map_err(|e| e.context("Tagging model error"))?;
+	Ok(top_tags(
+		&scores,
+		&config.classifier.labels,
+		max_tags as usize,
+		config.min_score,
+	))
+}
+
+/// Scene/object tags of a photo, see `tag_photo`
+pub fn tag_photo_internal(
+	file_path: &str,
+	max_tags: Option<u32>,
+) -> Result<Vec<LabelScore>, ProcessingError> {
+	let img = decode_upright(file_path)?;
+	tag_image(&img, max_tags.unwrap_or(DEFAULT_MAX_TAGS))
+}
+
+/// Tag a photo with scene/object labels from the model set with `configure_tagging_model`
+/// Returns up to `max_tags` (default 5) labels with their scores, best first
+#[cfg(feature = "node")]
+#[napi]
+pub fn tag_photo(
+	file_path: String,
+	max_tags: Option<u32>,
+) -> napi::Result<Vec<LabelScore>, ErrorCode> {
+	tag_photo_internal(&file_path, max_tags).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_top_tags() {
+		let labels: Vec<String> = ["beach", "dog", "mountain", "car"]
+			.iter()
+			.map(|label| label.to_string())
+			.collect();
+		let tags = top_tags(&[0.3, 0.05, 0.6, 0.2], &labels, 2, 0.1);
+		assert_eq!(
+			tags.iter()
+				.map(|tag| tag.label.as_str())
+				.collect::<Vec<_>>(),
+			["mountain", "beach"]
+		);
+		assert!(top_tags(&[0.3, 0.05, 0.6, 0.2], &labels, 5, 0.7).is_empty());
+	}
+}
@@ -1,18 +1,109 @@
-use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+#[cfg(feature = "node")]
 use napi_derive::napi;
 use rayon::prelude::*;
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
 
+use crate::animation::{decode_animation, Animation};
+use crate::color::{srgb_converter, SrgbConverter};
+use crate::content_hash::content_hash_bytes;
+use crate::crop::{center_square, smart_square};
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::logging::{log_event, LogLevel};
 use crate::orientation::apply_orientation;
+use crate::panorama::{aspect_ratio, PANORAMA_ASPECT_RATIO};
+use crate::runtime::default_thumbnail_format;
+use crate::Buffer;
 
-#[napi(object)]
+/// Largest thumbnail encoded as AVIF; bigger sizes fall back to WebP since AVIF encoding
+/// time grows quickly with resolution
+const AVIF_MAX_DIMENSION: u32 = 400;
+
+/// AVIF encoder speed (1 = slowest/smallest, 10 = fastest)
+const AVIF_SPEED: u8 = 6;
+
+/// Longest edge of the image a BlurHash is computed from
+const BLURHASH_SIZE: u32 = 32;
+
+/// Output format of generated thumbnails
+#[cfg_attr(feature = "node", napi(string_enum = "lowercase"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThumbnailFormat {
+  #[default]
+  Webp,
+  Avif,
+  Jpeg,
+}
+
+impl ThumbnailFormat {
+  pub const ALL: [ThumbnailFormat; 3] = [
+    ThumbnailFormat::Webp,
+    ThumbnailFormat::Avif,
+    ThumbnailFormat::Jpeg,
+  ];
+
+  /// Format actually used for a thumbnail of the given size
+  pub fn for_dimension(self, max_dimension: u32) -> Self {
+    match self {
+      ThumbnailFormat::Avif if max_dimension > AVIF_MAX_DIMENSION => ThumbnailFormat::Webp,
+      format => format,
+    }
+  }
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      ThumbnailFormat::Webp => "webp",
+      ThumbnailFormat::Avif => "avif",
+      ThumbnailFormat::Jpeg => "jpg",
+    }
+  }
+}
+
+/// Square crop applied before resizing, for grid tiles
+#[cfg_attr(feature = "node", napi(string_enum = "lowercase"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailCrop {
+  /// Largest centered square
+  Center,
+  /// Largest square over the most detailed part of the photo
+  Smart,
+}
+
+/// Unsharp mask applied after resizing
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnsharpMask {
+  /// Strength of the sharpening, e.g. 0.5 (0-5)
+  pub amount: f64,
+  /// Blur radius (sigma) in pixels, e.g. 0.8 (0-10)
+  pub radius: f64,
+}
+
+#[cfg_attr(feature = "node", napi(object))]
 pub struct ThumbnailConfig {
   pub max_dimension: u32,
   pub quality: u8,
+  /// Crop to a square; the full photo is kept when omitted
+  pub crop: Option<ThumbnailCrop>,
+  /// Sharpen after resizing, to counter the softness of downscaling
+  pub sharpen: Option<UnsharpMask>,
+  /// Encode JPEG thumbnails as progressive, for web delivery
+  pub progressive: Option<bool>,
+  /// Short animated WebP (8 sampled frames) for animated GIF/WebP sources
+  pub animated: Option<bool>,
+  /// Pad wide panoramas with bars to a usable tile shape instead of a sliver, for the grid
+  pub letterbox: Option<bool>,
 }
 
-#[napi(object)]
+#[cfg_attr(feature = "node", napi(object))]
 pub struct ThumbnailSizes {
   pub tiny: ThumbnailConfig,
   pub small: ThumbnailConfig,
@@ -20,47 +111,327 @@ pub struct ThumbnailSizes {
   pub large: ThumbnailConfig,
 }
 
+/// One caller-defined thumbnail size, written to `{thumbnails_dir}/{name}/...`
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailSize {
+  /// Folder name, e.g. "small" or "xlarge" (letters, digits, "-" and "_")
+  pub name: String,
+  pub max_dimension: u32,
+  pub quality: u8,
+  /// Overrides the default format for this size
+  pub format: Option<ThumbnailFormat>,
+  /// Crop to a square, e.g. "smart" for grid tiles
+  pub crop: Option<ThumbnailCrop>,
+  pub sharpen: Option<UnsharpMask>,
+  /// Progressive encoding when the format is JPEG
+  pub progressive: Option<bool>,
+  /// Animated WebP for animated GIF/WebP sources, best kept to grid sizes (tiny/small)
+  pub animated: Option<bool>,
+  /// Letterbox wide panoramas, for the grid size (small by default); other sizes keep the
+  /// panorama's own shape
+  pub letterbox: Option<bool>,
+}
+
+impl ThumbnailSize {
+  fn config(&self) -> ThumbnailConfig {
+    ThumbnailConfig {
+      max_dimension: self.max_dimension,
+      quality: self.quality,
+      crop: self.crop,
+      sharpen: self.sharpen,
+      progressive: self.progressive,
+      animated: self.animated,
+      letterbox: self.letterbox,
+    }
+  }
+
+  /// Whether a source image of this size renders the thumbnail without upscaling
+  fn fits_source(&self, width: u32, height: u32) -> bool {
+    let side = match self.crop {
+      Some(_) => width.min(height),
+      None => width.max(height),
+    };
+    side >= self.max_dimension
+  }
+}
+
 impl Default for ThumbnailSizes {
   fn default() -> Self {
     Self {
       tiny: ThumbnailConfig {
         max_dimension: 150,
         quality: 80,
+        crop: None,
+        sharpen: None,
+        progressive: None,
+        animated: None,
+        letterbox: None,
       },
       small: ThumbnailConfig {
         max_dimension: 400,
         quality: 85,
+        crop: None,
+        sharpen: None,
+        progressive: None,
+        animated: None,
+        letterbox: Some(true),
       },
       medium: ThumbnailConfig {
         max_dimension: 800,
         quality: 85,
+        crop: None,
+        sharpen: None,
+        progressive: None,
+        animated: None,
+        letterbox: None,
       },
       large: ThumbnailConfig {
         max_dimension: 1600,
         quality: 90,
+        crop: None,
+        sharpen: None,
+        progressive: None,
+        animated: None,
+        letterbox: None,
       },
     }
   }
 }
 
-/// Generate a single thumbnail from an image
-/// Maintains aspect ratio and uses Lanczos3 filter for best quality
-/// Saves as WebP format for optimal compression
-pub fn generate_thumbnail_from_image(
+impl ThumbnailSizes {
+  /// The default sizes as a list, named tiny/small/medium/large
+  pub fn to_list(&self) -> Vec<ThumbnailSize> {
+    [
+      ("tiny", &self.tiny),
+      ("small", &self.small),
+      ("medium", &self.medium),
+      ("large", &self.large),
+    ]
+    .into_iter()
+    .map(|(name, config)| ThumbnailSize {
+      name: name.to_string(),
+      max_dimension: config.max_dimension,
+      quality: config.quality,
+      format: None,
+      crop: config.crop,
+      sharpen: config.sharpen,
+      progressive: config.progressive,
+      animated: config.animated,
+      letterbox: config.letterbox,
+    })
+    .collect()
+  }
+}
+
+/// Validated thumbnail sizes to generate, with the format used by sizes that don't set one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailPlan {
+  pub sizes: Vec<ThumbnailSize>,
+  pub format: ThumbnailFormat,
+  /// Convert photos with a wide-gamut embedded profile (Display P3, Adobe RGB) to sRGB
+  pub color_manage: bool,
+  /// Render HDR photos (Apple HDR HEIC, Ultra HDR JPEG) with their gain map tone mapped in
+  pub tone_map_hdr: bool,
+}
+
+impl Default for ThumbnailPlan {
+  fn default() -> Self {
+    Self {
+      sizes: ThumbnailSizes::default().to_list(),
+      format: default_thumbnail_format(),
+      color_manage: true,
+      tone_map_hdr: false,
+    }
+  }
+}
+
+impl ThumbnailPlan {
+  /// Build a plan from optional caller settings, falling back to the default sizes
+  /// An empty size list is allowed and generates no thumbnails
+  pub fn new(
+    sizes: Option<Vec<ThumbnailSize>>,
+    format: Option<ThumbnailFormat>,
+  ) -> Result<Self, ProcessingError> {
+    let sizes = sizes.unwrap_or_else(|| ThumbnailSizes::default().to_list());
+
+    for (i, size) in sizes.iter().enumerate() {
+      let valid_name = !size.name.is_empty()
+        && size
+          .name
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+      if !valid_name {
+        return Err(ProcessingError::invalid(format!(
+          "Invalid thumbnail size name: {:?}",
+          size.name
+        )));
+      }
+      if size.max_dimension == 0 {
+        return Err(ProcessingError::invalid(format!(
+          "Thumbnail size {} has no max dimension",
+          size.name
+        )));
+      }
+      if let Some(mask) = size.sharpen
+        && !((0.0..=5.0).contains(&mask.amount) && mask.radius > 0.0 && mask.radius <= 10.0)
+      {
+        return Err(ProcessingError::invalid(format!(
+          "Invalid sharpening for size {}",
+          size.name
+        )));
+      }
+      if sizes[..i].iter().any(|other| other.name == size.name) {
+        return Err(ProcessingError::invalid(format!(
+          "Duplicate thumbnail size: {}",
+          size.name
+        )));
+      }
+    }
+
+    Ok(Self {
+      sizes,
+      format: format.unwrap_or_else(default_thumbnail_format),
+      color_manage: true,
+      tone_map_hdr: false,
+    })
+  }
+
+  /// Format used for a size: its own, or the plan's default (AVIF only for small sizes)
+  pub fn format_for(&self, size: &ThumbnailSize) -> ThumbnailFormat {
+    size
+      .format
+      .unwrap_or_else(|| self.format.for_dimension(size.max_dimension))
+  }
+
+  /// Largest animated size, which animation frames are decoded for; None without any
+  pub fn animated_dimension(&self) -> Option<u32> {
+    self
+      .sizes
+      .iter()
+      .filter(|size| size.animated == Some(true))
+      .map(|size| size.max_dimension)
+      .max()
+  }
+
+  /// Whether an image of this size (e.g. an embedded thumbnail) renders any size of the plan
+  pub fn any_fits(&self, width: u32, height: u32) -> bool {
+    self
+      .sizes
+      .iter()
+      .any(|size| size.fits_source(width, height))
+  }
+
+  /// Whether an image of this size renders every size of the plan, so the full image isn't needed
+  pub fn all_fit(&self, width: u32, height: u32) -> bool {
+    self
+      .sizes
+      .iter()
+      .all(|size| size.fits_source(width, height))
+  }
+
+  /// Fingerprint of every setting, to tell whether existing thumbnails were made with them
+  pub fn key(&self) -> String {
+    content_hash_bytes(format!("{:?}", self).as_bytes())
+  }
+}
+
+/// Encoded thumbnail returned in memory
+#[cfg_attr(feature = "node", napi(object))]
+pub struct ThumbnailBuffer {
+  pub size_name: String,
+  pub width: u32,
+  pub height: u32,
+  pub format: ThumbnailFormat,
+  pub data: Buffer,
+}
+
+/// Write an encoded thumbnail, creating its parent directory if it doesn't exist
+fn write_thumbnail(output_path: &str, data: &[u8]) -> Result<(), String> {
+  if let Some(parent) = Path::new(output_path).parent() {
+    fs::create_dir_all(parent)
+      .map_err(|e| format!("Failed to create thumbnail directory: {}", e))?;
+  }
+
+  fs::write(output_path, data).map_err(|e| format!("Failed to save thumbnail: {}", e))
+}
+
+/// Render one size of a plan, returning its format, width, height and encoded bytes
+/// Animated sizes of an animated source are always WebP, and sizes an embedded
+/// thumbnail is big enough for are rendered from it
+fn render_size(
+  img: &DynamicImage,
+  embedded: Option<&DynamicImage>,
+  size: &ThumbnailSize,
+  plan: &ThumbnailPlan,
+  srgb: Option<&SrgbConverter>,
+  animation: Option<&Animation>,
+) -> Result<(ThumbnailFormat, u32, u32, Vec<u8>), String> {
+  let config = size.config();
+  match animation.filter(|_| size.animated == Some(true)) {
+    Some(animation) => {
+      let (width, height, data) = animation.render(&config, srgb)?;
+      Ok((ThumbnailFormat::Webp, width, height, data))
+    }
+    None => {
+      let format = plan.format_for(size);
+      let source = embedded
+        .filter(|embedded| size.fits_source(embedded.width(), embedded.height()))
+        .unwrap_or(img);
+      let (width, height, data) = render_thumbnail(source, &config, format, srgb)?;
+      Ok((format, width, height, data))
+    }
+  }
+}
+
+/// Resize and encode a single thumbnail, returning its width, height and encoded bytes
+/// Pixels are converted to sRGB after resizing when a converter is given, since the
+/// encoded thumbnails carry no color profile
+pub fn render_thumbnail(
   img: &DynamicImage,
   config: &ThumbnailConfig,
-  output_path: &str,
-) -> Result<(), String> {
+  format: ThumbnailFormat,
+  srgb: Option<&SrgbConverter>,
+) -> Result<(u32, u32, Vec<u8>), String> {
+  let thumbnail = resize_thumbnail(img, config, srgb);
+
+  // Encode with the specified quality
+  let progressive = config.progressive.unwrap_or(false);
+  let data = encode_thumbnail(&thumbnail, format, config.quality, progressive)?;
+  Ok((thumbnail.width(), thumbnail.height(), data))
+}
+
+/// Crop, resize, color convert and sharpen an image according to a thumbnail config
+/// Maintains aspect ratio (unless cropped to a square) and uses Lanczos3 filter for best quality
+pub fn resize_thumbnail(
+  img: &DynamicImage,
+  config: &ThumbnailConfig,
+  srgb: Option<&SrgbConverter>,
+) -> DynamicImage {
+  // Crop to a square first if requested
+  let cropped;
+  let img = match config.crop {
+    Some(crop) => {
+      let region = match crop {
+        ThumbnailCrop::Center => center_square(img.width(), img.height()),
+        ThumbnailCrop::Smart => smart_square(img),
+      };
+      cropped = img.crop_imm(region.x, region.y, region.side, region.side);
+      &cropped
+    }
+    None => img,
+  };
+
   // Calculate new dimensions maintaining aspect ratio
   let (width, height) = img.dimensions();
   let max_dim = config.max_dimension;
 
   let (new_width, new_height) = if width > height {
     let ratio = width as f32 / height as f32;
-    (max_dim, (max_dim as f32 / ratio) as u32)
+    (max_dim, ((max_dim as f32 / ratio) as u32).max(1))
   } else {
     let ratio = height as f32 / width as f32;
-    ((max_dim as f32 / ratio) as u32, max_dim)
+    (((max_dim as f32 / ratio) as u32).max(1), max_dim)
   };
 
   // Only resize if image is larger than target
@@ -70,100 +441,347 @@ pub fn generate_thumbnail_from_image(
     // Image is already smaller than target, use as-is
     img.clone()
   };
+  let thumbnail = match srgb {
+    Some(converter) => converter.convert(thumbnail),
+    None => thumbnail,
+  };
+  let thumbnail = match config.sharpen {
+    Some(mask) => unsharp_mask(&thumbnail, mask),
+    None => thumbnail,
+  };
+  if config.letterbox == Some(true) && aspect_ratio(width, height) >= PANORAMA_ASPECT_RATIO {
+    letterbox(&thumbnail, PANORAMA_ASPECT_RATIO)
+  } else {
+    thumbnail
+  }
+}
 
-  // Create parent directory if it doesn't exist
-  if let Some(parent) = Path::new(output_path).parent() {
-    fs::create_dir_all(parent)
-      .map_err(|e| format!("Failed to create thumbnail directory: {}", e))?;
+/// Pad an image with black (transparent with alpha) bars to at most `max_ratio`, centered,
+/// so wide panoramas keep a usable preview shape instead of a sliver
+fn letterbox(img: &DynamicImage, max_ratio: f64) -> DynamicImage {
+  let (width, height) = img.dimensions();
+  let (canvas_width, canvas_height) = if width >= height {
+    (width, height.max((width as f64 / max_ratio).round() as u32))
+  } else {
+    (width.max((height as f64 / max_ratio).round() as u32), height)
+  };
+  let mut canvas = DynamicImage::new(canvas_width, canvas_height, img.color());
+  let x = (canvas_width - width) / 2;
+  let y = (canvas_height - height) / 2;
+  image::imageops::overlay(&mut canvas, img, x as i64, y as i64);
+  canvas
+}
+
+/// Sharpen an image: original + amount * (original - blurred), leaving alpha untouched
+pub fn unsharp_mask(img: &DynamicImage, mask: UnsharpMask) -> DynamicImage {
+  let mut pixels = img.to_rgba8();
+  let blurred = image::imageops::blur(&pixels, mask.radius as f32);
+  let amount = mask.amount as f32;
+
+  for (pixel, soft) in pixels.pixels_mut().zip(blurred.pixels()) {
+    for c in 0..3 {
+      let value = pixel[c] as f32;
+      let sharpened = value + amount * (value - soft[c] as f32);
+      pixel[c] = sharpened.round().clamp(0.0, 255.0) as u8;
+    }
   }
 
-  // Save as WebP with specified quality
-  // Note: The image crate's WebP encoder doesn't support quality parameter directly
-  // It uses lossless WebP by default, which is still much smaller than JPEG
-  thumbnail
-    .save_with_format(output_path, ImageFormat::WebP)
-    .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+  if img.color().has_alpha() {
+    DynamicImage::ImageRgba8(pixels)
+  } else {
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(pixels).to_rgb8())
+  }
+}
 
-  Ok(())
+/// Encode a thumbnail in the given format at the given quality (0-100)
+/// `progressive` only applies to JPEG
+pub fn encode_thumbnail(
+  img: &DynamicImage,
+  format: ThumbnailFormat,
+  quality: u8,
+  progressive: bool,
+) -> Result<Vec<u8>, String> {
+  let quality = quality.min(100);
+  match format {
+    ThumbnailFormat::Webp => encode_webp(img, quality),
+    ThumbnailFormat::Avif => {
+      let mut data = Vec::new();
+      let encoder = AvifEncoder::new_with_speed_quality(&mut data, AVIF_SPEED, quality);
+      img
+        .write_with_encoder(encoder)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+      Ok(data)
+    }
+    ThumbnailFormat::Jpeg if progressive => encode_progressive_jpeg(img, quality),
+    ThumbnailFormat::Jpeg => {
+      // JPEG has no alpha channel
+      let mut data = Vec::new();
+      DynamicImage::ImageRgb8(img.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut data, quality))
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+      Ok(data)
+    }
+  }
+}
+
+/// Encode a progressive JPEG (the image crate only writes baseline JPEGs)
+fn encode_progressive_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+  let rgb = img.to_rgb8();
+  let (width, height) = match (u16::try_from(rgb.width()), u16::try_from(rgb.height())) {
+    (Ok(width), Ok(height)) => (width, height),
+    _ => return Err("Thumbnail too large for JPEG".to_string()),
+  };
+
+  let mut data = Vec::new();
+  let mut encoder = jpeg_encoder::Encoder::new(&mut data, quality);
+  encoder.set_progressive(true);
+  encoder
+    .encode(rgb.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+    .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+  Ok(data)
+}
+
+/// Compute a BlurHash placeholder for an image
+/// Uses 4x3 components (3x4 for portrait), computed on a 32px version since the hash
+/// only keeps low frequencies
+pub fn generate_blurhash(img: &DynamicImage) -> Option<String> {
+  let small = img.thumbnail(BLURHASH_SIZE, BLURHASH_SIZE).to_rgba8();
+  let (components_x, components_y) = if small.width() >= small.height() {
+    (4, 3)
+  } else {
+    (3, 4)
+  };
+  blurhash::encode(
+    components_x,
+    components_y,
+    small.width(),
+    small.height(),
+    small.as_raw(),
+  )
+  .ok()
+}
+
+/// Encode an image as lossy WebP at the given quality (0-100)
+/// The image crate only writes lossless WebP, so this uses libwebp
+pub fn encode_webp(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+  // libwebp only accepts 8-bit RGB or RGBA
+  let converted;
+  let img = match img {
+    DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => img,
+    _ if img.color().has_alpha() => {
+      converted = DynamicImage::ImageRgba8(img.to_rgba8());
+      &converted
+    }
+    _ => {
+      converted = DynamicImage::ImageRgb8(img.to_rgb8());
+      &converted
+    }
+  };
+
+  let encoder =
+    webp::Encoder::from_image(img).map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+  Ok(encoder.encode(quality.min(100) as f32).to_vec())
+}
+
+/// Generate thumbnails from a file, see `generate_thumbnails_from_file`
+pub fn generate_thumbnails_from_file_internal(
+  file_path: &str,
+  relative_path: &str,
+  thumbnails_base_dir: &str,
+  orientation: Option<u32>,
+  format: Option<ThumbnailFormat>,
+  sizes: Option<Vec<ThumbnailSize>>,
+  color_manage: Option<bool>,
+) -> Result<(), String> {
+  let mut plan = ThumbnailPlan::new(sizes, format)?;
+  plan.color_manage = color_manage.unwrap_or(true);
+  let (img, embedded, srgb) = decode_upright(file_path, orientation, &plan)?;
+  let animation = plan
+    .animated_dimension()
+    .and_then(|max_dimension| decode_animation(file_path, max_dimension));
+
+  generate_all_thumbnails_internal(
+    &img,
+    embedded.as_ref(),
+    relative_path,
+    thumbnails_base_dir,
+    &plan,
+    srgb.as_ref(),
+    animation.as_ref(),
+  )
 }
 
 /// Generate thumbnails from a file with a custom relative path
-/// Optionally accepts an orientation value to apply
+/// Optionally accepts an orientation value to apply, an output format (WebP unless
+/// `configure_runtime` sets another default) and a custom set of sizes
+/// (tiny/small/medium/large by default)
+/// Wide-gamut photos are converted to sRGB unless `color_manage` is false
+#[cfg(feature = "node")]
 #[napi]
 pub fn generate_thumbnails_from_file(
   file_path: String,
   relative_path: String,
   thumbnails_base_dir: String,
   orientation: Option<u32>,
-) -> napi::Result<()> {
-  use crate::heif::{decode_heif, is_heif_file};
-  use crate::preview::{extract_preview, is_raw_file};
-  use image::ImageReader;
-  use std::io::Cursor;
-
-  // Decode the image based on file type
-  let img = if is_heif_file(&file_path) {
-    // HEIC/HEIF: decode using libheif
-    decode_heif(&file_path)
-      .map_err(|e| napi::Error::from_reason(format!("Failed to decode HEIF: {}", e)))?
-  } else if is_raw_file(&file_path) {
-    // RAW: extract embedded preview
-    let preview = extract_preview(&file_path)
-      .ok_or_else(|| napi::Error::from_reason("No embedded preview found"))?;
-    ImageReader::new(Cursor::new(preview))
-      .with_guessed_format()
-      .map_err(|e| napi::Error::from_reason(format!("Failed to read preview: {}", e)))?
-      .decode()
-      .map_err(|e| napi::Error::from_reason(format!("Failed to decode preview: {}", e)))?
-  } else {
-    // Standard image: decode directly
-    ImageReader::open(&file_path)
-      .map_err(|e| napi::Error::from_reason(format!("Failed to open image: {}", e)))?
-      .decode()
-      .map_err(|e| napi::Error::from_reason(format!("Failed to decode image: {}", e)))?
-  };
+  format: Option<ThumbnailFormat>,
+  sizes: Option<Vec<ThumbnailSize>>,
+  color_manage: Option<bool>,
+) -> napi::Result<(), ErrorCode> {
+  generate_thumbnails_from_file_internal(
+    &file_path,
+    &relative_path,
+    &thumbnails_base_dir,
+    orientation,
+    format,
+    sizes,
+    color_manage,
+  )
+  .map_err(|e| ProcessingError::from_message(ErrorCode::DecodeFailed, e).into())
+}
+
+/// Generate thumbnails from a file in memory, see `generate_thumbnail_buffers`
+pub fn generate_thumbnail_buffers_from_file(
+  file_path: &str,
+  orientation: Option<u32>,
+  format: Option<ThumbnailFormat>,
+  sizes: Option<Vec<ThumbnailSize>>,
+  color_manage: Option<bool>,
+) -> Result<Vec<ThumbnailBuffer>, String> {
+  let mut plan = ThumbnailPlan::new(sizes, format)?;
+  plan.color_manage = color_manage.unwrap_or(true);
+  let (img, embedded, srgb) = decode_upright(file_path, orientation, &plan)?;
+  let animation = plan
+    .animated_dimension()
+    .and_then(|max_dimension| decode_animation(file_path, max_dimension));
+
+  generate_thumbnail_buffers_internal(
+    &img,
+    embedded.as_ref(),
+    &plan,
+    srgb.as_ref(),
+    animation.as_ref(),
+  )
+}
+
+/// Generate thumbnails from a file and return them in memory instead of writing them to disk
+/// Takes the same options as `generate_thumbnails_from_file`; works for RAW and HEIF files too
+#[cfg(feature = "node")]
+#[napi]
+pub fn generate_thumbnail_buffers(
+  file_path: String,
+  orientation: Option<u32>,
+  format: Option<ThumbnailFormat>,
+  sizes: Option<Vec<ThumbnailSize>>,
+  color_manage: Option<bool>,
+) -> napi::Result<Vec<ThumbnailBuffer>, ErrorCode> {
+  generate_thumbnail_buffers_from_file(&file_path, orientation, format, sizes, color_manage)
+    .map_err(|e| ProcessingError::from_message(ErrorCode::DecodeFailed, e).into())
+}
+
+/// Decode a file with the matching decoder and apply its orientation
+/// (HEIF is already upright after decoding), along with an sRGB converter for its profile
+/// HEIF files also return their embedded thumbnail for the sizes it fits, and it replaces
+/// the full image (which is then never decoded) when it's big enough for every size
+fn decode_upright(
+  file_path: &str,
+  orientation: Option<u32>,
+  plan: &ThumbnailPlan,
+) -> Result<(DynamicImage, Option<DynamicImage>, Option<SrgbConverter>), ProcessingError> {
+  use crate::decode::{decode_image_with_profile, source_kind, SourceKind};
+  use crate::heif::decode_heif_thumbnail;
+  use crate::orientation::resolve_orientation;
 
-  // Apply orientation if provided
-  let img = apply_orientation(img, orientation);
+  let kind = source_kind(file_path).ok_or_else(|| ProcessingError::unsupported(file_path))?;
+
+  // The embedded thumbnail is decoded first, the full image only for the sizes it's too small for
+  let embedded = (kind == SourceKind::Heif)
+    .then(|| decode_heif_thumbnail(file_path, |width, height| plan.any_fits(width, height)))
+    .flatten();
+  match embedded {
+    Some(thumbnail) if plan.all_fit(thumbnail.width(), thumbnail.height()) => {
+      let message = "Rendering every size from the embedded thumbnail".to_string();
+      log_event(LogLevel::Debug, "thumbnails", Some(file_path), message);
+      let srgb = plan
+        .color_manage
+        .then(|| srgb_converter(file_path, kind))
+        .flatten();
+      Ok((thumbnail, None, srgb))
+    }
+    embedded => {
+      let (img, profile) = decode_image_with_profile(file_path, kind)?;
+      let srgb = profile
+        .filter(|_| plan.color_manage)
+        .and_then(|profile| SrgbConverter::new(&profile));
+      let orientation = resolve_orientation(file_path, kind, orientation);
+      Ok((apply_orientation(img, orientation), embedded, srgb))
+    }
+  }
+}
 
-  generate_all_thumbnails_internal(&img, &relative_path, &thumbnails_base_dir)
-    .map_err(|e| napi::Error::from_reason(e))
+/// Generate every size of the plan in memory, in parallel
+pub fn generate_thumbnail_buffers_internal(
+  img: &DynamicImage,
+  embedded: Option<&DynamicImage>,
+  plan: &ThumbnailPlan,
+  srgb: Option<&SrgbConverter>,
+  animation: Option<&Animation>,
+) -> Result<Vec<ThumbnailBuffer>, String> {
+  plan
+    .sizes
+    .par_iter()
+    .map(|size| {
+      let (format, width, height, data) = render_size(img, embedded, size, plan, srgb, animation)?;
+      Ok(ThumbnailBuffer {
+        size_name: size.name.clone(),
+        width,
+        height,
+        format,
+        data: data.into(),
+      })
+    })
+    .collect()
 }
 
 /// Generate all thumbnail sizes from an image based on the relative file path
 /// Thumbnails mirror the original directory structure
 /// Each size is generated in parallel using Rayon
+/// With a default format of AVIF, only sizes up to 400px are AVIF; larger ones stay WebP
+/// Given an animation, sizes marked as animated are animated WebP instead
+/// Given an embedded thumbnail (HEIC), the sizes it's big enough for are rendered from it
 /// Example: photo at "2024/vacation/IMG_1234.jpg" creates thumbnails at:
 ///   - thumbnails/tiny/2024/vacation/IMG_1234.webp
 ///   - thumbnails/small/2024/vacation/IMG_1234.webp
 ///   - etc.
 pub fn generate_all_thumbnails_internal(
   img: &DynamicImage,
+  embedded: Option<&DynamicImage>,
   relative_path: &str,
   thumbnails_base_dir: &str,
+  plan: &ThumbnailPlan,
+  srgb: Option<&SrgbConverter>,
+  animation: Option<&Animation>,
 ) -> Result<(), String> {
-  let sizes = ThumbnailSizes::default();
-
-  // Get the path without extension and convert to .webp
+  // Get the path without extension; each size adds its format's extension
   let path_obj = Path::new(relative_path);
   let path_without_ext = path_obj
     .with_extension("")
     .to_string_lossy()
     .to_string();
 
-  let thumbnail_configs = [
-    ("tiny", &sizes.tiny),
-    ("small", &sizes.small),
-    ("medium", &sizes.medium),
-    ("large", &sizes.large),
-  ];
-
-  // Generate all 4 thumbnail sizes in parallel
-  let results: Vec<Result<(), String>> = thumbnail_configs
+  // Generate all thumbnail sizes in parallel
+  let results: Vec<Result<(), String>> = plan
+    .sizes
     .par_iter()
-    .map(|(size_name, config)| {
-      let output_path = format!("{}/{}/{}.webp", thumbnails_base_dir, size_name, path_without_ext);
-      generate_thumbnail_from_image(img, config, &output_path)
+    .map(|size| {
+      let (format, _, _, data) = render_size(img, embedded, size, plan, srgb, animation)?;
+      let output_path = format!(
+        "{}/{}/{}.{}",
+        thumbnails_base_dir,
+        size.name,
+        path_without_ext,
+        format.extension()
+      );
+      write_thumbnail(&output_path, &data)
     })
     .collect();
 
@@ -174,3 +792,322 @@ pub fn generate_all_thumbnails_internal(
 
   Ok(())
 }
+
+/// Give a photo the thumbnails generated for the same contents at another relative path
+/// (a moved or renamed file), copying every size of the plan
+/// Returns false, leaving any copies made, when one of the sizes can't be reused
+pub(crate) fn reuse_thumbnails(
+  from_relative_path: &str,
+  to_relative_path: &str,
+  thumbnails_dir: &str,
+  plan: &ThumbnailPlan,
+) -> bool {
+  let (Some(from), Some(to)) = (
+    thumbnail_stem(from_relative_path),
+    thumbnail_stem(to_relative_path),
+  ) else {
+    return false;
+  };
+  plan.sizes.iter().all(|size| {
+    let extension = plan.format_for(size).extension();
+    let size_dir = Path::new(thumbnails_dir).join(&size.name);
+    let source = size_dir.join(format!("{}.{}", from, extension));
+    let target = size_dir.join(format!("{}.{}", to, extension));
+    if source == target {
+      return target.is_file();
+    }
+    target
+      .parent()
+      .is_some_and(|parent| fs::create_dir_all(parent).is_ok())
+      && fs::copy(&source, &target).is_ok()
+  })
+}
+
+/// Files and folders removed by a thumbnail cleanup
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Default, PartialEq)]
+pub struct ThumbnailCleanup {
+  pub removed_files: u32,
+  pub removed_directories: u32,
+}
+
+/// Thumbnail path of a photo within a size folder, without extension ("2024/IMG_1234")
+/// None for absolute paths or paths escaping the thumbnails folder
+fn thumbnail_stem(relative_path: &str) -> Option<String> {
+  let path = Path::new(relative_path);
+  let escapes = path
+    .components()
+    .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+  if escapes || relative_path.is_empty() {
+    return None;
+  }
+  Some(path.with_extension("").to_string_lossy().replace('\\', "/"))
+}
+
+/// Size folders (tiny, small, ...) of a thumbnails directory
+fn size_dirs(thumbnails_dir: &str) -> Vec<PathBuf> {
+  fs::read_dir(thumbnails_dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Remove a thumbnail, reporting failures other than it not existing
+fn remove_thumbnail(path: &Path) -> bool {
+  match fs::remove_file(path) {
+    Ok(()) => true,
+    Err(e) => {
+      if e.kind() != io::ErrorKind::NotFound {
+        let message = format!("Failed to remove thumbnail: {}", e);
+        log_event(LogLevel::Warn, "thumbnails", path.to_str(), message);
+      }
+      false
+    }
+  }
+}
+
+/// Remove `dir` and its parents while they are empty, stopping at `root`
+fn remove_empty_dirs(dir: &Path, root: &Path) -> u32 {
+  let mut removed = 0;
+  let mut current = Some(dir);
+  while let Some(dir) = current.filter(|d| d.starts_with(root) && *d != root) {
+    if fs::remove_dir(dir).is_err() {
+      break;
+    }
+    removed += 1;
+    current = dir.parent();
+  }
+  removed
+}
+
+/// Delete every size and format of a photo's thumbnails, see `delete_thumbnails`
+pub fn delete_thumbnails_internal(
+  relative_path: &str,
+  thumbnails_dir: &str,
+) -> Result<ThumbnailCleanup, ProcessingError> {
+  let stem = thumbnail_stem(relative_path)
+    .ok_or_else(|| ProcessingError::invalid(format!("Invalid relative path: {}", relative_path)))?;
+
+  let mut cleanup = ThumbnailCleanup::default();
+  for size_dir in size_dirs(thumbnails_dir) {
+    for format in ThumbnailFormat::ALL {
+      let path = size_dir.join(format!("{}.{}", stem, format.extension()));
+      if remove_thumbnail(&path) {
+        cleanup.removed_files += 1;
+      }
+    }
+    if let Some(parent) = size_dir.join(&stem).parent() {
+      cleanup.removed_directories += remove_empty_dirs(parent, &size_dir);
+    }
+  }
+  Ok(cleanup)
+}
+
+/// Delete every size and format of a photo's thumbnails, and the folders left empty
+#[cfg(feature = "node")]
+#[napi]
+pub fn delete_thumbnails(
+  relative_path: String,
+  thumbnails_dir: String,
+) -> napi::Result<ThumbnailCleanup, ErrorCode> {
+  delete_thumbnails_internal(&relative_path, &thumbnails_dir).map_err(napi::Error::from)
+}
+
+/// Remove thumbnails whose photo is no longer in the library, and empty folders
+/// `valid_relative_paths` are the relative paths of every photo that still exists
+/// The thumbnails directory should only contain generated thumbnails
+#[cfg_attr(feature = "node", napi)]
+pub fn prune_thumbnails(
+  valid_relative_paths: Vec<String>,
+  thumbnails_dir: String,
+) -> ThumbnailCleanup {
+  let valid: HashSet<String> = valid_relative_paths
+    .iter()
+    .filter_map(|path| thumbnail_stem(path))
+    .collect();
+  let extensions = ThumbnailFormat::ALL.map(ThumbnailFormat::extension);
+
+  let mut cleanup = ThumbnailCleanup::default();
+  for size_dir in size_dirs(&thumbnails_dir) {
+    // Contents first, so folders emptied by this pass are removed too
+    for entry in WalkDir::new(&size_dir)
+      .min_depth(1)
+      .contents_first(true)
+      .into_iter()
+      .flatten()
+    {
+      let path = entry.path();
+      if entry.file_type().is_dir() {
+        if fs::remove_dir(path).is_ok() {
+          cleanup.removed_directories += 1;
+        }
+        continue;
+      }
+
+      let is_thumbnail = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext));
+      let orphaned = path
+        .strip_prefix(&size_dir)
+        .ok()
+        .and_then(|relative| thumbnail_stem(&relative.to_string_lossy()))
+        .is_some_and(|stem| !valid.contains(&stem));
+      if is_thumbnail && orphaned && remove_thumbnail(path) {
+        cleanup.removed_files += 1;
+      }
+    }
+  }
+  cleanup
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_avif_only_for_small_sizes() {
+    let avif = ThumbnailFormat::Avif;
+    assert_eq!(avif.for_dimension(150), ThumbnailFormat::Avif);
+    assert_eq!(avif.for_dimension(400), ThumbnailFormat::Avif);
+    assert_eq!(avif.for_dimension(1600), ThumbnailFormat::Webp);
+    assert_eq!(ThumbnailFormat::Jpeg.for_dimension(1600).extension(), "jpg");
+  }
+
+  #[test]
+  fn test_blurhash() {
+    let pixels = image::RgbImage::from_pixel(640, 480, image::Rgb([200, 80, 40]));
+    let img = DynamicImage::ImageRgb8(pixels);
+    let hash = generate_blurhash(&img).unwrap();
+    // 1 size char + 1 max AC char + 4 DC chars + 2 per AC component
+    assert_eq!(hash.len(), 6 + 2 * (4 * 3 - 1));
+  }
+
+  #[test]
+  fn test_unsharp_mask_increases_edge_contrast() {
+    let pixels =
+      image::GrayImage::from_fn(20, 20, |x, _| image::Luma([if x < 10 { 100 } else { 150 }]));
+    let mask = UnsharpMask {
+      amount: 1.0,
+      radius: 1.5,
+    };
+    let sharpened = unsharp_mask(&DynamicImage::ImageLuma8(pixels), mask).to_rgb8();
+    assert!(sharpened.get_pixel(9, 10)[0] < 100);
+    assert!(sharpened.get_pixel(10, 10)[0] > 150);
+    // Flat areas are unchanged
+    assert_eq!(sharpened.get_pixel(2, 10)[0], 100);
+  }
+
+  #[test]
+  fn test_panorama_thumbnails_are_letterboxed() {
+    let panorama = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+      3000,
+      100,
+      image::Rgb([200, 200, 200]),
+    ));
+    let sizes = ThumbnailSizes::default();
+    let small = resize_thumbnail(&panorama, &sizes.small, None).to_rgb8();
+    assert_eq!(small.dimensions(), (390, 156));
+    assert_eq!(small.get_pixel(195, 0)[0], 0);
+    assert_eq!(small.get_pixel(195, 78)[0], 200);
+
+    // Only the grid size is padded
+    let large = resize_thumbnail(&panorama, &sizes.large, None);
+    assert_eq!(large.dimensions(), (1590, 53));
+  }
+
+  #[test]
+  fn test_progressive_jpeg() {
+    let img = DynamicImage::ImageRgb8(image::RgbImage::new(64, 48));
+    let data = encode_thumbnail(&img, ThumbnailFormat::Jpeg, 80, true).unwrap();
+    // Progressive JPEGs use an SOF2 marker
+    assert!(data.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+
+    let decoded = image::load_from_memory(&data).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (64, 48));
+  }
+
+  #[test]
+  fn test_prune_and_delete_thumbnails() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = dir.path().to_string_lossy().to_string();
+    for file in [
+      "tiny/2024/trip/IMG_1.webp",
+      "tiny/2024/trip/IMG_2.webp",
+      "small/2024/trip/IMG_1.avif",
+      "small/2024/gone/IMG_3.webp",
+    ] {
+      let path = dir.path().join(file);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, b"").unwrap();
+    }
+
+    let valid = vec![
+      "2024/trip/IMG_1.jpg".to_string(),
+      "2024/trip/IMG_2.heic".to_string(),
+    ];
+    let pruned = prune_thumbnails(valid, base.clone());
+    assert_eq!(pruned.removed_files, 1);
+    assert!(!dir.path().join("small/2024/gone").exists());
+    assert!(dir.path().join("small/2024/trip/IMG_1.avif").exists());
+
+    let deleted = delete_thumbnails_internal("2024/trip/IMG_1.jpg", &base).unwrap();
+    assert_eq!(deleted.removed_files, 2);
+    assert!(!dir.path().join("small/2024").exists());
+    assert!(dir.path().join("tiny/2024/trip/IMG_2.webp").exists());
+
+    assert!(delete_thumbnails_internal("../outside.jpg", &base).is_err());
+  }
+
+  #[test]
+  fn test_plan_validates_sizes() {
+    let size = |name: &str, format| ThumbnailSize {
+      name: name.to_string(),
+      max_dimension: 2560,
+      quality: 90,
+      format,
+      crop: None,
+      sharpen: None,
+      progressive: None,
+      animated: None,
+      letterbox: None,
+    };
+
+    let mut sizes = ThumbnailSizes::default().to_list();
+    sizes.push(size("xlarge", Some(ThumbnailFormat::Avif)));
+    let plan = ThumbnailPlan::new(Some(sizes), Some(ThumbnailFormat::Avif)).unwrap();
+    assert_eq!(plan.format_for(&plan.sizes[0]), ThumbnailFormat::Avif);
+    assert_eq!(plan.format_for(&plan.sizes[3]), ThumbnailFormat::Webp);
+    // An explicit format is kept even for large sizes
+    assert_eq!(plan.format_for(&plan.sizes[4]), ThumbnailFormat::Avif);
+
+    assert!(ThumbnailPlan::new(Some(vec![size("../x", None)]), None).is_err());
+    assert!(ThumbnailPlan::new(Some(vec![size("a", None), size("a", None)]), None).is_err());
+  }
+
+  #[test]
+  fn test_embedded_thumbnail_renders_small_sizes() {
+    let sizes = ThumbnailSizes::default().to_list();
+    let img = DynamicImage::ImageRgb8(image::RgbImage::new(800, 600));
+    let embedded = DynamicImage::ImageRgb8(image::RgbImage::new(400, 300));
+    assert!(sizes[1].fits_source(400, 300));
+    assert!(!sizes[2].fits_source(400, 300));
+
+    let plan = ThumbnailPlan::default();
+    let (_, width, height, _) =
+      render_size(&img, Some(&embedded), &sizes[2], &plan, None, None).unwrap();
+    // Too small for medium, which is rendered from the full image
+    assert_eq!((width, height), (800, 600));
+
+    let square = ThumbnailSize {
+      crop: Some(ThumbnailCrop::Center),
+      ..sizes[1].clone()
+    };
+    assert!(!square.fits_source(400, 300));
+  }
+}
@@ -0,0 +1,216 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use rayon::prelude::*;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ErrorCode, ErrorStage, ProcessingError};
+use crate::thumbnails::{encode_thumbnail, ThumbnailFormat};
+
+/// Default tile edge; with a 1px overlap on both sides tiles are 256px
+const DEFAULT_TILE_SIZE: u32 = 254;
+
+/// Pixels each tile shares with its neighbors, to hide seams when zooming
+const TILE_OVERLAP: u32 = 1;
+
+/// Encoding quality of tiles
+const TILE_QUALITY: u8 = 85;
+
+/// Deep Zoom (DZI) pyramid written for a photo
+#[cfg_attr(feature = "node", napi(object))]
+pub struct TilePyramid {
+	/// Path of the .dzi descriptor; tiles are in the "{name}_files" folder next to it
+	pub dzi_path: String,
+	pub width: u32,
+	pub height: u32,
+	pub tile_size: u32,
+	pub overlap: u32,
+	/// Number of levels, from 1x1 (level 0) up to full resolution
+	pub levels: u32,
+	pub format: ThumbnailFormat,
+}
+
+/// Number of DZI levels for an image: level 0 is 1x1, the last is full resolution
+fn level_count(width: u32, height: u32) -> u32 {
+	let max_dim = width.max(height).max(1);
+	// ceil(log2(max_dim)) + 1
+	u32::BITS - (max_dim - 1).leading_zeros() + 1
+}
+
+/// Tiles of a level as (column, row, x, y, width, height), overlap included
+fn tile_grid(width: u32, height: u32, tile_size: u32) -> Vec<(u32, u32, u32, u32, u32, u32)> {
+	let columns = width.div_ceil(tile_size);
+	let rows = height.div_ceil(tile_size);
+	let span = |index: u32, extent: u32| {
+		let start = (index * tile_size).saturating_sub(TILE_OVERLAP);
+		let end = ((index + 1) * tile_size + TILE_OVERLAP).min(extent);
+		(start, end - start)
+	};
+
+	(0..rows)
+		.flat_map(|row| (0..columns).map(move |column| (column, row)))
+		.map(|(column, row)| {
+			let (x, tile_width) = span(column, width);
+			let (y, tile_height) = span(row, height);
+			(column, row, x, y, tile_width, tile_height)
+		})
+		.collect()
+}
+
+/// Generate a Deep Zoom tile pyramid for OpenSeadragon-style zooming
+/// Writes `{tiles_dir}/{path}.dzi` and the tiles in `{tiles_dir}/{path}_files/{level}/{col}_{row}.jpg`
+pub fn generate_tiles(
+	img: &DynamicImage,
+	relative_path: &str,
+	tiles_dir: &str,
+	tile_size: u32,
+	format: ThumbnailFormat,
+) -> Result<TilePyramid, ProcessingError> {
+	if !(16..=4096).contains(&tile_size) {
+		return Err(ProcessingError::invalid(format!(
+			"Invalid tile size: {}",
+			tile_size
+		)));
+	}
+
+	let path_without_ext = Path::new(relative_path)
+		.with_extension("")
+		.to_string_lossy()
+		.to_string();
+	let dzi_path = format!("{}/{}.dzi", tiles_dir, path_without_ext);
+	let files_dir = format!("{}/{}_files", tiles_dir, path_without_ext);
+
+	let (width, height) = img.dimensions();
+	let levels = level_count(width, height);
+
+	// Walk down from full resolution, halving the previous level each time
+	let mut level_img = img.clone();
+	for level in (0..levels).rev() {
+		let level_dir = format!("{}/{}", files_dir, level);
+		fs::create_dir_all(&level_dir).map_err(|e| {
+			ProcessingError::io(&e, "Failed to create tile directory").at(ErrorStage::Write)
+		})?;
+
+		tile_grid(level_img.width(), level_img.height(), tile_size)
+			.par_iter()
+			.try_for_each(|&(column, row, x, y, tile_width, tile_height)| {
+				let tile = level_img.crop_imm(x, y, tile_width, tile_height);
+				let data = encode_thumbnail(&tile, format, TILE_QUALITY, false).map_err(|e| {
+					ProcessingError::from_message(ErrorCode::Internal, e).at(ErrorStage::Thumbnails)
+				})?;
+				let tile_path = format!("{}/{}_{}.{}", level_dir, column, row, format.extension());
+				fs::write(&tile_path, data).map_err(|e| {
+					ProcessingError::io(&e, "Failed to save tile").at(ErrorStage::Write)
+				})
+			})?;
+
+		if level > 0 {
+			let (next_width, next_height) = (
+				level_img.width().div_ceil(2).max(1),
+				level_img.height().div_ceil(2).max(1),
+			);
+			level_img = level_img.resize_exact(next_width, next_height, FilterType::Triangle);
+		}
+	}
+
+	let descriptor = format!(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" Format=\"{}\" Overlap=\"{}\" TileSize=\"{}\">\n\
+		\t<Size Width=\"{}\" Height=\"{}\"/>\n\
+		</Image>\n",
+		format.extension(),
+		TILE_OVERLAP,
+		tile_size,
+		width,
+		height
+	);
+	fs::write(&dzi_path, descriptor).map_err(|e| {
+		ProcessingError::io(&e, "Failed to save DZI descriptor").at(ErrorStage::Write)
+	})?;
+
+	Ok(TilePyramid {
+		dzi_path,
+		width,
+		height,
+		tile_size,
+		overlap: TILE_OVERLAP,
+		levels,
+		format,
+	})
+}
+
+/// Generate a Deep Zoom tile pyramid for a photo file, see `generate_tiles_from_file`
+pub fn generate_tiles_from_file_internal(
+	file_path: &str,
+	relative_path: &str,
+	tiles_dir: &str,
+	tile_size: Option<u32>,
+	format: Option<ThumbnailFormat>,
+) -> Result<TilePyramid, ProcessingError> {
+	use crate::decode::{decode_image, source_kind};
+	use crate::exif::extract_exif_internal;
+	use crate::orientation::{apply_orientation, resolve_orientation};
+
+	let kind = source_kind(file_path).ok_or_else(|| ProcessingError::unsupported(file_path))?;
+	let img = decode_image(file_path, kind)?;
+	let exif_orientation = extract_exif_internal(file_path).and_then(|e| e.orientation);
+	let img = apply_orientation(img, resolve_orientation(file_path, kind, exif_orientation));
+
+	generate_tiles(
+		&img,
+		relative_path,
+		tiles_dir,
+		tile_size.unwrap_or(DEFAULT_TILE_SIZE),
+		format.unwrap_or(ThumbnailFormat::Jpeg),
+	)
+}
+
+/// Generate a Deep Zoom tile pyramid for a photo file (e.g. panoramas and large scans)
+/// Tiles are JPEG by default; tile size defaults to 254 (256 with overlap)
+#[cfg(feature = "node")]
+#[napi]
+pub fn generate_tiles_from_file(
+	file_path: String,
+	relative_path: String,
+	tiles_dir: String,
+	tile_size: Option<u32>,
+	format: Option<ThumbnailFormat>,
+) -> napi::Result<TilePyramid, ErrorCode> {
+	generate_tiles_from_file_internal(&file_path, &relative_path, &tiles_dir, tile_size, format)
+		.map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::RgbImage;
+
+	#[test]
+	fn test_level_count_and_grid() {
+		assert_eq!(level_count(1, 1), 1);
+		assert_eq!(level_count(1024, 512), 11);
+		assert_eq!(level_count(1025, 512), 12);
+
+		let grid = tile_grid(600, 300, 254);
+		assert_eq!(grid.len(), 3 * 2);
+		assert_eq!(grid[0], (0, 0, 0, 0, 255, 255));
+		// Inner tiles overlap on both sides, edge tiles are clipped
+		assert_eq!(grid[1], (1, 0, 253, 0, 256, 255));
+		assert_eq!(grid[5], (2, 1, 507, 253, 93, 47));
+	}
+
+	#[test]
+	fn test_generate_tiles_writes_pyramid() {
+		let dir = tempfile::tempdir().unwrap();
+		let tiles_dir = dir.path().to_string_lossy().to_string();
+		let img = DynamicImage::ImageRgb8(RgbImage::new(600, 300));
+
+		let format = ThumbnailFormat::Jpeg;
+		let pyramid = generate_tiles(&img, "2024/pano.jpg", &tiles_dir, 254, format).unwrap();
+		assert_eq!(pyramid.levels, 11);
+		assert!(Path::new(&pyramid.dzi_path).exists());
+		assert!(dir.path().join("2024/pano_files/10/2_1.jpg").exists());
+		assert!(dir.path().join("2024/pano_files/0/0_0.jpg").exists());
+	}
+}
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use tzf_rs::DefaultFinder;
+
+/// Timezone boundaries, loaded on the first lookup
+static FINDER: Lazy<DefaultFinder> = Lazy::new(DefaultFinder::new);
+
+/// IANA timezone at a GPS position, e.g. "Europe/Paris"
+/// Positions at sea get the nautical zone ("Etc/GMT-3"); invalid coordinates return None
+pub fn timezone_at(latitude: f64, longitude: f64) -> Option<Tz> {
+	if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+		return None;
+	}
+	FINDER.get_tz_name(longitude, latitude).parse().ok()
+}
+
+/// Offset of a timezone, in minutes east of UTC, at a local time given as epoch milliseconds
+/// read as if it were UTC (how `date_taken_ms` stores dates without an offset)
+/// Repeated times when DST ends take the earlier offset; skipped ones the offset before the jump
+pub fn offset_minutes_at_local(tz: Tz, local_ms: f64) -> Option<i32> {
+	let local = DateTime::from_timestamp_millis(local_ms as i64)?.naive_utc();
+	let offset = tz
+		.from_local_datetime(&local)
+		.earliest()
+		.map(|date| date.offset().fix())
+		.unwrap_or_else(|| tz.offset_from_utc_datetime(&local).fix());
+	Some(offset.local_minus_utc() / 60)
+}
+
+/// Offset of a timezone, in minutes east of UTC, at a UTC time given as epoch milliseconds
+pub fn offset_minutes_at_utc(tz: Tz, utc_ms: f64) -> Option<i32> {
+	let utc = DateTime::from_timestamp_millis(utc_ms as i64)?.naive_utc();
+	Some(tz.offset_from_utc_datetime(&utc).fix().local_minus_utc() / 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_offset_follows_dst() {
+		// 2023-07-14T10:00:00 and 2023-01-14T10:00:00
+		let (july, january) = (1_689_328_800_000.0, 1_673_690_400_000.0);
+		let paris = timezone_at(48.8566, 2.3522).unwrap();
+		assert_eq!(paris.name(), "Europe/Paris");
+		assert_eq!(offset_minutes_at_local(paris, july), Some(120));
+		assert_eq!(offset_minutes_at_local(paris, january), Some(60));
+
+		let new_york = timezone_at(40.7128, -74.006).unwrap();
+		assert_eq!(new_york.name(), "America/New_York");
+		assert_eq!(offset_minutes_at_local(new_york, july), Some(-240));
+		assert!(timezone_at(91.0, 0.0).is_none());
+	}
+}
@@ -0,0 +1,355 @@
+use chrono::{DateTime, FixedOffset};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+use crate::error::{ErrorCode, ProcessingError};
+use crate::exif::date_taken_to_epoch_ms;
+use crate::features::{require, Component};
+use crate::timezone::{offset_minutes_at_utc, timezone_at};
+
+/// Video container extensions
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+	".mp4", ".mov", ".m4v", ".3gp", ".avi", ".mkv", ".webm", ".mts",
+];
+
+/// Check if a file is a video by extension
+pub fn is_video_file(file_path: &str) -> bool {
+	let lower = file_path.to_lowercase();
+	VIDEO_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+/// Get the MIME type for a video file
+pub fn get_video_mime_type(file_path: &str) -> Option<String> {
+	let lower = file_path.to_lowercase();
+	let mime = if lower.ends_with(".mp4") || lower.ends_with(".m4v") {
+		"video/mp4"
+	} else if lower.ends_with(".mov") {
+		"video/quicktime"
+	} else if lower.ends_with(".3gp") {
+		"video/3gpp"
+	} else if lower.ends_with(".avi") {
+		"video/x-msvideo"
+	} else if lower.ends_with(".mkv") {
+		"video/x-matroska"
+	} else if lower.ends_with(".webm") {
+		"video/webm"
+	} else if lower.ends_with(".mts") {
+		"video/mp2t"
+	} else {
+		return None;
+	};
+	Some(mime.to_string())
+}
+
+/// Container metadata of a video, read with ffprobe
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+	pub duration: Option<f64>,         // in seconds
+	pub width: Option<u32>,            // display width (rotation applied)
+	pub height: Option<u32>,           // display height (rotation applied)
+	pub codec: Option<String>,         // e.g. "hevc", "h264"
+	pub frame_rate: Option<f64>,       // average frames per second
+	pub creation_time: Option<String>, // ISO 8601 in UTC, e.g. "2023-07-14T10:22:01Z"
+	/// Local capture time with its offset, when the camera recorded it (QuickTime's creation
+	/// date), e.g. "2023-07-14T12:22:01+0200"
+	pub local_creation_time: Option<String>,
+	pub gps_latitude: Option<f64>,
+	pub gps_longitude: Option<f64>,
+	pub gps_altitude: Option<f64>, // meters
+}
+
+/// Read duration, resolution, codec, frame rate, creation date and location using ffprobe
+pub fn probe_video(file_path: &str) -> Option<VideoMetadata> {
+	let output = Command::new("ffprobe")
+		.args([
+			"-v",
+			"quiet",
+			"-print_format",
+			"json",
+			"-show_format",
+			"-show_streams",
+			file_path,
+		])
+		.output()
+		.ok()?;
+
+	if !output.status.success() {
+		return None;
+	}
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+	let format = json.get("format");
+	let video_stream = json
+		.get("streams")
+		.and_then(|s| s.as_array())
+		.and_then(|streams| {
+			streams
+				.iter()
+				.find(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("video"))
+		});
+	let format_tag = |key: &str| {
+		format
+			.and_then(|f| f.get("tags"))
+			.and_then(|t| t.get(key))
+			.and_then(|v| v.as_str())
+	};
+
+	// ffprobe reports numbers inside strings ("12.345000")
+	let duration = format
+		.and_then(|f| f.get("duration"))
+		.and_then(|d| d.as_str())
+		.and_then(|d| d.parse::<f64>().ok());
+
+	let coded_width = video_stream
+		.and_then(|s| s.get("width"))
+		.and_then(|w| w.as_u64())
+		.map(|w| w as u32);
+	let coded_height = video_stream
+		.and_then(|s| s.get("height"))
+		.and_then(|h| h.as_u64())
+		.map(|h| h as u32);
+
+	// Phones record portrait video as landscape plus a rotation
+	let rotation = video_stream.and_then(stream_rotation).unwrap_or(0);
+	let (width, height) = if rotation.abs() % 180 == 90 {
+		(coded_height, coded_width)
+	} else {
+		(coded_width, coded_height)
+	};
+
+	let codec = video_stream
+		.and_then(|s| s.get("codec_name"))
+		.and_then(|c| c.as_str())
+		.map(str::to_string);
+	let frame_rate = video_stream
+		.and_then(|s| s.get("avg_frame_rate"))
+		.and_then(|r| r.as_str())
+		.and_then(parse_frame_rate);
+
+	let creation_time = format_tag("creation_time")
+		.or_else(|| {
+			video_stream
+				.and_then(|s| s.get("tags"))
+				.and_then(|t| t.get("creation_time"))
+				.and_then(|c| c.as_str())
+		})
+		.map(normalize_creation_time);
+	// Only kept with an offset, which iPhones always write
+	let local_creation_time = format_tag("com.apple.quicktime.creationdate")
+		.filter(|date| {
+			date_taken_to_epoch_ms(date, None, None).is_some_and(|(_, offset)| offset.is_some())
+		})
+		.map(str::to_string);
+
+	// iPhones write the QuickTime key, Android the older user data atom
+	let location = format_tag("com.apple.quicktime.location.ISO6709")
+		.or_else(|| format_tag("location"))
+		.and_then(parse_iso6709);
+
+	Some(VideoMetadata {
+		duration,
+		width,
+		height,
+		codec,
+		frame_rate,
+		creation_time,
+		local_creation_time,
+		gps_latitude: location.map(|(lat, _, _)| lat),
+		gps_longitude: location.map(|(_, lon, _)| lon),
+		gps_altitude: location.and_then(|(_, _, alt)| alt),
+	})
+}
+
+/// Parse an ffprobe frame rate ("30000/1001"); "0/0" for unknown rates gives None
+fn parse_frame_rate(value: &str) -> Option<f64> {
+	let (num, den) = value.split_once('/')?;
+	let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+	(num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+/// Parse an ISO 6709 location ("+37.7749-122.4194+010.000/") into latitude, longitude and
+/// altitude in meters
+fn parse_iso6709(value: &str) -> Option<(f64, f64, Option<f64>)> {
+	let value = value.trim().trim_end_matches('/');
+	let starts: Vec<usize> = value
+		.char_indices()
+		.filter(|&(_, c)| c == '+' || c == '-')
+		.map(|(i, _)| i)
+		.collect();
+	if starts.first() != Some(&0) {
+		return None;
+	}
+	let number = |i: usize| -> Option<f64> {
+		let end = starts.get(i + 1).copied().unwrap_or(value.len());
+		value[*starts.get(i)?..end].parse().ok()
+	};
+
+	let latitude = number(0).filter(|lat| (-90.0..=90.0).contains(lat))?;
+	let longitude = number(1).filter(|lon| (-180.0..=180.0).contains(lon))?;
+	Some((latitude, longitude, number(2)))
+}
+
+/// Container metadata of a video, see `extract_video_metadata`
+pub fn extract_video_metadata_internal(file_path: &str) -> Result<VideoMetadata, ProcessingError> {
+	require(Component::Ffmpeg)?;
+	probe_video(file_path).ok_or_else(|| {
+		ProcessingError::new(ErrorCode::DecodeFailed, "Failed to read video metadata")
+			.for_file(file_path)
+	})
+}
+
+/// Container metadata of a video: duration, display resolution, codec, frame rate,
+/// creation date and recorded location
+#[cfg(feature = "node")]
+#[napi]
+pub fn extract_video_metadata(file_path: String) -> napi::Result<VideoMetadata, ErrorCode> {
+	extract_video_metadata_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Get the rotation of a video stream in degrees (from side data or the legacy rotate tag)
+fn stream_rotation(stream: &serde_json::Value) -> Option<i64> {
+	let side_data_rotation = stream
+		.get("side_data_list")
+		.and_then(|l| l.as_array())
+		.and_then(|list| list.iter().find_map(|d| d.get("rotation")))
+		.and_then(|r| r.as_i64());
+
+	side_data_rotation.or_else(|| {
+		stream
+			.get("tags")
+			.and_then(|t| t.get("rotate"))
+			.and_then(|r| r.as_str())
+			.and_then(|r| r.parse::<i64>().ok())
+	})
+}
+
+/// Drop fractional seconds from an ffprobe timestamp ("2023-07-14T10:22:01.000000Z")
+fn normalize_creation_time(value: &str) -> String {
+	match value.split_once('.') {
+		Some((seconds, rest)) if rest.ends_with('Z') => format!("{}Z", seconds),
+		Some((seconds, _)) => seconds.to_string(),
+		None => value.to_string(),
+	}
+}
+
+/// Capture date of a video as local wall-clock time with its offset, like photos' EXIF dates
+/// ("2023-07-14T12:22:01+02:00")
+/// Without a recorded local time, the UTC creation time is moved to the timezone at the video's
+/// location, or kept in UTC (+00:00) when it has none
+pub fn local_creation_date(video: &VideoMetadata) -> Option<String> {
+	if let Some(local) = &video.local_creation_time {
+		return Some(local.clone());
+	}
+	let (utc_ms, _) = date_taken_to_epoch_ms(video.creation_time.as_deref()?, None, None)?;
+	let offset_minutes = video
+		.gps_latitude
+		.zip(video.gps_longitude)
+		.and_then(|(lat, lon)| timezone_at(lat, lon))
+		.and_then(|tz| offset_minutes_at_utc(tz, utc_ms))
+		.unwrap_or(0);
+	let offset = FixedOffset::east_opt(offset_minutes * 60)?;
+	let local = DateTime::from_timestamp_millis(utc_ms as i64)?.with_timezone(&offset);
+	Some(local.format("%Y-%m-%dT%H:%M:%S%:z").to_string())
+}
+
+/// Points through a clip (share of its duration) sampled for a poster frame
+const POSTER_FRAME_POINTS: &[f64] = &[0.1, 0.3, 0.5, 0.7];
+
+/// Clips shorter than this (seconds) only get one poster frame candidate
+const MIN_SAMPLED_DURATION: f64 = 2.0;
+
+/// Seek positions (seconds) of the poster frame candidates of a clip
+/// Short or unprobed clips get a single frame slightly in, skipping black lead-in frames
+pub fn poster_frame_seeks(duration: Option<f64>) -> Vec<f64> {
+	match duration {
+		Some(duration) if duration >= MIN_SAMPLED_DURATION => POSTER_FRAME_POINTS
+			.iter()
+			.map(|point| duration * point)
+			.collect(),
+		Some(duration) => vec![(duration * 0.1).min(1.0)],
+		None => vec![0.0],
+	}
+}
+
+/// Extract the frame at `seek` seconds as PNG bytes using ffmpeg, optionally scaled down
+/// to `width` pixels wide
+pub fn extract_frame(file_path: &str, seek: f64, width: Option<u32>) -> Option<Vec<u8>> {
+	let mut command = Command::new("ffmpeg");
+	command.args([
+		"-v",
+		"quiet",
+		"-ss",
+		&format!("{:.3}", seek),
+		"-i",
+		file_path,
+	]);
+	if let Some(width) = width {
+		command.args(["-vf", &format!("scale={}:-2", width)]);
+	}
+	let output = command
+		.args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+		.output()
+		.ok()?;
+
+	if output.status.success() && !output.stdout.is_empty() {
+		Some(output.stdout)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_container_values() {
+		assert_eq!(
+			parse_iso6709("+37.7749-122.4194+010.000/"),
+			Some((37.7749, -122.4194, Some(10.0)))
+		);
+		assert_eq!(
+			parse_iso6709("-33.8688+151.2093/"),
+			Some((-33.8688, 151.2093, None))
+		);
+		assert_eq!(parse_iso6709("37.7749-122.4194/"), None);
+
+		assert_eq!(parse_frame_rate("30/1"), Some(30.0));
+		assert!((parse_frame_rate("30000/1001").unwrap() - 29.97).abs() < 0.01);
+		assert_eq!(parse_frame_rate("0/0"), None);
+	}
+
+	#[test]
+	fn test_local_creation_date() {
+		let mut video = VideoMetadata {
+			creation_time: Some("2023-07-14T10:22:01Z".to_string()),
+			..Default::default()
+		};
+		// Without a location the date stays in UTC, with its offset spelled out
+		assert_eq!(
+			local_creation_date(&video).as_deref(),
+			Some("2023-07-14T10:22:01+00:00")
+		);
+
+		// A recorded local time wins, and gives the same instant
+		video.local_creation_time = Some("2023-07-14T12:22:01+0200".to_string());
+		let local = local_creation_date(&video).unwrap();
+		assert_eq!(local, "2023-07-14T12:22:01+0200");
+		assert_eq!(
+			date_taken_to_epoch_ms(&local, None, None),
+			date_taken_to_epoch_ms("2023-07-14T10:22:01Z", None, None)
+				.map(|(ms, _)| (ms, Some(120)))
+		);
+	}
+
+	#[test]
+	fn test_poster_frame_seeks() {
+		assert_eq!(poster_frame_seeks(Some(8.0)), [0.8, 2.4, 4.0, 5.6]);
+		assert_eq!(poster_frame_seeks(Some(1.0)), [0.1]);
+		assert_eq!(poster_frame_seeks(None), [0.0]);
+	}
+}
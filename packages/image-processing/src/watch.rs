@@ -0,0 +1,333 @@
+#[cfg(feature = "node")]
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, Debouncer, RecommendedCache};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+use crate::batch::is_supported_image;
+use crate::discovery::{library_excludes, Excludes};
+use crate::error::{ErrorCode, ProcessingError};
+
+/// Quiet period before changes to a file are reported, e.g. while it's being copied
+const DEFAULT_DEBOUNCE_MS: u32 = 500;
+
+/// What happened to a file
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEventKind {
+	Created,
+	Modified,
+	Deleted,
+}
+
+/// Change to a supported image file under a watched directory, or a removed directory
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchEvent {
+	pub kind: WatchEventKind,
+	pub file_path: String,
+	/// Path relative to the watched directory, as returned by discovery
+	pub relative_path: String,
+	/// A deleted path that wasn't a photo, usually a directory removed or moved out of the tree:
+	/// every photo under `relative_path` is gone, as its contents can't be listed anymore
+	pub is_directory: bool,
+}
+
+/// Directory watch options
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct WatchOptions {
+	/// Quiet period in milliseconds before changes are reported (default 500)
+	pub debounce_ms: Option<u32>,
+	/// Glob patterns of files and folders to ignore, as in `DiscoveryOptions.exclude`
+	/// The default and .photobrainignore patterns of discovery always apply
+	pub exclude: Option<Vec<String>>,
+}
+
+type DirectoryWatcher = Debouncer<RecommendedWatcher, RecommendedCache>;
+
+/// Active watchers by id, until `unwatch_directory` stops them
+static WATCHERS: Lazy<Mutex<HashMap<u32, DirectoryWatcher>>> = Lazy::new(Default::default);
+
+static NEXT_WATCHER_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Changes an event makes to files; a rename is a deletion plus a creation
+fn file_changes(kind: &EventKind, paths: &[PathBuf]) -> Vec<(WatchEventKind, PathBuf)> {
+	use WatchEventKind::*;
+	let all = |kind: WatchEventKind| paths.iter().map(|path| (kind, path.clone())).collect();
+	match kind {
+		EventKind::Create(_) => all(Created),
+		EventKind::Remove(_) => all(Deleted),
+		EventKind::Modify(ModifyKind::Name(RenameMode::From)) => all(Deleted),
+		EventKind::Modify(ModifyKind::Name(RenameMode::To)) => all(Created),
+		EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match paths {
+			[from, to] => vec![(Deleted, from.clone()), (Created, to.clone())],
+			_ => vec![],
+		},
+		// Renames of unknown direction, e.g. moves to the trash on macOS
+		EventKind::Modify(ModifyKind::Name(_)) => paths
+			.iter()
+			.map(|path| (if path.exists() { Created } else { Deleted }, path.clone()))
+			.collect(),
+		// Permission and timestamp changes leave the photo as it was
+		EventKind::Modify(ModifyKind::Metadata(_)) => vec![],
+		EventKind::Modify(_) => all(Modified),
+		_ => vec![],
+	}
+}
+
+/// Event for a changed path if it's a supported image outside hidden and excluded folders,
+/// or a deleted path that may have been a directory of photos
+/// `roots` are the watched directory as given and canonicalized, since some platforms
+/// report canonical paths; paths are reported under the directory as given
+fn watch_event(
+	roots: &[PathBuf; 2],
+	excludes: &Excludes,
+	kind: WatchEventKind,
+	path: &Path,
+) -> Option<WatchEvent> {
+	let relative = roots.iter().find_map(|root| path.strip_prefix(root).ok())?;
+	let hidden = relative
+		.components()
+		.any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+	let file_path = roots[0].join(relative).to_string_lossy().to_string();
+	// The watched directory itself going away (e.g. an unmounted volume) removes nothing
+	let is_image = is_supported_image(file_path.clone());
+	let is_directory =
+		kind == WatchEventKind::Deleted && !is_image && !relative.as_os_str().is_empty();
+	if hidden || excludes.is_excluded_or_inside(relative) || !(is_image || is_directory) {
+		return None;
+	}
+
+	Some(WatchEvent {
+		kind,
+		file_path,
+		relative_path: relative.to_string_lossy().to_string(),
+		is_directory,
+	})
+}
+
+/// Events for a changed path; a directory created or moved into the tree reports each photo
+/// inside it, as only the directory itself is reported
+fn watch_events(
+	roots: &[PathBuf; 2],
+	excludes: &Excludes,
+	kind: WatchEventKind,
+	path: &Path,
+) -> Vec<WatchEvent> {
+	if kind != WatchEventKind::Created || !path.is_dir() {
+		return watch_event(roots, excludes, kind, path)
+			.into_iter()
+			.collect();
+	}
+	WalkDir::new(path)
+		.into_iter()
+		.filter_map(Result::ok)
+		.filter(|entry| entry.file_type().is_file())
+		.filter_map(|entry| watch_event(roots, excludes, kind, entry.path()))
+		.collect()
+}
+
+/// Watch a directory tree and call `on_change` for each change, see `watch_directory`
+pub fn watch_directory_internal(
+	directory: &str,
+	on_change: impl Fn(WatchEvent) + Send + 'static,
+	options: Option<WatchOptions>,
+) -> Result<u32, ProcessingError> {
+	let root = PathBuf::from(directory);
+	let canonical = fs::canonicalize(&root)
+		.map_err(|e| ProcessingError::io(&e, &format!("Failed to watch {}", directory)))?;
+	let roots = [root, canonical];
+	let options = options.unwrap_or_default();
+	let debounce_ms = options.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS);
+	let excludes =
+		library_excludes(&roots[0], options.exclude).map_err(ProcessingError::invalid)?;
+
+	let handler_roots = roots.clone();
+	let mut watcher = new_debouncer(
+		Duration::from_millis(debounce_ms as u64),
+		None,
+		move |result: DebounceEventResult| {
+			// Watch errors (e.g. an unmounted volume) are dropped; the next full scan catches up
+			let Ok(events) = result else {
+				return;
+			};
+			for event in events {
+				for (kind, path) in file_changes(&event.kind, &event.paths) {
+					for event in watch_events(&handler_roots, &excludes, kind, &path) {
+						on_change(event);
+					}
+				}
+			}
+		},
+	)
+	.map_err(|e| {
+		ProcessingError::new(
+			ErrorCode::Internal,
+			format!("Failed to start watcher: {}", e),
+		)
+	})?;
+	watcher
+		.watch(&roots[0], RecursiveMode::Recursive)
+		.map_err(|e| {
+			ProcessingError::new(
+				ErrorCode::Io,
+				format!("Failed to watch {}: {}", directory, e),
+			)
+		})?;
+
+	let id = NEXT_WATCHER_ID.fetch_add(1, Ordering::Relaxed);
+	WATCHERS
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.insert(id, watcher);
+	Ok(id)
+}
+
+/// Watch a directory tree and report created, modified and deleted photos, so the library
+/// stays in sync without periodic full scans
+/// Changes are debounced: a file being copied is reported once, after it settles
+/// A directory removed or moved out is reported once with `is_directory`: treat it as the
+/// deletion of every photo under its `relative_path`
+/// Returns an id to pass to `unwatch_directory`
+#[cfg(feature = "node")]
+#[napi]
+pub fn watch_directory(
+	directory: String,
+	#[napi(ts_arg_type = "(event: WatchEvent) => void")] on_change: ThreadsafeFunction<WatchEvent>,
+	options: Option<WatchOptions>,
+) -> napi::Result<u32, ErrorCode> {
+	watch_directory_internal(
+		&directory,
+		move |event| {
+			on_change.call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking);
+		},
+		options,
+	)
+	.map_err(napi::Error::from)
+}
+
+/// Stop a watcher started by `watch_directory`
+/// Returns whether it was running
+#[cfg_attr(feature = "node", napi)]
+pub fn unwatch_directory(id: u32) -> bool {
+	let watcher = WATCHERS
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.remove(&id);
+	match watcher {
+		Some(watcher) => {
+			watcher.stop();
+			true
+		}
+		None => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use notify::event::{CreateKind, DataChange, MetadataKind};
+
+	#[test]
+	fn test_file_changes_and_filtering() {
+		let roots = [PathBuf::from("/photos"), PathBuf::from("/private/photos")];
+		let path = |p: &str| PathBuf::from(p);
+
+		let renamed = file_changes(
+			&EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+			&[path("/photos/a.jpg"), path("/photos/b.jpg")],
+		);
+		assert_eq!(
+			renamed,
+			vec![
+				(WatchEventKind::Deleted, path("/photos/a.jpg")),
+				(WatchEventKind::Created, path("/photos/b.jpg"))
+			]
+		);
+		let written = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+		assert_eq!(file_changes(&written, &[path("/photos/a.jpg")]).len(), 1);
+		let touched = EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime));
+		assert!(file_changes(&touched, &[path("/photos/a.jpg")]).is_empty());
+
+		let created = file_changes(
+			&EventKind::Create(CreateKind::File),
+			&[path("/private/photos/2024/IMG_1.HEIC")],
+		);
+		let excludes = library_excludes(Path::new("/photos"), None).unwrap();
+		let event = watch_event(&roots, &excludes, created[0].0, &created[0].1).unwrap();
+		assert_eq!(event.file_path, "/photos/2024/IMG_1.HEIC");
+		assert_eq!(event.relative_path, "2024/IMG_1.HEIC");
+
+		for ignored in [
+			"/photos/notes.txt",
+			"/photos/.cache/a.jpg",
+			"/photos/2024/@eaDir/a.jpg",
+			"/photos/Catalog Previews.lrdata/a.jpg",
+			"/elsewhere/a.jpg",
+		] {
+			let event = watch_event(
+				&roots,
+				&excludes,
+				WatchEventKind::Created,
+				Path::new(ignored),
+			);
+			assert!(event.is_none());
+		}
+	}
+
+	#[test]
+	fn test_directory_moved_in() {
+		let dir = tempfile::tempdir().unwrap();
+		let root = dir.path().to_path_buf();
+		let trip = root.join("trip");
+		fs::create_dir_all(trip.join("@eaDir")).unwrap();
+		for name in ["IMG_1.jpg", "notes.txt", "@eaDir/IMG_1.jpg"] {
+			fs::write(trip.join(name), b"").unwrap();
+		}
+		fs::write(root.join(".photobrainignore"), "*.txt\n").unwrap();
+
+		let roots = [root.clone(), root.clone()];
+		let excludes = library_excludes(&root, None).unwrap();
+		let events = watch_events(&roots, &excludes, WatchEventKind::Created, &trip);
+		let relative: Vec<_> = events.iter().map(|e| e.relative_path.as_str()).collect();
+		assert_eq!(relative, vec!["trip/IMG_1.jpg"]);
+	}
+
+	#[test]
+	fn test_directory_removed() {
+		let roots = [PathBuf::from("/photos"), PathBuf::from("/photos")];
+		let excludes = library_excludes(Path::new("/photos"), None).unwrap();
+		let deleted = |path: &str| {
+			let changes = file_changes(
+				&EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+				&[PathBuf::from(path)],
+			);
+			watch_events(&roots, &excludes, changes[0].0, &changes[0].1)
+		};
+
+		let trip = deleted("/photos/2024/trip");
+		assert_eq!(trip.len(), 1);
+		assert_eq!(trip[0].kind, WatchEventKind::Deleted);
+		assert_eq!(trip[0].relative_path, "2024/trip");
+		assert!(trip[0].is_directory);
+
+		let photo = deleted("/photos/2024/IMG_1.jpg");
+		assert!(!photo[0].is_directory);
+
+		// Hidden and excluded folders, and the watched directory itself, report nothing
+		for ignored in ["/photos/.cache", "/photos/2024/@eaDir", "/photos"] {
+			assert!(deleted(ignored).is_empty());
+		}
+	}
+}
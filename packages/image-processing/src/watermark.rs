@@ -0,0 +1,235 @@
+use ab_glyph::{point, Font, FontVec, Glyph, PxScale, ScaleFont};
+use image::{imageops, imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::fs;
+
+/// Size text is measured at to find the size that gives it the watermark's width
+const TEXT_MEASURE_SIZE: f32 = 128.0;
+
+/// Watermark width as a share of the photo's width when no scale is given
+const DEFAULT_SCALE: f64 = 0.2;
+
+/// Watermark opacity when none is given
+const DEFAULT_OPACITY: f64 = 0.5;
+
+/// Distance from the edges as a share of the photo's shorter side when none is given
+const DEFAULT_MARGIN: f64 = 0.02;
+
+/// Where a watermark is placed
+#[cfg_attr(feature = "node", napi(string_enum = "snake_case"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WatermarkPosition {
+	TopLeft,
+	TopRight,
+	BottomLeft,
+	#[default]
+	BottomRight,
+	Center,
+}
+
+/// Text or PNG drawn over an exported photo, e.g. a photographer credit
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default)]
+pub struct Watermark {
+	/// Text to draw, e.g. "© Jane Doe"; needs `font_path`
+	pub text: Option<String>,
+	/// TrueType/OpenType font the text is drawn with
+	pub font_path: Option<String>,
+	/// Text color as "#RRGGBB" (default white)
+	pub color: Option<String>,
+	/// PNG (transparency kept) drawn instead of text
+	pub image_path: Option<String>,
+	pub position: Option<WatermarkPosition>,
+	/// 0-1 (default 0.5)
+	pub opacity: Option<f64>,
+	/// Width of the watermark as a share of the photo's width (default 0.2)
+	pub scale: Option<f64>,
+	/// Distance from the edges as a share of the photo's shorter side (default 0.02)
+	pub margin: Option<f64>,
+}
+
+/// Parse a "#RRGGBB" color
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+	let hex = color.strip_prefix('#')?;
+	if hex.len() != 6 {
+		return None;
+	}
+	let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+	Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Place the glyphs of a line of text at a size, returning them with the line's width
+fn layout_text(font: &FontVec, text: &str, size: f32) -> (Vec<Glyph>, f32) {
+	let scale = PxScale::from(size);
+	let scaled = font.as_scaled(scale);
+	let mut glyphs = Vec::new();
+	let mut caret = 0.0;
+	let mut previous = None;
+	for c in text.chars() {
+		let id = scaled.glyph_id(c);
+		if let Some(previous) = previous {
+			caret += scaled.kern(previous, id);
+		}
+		glyphs.push(id.with_scale_and_position(scale, point(caret, scaled.ascent())));
+		caret += scaled.h_advance(id);
+		previous = Some(id);
+	}
+	(glyphs, caret)
+}
+
+/// Draw a line of text in a color onto a transparent image fitting it, `target_width` wide
+/// The glyphs are rasterized at the size they're shown at, so they stay sharp on large photos
+fn render_text(
+	text: &str,
+	font_path: &str,
+	color: [u8; 3],
+	target_width: u32,
+) -> Result<RgbaImage, String> {
+	let data = fs::read(font_path).map_err(|e| format!("Failed to read {}: {}", font_path, e))?;
+	let font = FontVec::try_from_vec(data).map_err(|e| format!("Invalid font: {}", e))?;
+
+	let (_, measured_width) = layout_text(&font, text, TEXT_MEASURE_SIZE);
+	if measured_width <= 0.0 {
+		return Err("Watermark text is empty".to_string());
+	}
+	let size = TEXT_MEASURE_SIZE * target_width as f32 / measured_width;
+	let (glyphs, caret) = layout_text(&font, text, size);
+	let scaled = font.as_scaled(PxScale::from(size));
+
+	let width = caret.ceil() as u32;
+	let height = (scaled.ascent() - scaled.descent()).ceil() as u32;
+	if width == 0 || height == 0 {
+		return Err("Watermark text is empty".to_string());
+	}
+	let mut canvas = RgbaImage::new(width, height);
+	for glyph in glyphs {
+		let outlined = match font.outline_glyph(glyph) {
+			Some(outlined) => outlined,
+			None => continue,
+		};
+		let bounds = outlined.px_bounds();
+		outlined.draw(|x, y, coverage| {
+			let x = bounds.min.x as i64 + x as i64;
+			let y = bounds.min.y as i64 + y as i64;
+			if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+				return;
+			}
+			let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+			let alpha = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+			*pixel = Rgba([color[0], color[1], color[2], pixel[3].max(alpha)]);
+		});
+	}
+	Ok(canvas)
+}
+
+/// Top-left corner of a watermark of `mark` size placed on a photo of `photo` size
+fn watermark_origin(
+	position: WatermarkPosition,
+	photo: (u32, u32),
+	mark: (u32, u32),
+	margin: u32,
+) -> (i64, i64) {
+	let (width, height) = (photo.0 as i64, photo.1 as i64);
+	let (mark_width, mark_height) = (mark.0 as i64, mark.1 as i64);
+	let margin = margin as i64;
+	let left = margin;
+	let right = width - mark_width - margin;
+	let top = margin;
+	let bottom = height - mark_height - margin;
+	match position {
+		WatermarkPosition::TopLeft => (left, top),
+		WatermarkPosition::TopRight => (right, top),
+		WatermarkPosition::BottomLeft => (left, bottom),
+		WatermarkPosition::BottomRight => (right, bottom),
+		WatermarkPosition::Center => ((width - mark_width) / 2, (height - mark_height) / 2),
+	}
+}
+
+/// Draw a text or PNG watermark over an image, scaled to its width
+pub fn apply_watermark(img: DynamicImage, watermark: &Watermark) -> Result<DynamicImage, String> {
+	let (width, height) = (img.width(), img.height());
+	let scale = watermark.scale.unwrap_or(DEFAULT_SCALE).clamp(0.01, 1.0);
+	let mark_width = ((width as f64 * scale).round() as u32).max(1);
+	let mut mark = match (&watermark.image_path, &watermark.text) {
+		(Some(image_path), _) => {
+			let mark = image::open(image_path)
+				.map_err(|e| format!("Failed to read {}: {}", image_path, e))?
+				.to_rgba8();
+			let mark_height = ((mark.height() as f64 * mark_width as f64 / mark.width() as f64)
+				.round() as u32)
+				.max(1);
+			imageops::resize(&mark, mark_width, mark_height, FilterType::Lanczos3)
+		}
+		(None, Some(text)) => {
+			let font_path = watermark
+				.font_path
+				.as_deref()
+				.ok_or("Text watermarks need a font")?;
+			let color = match &watermark.color {
+				Some(color) => parse_color(color).ok_or(format!("Invalid color: {}", color))?,
+				None => [255, 255, 255],
+			};
+			render_text(text, font_path, color, mark_width)?
+		}
+		(None, None) => return Err("Watermark needs text or an image".to_string()),
+	};
+
+	let opacity = watermark.opacity.unwrap_or(DEFAULT_OPACITY).clamp(0.0, 1.0);
+	for pixel in mark.pixels_mut() {
+		pixel[3] = (pixel[3] as f64 * opacity).round() as u8;
+	}
+	let margin = watermark.margin.unwrap_or(DEFAULT_MARGIN).clamp(0.0, 0.5);
+	let margin = (width.min(height) as f64 * margin).round() as u32;
+	let position = watermark.position.unwrap_or_default();
+	let (x, y) = watermark_origin(position, (width, height), mark.dimensions(), margin);
+
+	let has_alpha = img.color().has_alpha();
+	let mut canvas = img.to_rgba8();
+	imageops::overlay(&mut canvas, &mark, x, y);
+	Ok(if has_alpha {
+		DynamicImage::ImageRgba8(canvas)
+	} else {
+		DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::{Rgb, RgbImage};
+
+	#[test]
+	fn test_watermark_placement() {
+		assert_eq!(parse_color("#FF8000"), Some([255, 128, 0]));
+		assert_eq!(parse_color("FF8000"), None);
+		assert_eq!(
+			watermark_origin(WatermarkPosition::BottomRight, (1000, 500), (200, 50), 10),
+			(790, 440)
+		);
+		assert_eq!(
+			watermark_origin(WatermarkPosition::Center, (1000, 500), (200, 50), 10),
+			(400, 225)
+		);
+
+		let dir = tempfile::tempdir().unwrap();
+		let logo_path = dir.path().join("logo.png");
+		RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]))
+			.save(&logo_path)
+			.unwrap();
+		let photo = DynamicImage::ImageRgb8(RgbImage::from_pixel(400, 200, Rgb([0, 0, 0])));
+		let watermark = Watermark {
+			image_path: Some(logo_path.to_string_lossy().to_string()),
+			position: Some(WatermarkPosition::TopLeft),
+			opacity: Some(0.5),
+			scale: Some(0.25),
+			margin: Some(0.0),
+			..Default::default()
+		};
+		let marked = apply_watermark(photo, &watermark).unwrap().to_rgb8();
+		// A 100x50 half-transparent white logo in the top left corner
+		assert_eq!(marked.get_pixel(50, 25)[0], 128);
+		assert_eq!(marked.get_pixel(150, 25)[0], 0);
+		assert_eq!(marked.get_pixel(50, 75)[0], 0);
+	}
+}
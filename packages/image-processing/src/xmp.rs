@@ -0,0 +1,453 @@
+#[cfg(feature = "node")]
+use napi_derive::napi;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[cfg(feature = "node")]
+use crate::error::ErrorCode;
+use crate::error::ProcessingError;
+use crate::exif::{date_taken_to_epoch_ms, get_list, signed_altitude, ExifData};
+use crate::exif_write::run_exiftool;
+use crate::features::{require, Component};
+use crate::preview::is_raw_file;
+
+/// Metadata read from a standalone `.xmp` sidecar
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Debug, Clone, Default)]
+pub struct XmpSidecar {
+	pub path: String,
+	pub rating: Option<i32>,   // 0-5 stars, -1 = rejected
+	pub label: Option<String>, // e.g., "Red"
+	pub keywords: Vec<String>,
+	pub title: Option<String>,
+	pub caption: Option<String>,
+	/// Lightroom/Camera Raw settings or a darktable history stack are present
+	pub has_edit_history: bool,
+	// Fields written back for RAW files by `write_exif_fields`
+	pub date_taken: Option<String>,
+	pub gps_latitude: Option<f64>,
+	pub gps_longitude: Option<f64>,
+	pub gps_altitude: Option<f64>,
+	pub orientation: Option<u32>,
+}
+
+/// Fields to update in a sidecar; omitted fields are left untouched
+#[cfg_attr(feature = "node", napi(object))]
+#[derive(Default)]
+pub struct XmpSidecarUpdate {
+	/// 0-5 stars, -1 = rejected
+	pub rating: Option<i32>,
+	/// Color label; an empty string clears it
+	pub label: Option<String>,
+	/// Replaces all keywords
+	pub keywords: Option<Vec<String>>,
+	pub title: Option<String>,
+	pub caption: Option<String>,
+}
+
+/// Default sidecar path for a photo: Lightroom style for RAW files ("IMG_0001.CR2" ->
+/// "IMG_0001.xmp"), the full file name for others ("IMG_0001.JPG" -> "IMG_0001.JPG.xmp"),
+/// so the JPEG of a RAW+JPEG pair doesn't share the RAW's sidecar
+pub fn sidecar_path(file_path: &str) -> String {
+	if is_raw_file(file_path) {
+		Path::new(file_path)
+			.with_extension("xmp")
+			.to_string_lossy()
+			.to_string()
+	} else {
+		format!("{}.xmp", file_path)
+	}
+}
+
+/// Find an existing sidecar for a photo
+/// Checks darktable naming ("IMG_0001.CR2.xmp") first, then for RAW files only Lightroom's
+/// ("IMG_0001.xmp"), which belongs to the RAW of a RAW+JPEG pair
+pub fn find_sidecar(file_path: &str) -> Option<String> {
+	let path = Path::new(file_path);
+	let appended = PathBuf::from(format!("{}.xmp", file_path));
+	let replaced = if is_raw_file(file_path) {
+		vec![path.with_extension("xmp"), path.with_extension("XMP")]
+	} else {
+		Vec::new()
+	};
+
+	std::iter::once(appended)
+		.chain(replaced)
+		.find(|candidate| candidate.as_path() != path && candidate.is_file())
+		.map(|candidate| candidate.to_string_lossy().to_string())
+}
+
+/// Parse a sidecar file with exiftool
+pub fn read_sidecar_internal(xmp_path: &str) -> Option<XmpSidecar> {
+	let output = Command::new("exiftool")
+		.args([
+			"-json",
+			"-XMP:Rating#",
+			"-XMP:Label",
+			"-XMP:Subject",
+			"-XMP:Title",
+			"-XMP:Description",
+			"-XMP:DateTimeOriginal",
+			"-XMP:GPSLatitude#",
+			"-XMP:GPSLongitude#",
+			"-XMP:GPSAltitude#",
+			"-XMP:GPSAltitudeRef#",
+			"-XMP:Orientation#",
+			// Edit history markers (Lightroom/Camera Raw, darktable, generic xmpMM)
+			"-XMP-crs:ProcessVersion",
+			"-XMP-crs:HasSettings",
+			"-XMP-darktable:HistoryOperation",
+			"-XMP-xmpMM:HistoryAction",
+			xmp_path,
+		])
+		.output()
+		.ok()?;
+
+	if !output.status.success() {
+		return None;
+	}
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+	let obj = json.as_array()?.first()?.as_object()?;
+
+	let get_str = |key: &str| {
+		obj.get(key)
+			.and_then(|v| v.as_str())
+			.map(|s| s.trim().to_string())
+			.filter(|s| !s.is_empty())
+	};
+	let get_f64 = |key: &str| obj.get(key).and_then(|v| v.as_f64());
+
+	let has_edit_history = ["ProcessVersion", "HistoryOperation", "HistoryAction"]
+		.iter()
+		.any(|k| obj.contains_key(*k))
+		|| get_str("HasSettings").is_some_and(|v| v.eq_ignore_ascii_case("true"));
+
+	Some(XmpSidecar {
+		path: xmp_path.to_string(),
+		rating: get_f64("Rating")
+			.map(|r| r.round() as i32)
+			.filter(|r| (-1..=5).contains(r)),
+		label: get_str("Label"),
+		keywords: get_list(obj, "Subject"),
+		title: get_str("Title"),
+		caption: get_str("Description"),
+		has_edit_history,
+		date_taken: get_str("DateTimeOriginal"),
+		gps_latitude: get_f64("GPSLatitude"),
+		gps_longitude: get_f64("GPSLongitude"),
+		gps_altitude: get_f64("GPSAltitude")
+			.map(|a| signed_altitude(a, get_f64("GPSAltitudeRef").map(|r| r as u32))),
+		orientation: get_f64("Orientation").map(|o| o as u32),
+	})
+}
+
+impl XmpSidecar {
+	/// Overlay sidecar values on embedded EXIF; the sidecar wins, as in Lightroom and darktable
+	/// Rating and label are handled by `resolve_rating_and_label`
+	pub fn merge_into(&self, exif: &mut ExifData) {
+		// The sidecar's list replaces the embedded one, so keywords removed in the editor stay
+		// removed; exiftool reports a cleared list like a missing one, which keeps the embedded
+		if !self.keywords.is_empty() {
+			exif.keywords = Some(self.keywords.clone());
+		}
+		if self.title.is_some() {
+			exif.title = self.title.clone();
+		}
+		if self.caption.is_some() {
+			exif.caption = self.caption.clone();
+		}
+		if let Some(date_taken) = &self.date_taken
+			&& date_taken_to_epoch_ms(date_taken, None, None).is_some()
+		{
+			exif.set_date_taken(date_taken.clone());
+		}
+		if let (Some(latitude), Some(longitude)) = (self.gps_latitude, self.gps_longitude) {
+			exif.gps_latitude = Some(latitude);
+			exif.gps_longitude = Some(longitude);
+			exif.gps_altitude = self.gps_altitude.or(exif.gps_altitude);
+		}
+		if self.orientation.is_some() {
+			exif.orientation = self.orientation;
+		}
+	}
+}
+
+/// Rating and label as stored by each source of one photo
+#[derive(Debug, Clone, Default)]
+pub struct RatingSources {
+	pub exif_rating: Option<i32>,
+	pub xmp_rating: Option<i32>,
+	pub xmp_label: Option<String>,
+	pub sidecar_rating: Option<i32>,
+	pub sidecar_label: Option<String>,
+}
+
+/// One rating and label, with the source each was taken from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedRating {
+	pub rating: Option<i32>,
+	pub rating_source: Option<&'static str>,
+	pub label: Option<String>,
+	pub label_source: Option<&'static str>,
+}
+
+impl RatingSources {
+	/// Pick one rating and label: sidecar first (the latest edit), then embedded XMP, then EXIF
+	pub fn resolve(&self) -> ResolvedRating {
+		let valid = |rating: Option<i32>| rating.filter(|r| (-1..=5).contains(r));
+		let (rating, rating_source) = [
+			(valid(self.sidecar_rating), "sidecar"),
+			(valid(self.xmp_rating), "xmp"),
+			(valid(self.exif_rating), "exif"),
+		]
+		.into_iter()
+		.find_map(|(rating, source)| Some((rating?, source)))
+		.unzip();
+
+		let non_empty = |label: &Option<String>| label.clone().filter(|l| !l.trim().is_empty());
+		let (label, label_source) = [
+			(non_empty(&self.sidecar_label), "sidecar"),
+			(non_empty(&self.xmp_label), "xmp"),
+		]
+		.into_iter()
+		.find_map(|(label, source)| Some((label?, source)))
+		.unzip();
+
+		ResolvedRating {
+			rating,
+			rating_source,
+			label,
+			label_source,
+		}
+	}
+}
+
+/// Let the sidecar's rating and label take precedence over the embedded ones that
+/// `extract_exif` resolved
+pub fn resolve_rating_and_label(exif: &mut ExifData, sidecar: Option<&XmpSidecar>) {
+	let Some(sidecar) = sidecar else {
+		return;
+	};
+	let resolved = RatingSources {
+		sidecar_rating: sidecar.rating,
+		sidecar_label: sidecar.label.clone(),
+		..Default::default()
+	}
+	.resolve();
+
+	if resolved.rating.is_some() {
+		exif.rating = resolved.rating;
+		exif.rating_source = resolved.rating_source.map(str::to_string);
+	}
+	if resolved.label.is_some() {
+		exif.color_label = resolved.label;
+		exif.label_source = resolved.label_source.map(str::to_string);
+	}
+}
+
+/// Build exiftool assignments for a sidecar update
+fn update_assignments(update: &XmpSidecarUpdate) -> Result<Vec<String>, ProcessingError> {
+	let mut args = Vec::new();
+
+	if let Some(rating) = update.rating {
+		if !(-1..=5).contains(&rating) {
+			return Err(ProcessingError::invalid(format!(
+				"Invalid rating: {}",
+				rating
+			)));
+		}
+		args.push(format!("-XMP-xmp:Rating={}", rating));
+	}
+	if let Some(label) = &update.label {
+		args.push(format!("-XMP-xmp:Label={}", label.trim()));
+	}
+	if let Some(keywords) = &update.keywords {
+		// An empty assignment clears the list before the new items are added
+		args.push("-XMP-dc:Subject=".to_string());
+		for keyword in keywords.iter().map(|k| k.trim()).filter(|k| !k.is_empty()) {
+			args.push(format!("-XMP-dc:Subject={}", keyword));
+		}
+	}
+	if let Some(title) = &update.title {
+		args.push(format!("-XMP-dc:Title={}", title));
+	}
+	if let Some(caption) = &update.caption {
+		args.push(format!("-XMP-dc:Description={}", caption));
+	}
+
+	Ok(args)
+}
+
+/// Read the XMP sidecar next to a photo, see `read_xmp_sidecar`
+pub fn read_xmp_sidecar_internal(file_path: &str) -> Result<Option<XmpSidecar>, ProcessingError> {
+	require(Component::Exiftool)?;
+	Ok(find_sidecar(file_path).and_then(|xmp_path| read_sidecar_internal(&xmp_path)))
+}
+
+/// Read the XMP sidecar next to a photo, if there is one
+#[cfg(feature = "node")]
+#[napi]
+pub fn read_xmp_sidecar(file_path: String) -> napi::Result<Option<XmpSidecar>, ErrorCode> {
+	read_xmp_sidecar_internal(&file_path).map_err(napi::Error::from)
+}
+
+/// Update the XMP sidecar next to a photo, see `write_xmp_sidecar`
+pub fn write_xmp_sidecar_internal(
+	file_path: &str,
+	update: &XmpSidecarUpdate,
+) -> Result<String, ProcessingError> {
+	require(Component::Exiftool)?;
+
+	let assignments = update_assignments(update)?;
+	if assignments.is_empty() {
+		return Err(ProcessingError::invalid("No fields to write"));
+	}
+
+	let xmp_path = match find_sidecar(file_path) {
+		Some(existing) => {
+			run_exiftool(&assignments, &[], &existing)?;
+			existing
+		}
+		None => {
+			let xmp_path = sidecar_path(file_path);
+			// Create the sidecar with only the assigned tags
+			run_exiftool(&assignments, &["-o", &xmp_path], file_path)?;
+			xmp_path
+		}
+	};
+	Ok(xmp_path)
+}
+
+/// Update (or create) the XMP sidecar next to a photo; the photo itself is never modified
+/// Returns the sidecar path
+#[cfg(feature = "node")]
+#[napi]
+pub fn write_xmp_sidecar(
+	file_path: String,
+	update: XmpSidecarUpdate,
+) -> napi::Result<String, ErrorCode> {
+	write_xmp_sidecar_internal(&file_path, &update).map_err(napi::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sidecar_overrides_embedded_metadata() {
+		let mut exif = ExifData {
+			keywords: Some(vec!["Paris".to_string()]),
+			gps_altitude: Some(35.0),
+			..Default::default()
+		};
+		let sidecar = XmpSidecar {
+			keywords: vec!["paris".to_string(), "Eiffel Tower".to_string()],
+			gps_latitude: Some(48.8584),
+			gps_longitude: Some(2.2945),
+			date_taken: Some("2023:07:14 10:22:01+02:00".to_string()),
+			..Default::default()
+		};
+		sidecar.merge_into(&mut exif);
+
+		assert_eq!(
+			exif.keywords,
+			Some(vec!["paris".to_string(), "Eiffel Tower".to_string()])
+		);
+		assert_eq!(exif.gps_latitude, Some(48.8584));
+		assert_eq!(exif.gps_altitude, Some(35.0));
+		assert_eq!(exif.date_taken_offset_minutes, Some(120));
+	}
+
+	#[test]
+	fn test_sidecar_keywords_remove_embedded_ones() {
+		let mut exif = ExifData {
+			keywords: Some(vec!["Paris".to_string(), "Louvre".to_string()]),
+			..Default::default()
+		};
+		let sidecar = XmpSidecar {
+			keywords: vec!["Paris".to_string()],
+			..Default::default()
+		};
+		sidecar.merge_into(&mut exif);
+		assert_eq!(exif.keywords, Some(vec!["Paris".to_string()]));
+
+		// A sidecar without keywords leaves the embedded ones
+		XmpSidecar::default().merge_into(&mut exif);
+		assert_eq!(exif.keywords, Some(vec!["Paris".to_string()]));
+	}
+
+	#[test]
+	fn test_rating_resolution_prefers_sidecar_then_xmp() {
+		let sources = RatingSources {
+			exif_rating: Some(3),
+			xmp_rating: Some(4),
+			xmp_label: Some("Red".to_string()),
+			sidecar_rating: None,
+			sidecar_label: Some(" ".to_string()),
+		};
+		assert_eq!(
+			sources.resolve(),
+			ResolvedRating {
+				rating: Some(4),
+				rating_source: Some("xmp"),
+				label: Some("Red".to_string()),
+				label_source: Some("xmp"),
+			}
+		);
+
+		let with_sidecar = RatingSources {
+			sidecar_rating: Some(-1),
+			..sources
+		};
+		assert_eq!(with_sidecar.resolve().rating_source, Some("sidecar"));
+		assert_eq!(
+			RatingSources::default().resolve(),
+			ResolvedRating::default()
+		);
+	}
+
+	#[test]
+	fn test_update_assignments_replace_keywords() {
+		let update = XmpSidecarUpdate {
+			rating: Some(4),
+			keywords: Some(vec!["beach".to_string(), " ".to_string()]),
+			..Default::default()
+		};
+		assert_eq!(
+			update_assignments(&update).unwrap(),
+			vec![
+				"-XMP-xmp:Rating=4",
+				"-XMP-dc:Subject=",
+				"-XMP-dc:Subject=beach"
+			]
+		);
+
+		let invalid = XmpSidecarUpdate {
+			rating: Some(7),
+			..Default::default()
+		};
+		assert!(update_assignments(&invalid).is_err());
+	}
+
+	#[test]
+	fn test_sidecar_of_raw_jpeg_pair() {
+		let dir = std::env::temp_dir().join("photobrain-test-sidecar");
+		std::fs::create_dir_all(&dir).unwrap();
+		let raw = dir.join("IMG_0001.CR2").to_string_lossy().to_string();
+		let jpeg = dir.join("IMG_0001.JPG").to_string_lossy().to_string();
+		assert_eq!(
+			sidecar_path(&raw),
+			dir.join("IMG_0001.xmp").to_string_lossy()
+		);
+		assert_eq!(sidecar_path(&jpeg), format!("{}.xmp", jpeg));
+
+		std::fs::write(sidecar_path(&raw), "").unwrap();
+		assert_eq!(find_sidecar(&raw), Some(sidecar_path(&raw)));
+		assert_eq!(find_sidecar(&jpeg), None);
+
+		std::fs::write(sidecar_path(&jpeg), "").unwrap();
+		assert_eq!(find_sidecar(&jpeg), Some(sidecar_path(&jpeg)));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}